@@ -0,0 +1,150 @@
+//! Deterministic synthetic data shared by the benchmarks
+//!
+//! Every generator takes a seed so runs are reproducible across machines.
+
+#![allow(dead_code)]
+
+/// Record counts each scenario is measured at
+pub const SIZES: [usize; 2] = [100_000, 1_000_000];
+
+/// Small xorshift64* generator; no external RNG dependency needed
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform value in 0..bound
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// Random bytes drawn from an alphabet
+    pub fn word(&mut self, alphabet: &[u8], len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| alphabet[self.below(alphabet.len() as u64) as usize])
+            .collect()
+    }
+}
+
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const MIXED: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Printable ASCII lines of 8-24 bytes
+pub fn ascii_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    (0..n)
+        .map(|_| {
+            let len = 8 + rng.below(17) as usize;
+            rng.word(LOWER, len)
+        })
+        .collect()
+}
+
+/// Signed decimal numbers, some with fractions and leading blanks
+pub fn numeric_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    (0..n)
+        .map(|_| {
+            let mut line = Vec::new();
+            if rng.below(8) == 0 {
+                line.extend_from_slice(b"  ");
+            }
+            if rng.below(4) == 0 {
+                line.push(b'-');
+            }
+            line.extend_from_slice(rng.below(1_000_000).to_string().as_bytes());
+            if rng.below(2) == 0 {
+                line.push(b'.');
+                line.extend_from_slice(rng.below(1000).to_string().as_bytes());
+            }
+            line
+        })
+        .collect()
+}
+
+/// Comma-separated rows: word,number,word,word
+pub fn csv_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    (0..n)
+        .map(|_| {
+            let mut line = rng.word(LOWER, 6);
+            line.push(b',');
+            line.extend_from_slice(rng.below(100_000).to_string().as_bytes());
+            line.push(b',');
+            line.extend_from_slice(&rng.word(LOWER, 2));
+            line.push(b',');
+            line.extend_from_slice(&rng.word(LOWER, 10));
+            line
+        })
+        .collect()
+}
+
+/// Whitespace-separated rows with a low-cardinality first field for multi-key sorts
+pub fn multi_key_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    (0..n)
+        .map(|_| {
+            let mut line = rng.word(LOWER, 2);
+            line.push(b' ');
+            line.extend_from_slice(rng.below(1000).to_string().as_bytes());
+            line.push(b'\t');
+            line.extend_from_slice(&rng.word(MIXED, 12));
+            line
+        })
+        .collect()
+}
+
+/// Mixed-case words for -f
+pub fn mixed_case_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    (0..n)
+        .map(|_| {
+            let len = 4 + rng.below(9) as usize;
+            rng.word(MIXED, len)
+        })
+        .collect()
+}
+
+/// Lines drawn from a small vocabulary so -u removes most of them
+pub fn duplicate_heavy_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    let vocabulary: Vec<Vec<u8>> = (0..1000).map(|_| rng.word(LOWER, 10)).collect();
+    (0..n)
+        .map(|_| vocabulary[rng.below(vocabulary.len() as u64) as usize].clone())
+        .collect()
+}
+
+/// Long lines (about 1 KiB) sharing a common prefix
+pub fn long_lines(n: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    let prefix = rng.word(LOWER, 512);
+    (0..n)
+        .map(|_| {
+            let mut line = prefix.clone();
+            line.extend_from_slice(&rng.word(LOWER, 512));
+            line
+        })
+        .collect()
+}
+
+/// Join records into one delimited buffer, as they would arrive on stdin
+pub fn join(records: &[Vec<u8>], delimiter: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(records.iter().map(|r| r.len() + 1).sum());
+    for record in records {
+        out.extend_from_slice(record);
+        out.push(delimiter);
+    }
+    out
+}
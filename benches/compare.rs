@@ -0,0 +1,50 @@
+//! Per-comparison cost of the comparator, isolated from sorting
+//!
+//! Run with `cargo bench --bench compare`.
+
+mod common;
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use rsort::compare::Comparator;
+use rsort::config::Config;
+
+/// Compare every adjacent pair of a fixed record set
+fn compare_pairs(c: &mut Criterion, name: &str, records: &[Vec<u8>], config: &Config) {
+    let comparator = Comparator::new(config);
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            for pair in records.windows(2) {
+                black_box(comparator.cmp(&pair[0], &pair[1]));
+            }
+        });
+    });
+}
+
+fn bench_compare(c: &mut Criterion) {
+    let n = 10_000;
+
+    compare_pairs(c, "compare/bytes", &common::ascii_lines(n, 1), &Config::default());
+
+    let numeric = Config::builder().numeric(true).build().unwrap();
+    compare_pairs(c, "compare/numeric", &common::numeric_lines(n, 2), &numeric);
+
+    let fold_case = Config::builder().fold_case(true).build().unwrap();
+    compare_pairs(c, "compare/fold_case", &common::mixed_case_lines(n, 3), &fold_case);
+
+    let csv_key = Config::builder()
+        .field_separator(b',')
+        .add_key("2,2")
+        .numeric(true)
+        .build()
+        .unwrap();
+    compare_pairs(c, "compare/csv_key", &common::csv_lines(n, 4), &csv_key);
+
+    let multi_key = Config::builder().add_key("1,1").add_key("2,2").build().unwrap();
+    compare_pairs(c, "compare/multi_key", &common::multi_key_lines(n, 5), &multi_key);
+}
+
+criterion_group!(benches, bench_compare);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+//! End-to-end sorting benchmarks driven through the library API
+//!
+//! Run with `cargo bench --bench sort`; filter scenarios with e.g.
+//! `cargo bench --bench sort -- numeric`.
+
+mod common;
+
+use std::hint::black_box;
+use std::io::{self, Cursor};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use rsort::config::Config;
+use rsort::input::read_all_records;
+use rsort::output::write_records;
+use rsort::sort::sort_records;
+
+/// A named sort scenario: generator plus the options it is sorted with
+struct Scenario {
+    name: &'static str,
+    generate: fn(usize, u64) -> Vec<Vec<u8>>,
+    config: Config,
+}
+
+fn scenarios() -> Vec<Scenario> {
+    let numeric = Config::builder().numeric(true).build().unwrap();
+    let csv_key = Config::builder()
+        .field_separator(b',')
+        .add_key("2,2")
+        .numeric(true)
+        .build()
+        .unwrap();
+    let multi_key = Config::builder().add_key("1,1").add_key("2,2").build().unwrap();
+    let fold_case = Config::builder().fold_case(true).build().unwrap();
+    let unique = Config::builder().unique(true).build().unwrap();
+
+    vec![
+        Scenario { name: "ascii", generate: common::ascii_lines, config: Config::default() },
+        Scenario { name: "numeric", generate: common::numeric_lines, config: numeric },
+        Scenario { name: "csv_key", generate: common::csv_lines, config: csv_key },
+        Scenario { name: "multi_key", generate: common::multi_key_lines, config: multi_key },
+        Scenario { name: "fold_case", generate: common::mixed_case_lines, config: fold_case },
+        Scenario { name: "unique", generate: common::duplicate_heavy_lines, config: unique },
+        Scenario { name: "long_lines", generate: common::long_lines, config: Config::default() },
+    ]
+}
+
+/// In-memory sort of already-read records
+fn bench_sort(c: &mut Criterion) {
+    for scenario in scenarios() {
+        let mut group = c.benchmark_group(format!("sort/{}", scenario.name));
+        group.sample_size(10);
+
+        for &n in &common::SIZES {
+            // Long lines at 1M records would need ~1 GiB per sample
+            let n = if scenario.name == "long_lines" { n / 10 } else { n };
+            let records = (scenario.generate)(n, 42);
+            group.throughput(Throughput::Elements(n as u64));
+            group.bench_with_input(BenchmarkId::from_parameter(n), &records, |b, records| {
+                b.iter_batched(
+                    || records.clone(),
+                    |mut records| {
+                        sort_records(&mut records, &scenario.config);
+                        records
+                    },
+                    BatchSize::LargeInput,
+                );
+            });
+        }
+        group.finish();
+    }
+}
+
+/// Read, sort, and write: the whole pipeline minus process startup
+fn bench_pipeline(c: &mut Criterion) {
+    for scenario in scenarios() {
+        let mut group = c.benchmark_group(format!("pipeline/{}", scenario.name));
+        group.sample_size(10);
+
+        let n = common::SIZES[0];
+        let input = common::join(&(scenario.generate)(n, 7), b'\n');
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function(BenchmarkId::from_parameter(n), |b| {
+            b.iter(|| {
+                let (mut records, _) = read_all_records(Cursor::new(&input), b'\n').unwrap();
+                sort_records(&mut records, &scenario.config);
+                write_records(io::sink(), &records, &scenario.config, true).unwrap();
+                black_box(records.len())
+            });
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_sort, bench_pipeline);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Sorting through the core library only: no clap, libc or toml
+//!
+//! Kept buildable with `cargo check --examples --no-default-features`, which
+//! is how library-only consumers (e.g. WASM targets) build rsort.
+
+use rsort::{Config, SortedExt, Sorter};
+
+fn main() -> rsort::Result<()> {
+    let config = Config::builder().numeric(true).add_key("2,2").build()?;
+    let records = ["b 10", "a 9", "c 100"].into_iter().rsorted(&config);
+    for record in records {
+        println!("{}", record);
+    }
+
+    let sorted = Sorter::new().fold_case(true).unique(true).sort_bytes(b"b\nA\na\n")?;
+    print!("{}", String::from_utf8_lossy(&sorted));
+    Ok(())
+}
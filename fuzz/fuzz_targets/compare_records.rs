@@ -0,0 +1,59 @@
+//! The comparator must be antisymmetric and reflexive for any records and options
+
+#![no_main]
+
+use std::cmp::Ordering;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rsort::compare::Comparator;
+use rsort::config::Config;
+use rsort::fields::FieldSeparator;
+use rsort::key::KeySpec;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    a: Vec<u8>,
+    b: Vec<u8>,
+    reverse: bool,
+    numeric: bool,
+    fold_case: bool,
+    unique: bool,
+    stable: bool,
+    field_separator: Vec<u8>,
+    keydefs: Vec<String>,
+}
+
+fuzz_target!(|input: Input| {
+    let builder = Config::builder()
+        .reverse(input.reverse)
+        .numeric(input.numeric)
+        .fold_case(input.fold_case)
+        .unique(input.unique)
+        .stable(input.stable);
+    // Empty means no -t
+    let builder = match FieldSeparator::new(&input.field_separator) {
+        Some(separator) => builder.field_separator(separator),
+        None => builder,
+    };
+    let builder = input
+        .keydefs
+        .iter()
+        .filter_map(|keydef| KeySpec::parse(keydef).ok())
+        .fold(builder, |builder, key| builder.key(key));
+    // A separator containing the record delimiter is rejected up front
+    let Ok(config) = builder.build() else {
+        return;
+    };
+    let comparator = Comparator::new(&config);
+
+    assert_eq!(comparator.cmp(&input.a, &input.a), Ordering::Equal);
+    assert_eq!(comparator.cmp(&input.b, &input.b), Ordering::Equal);
+    assert_eq!(
+        comparator.cmp(&input.a, &input.b),
+        comparator.cmp(&input.b, &input.a).reverse()
+    );
+    if config.use_last_resort() && input.a != input.b {
+        assert_ne!(comparator.cmp(&input.a, &input.b), Ordering::Equal);
+    }
+});
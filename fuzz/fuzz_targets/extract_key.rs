@@ -0,0 +1,29 @@
+//! Extracted keys must always be sub-slices of their record
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rsort::fields::FieldSeparator;
+use rsort::key::{extract_key, KeySpec};
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    record: Vec<u8>,
+    keydef: String,
+    field_separator: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let Ok(spec) = KeySpec::parse(&input.keydef) else {
+        return;
+    };
+    let separator = FieldSeparator::new(&input.field_separator);
+    let key = extract_key(&input.record, &spec, separator.as_ref());
+
+    let record = input.record.as_ptr_range();
+    let key_range = key.as_ptr_range();
+    if !key.is_empty() {
+        assert!(record.start <= key_range.start && key_range.end <= record.end);
+    }
+});
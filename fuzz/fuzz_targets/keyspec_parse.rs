@@ -0,0 +1,15 @@
+//! KeySpec::parse must reject bad keydefs with Err, never panic
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsort::key::KeySpec;
+
+fuzz_target!(|keydef: &str| {
+    if let Ok(spec) = KeySpec::parse(keydef) {
+        assert!(spec.start_field >= 1);
+        if let Some(end_field) = spec.end_field {
+            assert!(end_field >= spec.start_field);
+        }
+    }
+});
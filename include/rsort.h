@@ -0,0 +1,60 @@
+/*
+ * rsort C ABI (Rust feature `capi`)
+ *
+ * Build:  cargo rustc --lib --release --features capi --crate-type cdylib
+ * Link:   -lrsort  (target/release/librsort.so)
+ */
+#ifndef RSORT_H
+#define RSORT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* RsortOptions.flags */
+#define RSORT_REVERSE       (1u << 0) /* -r */
+#define RSORT_NUMERIC       (1u << 1) /* -n */
+#define RSORT_FOLD_CASE     (1u << 2) /* -f */
+#define RSORT_UNIQUE        (1u << 3) /* -u */
+#define RSORT_STABLE        (1u << 4) /* -s */
+#define RSORT_HUMAN_NUMERIC (1u << 5) /* -h */
+#define RSORT_MONTH         (1u << 6) /* -M */
+#define RSORT_VERSION       (1u << 7) /* -V */
+
+/* Return codes */
+#define RSORT_OK            0
+#define RSORT_ERR_NULL      (-1) /* a required pointer was NULL */
+#define RSORT_ERR_OPTIONS   (-2) /* options rejected (bad keydef, incompatible flags) */
+#define RSORT_ERR_INTERNAL  (-3) /* rsort bug */
+
+typedef struct RsortOptions {
+    uint8_t delimiter;          /* '\n', or 0 for -z */
+    uint32_t flags;             /* RSORT_* flags */
+    int32_t field_separator;    /* -t byte, or -1 for blank-separated fields */
+    const char *const *keys;    /* num_keys keydefs (-k), e.g. "2,2"; may be NULL when 0 */
+    size_t num_keys;
+} RsortOptions;
+
+/*
+ * Sort len bytes at data with GNU sort (LC_ALL=C) semantics.
+ * On RSORT_OK, *out holds *out_len bytes, every record delimiter-terminated;
+ * release it with rsort_free.
+ */
+int rsort_sort_buffer(const uint8_t *data, size_t len, const RsortOptions *opts,
+                      uint8_t **out, size_t *out_len);
+
+/* Free a buffer from rsort_sort_buffer; NULL is ignored. */
+void rsort_free(uint8_t *out);
+
+/* Message for the last failed call on this thread, or NULL.
+ * Valid until the next rsort call on the same thread. */
+const char *rsort_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RSORT_H */
@@ -0,0 +1,37 @@
+#!/usr/bin/env python3
+"""Generate src/casefold/table.rs from Unicode's CaseFolding.txt.
+
+Usage: scripts/gen-casefold.py CaseFolding.txt > src/casefold/table.rs
+
+Only the simple foldings (status C and S) are kept: each maps one scalar
+to one scalar, so folding never changes the number of characters.
+"""
+import sys
+
+
+def main(path):
+    version = None
+    pairs = []
+    with open(path, encoding="utf-8") as f:
+        for line in f:
+            if version is None and line.startswith("# CaseFolding-"):
+                version = line[2:].strip()
+            line = line.split("#", 1)[0].strip()
+            if not line:
+                continue
+            code, status, mapping = [field.strip() for field in line.split(";")[:3]]
+            if status in ("C", "S"):
+                pairs.append((int(code, 16), int(mapping, 16)))
+    pairs.sort()
+
+    print(f"// Generated by scripts/gen-casefold.py from {version}; do not edit.")
+    print()
+    print("/// (scalar, simple case folding) for every scalar that folds, sorted")
+    print("pub(super) const SIMPLE_FOLDING: &[(char, char)] = &[")
+    for code, mapping in pairs:
+        print(f"    ('\\u{{{code:04X}}}', '\\u{{{mapping:04X}}}'),")
+    print("];")
+
+
+if __name__ == "__main__":
+    main(sys.argv[1])
@@ -0,0 +1,26 @@
+#!/usr/bin/env bash
+# Build rsort for wasm32-wasip1 and, when wasmtime is installed, sort through
+# stdin/stdout and through a preopened directory.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+rustup target add wasm32-wasip1 >/dev/null
+cargo build --release --target wasm32-wasip1
+
+wasm=target/wasm32-wasip1/release/rsort.wasm
+if ! command -v wasmtime >/dev/null; then
+    echo "built $wasm (wasmtime not found, skipping run)"
+    exit 0
+fi
+
+expected=$(printf 'a 1\nc 2\nb 10\n')
+got=$(printf 'b 10\na 1\nc 2\n' | wasmtime run "$wasm" -n -k2,2)
+[ "$got" = "$expected" ] || { echo "stdin sort mismatch: $got" >&2; exit 1; }
+
+dir=$(mktemp -d)
+trap 'rm -rf "$dir"' EXIT
+printf 'b 10\na 1\nc 2\n' > "$dir/in.txt"
+wasmtime run --dir "$dir::/data" "$wasm" -n -k2,2 -o /data/out.txt /data/in.txt
+[ "$(cat "$dir/out.txt")" = "$expected" ] || { echo "file sort mismatch" >&2; exit 1; }
+
+echo "wasi smoke test passed"
@@ -0,0 +1,262 @@
+//! Async input and merging for tokio services (feature `async`)
+//!
+//! Reading and writing are async; sorting stays synchronous and CPU-bound,
+//! and `sort_reader_to_writer` runs it on tokio's blocking pool.
+//!
+//! Only delimited records are read: a config with `record_size` or
+//! `framing` is a usage error here. `max_record_size` applies as it does
+//! to the synchronous readers.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io;
+use std::panic;
+use std::time::Instant;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::task;
+
+use crate::compare::Comparator;
+use crate::config::Config;
+use crate::error::{Result, RsortError};
+use crate::input::Framing;
+use crate::merge::{Head, MergeStats};
+use crate::output::{self, Grouper, KeyPrinter};
+use crate::sorter::Sorter;
+use crate::stats::SortStats;
+
+/// Async `input::read_all_records`: every record, without delimiters
+///
+/// Returns (records, had_trailing_delimiter), as the synchronous reader does.
+pub async fn read_all_records<R: AsyncBufRead + Unpin>(
+    reader: R,
+    delimiter: u8,
+) -> Result<(Vec<Vec<u8>>, bool)> {
+    read_all(AsyncRecords::new(reader, &[delimiter], None)).await
+}
+
+/// `read_all_records` for the records of `reader`
+async fn read_all<R: AsyncBufRead + Unpin>(mut reader: AsyncRecords<'_, R>) -> Result<(Vec<Vec<u8>>, bool)> {
+    let mut records = Vec::new();
+    let mut had_trailing = true;
+    loop {
+        let mut record = Vec::new();
+        let Some(terminated) = reader.read_terminated(&mut record).await? else {
+            break;
+        };
+        records.push(record);
+        had_trailing = terminated;
+    }
+    Ok((records, had_trailing))
+}
+
+/// Records of an async reader, each ended by `terminator` or EOF
+struct AsyncRecords<'t, R> {
+    reader: R,
+    terminator: &'t [u8],
+    /// --max-record-size, checked as each chunk of a record is buffered
+    limit: Option<u64>,
+    /// Bytes read so far, for the offset in `RecordTooLarge`
+    offset: u64,
+}
+
+impl<'t, R: AsyncBufRead + Unpin> AsyncRecords<'t, R> {
+    fn new(reader: R, terminator: &'t [u8], limit: Option<u64>) -> Self {
+        AsyncRecords {
+            reader,
+            terminator,
+            limit,
+            offset: 0,
+        }
+    }
+
+    /// Read the next record into `record`, without its terminator,
+    /// returning false at EOF
+    async fn read_record(&mut self, record: &mut Vec<u8>) -> Result<bool> {
+        Ok(self.read_terminated(record).await?.is_some())
+    }
+
+    /// `read_record`, returning whether the record had its terminator, or
+    /// None at EOF
+    async fn read_terminated(&mut self, record: &mut Vec<u8>) -> Result<Option<bool>> {
+        let delimiter = *self.terminator.last().expect("record delimiter is never empty");
+        let start = self.offset;
+        record.clear();
+        let terminated = loop {
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                break false;
+            }
+            let (len, found) = match memchr::memchr(delimiter, available) {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            record.extend_from_slice(&available[..len]);
+            self.reader.consume(len);
+            self.offset += len as u64;
+            let terminated = found && record.ends_with(self.terminator);
+            if let Some(limit) = self.limit {
+                // Until the terminator turns up, the tail may still be part of it
+                let record_len = if terminated {
+                    record.len() - self.terminator.len()
+                } else {
+                    record.len().saturating_sub(self.terminator.len() - 1)
+                };
+                if record_len as u64 > limit {
+                    return Err(RsortError::RecordTooLarge {
+                        path: None,
+                        offset: start,
+                        limit,
+                    });
+                }
+            }
+            if terminated {
+                break true;
+            }
+        };
+        if terminated {
+            record.truncate(record.len() - self.terminator.len());
+            return Ok(Some(true));
+        }
+        if let Some(limit) = self.limit {
+            if record.len() as u64 > limit {
+                return Err(RsortError::RecordTooLarge {
+                    path: None,
+                    offset: start,
+                    limit,
+                });
+            }
+        }
+        Ok((!record.is_empty()).then_some(false))
+    }
+}
+
+/// Reject `record_size` and `framing`, which the async readers do not
+/// implement
+fn check_delimited(config: &Config) -> Result<()> {
+    let option = if config.record_size.is_some() {
+        "--record-size"
+    } else if config.framing != Framing::Delimited {
+        "--framing"
+    } else {
+        return Ok(());
+    };
+    Err(RsortError::Usage(format!("{} is not supported by async sorting", option)))
+}
+
+/// Read `reader` to the end, sort, and write the result to `writer`
+///
+/// The sort runs on the blocking pool, so this must be called from inside a
+/// tokio runtime.
+pub async fn sort_reader_to_writer<R, W>(sorter: &Sorter, reader: R, writer: W) -> Result<SortStats>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    check_delimited(sorter.config())?;
+    let started = Instant::now();
+    let terminator = sorter.config().record_terminator();
+    let records = AsyncRecords::new(reader, &terminator, sorter.config().max_record_size);
+    let (mut records, had_trailing) = read_all(records).await?;
+    let final_delimiter = sorter.config().final_delimiter(had_trailing);
+    let read_time = started.elapsed();
+
+    let sorter = sorter.clone();
+    let sorted = task::spawn_blocking(move || {
+        let mut sorted = Vec::new();
+        let stats = sorter.sort_and_write(&mut records, &mut sorted, final_delimiter)?;
+        Ok::<_, io::Error>((sorted, stats))
+    })
+    .await;
+    let (sorted, mut stats) = match sorted {
+        Ok(result) => result?,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => return Err(io::Error::other(e).into()),
+    };
+
+    let started = Instant::now();
+    let mut writer = writer;
+    writer.write_all(&sorted).await?;
+    writer.flush().await?;
+    stats.read_time = read_time;
+    stats.write_time += started.elapsed();
+    Ok(stats)
+}
+
+/// Async `merge::merge_streams`: merge already sorted `sources` into `writer`
+///
+/// Same ordering, tie-breaking and -u behavior as the synchronous merge.
+pub async fn merge_streams<R, W>(sources: Vec<R>, config: &Config, writer: W) -> Result<MergeStats>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    check_delimited(config)?;
+    let comparator = Comparator::new(config);
+    let terminator = config.record_terminator();
+    let output_terminator = config.output_terminator();
+    let forbidden = config.forbidden_output_byte();
+    let mut stats = MergeStats {
+        sources: sources.len(),
+        ..MergeStats::default()
+    };
+
+    let mut sources: Vec<_> = sources
+        .into_iter()
+        .map(|reader| AsyncRecords::new(reader, &terminator, config.max_record_size))
+        .collect();
+    let mut heap = BinaryHeap::with_capacity(sources.len());
+    for (source, reader) in sources.iter_mut().enumerate() {
+        let mut record = Vec::new();
+        if reader.read_record(&mut record).await? {
+            stats.records_read += 1;
+            heap.push(Head {
+                record,
+                source,
+                comparator: &comparator,
+            });
+        }
+    }
+
+    let mut writer = BufWriter::new(writer);
+    let mut last: Option<Vec<u8>> = None;
+    let printer = KeyPrinter::new(config);
+    let mut grouper = config.group.as_deref().map(|separator| (Grouper::new(config), separator));
+
+    while let Some(mut head) = heap.pop() {
+        let is_dup = config.unique
+            && last
+                .as_ref()
+                .is_some_and(|prev| comparator.cmp(prev, &head.record) == Ordering::Equal);
+        if !is_dup {
+            let record = output::printed(head.record.as_slice(), printer.as_ref());
+            output::check_output_record(record.as_ref(), forbidden)?;
+            if let Some((grouper, separator)) = &mut grouper {
+                if grouper.starts_group(&head.record) {
+                    output::check_output_record(separator, forbidden)?;
+                    writer.write_all(separator).await?;
+                    writer.write_all(&output_terminator).await?;
+                }
+            }
+            writer.write_all(record.as_ref()).await?;
+            writer.write_all(&output_terminator).await?;
+            if config.line_buffered {
+                writer.flush().await?;
+            }
+            stats.records_written += 1;
+            if config.unique {
+                let prev = last.get_or_insert_with(Vec::new);
+                prev.clear();
+                prev.extend_from_slice(&head.record);
+            }
+        }
+
+        if sources[head.source].read_record(&mut head.record).await? {
+            stats.records_read += 1;
+            heap.push(head);
+        }
+    }
+
+    writer.flush().await?;
+    Ok(stats)
+}
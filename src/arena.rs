@@ -1,7 +1,10 @@
 //! Arena-based record storage with memory tracking
 //!
 //! For Phase 1, this is a simple wrapper around Vec.
-//! Future phases will add memory budgeting and external sort triggers.
+//! Allocation failures and an optional byte budget surface as
+//! `RsortError::MemoryExhausted`; future phases will spill to disk instead.
+
+use crate::error::{Result, RsortError};
 
 /// Stores records with memory tracking
 pub struct Arena {
@@ -9,6 +12,8 @@ pub struct Arena {
     records: Vec<Vec<u8>>,
     /// Total bytes stored
     total_bytes: usize,
+    /// Maximum record bytes to hold, None for no limit beyond the allocator
+    budget: Option<usize>,
 }
 
 impl Arena {
@@ -16,6 +21,15 @@ impl Arena {
         Self {
             records: Vec::new(),
             total_bytes: 0,
+            budget: None,
+        }
+    }
+
+    /// Create an arena that refuses to hold more than `budget` record bytes
+    pub fn with_budget(budget: usize) -> Self {
+        Self {
+            budget: Some(budget),
+            ..Self::new()
         }
     }
 
@@ -25,6 +39,32 @@ impl Arena {
         self.records.push(record);
     }
 
+    /// Copy a record into the arena, reporting allocation failure instead of aborting
+    pub fn try_push(&mut self, record: &[u8]) -> Result<()> {
+        let exhausted = |used| RsortError::MemoryExhausted {
+            needed: record.len(),
+            used,
+        };
+
+        if let Some(budget) = self.budget {
+            if self.total_bytes.saturating_add(record.len()) > budget {
+                return Err(exhausted(self.total_bytes));
+            }
+        }
+
+        let mut owned = Vec::new();
+        owned
+            .try_reserve_exact(record.len())
+            .map_err(|_| exhausted(self.total_bytes))?;
+        owned.extend_from_slice(record);
+        self.records
+            .try_reserve(1)
+            .map_err(|_| exhausted(self.total_bytes))?;
+
+        self.push(owned);
+        Ok(())
+    }
+
     /// Get total bytes stored
     pub fn bytes_used(&self) -> usize {
         self.total_bytes
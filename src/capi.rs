@@ -0,0 +1,250 @@
+//! C ABI for sorting a buffer (feature `capi`)
+//!
+//! Declared in `include/rsort.h`. Build the shared library with
+//! `cargo rustc --lib --release --features capi --crate-type cdylib`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::config::Config;
+use crate::error::{Result, RsortError};
+use crate::sorter::Sorter;
+
+pub const RSORT_REVERSE: u32 = 1 << 0;
+pub const RSORT_NUMERIC: u32 = 1 << 1;
+pub const RSORT_FOLD_CASE: u32 = 1 << 2;
+pub const RSORT_UNIQUE: u32 = 1 << 3;
+pub const RSORT_STABLE: u32 = 1 << 4;
+pub const RSORT_HUMAN_NUMERIC: u32 = 1 << 5;
+pub const RSORT_MONTH: u32 = 1 << 6;
+pub const RSORT_VERSION: u32 = 1 << 7;
+
+pub const RSORT_OK: c_int = 0;
+/// A required pointer was NULL
+pub const RSORT_ERR_NULL: c_int = -1;
+/// The options were rejected (bad keydef, incompatible flags, ...)
+pub const RSORT_ERR_OPTIONS: c_int = -2;
+/// rsort panicked; this is a bug
+pub const RSORT_ERR_INTERNAL: c_int = -3;
+
+/// Sort options, mirroring the command-line flags
+#[repr(C)]
+#[derive(Debug)]
+pub struct RsortOptions {
+    /// Record delimiter: `'\n'`, or 0 for `-z`
+    pub delimiter: u8,
+    /// `RSORT_*` flags
+    pub flags: u32,
+    /// `-t` byte, or -1 to split fields on blank runs
+    pub field_separator: i32,
+    /// `num_keys` NUL-terminated keydefs (`-k`), e.g. "2,2"; may be NULL when 0
+    pub keys: *const *const c_char,
+    pub num_keys: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Length header in front of every buffer handed to C, so `rsort_free` needs
+/// only the pointer
+const HEADER: usize = mem::size_of::<usize>();
+
+/// Sort `len` bytes at `data` into a new buffer stored in `*out` / `*out_len`
+///
+/// Every output record is terminated by the delimiter. Free the buffer with
+/// `rsort_free`. Returns `RSORT_OK` or a negative `RSORT_ERR_*` code, with
+/// details from `rsort_last_error_message`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes (or be NULL when `len` is 0),
+/// `opts` must point to a valid `RsortOptions` whose `keys` holds `num_keys`
+/// NUL-terminated strings, and `out` / `out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn rsort_sort_buffer(
+    data: *const u8,
+    len: usize,
+    opts: *const RsortOptions,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    if (data.is_null() && len > 0) || opts.is_null() || out.is_null() || out_len.is_null() {
+        return fail(RSORT_ERR_NULL, "NULL argument");
+    }
+    let input = if len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(data, len)
+    };
+    let opts = &*opts;
+
+    let sorted = panic::catch_unwind(AssertUnwindSafe(|| {
+        config_from(opts).and_then(|config| Sorter::from_config(config).sort_bytes(input))
+    }));
+    match sorted {
+        Ok(Ok(sorted)) => {
+            *out_len = sorted.len();
+            *out = into_c_buffer(sorted);
+            clear_error();
+            RSORT_OK
+        }
+        Ok(Err(e)) => fail(RSORT_ERR_OPTIONS, &e.to_string()),
+        Err(_) => fail(RSORT_ERR_INTERNAL, "internal error"),
+    }
+}
+
+/// Free a buffer returned by `rsort_sort_buffer`; NULL is ignored
+///
+/// # Safety
+///
+/// `buf` must be NULL or a buffer from `rsort_sort_buffer` not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn rsort_free(buf: *mut u8) {
+    if buf.is_null() {
+        return;
+    }
+    let start = buf.sub(HEADER);
+    let len = ptr::read_unaligned(start as *const usize);
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(start, HEADER + len)));
+}
+
+/// Message for the last failed call on this thread, or NULL
+///
+/// The string stays valid until the next rsort call on the same thread.
+#[no_mangle]
+pub extern "C" fn rsort_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
+
+unsafe fn config_from(opts: &RsortOptions) -> Result<Config> {
+    let flag = |bit: u32| opts.flags & bit != 0;
+    let mut builder = Config::builder()
+        .record_delimiter(opts.delimiter)
+        .reverse(flag(RSORT_REVERSE))
+        .numeric(flag(RSORT_NUMERIC))
+        .fold_case(flag(RSORT_FOLD_CASE))
+        .unique(flag(RSORT_UNIQUE))
+        .stable(flag(RSORT_STABLE))
+        .human_numeric(flag(RSORT_HUMAN_NUMERIC))
+        .month(flag(RSORT_MONTH))
+        .version(flag(RSORT_VERSION));
+
+    match opts.field_separator {
+        -1 => {}
+        sep => {
+            let sep = u8::try_from(sep).map_err(|_| RsortError::InvalidDelimiter)?;
+            builder = builder.field_separator(sep);
+        }
+    }
+
+    if opts.num_keys > 0 {
+        if opts.keys.is_null() {
+            return Err(RsortError::InvalidKey("NULL keys array".to_string()));
+        }
+        for &keydef in slice::from_raw_parts(opts.keys, opts.num_keys) {
+            if keydef.is_null() {
+                return Err(RsortError::InvalidKey("NULL keydef".to_string()));
+            }
+            let keydef = CStr::from_ptr(keydef)
+                .to_str()
+                .map_err(|_| RsortError::InvalidKey("keydef is not UTF-8".to_string()))?;
+            builder = builder.add_key(keydef);
+        }
+    }
+    builder.build()
+}
+
+fn into_c_buffer(data: Vec<u8>) -> *mut u8 {
+    let mut buf = Vec::with_capacity(HEADER + data.len());
+    buf.extend_from_slice(&data.len().to_ne_bytes());
+    buf.extend_from_slice(&data);
+    let start = Box::into_raw(buf.into_boxed_slice()) as *mut u8;
+    // SAFETY: the allocation is HEADER + data.len() bytes long
+    unsafe { start.add(HEADER) }
+}
+
+fn fail(code: c_int, message: &str) -> c_int {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    code
+}
+
+fn clear_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(flags: u32, keys: &[*const c_char]) -> RsortOptions {
+        RsortOptions {
+            delimiter: b'\n',
+            flags,
+            field_separator: -1,
+            keys: keys.as_ptr(),
+            num_keys: keys.len(),
+        }
+    }
+
+    /// Drive the ABI exactly as C would, copying the result out
+    fn sort(input: &[u8], opts: &RsortOptions) -> std::result::Result<Vec<u8>, (c_int, String)> {
+        let mut out = ptr::null_mut();
+        let mut out_len = 0;
+        unsafe {
+            let code = rsort_sort_buffer(input.as_ptr(), input.len(), opts, &mut out, &mut out_len);
+            if code != RSORT_OK {
+                let message = CStr::from_ptr(rsort_last_error_message());
+                return Err((code, message.to_string_lossy().into_owned()));
+            }
+            let sorted = slice::from_raw_parts(out, out_len).to_vec();
+            rsort_free(out);
+            Ok(sorted)
+        }
+    }
+
+    #[test]
+    fn test_sort_buffer_matches_sorter() {
+        let key = c"2,2";
+        let opts = options(RSORT_NUMERIC | RSORT_REVERSE, &[key.as_ptr()]);
+        let sorted = sort(b"a 1\nb 10\nc 2", &opts).unwrap();
+        let expected = Sorter::new().numeric(true).reverse(true).key("2,2").unwrap();
+        assert_eq!(sorted, expected.sort_bytes(b"a 1\nb 10\nc 2").unwrap());
+        assert!(rsort_last_error_message().is_null());
+    }
+
+    #[test]
+    fn test_separator_and_nul_delimiter() {
+        let key = c"2";
+        let mut opts = options(RSORT_UNIQUE, &[key.as_ptr()]);
+        opts.delimiter = 0;
+        opts.field_separator = i32::from(b',');
+        assert_eq!(sort(b"x,b\0y,a\0z,b\0", &opts).unwrap(), b"y,a\0x,b\0");
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let opts = options(0, &[]);
+        assert_eq!(sort(b"", &opts).unwrap(), b"");
+        unsafe { rsort_free(ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_errors_set_message() {
+        let key = c"0";
+        let err = sort(b"a\n", &options(0, &[key.as_ptr()])).unwrap_err();
+        assert_eq!(err.0, RSORT_ERR_OPTIONS);
+        assert!(err.1.contains("field number"), "{}", err.1);
+
+        let err = sort(b"a\n", &options(RSORT_NUMERIC | RSORT_MONTH, &[])).unwrap_err();
+        assert_eq!(err, (RSORT_ERR_OPTIONS, "options '-Mn' are incompatible".to_string()));
+
+        let code = unsafe { rsort_sort_buffer(ptr::null(), 1, ptr::null(), ptr::null_mut(), ptr::null_mut()) };
+        assert_eq!(code, RSORT_ERR_NULL);
+    }
+}
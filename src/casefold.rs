@@ -0,0 +1,130 @@
+//! Unicode case folding for -f (feature `unicode`)
+//!
+//! Keys are decoded as UTF-8 and each character is mapped by Unicode simple
+//! case folding (`table.rs`, generated by `scripts/gen-casefold.py`), then
+//! ASCII letters are upper-cased so ASCII text orders exactly as GNU's `-f`
+//! does. Simple folding maps one character to one character: `ß` stays
+//! `ß` rather than becoming `ss`, `ẞ` folds to `ß`, and Turkish `İ` and `ı`
+//! fold to nothing but themselves, so only `I` and `i` match each other.
+//!
+//! Bytes that are not valid UTF-8 compare raw. Against a character, such a
+//! byte compares with the character's first UTF-8 byte and sorts first on a
+//! tie, which keeps the order bytewise wherever folding changes nothing.
+
+use std::cmp::Ordering;
+
+mod table;
+
+/// `c` under simple case folding, with ASCII letters in upper case
+pub fn fold(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_uppercase();
+    }
+    match table::SIMPLE_FOLDING.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(i) => table::SIMPLE_FOLDING[i].1.to_ascii_uppercase(),
+        Err(_) => c,
+    }
+}
+
+/// Compare `a` and `b` case-insensitively, without allocating
+pub fn compare_folded(a: &[u8], b: &[u8]) -> Ordering {
+    let mut a = units(a);
+    let mut b = units(b);
+    loop {
+        let (a, b) = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => (a, b),
+        };
+        let ord = match (a, b) {
+            (Unit::Char(a), Unit::Char(b)) => a.cmp(&b),
+            (Unit::Byte(a), Unit::Byte(b)) => a.cmp(&b),
+            (Unit::Char(a), Unit::Byte(b)) => lead_byte(a).cmp(&b).then(Ordering::Greater),
+            (Unit::Byte(a), Unit::Char(b)) => a.cmp(&lead_byte(b)).then(Ordering::Less),
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// A folded character, or a byte that is not part of valid UTF-8
+#[derive(Clone, Copy)]
+enum Unit {
+    Char(char),
+    Byte(u8),
+}
+
+fn units(key: &[u8]) -> impl Iterator<Item = Unit> + '_ {
+    key.utf8_chunks().flat_map(|chunk| {
+        let chars = chunk.valid().chars().map(|c| Unit::Char(fold(c)));
+        chars.chain(chunk.invalid().iter().map(|&b| Unit::Byte(b)))
+    })
+}
+
+fn lead_byte(c: char) -> u8 {
+    c.encode_utf8(&mut [0; 4]).as_bytes()[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        compare_folded(a.as_bytes(), b.as_bytes())
+    }
+
+    #[test]
+    fn test_table_is_sorted() {
+        assert!(table::SIMPLE_FOLDING.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_non_ascii_letters_fold() {
+        assert_eq!(cmp("Ärger", "ärger"), Ordering::Equal);
+        assert_eq!(cmp("ΣΊΣΥΦΟΣ", "σίσυφος"), Ordering::Equal);
+        assert_eq!(cmp("σ", "ς"), Ordering::Equal);
+        assert_eq!(cmp("Ärger", "ärgern"), Ordering::Less);
+        // Kelvin sign and long s fold into ASCII
+        assert_eq!(cmp("\u{212A}", "k"), Ordering::Equal);
+        assert_eq!(cmp("\u{17F}", "S"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_sharp_s_uses_simple_folding() {
+        assert_eq!(cmp("STRAẞE", "straße"), Ordering::Equal);
+        // Full folding would make these equal; simple folding does not
+        assert_ne!(cmp("straße", "strasse"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_turkish_dotted_and_dotless_i() {
+        assert_eq!(cmp("I", "i"), Ordering::Equal);
+        // İ and ı have no simple folding, so they match only themselves
+        assert_ne!(cmp("İ", "i"), Ordering::Equal);
+        assert_ne!(cmp("ı", "I"), Ordering::Equal);
+        assert_eq!(fold('İ'), 'İ');
+        assert_eq!(fold('ı'), 'ı');
+    }
+
+    #[test]
+    fn test_ascii_matches_gnu() {
+        // Folding to upper case puts '_' after the letters, as in GNU sort -f
+        assert_eq!(cmp("a", "_"), Ordering::Less);
+        for (a, b) in [("a", "B"), ("Zz", "zZ"), ("x[", "XA"), ("", "a")] {
+            let ascii = a.to_ascii_uppercase().cmp(&b.to_ascii_uppercase());
+            assert_eq!(cmp(a, b), ascii, "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8_compares_raw() {
+        assert_eq!(compare_folded(b"A\xff", b"a\xff"), Ordering::Equal);
+        assert_eq!(compare_folded(b"a\xfe", b"A\xff"), Ordering::Less);
+        // "\xC3" cut short sorts before the "é" it starts, as bytewise
+        assert_eq!(compare_folded(b"\xC3x", "é".as_bytes()), Ordering::Less);
+        assert_eq!(compare_folded("É".as_bytes(), b"\xC3"), Ordering::Greater);
+        assert_eq!(compare_folded(b"\x80", b"a"), Ordering::Greater);
+    }
+}
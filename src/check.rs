@@ -0,0 +1,360 @@
+//! Verify that input is already sorted (-c / -C)
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::compare::{self, Comparator, Decision};
+use crate::compress;
+use crate::config::Config;
+use crate::debug;
+use crate::encoding;
+use crate::error::{Result, RsortError};
+use crate::input::{self, RecordReader};
+use crate::stats::write_json_string;
+use crate::trace::{trace_event, trace_span};
+
+/// How the CLI reports disorder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckMode {
+    /// -c: report the first out-of-order record on stderr
+    Diagnose,
+    /// -C: exit status only
+    Quiet,
+}
+
+/// How -c writes what it found
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckFormat {
+    /// GNU's `disorder:` line on stderr
+    #[default]
+    Text,
+    /// One JSON object on stdout (`CheckResult::to_json`)
+    Json,
+}
+
+impl CheckFormat {
+    /// Parse a --format argument: `text` or `json`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(CheckFormat::Text),
+            "json" => Some(CheckFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of a sortedness check
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckResult {
+    /// Every record is in order; `records` is how many were read
+    Sorted { records: u64 },
+    /// `current` sorts before `previous` (or equals it under -u)
+    ///
+    /// `record_index` is the zero-based index of `current`.
+    Disorder {
+        record_index: u64,
+        previous: Vec<u8>,
+        current: Vec<u8>,
+    },
+}
+
+impl CheckResult {
+    pub fn is_sorted(&self) -> bool {
+        matches!(self, CheckResult::Sorted { .. })
+    }
+
+    /// This result for the input `file` as -c --format=json reports it
+    ///
+    /// `{"status":"sorted","file":"-","records":3}`, or for disorder
+    /// `{"status":"unsorted","file":"-","record_index":1,"kind":"disorder",
+    /// "previous":"b","current":"a","deciding_key":1}`. `kind` is
+    /// `duplicate` for equal records under -u; `deciding_key` counts keys
+    /// from 1 (the whole line without -k), or is `"last-resort"`, or null
+    /// for a duplicate. A record that is not UTF-8 is `{"base64":"..."}`.
+    pub fn to_json(&self, file: &Path, config: &Config) -> String {
+        let mut json = Vec::new();
+        self.write_json(&mut json, file, config).expect("writing to a Vec cannot fail");
+        String::from_utf8(json).expect("JSON escapes are UTF-8")
+    }
+
+    fn write_json<W: Write>(&self, writer: &mut W, file: &Path, config: &Config) -> io::Result<()> {
+        let status = if self.is_sorted() { "sorted" } else { "unsorted" };
+        write!(writer, "{{\"status\":\"{}\",\"file\":", status)?;
+        write_json_string(writer, &file.to_string_lossy())?;
+        let (record_index, previous, current) = match self {
+            CheckResult::Sorted { records } => return write!(writer, ",\"records\":{}}}", records),
+            CheckResult::Disorder { record_index, previous, current } => (record_index, previous, current),
+        };
+        let (order, decision) = compare::compare_records_explain(previous, current, config);
+        let kind = if order.is_eq() { "duplicate" } else { "disorder" };
+        write!(writer, ",\"record_index\":{},\"kind\":\"{}\",\"previous\":", record_index, kind)?;
+        write_json_record(writer, previous)?;
+        write!(writer, ",\"current\":")?;
+        write_json_record(writer, current)?;
+        match decision {
+            Decision::Key(index) => write!(writer, ",\"deciding_key\":{}}}", index + 1),
+            Decision::LastResort => write!(writer, ",\"deciding_key\":\"last-resort\"}}"),
+            Decision::Equal => write!(writer, ",\"deciding_key\":null}}"),
+        }
+    }
+}
+
+/// `record` as a JSON string, or `{"base64":"..."}` when it is not UTF-8
+fn write_json_record<W: Write>(writer: &mut W, record: &[u8]) -> io::Result<()> {
+    match std::str::from_utf8(record) {
+        Ok(text) => write_json_string(writer, text),
+        Err(_) => write!(writer, "{{\"base64\":\"{}\"}}", base64(record)),
+    }
+}
+
+/// Standard base64 with padding (RFC 4648)
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Check that `reader` is sorted according to `config`
+///
+/// Streams the input holding only the previous and current record. With
+/// `unique` set, adjacent equal records also count as disorder.
+pub fn check_sorted<R: BufRead>(reader: R, config: &Config) -> Result<CheckResult> {
+    check_with(reader, &Comparator::new(config), config)
+}
+
+/// Check sortedness with a prebuilt comparator
+pub fn check_with<R: BufRead>(reader: R, comparator: &Comparator, config: &Config) -> Result<CheckResult> {
+    trace_span!("check");
+    let unique = config.unique;
+    let mut reader = RecordReader::new(reader, config.record_delimiter)
+        .delimiter_prefix(&config.record_delimiter_prefix)
+        .max_record_size(config.max_record_size)
+        .record_size(config.record_size)
+        .framing(config.framing)
+        .strip_bom(config.strip_bom);
+    let mut previous: Vec<u8> = match reader.read_record()? {
+        Some(record) => record.to_vec(),
+        None => return Ok(CheckResult::Sorted { records: 0 }),
+    };
+    let mut records = 1u64;
+
+    while let Some(current) = reader.read_record()? {
+        let ord = comparator.cmp(&previous, current);
+        if ord.is_gt() || (unique && ord.is_eq()) {
+            trace_event!(record = records + 1, "disorder");
+            return Ok(CheckResult::Disorder {
+                record_index: records,
+                previous,
+                current: current.to_vec(),
+            });
+        }
+        previous.clear();
+        previous.extend_from_slice(current);
+        records += 1;
+    }
+
+    trace_event!(records, "sorted");
+    Ok(CheckResult::Sorted { records })
+}
+
+/// The file --verify reads back once `output` is written: None without
+/// --verify, and a usage error when there is no file (stdout or split
+/// outputs)
+pub fn verify_target<'a>(output: Option<&'a Path>, config: &Config) -> Result<Option<&'a Path>> {
+    match (config.verify, output) {
+        (false, _) => Ok(None),
+        (true, Some(path)) => Ok(Some(path)),
+        (true, None) => Err(RsortError::Usage("--verify requires -o FILE".to_string())),
+    }
+}
+
+/// Check the output file `path`, just written under `config` (--verify)
+///
+/// The file is read back decompressed and decoded as it was written, and
+/// checked as -c would check it. A file out of order is renamed to
+/// `path.unverified`, and an `Unverified` error says where.
+pub fn verify_output(path: &Path, config: &Config) -> Result<()> {
+    let reader = input::open_input(path)?;
+    let reader = match config.output_codec(path) {
+        Some(codec) => compress::decompress(reader, codec, path)?,
+        None => reader,
+    };
+    let mut written = config.clone();
+    written.strip_bom = config.keep_bom;
+    let reader = encoding::decode(reader, config.output_encoding);
+    let result = check_sorted(reader, &written).map_err(|e| e.reading(path))?;
+    let CheckResult::Disorder { record_index, current, .. } = result else {
+        return Ok(());
+    };
+    let moved = unverified_path(path);
+    fs::rename(path, &moved).map_err(|source| RsortError::OpenOutput {
+        path: moved.clone(),
+        source,
+    })?;
+    Err(RsortError::Unverified {
+        path: path.to_path_buf(),
+        line: record_index + 1,
+        record: debug::escaped(&current),
+        moved,
+    })
+}
+
+/// Where `verify_output` moves a bad output file
+fn unverified_path(path: &Path) -> PathBuf {
+    let mut moved = OsString::from(path.as_os_str());
+    moved.push(".unverified");
+    PathBuf::from(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn check(input: &[u8], config: &Config) -> CheckResult {
+        check_sorted(Cursor::new(input), config).unwrap()
+    }
+
+    #[test]
+    fn test_empty_input_is_sorted() {
+        assert_eq!(check(b"", &Config::default()), CheckResult::Sorted { records: 0 });
+    }
+
+    #[test]
+    fn test_single_record_is_sorted() {
+        assert_eq!(check(b"z\n", &Config::default()), CheckResult::Sorted { records: 1 });
+    }
+
+    #[test]
+    fn test_sorted_input() {
+        let result = check(b"a\nb\nb\nc", &Config::default());
+        assert_eq!(result, CheckResult::Sorted { records: 4 });
+    }
+
+    #[test]
+    fn test_disorder_at_first_pair() {
+        assert_eq!(
+            check(b"b\na\nc\n", &Config::default()),
+            CheckResult::Disorder {
+                record_index: 1,
+                previous: b"b".to_vec(),
+                current: b"a".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_disorder_at_last_pair() {
+        assert_eq!(
+            check(b"a\nb\nd\nc\n", &Config::default()),
+            CheckResult::Disorder {
+                record_index: 3,
+                previous: b"d".to_vec(),
+                current: b"c".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_honors_options() {
+        let config = Config::builder().numeric(true).reverse(true).build().unwrap();
+        assert!(check(b"10\n9\n1\n", &config).is_sorted());
+        assert!(!check(b"1\n9\n10\n", &config).is_sorted());
+    }
+
+    #[test]
+    fn test_unique_rejects_equal_neighbours() {
+        let config = Config::builder().unique(true).fold_case(true).build().unwrap();
+        assert!(check(b"a\nb\n", &config).is_sorted());
+        let result = check(b"a\nB\nb\n", &config);
+        assert_eq!(
+            result,
+            CheckResult::Disorder {
+                record_index: 2,
+                previous: b"B".to_vec(),
+                current: b"b".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nul_delimited() {
+        let config = Config::builder().record_delimiter(0).build().unwrap();
+        assert!(check(b"a\nz\0b\0", &config).is_sorted());
+        assert_eq!(
+            check(b"b\0a\nz\0", &config),
+            CheckResult::Disorder {
+                record_index: 1,
+                previous: b"b".to_vec(),
+                current: b"a\nz".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        for (bytes, encoded) in [(&b""[..], ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"\xff\x00ab", "/wBhYg==")] {
+            assert_eq!(base64(bytes), encoded);
+        }
+    }
+
+    #[test]
+    fn test_json_report() {
+        let file = Path::new("in.txt");
+        let config = Config::builder().field_separator(b':').add_key("2,2n").unique(true).build().unwrap();
+        assert_eq!(
+            check(b"a:1\nb:2\n", &config).to_json(file, &config),
+            r#"{"status":"sorted","file":"in.txt","records":2}"#
+        );
+        assert_eq!(
+            check(b"a:1\nb:01\n", &config).to_json(file, &config),
+            r#"{"status":"unsorted","file":"in.txt","record_index":1,"kind":"duplicate","previous":"a:1","current":"b:01","deciding_key":null}"#
+        );
+        let config = Config::default();
+        assert_eq!(
+            check(b"\xfe\n\"b\"\n", &config).to_json(file, &config),
+            r#"{"status":"unsorted","file":"in.txt","record_index":1,"kind":"disorder","previous":{"base64":"/g=="},"current":"\"b\"","deciding_key":1}"#
+        );
+    }
+
+    #[test]
+    fn test_verify_output_passes_what_was_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let (input, output) = (dir.path().join("in"), dir.path().join("out"));
+        fs::write(&input, b"c\na\nb\n").unwrap();
+        let config = Config::builder().verify(true).build().unwrap();
+        crate::pipeline::sort_files(&[input], &output, &config).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"a\nb\nc\n");
+        verify_output(&output, &config).unwrap();
+    }
+
+    #[test]
+    fn test_verify_output_moves_disorder_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out");
+        let config = Config::builder().numeric(true).verify(true).build().unwrap();
+        // What a sort that lost the order would have written
+        fs::write(&output, b"1\n10\n2\n").unwrap();
+        let err = verify_output(&output, &config).unwrap_err();
+        let moved = dir.path().join("out.unverified");
+        assert!(matches!(
+            &err,
+            RsortError::Unverified { line: 3, record, moved: m, .. } if record == "2" && *m == moved
+        ));
+        assert_eq!(err.exit_code(), 2);
+        assert!(!output.exists());
+        assert_eq!(fs::read(&moved).unwrap(), b"1\n10\n2\n");
+    }
+}
@@ -1,9 +1,54 @@
-use clap::Parser;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::builder::{PossibleValue, StringValueParser, TypedValueParser};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, Parser, ValueHint};
+use clap_complete::Shell;
+
+use crate::error::RsortError;
+use crate::fields::FieldSeparator;
 
 #[derive(Parser, Debug, Clone)]
-#[command(name = "rsort", about = "Sort lines of text")]
+#[command(
+    name = "rsort",
+    about = "Sort lines of text",
+    long_about = LONG_ABOUT,
+    after_long_help = examples_help(),
+    disable_help_flag = true,
+    args_override_self = true
+)]
 pub struct Args {
+    /// Print help (-h means --human-numeric-sort, as in GNU sort)
+    #[arg(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// Check for sorted input; do not sort
+    #[arg(short = 'c', long = "check", conflicts_with = "check_quiet")]
+    pub check: bool,
+
+    /// Like -c, but do not report the first bad line
+    #[arg(short = 'C')]
+    pub check_quiet: bool,
+
+    /// With -c, report disorder as FORMAT: text (default) or json, on stdout
+    #[arg(long, value_name = "FORMAT", requires = "check", value_parser = Suggested(&["text", "json"]), hide_possible_values = true)]
+    pub format: Option<String>,
+
+    /// With --format=json, report a sorted input too
+    #[arg(long, requires = "format")]
+    pub verbose: bool,
+
+    /// Merge already sorted files; do not sort
+    #[arg(short = 'm', long)]
+    pub merge: bool,
+
     /// Reverse the result of comparisons
+    ///
+    /// Reverses every key without options of its own, and the last-resort
+    /// comparison of whole lines.
     #[arg(short = 'r', long)]
     pub reverse: bool,
 
@@ -15,74 +60,710 @@ pub struct Args {
     #[arg(short = 'f', long = "ignore-case")]
     pub fold_case: bool,
 
+    /// Compare human readable numbers (e.g., 2K 1G)
+    #[arg(short = 'h', long = "human-numeric-sort")]
+    pub human_numeric: bool,
+
+    /// Compare (unknown) < 'JAN' < ... < 'DEC'
+    #[arg(short = 'M', long = "month-sort")]
+    pub month: bool,
+
+    /// Natural sort of (version) numbers within text
+    #[arg(short = 'V', long = "version-sort")]
+    pub version: bool,
+
     /// Output only unique lines
+    ///
+    /// Of each run of lines whose keys compare equal, only the first is
+    /// output. Equal means equal keys: there is no last-resort comparison, so
+    /// with -f, "a" and "A" are duplicates. With -c, equal neighbours are
+    /// disorder.
     #[arg(short = 'u', long)]
     pub unique: bool,
 
     /// Stabilize sort by disabling last-resort comparison
+    ///
+    /// Lines whose keys compare equal stay in input order instead of being
+    /// ordered by their bytes.
     #[arg(short = 's', long)]
     pub stable: bool,
 
     /// Write result to FILE instead of stdout
-    #[arg(short = 'o', long, value_name = "FILE")]
-    pub output: Option<String>,
+    #[arg(short = 'o', long, value_name = "FILE", value_hint = ValueHint::FilePath, value_parser = clap::value_parser!(PathBuf))]
+    pub output: Option<PathBuf>,
+
+    /// Compress a *.gz or *.zst output file at level N
+    #[arg(long, value_name = "N")]
+    pub compress_level: Option<u32>,
+
+    /// Write the -o file uncompressed, whatever its extension
+    #[arg(long)]
+    pub no_auto_compress: bool,
+
+    /// Add to the end of the -o file instead of replacing it (not atomic)
+    #[arg(long)]
+    pub append: bool,
+
+    /// Sync the -o file (and its directory) to disk before exiting
+    #[arg(long)]
+    pub fsync: bool,
+
+    /// Also write the output to FILE; may be repeated
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub tee: Vec<PathBuf>,
+
+    /// Write the output as N files, PREFIX.000 on, each the next range of the order
+    #[arg(long, value_name = "N", requires = "output_prefix")]
+    pub split_output: Option<usize>,
+
+    /// Name the --split-output files PREFIX.000, PREFIX.001, ...
+    #[arg(long, value_name = "PREFIX", value_hint = ValueHint::FilePath, requires = "split_output")]
+    pub output_prefix: Option<PathBuf>,
+
+    /// Keep records with equal keys in the same --split-output file
+    #[arg(long, requires = "split_output")]
+    pub split_by_key: bool,
+
+    /// Pipe the output through PROG, which writes the -o file or stdout (e.g. xz)
+    #[arg(long, value_name = "PROG", value_hint = ValueHint::CommandName, conflicts_with = "compress_level")]
+    pub compress_output_program: Option<String>,
 
     /// Use SEP as field separator
     #[arg(short = 't', long = "field-separator", value_name = "SEP")]
     pub delimiter: Option<String>,
 
+    /// Use the string STR as field separator; may be several characters
+    #[arg(long, value_name = "STR", conflicts_with = "delimiter")]
+    pub field_separator_str: Option<String>,
+
+    /// Use matches of the regex RE as field separators
+    #[cfg(feature = "regex")]
+    #[arg(
+        long,
+        value_name = "RE",
+        conflicts_with_all = ["delimiter", "field_separator_str", "csv"]
+    )]
+    pub field_separator_regex: Option<String>,
+
+    /// Split fields as CSV: quoted fields may contain the separator
+    #[arg(long)]
+    pub csv: bool,
+
+    /// Order text by the collation of LOCALE, e.g. de or sv-SE
+    #[cfg(feature = "collate")]
+    #[arg(long, value_name = "LOCALE")]
+    pub locale: Option<String>,
+
     /// Sort by key specification
+    ///
+    /// KEYDEF is F[.C][OPTS][,F[.C][OPTS]]. The key starts at character C
+    /// (default 1) of field F, and ends at character C of the second field
+    /// given, at the end of that field without .C, or at the end of the line
+    /// with no second part. Fields and characters count from 1: -k2,2 is the
+    /// second field, -k1.3,1.5 the third to fifth characters of the first.
+    ///
+    /// Fields are split at each -t SEP. Without -t, a field starts at the
+    /// blanks before it, which are part of the key unless b is given.
+    ///
+    /// OPTS are letters ordering this key alone: b (ignore leading blanks),
+    /// f, h, M, n, r and V, as the options of those names. A key with any
+    /// letters of its own ignores the global ordering options and -r; one
+    /// without inherits them.
+    ///
+    /// Repeat -k for further keys, each compared only when the keys before it
+    /// are equal.
     #[arg(short = 'k', long = "key", value_name = "KEYDEF")]
     pub keys: Vec<String>,
 
+    /// Sort by the header column NAME, with optional key options (NAME:nr)
+    #[arg(long = "key-name", value_name = "NAME[:OPTS]")]
+    pub key_names: Vec<String>,
+
+    /// Sort by the line CMD prints for each record; CMD runs once through the shell, reading every record on stdin
+    #[arg(long, value_name = "CMD", value_hint = ValueHint::CommandString)]
+    pub key_command: Option<String>,
+
+    /// Sort JSON Lines by the value at POINTER (RFC 6901, e.g. /user/id)
+    #[arg(long = "json-key", value_name = "POINTER")]
+    pub json_keys: Vec<String>,
+
+    /// Sort by bytes START to END of each record, counting from 1 (END defaults to the last)
+    #[arg(long = "key-bytes", value_name = "START[,END]")]
+    pub key_bytes: Vec<String>,
+
     /// Use NUL as line delimiter
     #[arg(short = 'z', long = "zero-terminated")]
     pub zero_terminated: bool,
 
-    /// Annotate the part of the line used to sort
+    /// End records with STR instead of newline, e.g. '\r\n' (-z is '\0'); escapes as for -t
+    #[arg(long = "record-delimiter", value_name = "STR")]
+    pub record_delimiter_char: Option<String>,
+
+    /// Write CHAR after each output record instead of the record delimiter; escapes as for -t
+    #[arg(long, value_name = "CHAR")]
+    pub output_delimiter: Option<String>,
+
+    /// Write records that contain the --output-delimiter anyway
+    #[arg(long)]
+    pub force: bool,
+
+    /// Flush the output after every record (the default when stdout is a terminal)
+    #[arg(long)]
+    pub line_buffered: bool,
+
+    /// Write SEP as a record between runs of equal keys (default: an empty record)
+    #[arg(long, value_name = "SEP", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub group: Option<String>,
+
+    /// Output each record's sort keys instead of the record
+    #[arg(long)]
+    pub print_key: bool,
+
+    /// Join the keys of --print-key with SEP (default: a tab)
+    #[arg(long, value_name = "SEP", requires = "print_key")]
+    pub key_output_separator: Option<String>,
+
+    /// Put each record's position in the input, and a tab, before it
+    #[arg(long)]
+    pub number: bool,
+
+    /// Print counts and timings to stderr when done; FORMAT is text (default) or json
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        value_parser = Suggested(&["text", "json"]),
+        hide_possible_values = true
+    )]
+    pub stats: Option<String>,
+
+    /// Read the -o file back and check it is sorted before exiting
+    #[arg(long)]
+    pub verify: bool,
+
+    /// End the output without a newline (delimiter) when the input does
     #[arg(long)]
-    pub debug: bool,
+    pub preserve_final_newline: bool,
+
+    /// Ignore a trailing carriage return on each line, keeping it in the output
+    #[arg(long)]
+    pub crlf: bool,
+
+    /// Ignore trailing blanks when comparing keys
+    #[arg(long)]
+    pub ignore_trailing_blanks: bool,
+
+    /// Decompress every input with CODEC (gzip or zstd), not just *.gz or *.zst files
+    #[arg(long, value_name = "CODEC", value_parser = Suggested(&["gzip", "zstd"]), hide_possible_values = true)]
+    pub decompress: Option<String>,
+
+    /// Read each input through PROG -d, the file on its stdin (e.g. xz)
+    #[arg(long, value_name = "PROG", value_hint = ValueHint::CommandName, conflicts_with = "decompress")]
+    pub decompress_program: Option<String>,
+
+    /// Decode input files from ENC: utf-8 (default), utf-16le or utf-16be
+    #[arg(long, value_name = "ENC", value_parser = Suggested(ENCODINGS), hide_possible_values = true)]
+    pub encoding: Option<String>,
+
+    /// Write output in ENC: utf-8 (default), utf-16le or utf-16be
+    #[arg(long, value_name = "ENC", value_parser = Suggested(ENCODINGS), hide_possible_values = true)]
+    pub output_encoding: Option<String>,
+
+    /// Remove a UTF-8 byte order mark from the start of the first input
+    #[arg(long)]
+    pub strip_bom: bool,
+
+    /// Like --strip-bom, but write the byte order mark back ahead of the output
+    #[arg(long)]
+    pub keep_bom: bool,
+
+    /// Fail on records longer than SIZE bytes (suffixes K, M, G, ...)
+    #[arg(long, value_name = "SIZE")]
+    pub max_record_size: Option<String>,
+
+    /// Read records of exactly N bytes, with no delimiter, and write them back to back
+    #[arg(long, value_name = "N")]
+    pub record_size: Option<String>,
+
+    /// Frame records as FRAMING on input and output: len32le puts a 4-byte little-endian length before each
+    #[arg(long, value_name = "FRAMING", value_parser = Suggested(&["delimited", "len32le"]), hide_possible_values = true)]
+    pub framing: Option<String>,
+
+    /// Sort only the first N records read, across all inputs
+    #[arg(long, value_name = "N")]
+    pub take: Option<u64>,
+
+    /// Output the first N lines first, unsorted
+    #[arg(long, value_name = "N")]
+    pub header: Option<usize>,
+
+    /// Output lines starting with C first, in input order, unsorted
+    #[arg(long, value_name = "C")]
+    pub comment_char: Option<String>,
+
+    /// Annotate the part of the line used to sort; FORMAT is text (default), json or decisions
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        value_parser = Suggested(&["text", "json", "decisions"]),
+        hide_possible_values = true
+    )]
+    pub debug: Option<String>,
+
+    /// Read two records and explain how they compare; do not sort
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Read default options from FILE (otherwise ./.rsort.toml if present)
+    #[cfg(feature = "profile")]
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub profile: Option<String>,
+
+    /// Log what rsort does to stderr, at LEVEL: error, warn, info, debug or trace
+    #[cfg(feature = "trace")]
+    #[arg(long, value_name = "LEVEL", value_parser = Suggested(&["error", "warn", "info", "debug", "trace"]), hide_possible_values = true)]
+    pub log_level: Option<String>,
+
+    /// Write a completion script for SHELL to stdout and exit
+    #[arg(long, value_name = "SHELL", hide = true)]
+    pub generate_completions: Option<Shell>,
+
+    /// Write the man page, in roff, to stdout and exit
+    #[arg(long, hide = true)]
+    pub generate_man: bool,
 
     /// Input files
-    #[arg(value_name = "FILE")]
-    pub files: Vec<String>,
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, value_parser = clap::value_parser!(PathBuf))]
+    pub files: Vec<PathBuf>,
+}
+
+/// What --help and the man page say first
+const LONG_ABOUT: &str = "\
+Write the sorted concatenation of all FILEs to standard output, or of \
+standard input with no FILE or where FILE is -.
+
+Lines are compared by their keys (-k), in order, or as a whole without -k, \
+under the ordering options (-n, -f, -h, -M, -V) and -r. Lines whose keys \
+all compare equal are then compared by their bytes, the last resort, which \
+only -r affects. -s and -u leave the last resort out: -s keeps equal lines \
+in input order, and -u outputs only the first of them.
+
+Bytes compare as unsigned values, as GNU sort does under LC_ALL=C.";
+
+/// (command, what it does), for the end of --help and the man page
+const EXAMPLES: &[(&str, &str)] = &[
+    ("rsort -n sizes.txt", "Sort numbers by value: 2 before 10."),
+    ("rsort -t: -k3,3n /etc/passwd", "Sort the password file by user ID, the third :-separated field."),
+    ("rsort -k2,2 -k1,1nr scores.txt", "Sort by the second field, then by the first as numbers, largest first."),
+    ("rsort -u -f words.txt", "Output one of each word, ignoring case."),
+    ("rsort -m -o all.txt a.txt b.txt", "Merge two files that are already sorted into all.txt."),
+    ("rsort -c data.txt", "Report the first line out of order, exiting 1 if there is one."),
+];
+
+/// `EXAMPLES` as text, after the options in --help
+fn examples_help() -> String {
+    let mut help = String::from("Examples:\n");
+    for (command, what) in EXAMPLES {
+        help.push_str(&format!("  {}\n      {}\n", command, what));
+    }
+    help
+}
+
+/// Write the man page, rsort(1), in roff (--generate-man)
+///
+/// The page is made from `Args` like --help is: the description, each
+/// option's long help, and `EXAMPLES` in a section of their own.
+pub fn write_man(out: &mut dyn Write) -> io::Result<()> {
+    let man = clap_mangen::Man::new(Args::command()).source(format!("rsort {}", env!("CARGO_PKG_VERSION")));
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+
+    let mut examples = roff::Roff::new();
+    examples.control("SH", ["EXAMPLES"]);
+    for (command, what) in EXAMPLES {
+        examples.control("TP", []);
+        examples.text([roff::bold(*command)]);
+        examples.text([roff::roman(*what)]);
+    }
+    out.write_all(examples.to_roff().as_bytes())
+}
+
+/// --encoding and --output-encoding names
+const ENCODINGS: &[&str] = &["utf-8", "utf-16le", "utf-16be"];
+
+/// Takes any string, leaving the check (and its message) to
+/// `Config::from_args`, but offers these values to shell completion
+///
+/// Arguments using it hide the values from --help, whose text lists them.
+#[derive(Clone)]
+struct Suggested(&'static [&'static str]);
+
+impl TypedValueParser for Suggested {
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<String, clap::Error> {
+        StringValueParser::new().parse_ref(cmd, arg, value)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        Some(Box::new(self.0.iter().copied().map(PossibleValue::new)))
+    }
+}
+
+/// Write the completion script for `shell` (--generate-completions)
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Args::command(), "rsort", out);
 }
 
 impl Args {
-    /// Parse -t argument, handling '\0' escape for NUL byte
-    pub fn field_separator(&self) -> crate::error::Result<Option<u8>> {
-        match &self.delimiter {
-            None => Ok(None),
-            Some(s) => {
-                if s == "\\0" || s == "\0" {
-                    Ok(Some(0u8))
-                } else if s.len() == 1 {
-                    Ok(Some(s.as_bytes()[0]))
-                } else if s.starts_with('\\') && s.len() == 2 {
-                    // Handle common escape sequences
-                    match s.chars().nth(1) {
-                        Some('t') => Ok(Some(b'\t')),
-                        Some('n') => Ok(Some(b'\n')),
-                        Some('r') => Ok(Some(b'\r')),
-                        Some('v') => Ok(Some(b'\x0B')), // vertical tab
-                        Some('f') => Ok(Some(b'\x0C')), // form feed
-                        Some('\\') => Ok(Some(b'\\')),
-                        Some('0') => Ok(Some(0u8)),
-                        _ => Err(crate::error::RsortError::InvalidDelimiter),
+    /// Parse -t argument: one character or an escape such as '\0'
+    pub fn field_separator(&self) -> crate::error::Result<Option<FieldSeparator>> {
+        self.delimiter
+            .as_deref()
+            .map(crate::config::parse_field_separator)
+            .transpose()
+    }
+
+    /// Record delimiter from -z or --record-delimiter, None when neither is given
+    pub fn record_delimiter(&self) -> crate::error::Result<Option<Vec<u8>>> {
+        let Some(arg) = &self.record_delimiter_char else {
+            return Ok(self.zero_terminated.then(|| vec![0]));
+        };
+        let delimiter = crate::config::parse_escapes(arg)?;
+        if delimiter.is_empty() {
+            return Err(RsortError::Usage(format!(
+                "invalid --record-delimiter argument '{}'",
+                arg
+            )));
+        }
+        if self.zero_terminated && delimiter != [0] {
+            return Err(RsortError::Usage(format!(
+                "options '-z' and '--record-delimiter={}' are incompatible; -z means '\\0'",
+                arg
+            )));
+        }
+        Ok(Some(delimiter))
+    }
+}
+
+/// Expand `*` and `?` in FILE operands, as a Unix shell would have
+///
+/// For Windows, where cmd.exe and PowerShell pass `*.txt` on as is. Only
+/// the last component of an operand is a pattern: it matches the names of
+/// the files (not directories) in its directory, ignoring case, and
+/// expands to them in sorted order. A pattern matching nothing is an
+/// error. An operand naming a file that exists is kept as is, and so are
+/// the last `literal` operands (those after `--`).
+pub fn expand_wildcards(files: &[PathBuf], literal: usize) -> crate::error::Result<Vec<PathBuf>> {
+    let patterns = files.len().saturating_sub(literal);
+    let mut expanded = Vec::with_capacity(files.len());
+    for (index, file) in files.iter().enumerate() {
+        let pattern = file.file_name().and_then(|name| name.to_str());
+        let pattern = pattern.filter(|name| index < patterns && name.contains(['*', '?']));
+        match pattern {
+            Some(pattern) if !file.exists() => {
+                let matched = matching_files(file, pattern);
+                if matched.is_empty() {
+                    return Err(RsortError::Usage(format!("no files match '{}'", file.display())));
+                }
+                expanded.extend(matched);
+            }
+            _ => expanded.push(file.clone()),
+        }
+    }
+    Ok(expanded)
+}
+
+/// The files beside `file` whose names match `pattern`, sorted
+fn matching_files(file: &Path, pattern: &str) -> Vec<PathBuf> {
+    let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let Ok(entries) = fs::read_dir(dir.unwrap_or(Path::new("."))) else {
+        return Vec::new();
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut matched: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| !kind.is_dir()))
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let chars: Vec<char> = name.to_str()?.chars().collect();
+            wildcard_match(&pattern, &chars).then(|| match dir {
+                Some(dir) => dir.join(&name),
+                None => PathBuf::from(name),
+            })
+        })
+        .collect();
+    matched.sort();
+    matched
+}
+
+/// `*` matches any run of characters and `?` any one; the rest match
+/// themselves, ignoring case
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..])),
+        Some((&want, rest)) => match name.split_first() {
+            Some((&have, name)) if want == '?' || want.to_lowercase().eq(have.to_lowercase()) => {
+                wildcard_match(rest, name)
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Options from RSORT_OPTS that would write or run something, or name
+/// inputs: the environment may only change how rsort sorts
+const NOT_FROM_ENV: &[&str] = &[
+    "output",
+    "tee",
+    "split_output",
+    "output_prefix",
+    "append",
+    "compress_output_program",
+    "decompress_program",
+    "key_command",
+    "profile",
+    "generate_completions",
+    "generate_man",
+    "help",
+    "files",
+];
+
+/// `args` with the options in `env` (RSORT_OPTS) put first, after the
+/// program name
+///
+/// `env` is split into words as a shell would (see `split_words`). Given
+/// first, its options are overridden by the same options on the command
+/// line, as Args takes the last value given; repeatable ones like -k add to
+/// them. Options in `NOT_FROM_ENV`, such as -o, and FILE operands are an
+/// error.
+pub fn with_env_options(
+    args: impl IntoIterator<Item = OsString>,
+    env: Option<&OsStr>,
+) -> crate::error::Result<Vec<OsString>> {
+    let mut args = args.into_iter();
+    let program = args.next().unwrap_or_else(|| OsString::from("rsort"));
+    let Some(env) = env else {
+        return Ok(std::iter::once(program).chain(args).collect());
+    };
+    let env = env
+        .to_str()
+        .ok_or_else(|| RsortError::Usage("RSORT_OPTS is not valid UTF-8".to_string()))?;
+    let words = split_words(env)?;
+
+    let command = Args::command();
+    let matches = command
+        .clone()
+        .try_get_matches_from(std::iter::once("rsort").chain(words.iter().map(String::as_str)))
+        .map_err(|err| {
+            let message = err.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            RsortError::Usage(format!("RSORT_OPTS: {}", first.trim_start_matches("error: ")))
+        })?;
+    for id in matches.ids() {
+        let given = matches.value_source(id.as_str()) == Some(ValueSource::CommandLine);
+        if !given || !NOT_FROM_ENV.contains(&id.as_str()) {
+            continue;
+        }
+        let arg = command.get_arguments().find(|arg| arg.get_id() == id);
+        let option = match arg.and_then(|arg| arg.get_long()) {
+            _ if id == "files" => "FILE operands".to_string(),
+            Some(long) => format!("--{}", long),
+            None => format!("--{}", id),
+        };
+        return Err(RsortError::Usage(format!("RSORT_OPTS cannot give {}", option)));
+    }
+
+    Ok(std::iter::once(program)
+        .chain(words.into_iter().map(OsString::from))
+        .chain(args)
+        .collect())
+}
+
+/// Split `text` into words as a POSIX shell would, without expansions
+///
+/// Blanks separate words. Within single quotes every character is literal;
+/// within double quotes a backslash escapes only `"` and `\`; elsewhere it
+/// escapes any character, so `a\ b` is one word.
+fn split_words(text: &str) -> crate::error::Result<Vec<String>> {
+    let mut words = Vec::new();
+    // None between words; quotes alone ('') still make a word
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => words.extend(word.take()),
+            '\'' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(unterminated('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let current = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(unterminated('"')),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(unterminated('"')),
                     }
-                } else {
-                    Err(crate::error::RsortError::InvalidDelimiter)
                 }
             }
+            '\\' => {
+                let escaped = chars.next().unwrap_or('\\');
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn unterminated(quote: char) -> RsortError {
+    RsortError::Usage(format!("RSORT_OPTS: unterminated {} quote", quote))
+}
+
+/// How many arguments follow the first `--`, which are all FILE operands
+///
+/// `args` are the program's, program name included.
+pub fn operands_after_separator(args: impl IntoIterator<Item = OsString>) -> usize {
+    let mut args = args.into_iter().skip(1);
+    match args.position(|arg| arg == "--") {
+        Some(_) => args.count(),
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        wildcard_match(&chars(pattern), &chars(name))
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(matches("*.txt", "a.txt"));
+        assert!(matches("*.txt", ".txt"));
+        assert!(matches("*.TXT", "b.txt"));
+        assert!(matches("a?c*", "abc"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(!matches("*.txt", "a.txt.gz"));
+        assert!(!matches("a?c", "ac"));
+    }
+
+    #[test]
+    fn test_expand_wildcards() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.txt", "a.txt", "c.log", "a*.txt"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        fs::create_dir(dir.path().join("d.txt")).unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        let expanded = expand_wildcards(&[path("?.txt"), path("c.log")], 0).unwrap();
+        assert_eq!(expanded, [path("a.txt"), path("b.txt"), path("c.log")]);
+        // An existing file is not a pattern
+        assert_eq!(expand_wildcards(&[path("a*.txt")], 0).unwrap(), [path("a*.txt")]);
+        // Nor is an operand after `--`
+        let literal = [path("b.txt"), path("*.log")];
+        assert_eq!(expand_wildcards(&literal, 1).unwrap(), literal);
+        let err = expand_wildcards(&[path("*.csv")], 0).unwrap_err();
+        assert_eq!(err.to_string(), format!("no files match '{}'", path("*.csv").display()));
+    }
+
+    #[test]
+    fn test_man_page() {
+        let mut page = Vec::new();
+        write_man(&mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains(r"KEYDEF is F[.C][OPTS][,F[.C][OPTS]]."), "{}", page);
+        assert!(page.contains(".SH EXAMPLES"));
+        for short in Args::command().get_arguments().filter_map(clap::Arg::get_short) {
+            assert!(page.contains(&format!(r"\fB\-{}\fR", short)), "-{} missing", short);
         }
+        assert!(!page.contains("generate"));
+    }
+
+    #[test]
+    fn test_split_words() {
+        let words = |text| split_words(text).unwrap();
+        assert_eq!(words("  -n   -r\t-k2,2 "), ["-n", "-r", "-k2,2"]);
+        assert_eq!(words("-t ' ' --group=''"), ["-t", " ", "--group="]);
+        assert_eq!(words(r#"-t"\"" "a\b" "\\""#), ["-t\"", "a\\b", "\\"]);
+        assert_eq!(words(r"--group=a\ b \'x"), ["--group=a b", "'x"]);
+        assert_eq!(words("''"), [""]);
+        assert!(words("").is_empty());
+        let err = split_words("-t 'x").unwrap_err();
+        assert_eq!(err.to_string(), "RSORT_OPTS: unterminated ' quote");
+        assert!(split_words("-t \"x").is_err());
     }
 
-    /// Get the record delimiter (newline or NUL)
-    pub fn record_delimiter(&self) -> u8 {
-        if self.zero_terminated {
-            0u8
-        } else {
-            b'\n'
+    #[test]
+    fn test_with_env_options() {
+        let with = |env: &str, args: &[&str]| {
+            let args = std::iter::once("rsort").chain(args.iter().copied()).map(OsString::from);
+            with_env_options(args, Some(OsStr::new(env)))
+        };
+        assert_eq!(with("-n -t ':'", &["-r", "in"]).unwrap(), ["rsort", "-n", "-t", ":", "-r", "in"]);
+        assert_eq!(
+            with_env_options([OsString::from("rsort"), OsString::from("-r")], None).unwrap(),
+            ["rsort", "-r"]
+        );
+        for (env, option) in [("-o out", "--output"), ("--tee=copy", "--tee"), ("-n in.txt", "FILE operands")] {
+            let err = with(env, &[]).unwrap_err();
+            assert_eq!(err.to_string(), format!("RSORT_OPTS cannot give {}", option));
         }
+        let err = with("--no-such-option", &[]).unwrap_err();
+        assert!(err.to_string().starts_with("RSORT_OPTS: unexpected argument"), "{}", err);
+    }
+
+    #[test]
+    fn test_command_line_overrides_env() {
+        let args = with_env_options(
+            ["rsort", "-t:", "-k1"].map(OsString::from),
+            Some(OsStr::new("-t, -k2 -n")),
+        )
+        .unwrap();
+        let args = Args::parse_from(args);
+        assert_eq!(args.delimiter.as_deref(), Some(":"));
+        assert_eq!(args.keys, ["2", "1"]);
+        assert!(args.numeric);
+    }
+
+    #[test]
+    fn test_operands_after_separator() {
+        let args = |args: &[&str]| operands_after_separator(args.iter().map(OsString::from));
+        assert_eq!(args(&["rsort", "*.txt"]), 0);
+        assert_eq!(args(&["rsort", "-r", "--", "*.txt", "--"]), 2);
     }
 }
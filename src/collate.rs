@@ -0,0 +1,87 @@
+//! Locale collation for text keys (`--locale`, feature `collate`)
+//!
+//! With a locale, keys compared as text (no -n, -h, -M or -V) are ordered
+//! by ICU's collator for that locale, so `ä` sorts next to `a` in German
+//! and after `z` in Swedish. The last-resort comparison stays bytewise, as
+//! in GNU sort.
+//!
+//! Keys that are not valid UTF-8 sort after every valid key and compare
+//! bytewise among themselves, which keeps the ordering total.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use icu_collator::{Collator, CollatorOptions};
+use icu_provider::DataLocale;
+
+use crate::error::{Result, RsortError};
+
+/// A collator built once for a BCP 47 locale tag such as `de` or `sv-SE`
+#[derive(Clone)]
+pub struct Collation {
+    tag: String,
+    collator: Arc<Collator>,
+}
+
+impl Collation {
+    pub fn new(tag: &str) -> Result<Self> {
+        let invalid = |e: &dyn fmt::Display| {
+            RsortError::Usage(format!("invalid --locale '{}': {}", tag, e))
+        };
+        let locale: DataLocale = tag.parse().map_err(|e| invalid(&e))?;
+        let collator = Collator::try_new(&locale, CollatorOptions::new()).map_err(|e| invalid(&e))?;
+        Ok(Collation {
+            tag: tag.to_string(),
+            collator: Arc::new(collator),
+        })
+    }
+
+    /// The locale tag this collation was built from
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Compare two keys in the locale's order
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+            (Ok(a), Ok(b)) => self.collator.compare(a, b),
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a.cmp(b),
+        }
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Collation").field(&self.tag).finish()
+    }
+}
+
+impl PartialEq for Collation {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_utf8_sorts_last() {
+        let german = Collation::new("de").unwrap();
+        assert_eq!(german.compare("zz".as_bytes(), b"a\xff"), Ordering::Less);
+        assert_eq!(german.compare(b"b\xff", b"a\xff"), Ordering::Greater);
+        assert_eq!(german.compare("Äpfel".as_bytes(), b"Apfel"), Ordering::Greater);
+        assert_eq!(german.compare("Äpfel".as_bytes(), b"Birne"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        let err = Collation::new("not a locale").unwrap_err();
+        assert!(err.to_string().starts_with("invalid --locale 'not a locale': "), "{}", err);
+        assert_eq!(Collation::new("sv-SE").unwrap().tag(), "sv-SE");
+    }
+}
@@ -1,60 +1,377 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
 
+#[cfg(feature = "collate")]
+use crate::collate::Collation;
 use crate::config::Config;
-use crate::key::extract_key;
+use crate::fields::{is_blank, Splitter};
+use crate::input::strip_cr;
+use crate::json::JsonPointer;
+use crate::key::{
+    compare_versions_folded, ByteRange, FieldIndex, KeyExtractor, KeyKind, KeyModifiers, KeySpec, KeyValue,
+};
 
-/// Main comparison function implementing GNU sort semantics
+/// Signature shared by all key comparison functions
+type KeyCompareFn = fn(&[u8], &[u8]) -> Ordering;
+
+/// One key's bytes in two records and how they compare (`compare_each_key`)
+pub type KeyComparison<'a> = (Cow<'a, [u8]>, Cow<'a, [u8]>, Ordering);
+
+/// Shared, thread-safe form of a caller-supplied key comparison
+type DynKeyCompare = dyn Fn(&[u8], &[u8]) -> Ordering + Send + Sync;
+
+/// Caller-supplied key comparison, used in place of the -n/-f/bytewise choice
 ///
-/// 1. Compare by keys (or whole line if no keys specified)
-/// 2. If keys equal and last-resort enabled, compare whole line bytewise
-/// 3. Last-resort ignores ALL options except -r
-pub fn compare_records(a: &[u8], b: &[u8], config: &Config) -> Ordering {
-    // Step 1: Compare by keys
-    let key_result = compare_by_keys(a, b, config);
+/// Key extraction, multi-key fallback, -r, last-resort and -u still apply
+/// around it.
+#[derive(Clone)]
+pub struct KeyCompare(Arc<DynKeyCompare>);
+
+impl KeyCompare {
+    pub fn new<F>(compare: F) -> Self
+    where
+        F: Fn(&[u8], &[u8]) -> Ordering + Send + Sync + 'static,
+    {
+        KeyCompare(Arc::new(compare))
+    }
 
-    if key_result != Ordering::Equal {
-        return maybe_reverse(key_result, config.reverse);
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        (self.0)(a, b)
     }
+}
 
-    // Step 2: Last-resort comparison (if enabled)
-    // CRITICAL: Last-resort ignores ALL options except -r
-    if config.use_last_resort() {
-        let last_resort = compare_bytes_raw(a, b);
-        return maybe_reverse(last_resort, config.reverse);
+impl fmt::Debug for KeyCompare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyCompare(..)")
     }
+}
+
+/// Comparison plan compiled once from a `Config`
+///
+/// Resolves the ordering options, `-r`, and the last-resort decision up front
+/// so the per-comparison path does no option branching.
+#[derive(Clone, Debug)]
+pub struct Comparator {
+    keys: Vec<KeyOrder>,
+    /// How whole lines compare when there are no keys
+    line: KeyOrder,
+    splitter: Splitter,
+    /// Fields to index per record, None when some key runs to end of line
+    field_limit: Option<usize>,
+    /// Overrides every key's compare function when set
+    custom_compare: Option<KeyCompare>,
+    /// --locale: collation for keys with `KeyOrder::collates`
+    #[cfg(feature = "collate")]
+    collation: Option<Collation>,
+    /// --crlf: compare records without their trailing `\r`
+    crlf: bool,
+    /// --ignore-trailing-blanks: compare keys without their trailing blanks
+    trim_trailing_blanks: bool,
+    reverse: bool,
+    last_resort: bool,
+}
+
+/// One key's extraction and ordering, modifiers already resolved
+#[derive(Clone, Debug)]
+struct KeyOrder {
+    source: KeySource,
+    compare: KeyCompareFn,
+    reverse: bool,
+    /// Compared as text, so a --locale collation applies
+    #[cfg(feature = "collate")]
+    collates: bool,
+}
 
-    // Keys equal and no last-resort: preserve input order (stable sort handles this)
-    Ordering::Equal
+/// Where a key's bytes come from
+#[derive(Clone, Debug)]
+enum KeySource {
+    /// Fields of the record (-k)
+    Fields(KeyExtractor),
+    /// A value in a JSON record (--json-key)
+    Json(JsonPointer),
+    /// Byte positions in the record (--key-bytes)
+    Bytes(ByteRange),
 }
 
-/// Compare by key specifications
-fn compare_by_keys(a: &[u8], b: &[u8], config: &Config) -> Ordering {
-    if config.keys.is_empty() {
-        // No -k: compare entire line with options
-        return compare_with_options(a, b, config);
+impl KeyOrder {
+    fn new(spec: &KeySpec, config: &Config) -> Self {
+        let extractor = KeyExtractor::with_splitter(spec, Splitter::from_config(config));
+        KeyOrder::with_source(KeySource::Fields(extractor), config.key_modifiers(spec))
+    }
+
+    /// A --json-key, compared under the global options
+    fn json(pointer: &JsonPointer, config: &Config) -> Self {
+        KeyOrder::with_source(KeySource::Json(pointer.clone()), config.modifiers())
+    }
+
+    /// A --key-bytes key, compared under the global options
+    fn bytes(range: ByteRange, config: &Config) -> Self {
+        KeyOrder::with_source(KeySource::Bytes(range), config.modifiers())
     }
 
-    for key_spec in &config.keys {
-        let key_a = extract_key(a, key_spec, config.field_separator);
-        let key_b = extract_key(b, key_spec, config.field_separator);
+    fn with_source(source: KeySource, modifiers: KeyModifiers) -> Self {
+        KeyOrder {
+            source,
+            compare: select_key_compare(&modifiers),
+            reverse: modifiers.reverse,
+            #[cfg(feature = "collate")]
+            collates: matches!(modifiers.key_kind(), KeyKind::Bytes | KeyKind::FoldCase),
+        }
+    }
+
+    /// Number of leading fields the key can look at (see `KeyExtractor`)
+    fn fields_needed(&self) -> Option<usize> {
+        match &self.source {
+            KeySource::Fields(extractor) => extractor.fields_needed(),
+            KeySource::Json(_) | KeySource::Bytes(_) => Some(0),
+        }
+    }
 
-        let result = compare_with_options(&key_a, &key_b, config);
-        if result != Ordering::Equal {
-            return result;
+    /// The key's bytes in `record`, whose fields `fields` indexes
+    fn extract<'a>(&self, record: &'a [u8], fields: &FieldIndex) -> Cow<'a, [u8]> {
+        match &self.source {
+            KeySource::Fields(extractor) => Cow::Borrowed(extractor.extract(record, fields)),
+            KeySource::Json(pointer) => pointer.extract(record),
+            KeySource::Bytes(range) => Cow::Borrowed(range.extract(record)),
         }
     }
 
-    Ordering::Equal
+    /// `extract` without a FieldIndex
+    fn extract_in<'a>(&self, record: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.source {
+            KeySource::Fields(extractor) => Cow::Borrowed(extractor.extract_in(record)),
+            KeySource::Json(pointer) => pointer.extract(record),
+            KeySource::Bytes(range) => Cow::Borrowed(range.extract(record)),
+        }
+    }
 }
 
-/// Compare with -n, -f options applied
-fn compare_with_options(a: &[u8], b: &[u8], config: &Config) -> Ordering {
-    if config.numeric {
-        compare_numeric(a, b)
-    } else if config.fold_case {
-        compare_fold_case(a, b)
-    } else {
-        compare_bytes_raw(a, b)
+impl Comparator {
+    /// Build a comparator from configuration
+    pub fn new(config: &Config) -> Self {
+        let keys: Vec<KeyOrder> = config
+            .keys
+            .iter()
+            .map(|spec| KeyOrder::new(spec, config))
+            .chain(config.json_keys.iter().map(|pointer| KeyOrder::json(pointer, config)))
+            .chain(config.key_bytes.iter().map(|&range| KeyOrder::bytes(range, config)))
+            .collect();
+        let field_limit = keys
+            .iter()
+            .map(KeyOrder::fields_needed)
+            .try_fold(0, |max, needed| needed.map(|n| n.max(max)));
+
+        Comparator {
+            keys,
+            line: KeyOrder::new(&KeySpec::default(), config),
+            splitter: Splitter::from_config(config),
+            field_limit,
+            custom_compare: config.key_compare.clone(),
+            #[cfg(feature = "collate")]
+            collation: config.locale.clone(),
+            crlf: config.crlf,
+            trim_trailing_blanks: config.ignore_trailing_blanks,
+            reverse: config.reverse,
+            last_resort: config.use_last_resort(),
+        }
+    }
+
+    /// Compare two records implementing GNU sort semantics
+    ///
+    /// 1. Compare by keys (or whole line if no keys specified)
+    /// 2. If keys equal and last-resort enabled, compare whole line bytewise
+    /// 3. Last-resort ignores ALL options except the global -r
+    #[inline]
+    pub fn cmp(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.explain(a, b).0
+    }
+
+    /// `cmp`, also saying which step decided it (--debug=decisions)
+    pub fn explain(&self, a: &[u8], b: &[u8]) -> (Ordering, Decision) {
+        self.explain_keyed(a, a, b, b)
+    }
+
+    /// `cmp` for records ordered by key lines computed outside rsort
+    /// (--key-command)
+    ///
+    /// `key_a` and `key_b` stand in for the records in step 1, so -k picks
+    /// from them; the last resort still compares the records themselves.
+    pub fn cmp_keyed(&self, a: &[u8], key_a: &[u8], b: &[u8], key_b: &[u8]) -> Ordering {
+        self.explain_keyed(a, key_a, b, key_b).0
+    }
+
+    /// `explain` with the keys taken from `key_a` and `key_b`
+    fn explain_keyed(&self, a: &[u8], key_a: &[u8], b: &[u8], key_b: &[u8]) -> (Ordering, Decision) {
+        let (a, b) = if self.crlf { (strip_cr(a), strip_cr(b)) } else { (a, b) };
+        let (key_a, key_b) = if self.crlf { (strip_cr(key_a), strip_cr(key_b)) } else { (key_a, key_b) };
+
+        // Step 1: Compare by keys, each already reversed as its options say
+        let (key_result, key) = self.compare_keys(key_a, key_b);
+
+        if key_result != Ordering::Equal {
+            return (key_result, Decision::Key(key));
+        }
+
+        // Step 2: Last-resort comparison (if enabled)
+        // CRITICAL: Last-resort ignores ALL options except -r
+        if self.last_resort {
+            let last_resort = maybe_reverse(compare_bytes_raw(a, b), self.reverse);
+            if last_resort != Ordering::Equal {
+                return (last_resort, Decision::LastResort);
+            }
+        }
+
+        // Keys equal and no last-resort: preserve input order (stable sort handles this)
+        (Ordering::Equal, Decision::Equal)
+    }
+
+    /// Whether equal records must keep their input order (-s or -u)
+    pub fn is_stable(&self) -> bool {
+        !self.last_resort
+    }
+
+    /// Compare by key specifications, also returning the index of the key
+    /// that decided (0 when all are equal)
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> (Ordering, usize) {
+        if self.keys.is_empty() {
+            // No -k: compare entire line with options
+            return (self.compare_key(&self.line, a, b), 0);
+        }
+
+        if let [key] = &self.keys[..] {
+            // One key: find it without indexing the fields
+            return (self.compare_key(key, &key.extract_in(a), &key.extract_in(b)), 0);
+        }
+
+        // Split each record once, shared by all keys
+        let fields_a = self.index_fields(a);
+        let fields_b = self.index_fields(b);
+
+        for (index, key) in self.keys.iter().enumerate() {
+            let key_a = key.extract(a, &fields_a);
+            let key_b = key.extract(b, &fields_b);
+
+            let result = self.compare_key(key, &key_a, &key_b);
+            if result != Ordering::Equal {
+                return (result, index);
+            }
+        }
+
+        (Ordering::Equal, 0)
+    }
+
+    /// Compare two extracted keys
+    fn compare_key(&self, key: &KeyOrder, a: &[u8], b: &[u8]) -> Ordering {
+        let (a, b) = (self.trim_key(a), self.trim_key(b));
+        let result = match &self.custom_compare {
+            Some(custom) => custom.compare(a, b),
+            #[cfg(feature = "collate")]
+            None if key.collates => match &self.collation {
+                Some(collation) => collation.compare(a, b),
+                None => (key.compare)(a, b),
+            },
+            None => (key.compare)(a, b),
+        };
+        maybe_reverse(result, key.reverse)
+    }
+
+    /// An extracted key without the trailing blanks --ignore-trailing-blanks drops
+    fn trim_key<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        if self.trim_trailing_blanks {
+            trim_trailing_blanks(bytes)
+        } else {
+            bytes
+        }
+    }
+
+    /// The bytes each key compares in `record` (--print-key)
+    ///
+    /// One entry per -k, --json-key and --key-bytes, or the whole record
+    /// without keys, after the same blank skipping and trimming as `cmp`. A
+    /// key whose start field, JSON value or start byte is missing is empty. Only a JSON string
+    /// with escapes is not borrowed from the record.
+    pub fn keys<'a>(&self, record: &'a [u8]) -> Vec<Cow<'a, [u8]>> {
+        let record = if self.crlf { strip_cr(record) } else { record };
+        if self.keys.is_empty() {
+            return vec![Cow::Borrowed(self.trim_key(record))];
+        }
+        let fields = self.index_fields(record);
+        self.keys
+            .iter()
+            .map(|key| match key.extract(record, &fields) {
+                Cow::Borrowed(bytes) => Cow::Borrowed(self.trim_key(bytes)),
+                Cow::Owned(bytes) => Cow::Owned(self.trim_key(&bytes).to_vec()),
+            })
+            .collect()
+    }
+
+    /// Each key's bytes in `a` and in `b` and how they compare, in key
+    /// order (--explain)
+    ///
+    /// Every key is compared, not just up to the deciding one; without -k
+    /// the whole line is the only key. Orderings are reversed as each
+    /// key's options say.
+    pub fn compare_each_key<'a>(&self, a: &'a [u8], b: &'a [u8]) -> Vec<KeyComparison<'a>> {
+        let (a, b) = if self.crlf { (strip_cr(a), strip_cr(b)) } else { (a, b) };
+        let (keys_a, keys_b) = (self.keys(a), self.keys(b));
+        let orders = match &self.keys[..] {
+            [] => vec![&self.line],
+            keys => keys.iter().collect(),
+        };
+        // `keys` has already trimmed them, which trimming again leaves alone
+        orders
+            .into_iter()
+            .zip(keys_a.into_iter().zip(keys_b))
+            .map(|(key, (key_a, key_b))| {
+                let result = self.compare_key(key, &key_a, &key_b);
+                (key_a, key_b, result)
+            })
+            .collect()
+    }
+
+    /// Index only as many fields as the keys can reach
+    fn index_fields(&self, record: &[u8]) -> FieldIndex {
+        FieldIndex::split(record, &self.splitter, self.field_limit)
+    }
+}
+
+/// What decided a comparison between two records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// The key at this index (from 0) differed; without -k, the whole line
+    /// under the ordering options is key 0
+    Key(usize),
+    /// Every key was equal and the last-resort whole-line comparison decided
+    LastResort,
+    /// The records compare equal, so they keep their input order
+    Equal,
+}
+
+/// Compare two records using a one-off comparator built from `config`
+///
+/// Convenient for single comparisons; hot paths should build a
+/// `Comparator` once and reuse it.
+pub fn compare_records(a: &[u8], b: &[u8], config: &Config) -> Ordering {
+    compare_records_explain(a, b, config).0
+}
+
+/// `compare_records`, also saying what decided the order
+pub fn compare_records_explain(a: &[u8], b: &[u8], config: &Config) -> (Ordering, Decision) {
+    Comparator::new(config).explain(a, b)
+}
+
+/// Resolve the ordering options to a key comparison function
+fn select_key_compare(modifiers: &KeyModifiers) -> KeyCompareFn {
+    match modifiers.key_kind() {
+        KeyKind::Bytes => compare_bytes_raw,
+        KeyKind::FoldCase => compare_fold_case,
+        KeyKind::Numeric => compare_numeric,
+        KeyKind::Human => compare_human,
+        KeyKind::Month => compare_month,
+        KeyKind::Version if modifiers.fold_case => compare_version_folded,
+        KeyKind::Version => compare_version,
     }
 }
 
@@ -64,82 +381,50 @@ fn compare_bytes_raw(a: &[u8], b: &[u8]) -> Ordering {
     a.cmp(b)
 }
 
+/// Compare `a` and `b` parsed as `kind`
+#[inline]
+fn compare_as(a: &[u8], b: &[u8], kind: KeyKind) -> Ordering {
+    KeyValue::parse(a, kind).cmp(&KeyValue::parse(b, kind))
+}
+
 /// Numeric comparison matching GNU sort -n behavior
 ///
-/// Parses leading numeric value (with optional sign and decimal point).
+/// Compares the leading decimal number exactly (see `key::Number`).
 /// Non-numeric lines compare as 0.
 /// Does NOT handle scientific notation (use -g for that).
 pub fn compare_numeric(a: &[u8], b: &[u8]) -> Ordering {
-    let num_a = parse_leading_number(a);
-    let num_b = parse_leading_number(b);
+    compare_as(a, b, KeyKind::Numeric)
+}
 
-    // Use partial_cmp which treats -0.0 == +0.0 (matching GNU sort behavior)
-    // This allows last-resort comparison to break ties based on byte values
-    // For NaN, treat as 0 (compare equal, let last-resort handle it)
-    num_a.partial_cmp(&num_b).unwrap_or(Ordering::Equal)
+/// Human-readable numbers: SI suffix first, then value (-h)
+fn compare_human(a: &[u8], b: &[u8]) -> Ordering {
+    compare_as(a, b, KeyKind::Human)
 }
 
-/// Parse leading number from bytes (GNU sort -n compatible)
-///
-/// - Skips leading whitespace (space and tab only)
-/// - Handles optional sign (+ or -)
-/// - Handles decimal point
-/// - Stops at first non-numeric character
-/// - Returns 0.0 for non-numeric input
-/// - Works directly on bytes without requiring valid UTF-8
-fn parse_leading_number(s: &[u8]) -> f64 {
-    // Skip leading whitespace (bytes)
-    let mut idx = 0;
-    while idx < s.len() && (s[idx] == b' ' || s[idx] == b'\t') {
-        idx += 1;
-    }
-    if idx >= s.len() {
-        return 0.0;
-    }
-
-    let s = &s[idx..];
-    let mut end = 0;
-    let mut has_dot = false;
-
-    // Optional sign
-    if end < s.len() && (s[end] == b'-' || s[end] == b'+') {
-        end += 1;
-    }
-
-    // Digits and decimal point
-    while end < s.len() {
-        if s[end].is_ascii_digit() {
-            end += 1;
-        } else if s[end] == b'.' && !has_dot {
-            has_dot = true;
-            end += 1;
-        } else {
-            break;
-        }
-    }
+/// Month abbreviations, unknown < JAN < ... < DEC (-M)
+fn compare_month(a: &[u8], b: &[u8]) -> Ordering {
+    compare_as(a, b, KeyKind::Month)
+}
 
-    // Edge cases: just sign or just dot
-    if end == 0 || (end == 1 && matches!(s[0], b'-' | b'+' | b'.')) {
-        return 0.0;
-    }
+/// Version strings (-V)
+fn compare_version(a: &[u8], b: &[u8]) -> Ordering {
+    compare_as(a, b, KeyKind::Version)
+}
 
-    // Convert only the numeric prefix to string (guaranteed ASCII, so always valid UTF-8)
-    // SAFETY: We've verified all bytes are ASCII digits, sign, or dot
-    let num_str = unsafe { std::str::from_utf8_unchecked(&s[..end]) };
-    num_str.parse().unwrap_or(0.0)
+/// -V -f: GNU folds the keys before comparing them as versions
+fn compare_version_folded(a: &[u8], b: &[u8]) -> Ordering {
+    compare_versions_folded(a, b)
 }
 
 /// Case-folded comparison (ASCII only, a-z → A-Z)
 fn compare_fold_case(a: &[u8], b: &[u8]) -> Ordering {
-    let fold = |&b: &u8| -> u8 {
-        if b.is_ascii_lowercase() {
-            b.to_ascii_uppercase()
-        } else {
-            b
-        }
-    };
+    compare_as(a, b, KeyKind::FoldCase)
+}
 
-    a.iter().map(fold).cmp(b.iter().map(fold))
+/// `key` without its trailing blanks
+fn trim_trailing_blanks(key: &[u8]) -> &[u8] {
+    let blanks = key.iter().rev().take_while(|&&b| is_blank(b)).count();
+    &key[..key.len() - blanks]
 }
 
 /// Apply reverse if needed
@@ -156,20 +441,22 @@ fn maybe_reverse(ord: Ordering, reverse: bool) -> Ordering {
 mod tests {
     use super::*;
 
-    fn test_config() -> Config {
-        Config {
-            reverse: false,
-            numeric: false,
-            fold_case: false,
-            unique: false,
-            stable: false,
-            debug: false,
-            record_delimiter: b'\n',
-            field_separator: None,
-            keys: vec![],
-            output_file: None,
-            input_files: vec![],
-        }
+    #[test]
+    fn test_explain_decisions() {
+        let config = Config::builder().field_separator(b':').add_key("1,1").add_key("2,2n").add_key("3,3r").build().unwrap();
+        let explain = |a: &[u8], b: &[u8]| compare_records_explain(a, b, &config);
+        assert_eq!(explain(b"a:2:x", b"b:1:x"), (Ordering::Less, Decision::Key(0)));
+        assert_eq!(explain(b"a:10:x", b"a:9:x"), (Ordering::Greater, Decision::Key(1)));
+        assert_eq!(explain(b"a:9:x", b"a:09:y"), (Ordering::Greater, Decision::Key(2)));
+        assert_eq!(explain(b"a:9:x", b"a:09:x"), (Ordering::Greater, Decision::LastResort));
+        assert_eq!(explain(b"a:9:x", b"a:9:x"), (Ordering::Equal, Decision::Equal));
+
+        // -s: equal keys keep their input order
+        let config = Config::builder().numeric(true).stable(true).build().unwrap();
+        assert_eq!(compare_records_explain(b"09", b"9", &config), (Ordering::Equal, Decision::Equal));
+        let config = Config::builder().numeric(true).build().unwrap();
+        assert_eq!(compare_records_explain(b"1", b"09", &config), (Ordering::Less, Decision::Key(0)));
+        assert_eq!(compare_records_explain(b"09", b"9", &config), (Ordering::Less, Decision::LastResort));
     }
 
     #[test]
@@ -235,6 +522,28 @@ mod tests {
         assert_eq!(compare_numeric(b"\xff5", b"0"), Ordering::Equal);
     }
 
+    #[test]
+    fn test_numeric_exact_beyond_f64() {
+        assert_eq!(
+            compare_numeric(b"9007199254740993", b"9007199254740992"),
+            Ordering::Greater
+        );
+        assert_eq!(compare_numeric(b"+5", b"0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_typed_orderings() {
+        let cmp = |config: Config, a: &[u8], b: &[u8]| Comparator::new(&config).cmp(a, b);
+        let human = Config::builder().human_numeric(true).build().unwrap();
+        assert_eq!(cmp(human, b"2K", b"1M"), Ordering::Less);
+        let month = Config::builder().month(true).build().unwrap();
+        assert_eq!(cmp(month, b"dec", b"Jan"), Ordering::Greater);
+        let version = Config::builder().version(true).build().unwrap();
+        assert_eq!(cmp(version, b"v1.9", b"v1.10"), Ordering::Less);
+        let version_fold = Config::builder().version(true).fold_case(true).build().unwrap();
+        assert_eq!(cmp(version_fold, b"1_a", b"1_B"), Ordering::Less);
+    }
+
     #[test]
     fn test_fold_case() {
         assert_eq!(compare_fold_case(b"A", b"a"), Ordering::Equal);
@@ -242,51 +551,156 @@ mod tests {
         assert_eq!(compare_fold_case(b"a", b"B"), Ordering::Less);
     }
 
+    #[test]
+    fn test_fold_case_non_ascii() {
+        let folded = compare_fold_case("Ärger".as_bytes(), "ärger".as_bytes());
+        let expected = if cfg!(feature = "unicode") { Ordering::Equal } else { Ordering::Less };
+        assert_eq!(folded, expected);
+
+        // -u keeps one of the two spellings only when they fold together
+        let config = Config::builder().fold_case(true).unique(true).build().unwrap();
+        let comparator = Comparator::new(&config);
+        let mut records: Vec<&[u8]> = vec!["ärger".as_bytes(), b"zorn", "Ärger".as_bytes()];
+        records.sort_by(|a, b| comparator.cmp(a, b));
+        records.dedup_by(|a, b| comparator.cmp(a, b) == Ordering::Equal);
+        assert_eq!(records.len(), if cfg!(feature = "unicode") { 2 } else { 3 });
+    }
+
     #[test]
     fn test_last_resort_ignores_fold() {
-        let mut config = test_config();
-        config.fold_case = true;
+        let config = Config::builder().fold_case(true).build().unwrap();
 
         // 'A' (0x41) < 'a' (0x61) bytewise
         // With -f: keys are equal, so last-resort kicks in
-        assert_eq!(compare_records(b"A", b"a", &config), Ordering::Less);
+        assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Less);
     }
 
     #[test]
     fn test_last_resort_ignores_numeric() {
-        let mut config = test_config();
-        config.numeric = true;
+        let config = Config::builder().numeric(true).build().unwrap();
 
         // "010" and "10" equal numerically, but "010" < "10" bytewise
-        assert_eq!(compare_records(b"010", b"10", &config), Ordering::Less);
+        assert_eq!(Comparator::new(&config).cmp(b"010", b"10"), Ordering::Less);
     }
 
     #[test]
     fn test_last_resort_with_reverse() {
-        let mut config = test_config();
-        config.reverse = true;
+        let config = Config::builder().reverse(true).build().unwrap();
 
         // With -r: both key and last-resort are reversed
-        assert_eq!(compare_records(b"a", b"b", &config), Ordering::Greater);
+        assert_eq!(Comparator::new(&config).cmp(b"a", b"b"), Ordering::Greater);
     }
 
     #[test]
     fn test_stable_disables_last_resort() {
-        let mut config = test_config();
-        config.fold_case = true;
-        config.stable = true;
+        let config = Config::builder().fold_case(true).stable(true).build().unwrap();
 
         // With -s: no last-resort, so A == a
-        assert_eq!(compare_records(b"A", b"a", &config), Ordering::Equal);
+        assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_keys_are_what_is_compared() {
+        let config = Config::builder()
+            .field_separator(b',')
+            .add_key("2b")
+            .add_key("3,3")
+            .ignore_trailing_blanks(true)
+            .build()
+            .unwrap();
+        let comparator = Comparator::new(&config);
+        assert_eq!(comparator.keys(b"a,  b ,c\t"), [&b"b ,c"[..], b"c"]);
+        assert_eq!(comparator.keys(b"a"), [&b""[..], b""]);
+        assert_eq!(Comparator::new(&Config::default()).keys(b"a b"), [&b"a b"[..]]);
+    }
+
+    #[test]
+    fn test_json_keys() {
+        let config = Config::builder().json_key("/user/score").numeric(true).build().unwrap();
+        let comparator = Comparator::new(&config);
+        let (low, high) = (br#"{"user": {"score": 2.5}}"#, br#"{"user": {"score": 10}}"#);
+        assert_eq!(comparator.cmp(low, high), Ordering::Less);
+        // Malformed and missing values are empty keys, zero under -n
+        assert_eq!(comparator.cmp(b"{oops", br#"{"user": {"score": -1}}"#), Ordering::Greater);
+        assert_eq!(comparator.cmp(b"{oops", low), Ordering::Less);
+
+        // After the -k keys, and unescaped
+        let config = Config::builder().add_key("1,1").json_key("/name").build().unwrap();
+        let comparator = Comparator::new(&config);
+        assert_eq!(comparator.keys(br#"{"name": "a\u0062"}"#), [&br#"{"name":"#[..], b"ab"]);
+        assert_eq!(comparator.cmp(br#"{"name": "\u0062"}"#, br#"{"name": "a"}"#), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_key_bytes() {
+        let config = Config::builder().key_bytes("3,4").build().unwrap();
+        let comparator = Comparator::new(&config);
+        assert_eq!(comparator.keys(b"zz\x01\x00zz"), [&b"\x01\x00"[..]]);
+        assert_eq!(comparator.cmp(b"zz\x00\xffzz", b"aa\x01\x00aa"), Ordering::Less);
+        // Ties fall back to the whole record
+        assert_eq!(comparator.cmp(b"zz\x01\x00", b"aa\x01\x00"), Ordering::Greater);
     }
 
     #[test]
     fn test_unique_disables_last_resort() {
-        let mut config = test_config();
-        config.fold_case = true;
-        config.unique = true;
+        let config = Config::builder().fold_case(true).unique(true).build().unwrap();
 
         // With -u: no last-resort, so A == a
-        assert_eq!(compare_records(b"A", b"a", &config), Ordering::Equal);
+        assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_comparator_stability() {
+        let mut config = Config::default();
+        assert!(!Comparator::new(&config).is_stable());
+
+        config.unique = true;
+        assert!(Comparator::new(&config).is_stable());
+
+        config.unique = false;
+        config.stable = true;
+        assert!(Comparator::new(&config).is_stable());
+    }
+
+    #[test]
+    fn test_key_modifiers_replace_global_options() {
+        let cmp = |config: Config, a: &[u8], b: &[u8]| Comparator::new(&config).cmp(a, b);
+
+        // 2,2n is numeric even without -n
+        let config = Config::builder().add_key("2,2n").build().unwrap();
+        assert_eq!(cmp(config, b"x 9", b"x 10"), Ordering::Less);
+
+        // A key with modifiers drops the global -r; one without keeps it
+        let config = Config::builder().reverse(true).add_key("1,1").add_key("2,2n").build().unwrap();
+        assert_eq!(cmp(config.clone(), b"a 1", b"b 1"), Ordering::Greater);
+        assert_eq!(cmp(config, b"a 1", b"a 2"), Ordering::Less);
+
+        // r reverses only its own key; the last resort stays forward
+        let config = Config::builder().add_key("1,1r").build().unwrap();
+        assert_eq!(cmp(config.clone(), b"a", b"b"), Ordering::Greater);
+        let config = Config::builder().field_separator(b',').add_key("1,1f").build().unwrap();
+        assert_eq!(cmp(config, b"A,2", b"a,1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_blank_modifier() {
+        let config = Config::builder().field_separator(b',').add_key("2b,2").build().unwrap();
+        assert_eq!(Comparator::new(&config).cmp(b"x,  b", b"y,a"), Ordering::Greater);
+        let config = Config::builder().field_separator(b',').add_key("2,2").build().unwrap();
+        assert_eq!(Comparator::new(&config).cmp(b"x,  b", b"y,a"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_ignore_trailing_blanks() {
+        let config = Config::builder().ignore_trailing_blanks(true).build().unwrap();
+        let comparator = Comparator::new(&config);
+        // Keys tie, so the raw last resort decides
+        assert_eq!(comparator.cmp(b"a \t", b"a"), Ordering::Greater);
+        assert_eq!(comparator.cmp(b"a  ", b"a b"), Ordering::Less);
+        // Leading blanks still count
+        assert_eq!(comparator.cmp(b" b", b"a"), Ordering::Less);
+
+        let config = Config::builder().ignore_trailing_blanks(true).stable(true).build().unwrap();
+        assert_eq!(Comparator::new(&config).cmp(b"a \t", b"a"), Ordering::Equal);
     }
 }
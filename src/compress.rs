@@ -0,0 +1,581 @@
+//! Compressed input and output (`.gz` and `.zst` files, `--decompress`,
+//! `--decompress-program`, `--compress-level`)
+//!
+//! An input is decompressed as it is read, before `--encoding` decodes it,
+//! so records are split on the plain text. A file is decompressed when its
+//! name ends in the codec's extension, or always with `--decompress`.
+//! `--decompress-program` hands every input to an external program instead.
+//!
+//! Likewise an `-o` file named `*.gz` or `*.zst` is written compressed,
+//! unless `--no-auto-compress` is given. `--compress-output-program` pipes
+//! the output through an external program instead.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+
+use crate::error::{os_message, Result, RsortError};
+use crate::platform;
+
+/// How inputs are decompressed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Decompression {
+    /// Files whose extension names a supported codec; the default
+    #[default]
+    Auto,
+    /// Every input, stdin included, with one codec (--decompress)
+    Codec(Codec),
+    /// Every input, stdin included, through `PROGRAM -d` (--decompress-program)
+    Program(String),
+}
+
+/// A compression format rsort can read and write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// gzip, including concatenated multi-member files (feature `gzip`)
+    Gzip,
+    /// Zstandard, any number of frames (feature `zstd`)
+    Zstd,
+}
+
+impl Codec {
+    const ALL: [Codec; 2] = [Codec::Gzip, Codec::Zstd];
+
+    /// Parse a codec name such as `gzip`, ignoring case
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|codec| codec.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The name `parse` accepts
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    /// File name extension, without the dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Zstd => "zst",
+        }
+    }
+
+    /// Cargo feature that enables this codec
+    pub fn feature(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    /// Compression levels `--compress-level` accepts
+    pub fn levels(self) -> RangeInclusive<u32> {
+        match self {
+            Codec::Gzip => 0..=9,
+            Codec::Zstd => 1..=22,
+        }
+    }
+
+    /// Level used without `--compress-level`
+    pub fn default_level(self) -> u32 {
+        match self {
+            Codec::Gzip => 6,
+            Codec::Zstd => 3,
+        }
+    }
+
+    /// Check `level` is one of `levels`
+    pub fn check_level(self, level: u32) -> Result<()> {
+        let levels = self.levels();
+        if levels.contains(&level) {
+            return Ok(());
+        }
+        Err(RsortError::Usage(format!(
+            "invalid --compress-level '{}' for {} output; expected {} to {}",
+            level,
+            self,
+            levels.start(),
+            levels.end()
+        )))
+    }
+
+    /// Whether this build can read and write the codec
+    pub fn is_supported(self) -> bool {
+        match self {
+            Codec::Gzip => cfg!(feature = "gzip"),
+            Codec::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    /// Every codec name, for messages: `gzip or zstd`
+    pub fn names() -> String {
+        let names: Vec<&str> = Self::ALL.into_iter().map(Codec::name).collect();
+        names.join(" or ")
+    }
+
+    /// The supported codec named by `path`'s extension, if any
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+        Self::ALL
+            .into_iter()
+            .find(|codec| codec.is_supported() && extension == codec.extension())
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// `reader` decompressed with `codec`; read errors name `path`
+// Without any codec feature every arm returns early
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "zstd")),
+    allow(unreachable_code, unused_variables)
+)]
+pub fn decompress<'a>(reader: Box<dyn BufRead + 'a>, codec: Codec, path: &Path) -> Result<Box<dyn BufRead + 'a>> {
+    let decoder: Box<dyn Read + 'a> = match codec {
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        #[cfg(not(feature = "gzip"))]
+        Codec::Gzip => return Err(unsupported(codec)),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => Box::new(ZstdDecoder::new(reader)?),
+        #[cfg(not(feature = "zstd"))]
+        Codec::Zstd => return Err(unsupported(codec)),
+    };
+    Ok(Box::new(BufReader::new(Decompressor::new(decoder, codec, path))))
+}
+
+/// The error for a codec this build leaves out
+pub fn unsupported(codec: Codec) -> RsortError {
+    RsortError::Usage(format!(
+        "cannot decompress {} input: rsort was built without the '{}' feature",
+        codec,
+        codec.feature()
+    ))
+}
+
+/// `writer` compressed with `codec`, at `level` or the codec's default
+///
+/// Nothing is complete until `Compressor::finish`.
+// Without any codec feature every arm returns early
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "zstd")),
+    allow(unreachable_code, unused_variables)
+)]
+pub fn compress<'a>(writer: Box<dyn Write + 'a>, codec: Codec, level: Option<u32>) -> Result<Compressor<'a>> {
+    let level = level.unwrap_or(codec.default_level());
+    codec.check_level(level)?;
+    let encoder: Box<dyn Encode + 'a> = match codec {
+        #[cfg(feature = "gzip")]
+        Codec::Gzip => Box::new(flate2::write::GzEncoder::new(writer, flate2::Compression::new(level))),
+        #[cfg(not(feature = "gzip"))]
+        Codec::Gzip => return Err(unsupported(codec)),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => Box::new(zstd::stream::write::Encoder::new(writer, level as i32)?),
+        #[cfg(not(feature = "zstd"))]
+        Codec::Zstd => return Err(unsupported(codec)),
+    };
+    Ok(Compressor { encoder })
+}
+
+/// A compressing writer from `compress`
+pub struct Compressor<'a> {
+    encoder: Box<dyn Encode + 'a>,
+}
+
+impl Compressor<'_> {
+    /// Write the end of the stream and flush the writer underneath
+    pub fn finish(self) -> io::Result<()> {
+        self.encoder.finish_stream()
+    }
+}
+
+impl Write for Compressor<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+/// An encoder that must be told where its stream ends
+trait Encode: Write {
+    fn finish_stream(self: Box<Self>) -> io::Result<()>;
+}
+
+#[cfg(feature = "gzip")]
+impl<W: Write> Encode for flate2::write::GzEncoder<W> {
+    fn finish_stream(self: Box<Self>) -> io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> Encode for zstd::stream::write::Encoder<'_, W> {
+    fn finish_stream(self: Box<Self>) -> io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+/// Read `path` ("-" for stdin) through `program -d` (--decompress-program)
+///
+/// As with GNU sort's `--compress-program`, the program gets `-d`, reads the
+/// file on its stdin and writes the plain text to its stdout. A nonzero exit
+/// is an error once its output has been read.
+pub fn run_program(program: &str, path: &Path) -> Result<Box<dyn BufRead>> {
+    let stdin = if path == Path::new("-") {
+        Stdio::inherit()
+    } else {
+        let file = File::open(platform::long_path(path)).map_err(|source| RsortError::OpenInput {
+            path: path.into(),
+            source,
+        })?;
+        Stdio::from(file)
+    };
+    let mut child = Command::new(program)
+        .arg("-d")
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            RsortError::Usage(format!(
+                "cannot run --decompress-program '{}': {}",
+                program,
+                os_message(&e)
+            ))
+        })?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok(Box::new(BufReader::new(ProgramReader {
+        child,
+        stdout,
+        program: program.to_string(),
+        path: path.to_path_buf(),
+        exited: false,
+    })))
+}
+
+/// The stdout of a `--decompress-program` child
+struct ProgramReader {
+    child: Child,
+    stdout: ChildStdout,
+    program: String,
+    path: PathBuf,
+    /// Reaped, after its output ended
+    exited: bool,
+}
+
+impl Read for ProgramReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() && !self.exited {
+            self.exited = true;
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(RsortError::ChildProcess {
+                    program: self.program.clone(),
+                    path: Some(self.path.clone()),
+                    status,
+                }
+                .into_io());
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for ProgramReader {
+    /// Stop and reap a child whose output was not read to the end
+    fn drop(&mut self) {
+        if !self.exited {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Start `program` to filter the output (--compress-output-program)
+///
+/// What is written to the returned writer goes to the program's stdin; its
+/// stdout is `stdout`, the `-o` file (`path`) or rsort's own stdout. rsort
+/// never reads from the program, so it cannot block on a full pipe.
+pub fn spawn_program(program: &str, stdout: Stdio, path: Option<&Path>) -> Result<ProgramWriter> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .spawn()
+        .map_err(|e| {
+            RsortError::Usage(format!(
+                "cannot run --compress-output-program '{}': {}",
+                program,
+                os_message(&e)
+            ))
+        })?;
+    let stdin = child.stdin.take();
+    Ok(ProgramWriter {
+        child,
+        stdin,
+        program: program.to_string(),
+        path: path.map(Path::to_path_buf),
+    })
+}
+
+/// The stdin of a `--compress-output-program` child
+pub struct ProgramWriter {
+    child: Child,
+    /// None once closed
+    stdin: Option<ChildStdin>,
+    program: String,
+    path: Option<PathBuf>,
+}
+
+impl ProgramWriter {
+    /// Close the program's stdin and wait for it; a nonzero exit is an error
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(mut stdin) = self.stdin.take() {
+            stdin.flush()?;
+        }
+        let status = self.child.wait()?;
+        self.check(status)
+    }
+
+    fn check(&self, status: ExitStatus) -> io::Result<()> {
+        if status.success() {
+            return Ok(());
+        }
+        Err(RsortError::ChildProcess {
+            program: self.program.clone(),
+            path: self.path.clone(),
+            status,
+        }
+        .into_io())
+    }
+
+    /// A write that failed because the program went away reports its exit
+    /// status instead
+    fn failed(&mut self, err: io::Error) -> io::Error {
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            return err;
+        }
+        self.stdin = None;
+        match self.child.wait() {
+            Ok(status) => self.check(status).err().unwrap_or(err),
+            Err(_) => err,
+        }
+    }
+
+    fn stdin(&mut self) -> io::Result<&mut ChildStdin> {
+        self.stdin
+            .as_mut()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for ProgramWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.stdin()?.write(buf);
+        result.map_err(|err| self.failed(err))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.stdin()?.flush();
+        result.map_err(|err| self.failed(err))
+    }
+}
+
+impl Drop for ProgramWriter {
+    /// Stop and reap a program whose output was abandoned
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// A decoder whose read errors become `RsortError::Decompress`
+struct Decompressor<R> {
+    inner: R,
+    codec: Codec,
+    path: PathBuf,
+}
+
+impl<R> Decompressor<R> {
+    fn new(inner: R, codec: Codec, path: &Path) -> Self {
+        Decompressor {
+            inner,
+            codec,
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl<R: Read> Read for Decompressor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|source| match source.kind() {
+            io::ErrorKind::Interrupted => source,
+            _ => RsortError::Decompress {
+                path: self.path.clone(),
+                codec: self.codec,
+                source,
+            }
+            .into_io(),
+        })
+    }
+}
+
+/// Streaming zstd decoder that knows where each frame starts
+///
+/// Frames are decoded one after another, as `zstd -d` does, and errors
+/// give the compressed offset of the frame they hit.
+#[cfg(feature = "zstd")]
+struct ZstdDecoder<R> {
+    inner: R,
+    decoder: zstd::stream::raw::Decoder<'static>,
+    /// Compressed bytes consumed so far
+    consumed: u64,
+    /// Offset of the frame being decoded, None between frames
+    frame_start: Option<u64>,
+}
+
+#[cfg(feature = "zstd")]
+impl<R: BufRead> ZstdDecoder<R> {
+    fn new(inner: R) -> io::Result<Self> {
+        Ok(ZstdDecoder {
+            inner,
+            decoder: zstd::stream::raw::Decoder::new()?,
+            consumed: 0,
+            frame_start: None,
+        })
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: BufRead> Read for ZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let input = self.inner.fill_buf()?;
+            let eof = input.is_empty();
+            if eof && self.frame_start.is_none() {
+                return Ok(0);
+            }
+            let frame_start = *self.frame_start.get_or_insert(self.consumed);
+
+            let mut input = InBuffer::around(input);
+            let mut output = OutBuffer::around(&mut *buf);
+            let remaining = self.decoder.run(&mut input, &mut output).map_err(|e| {
+                io::Error::new(e.kind(), format!("frame at byte {}: {}", frame_start, e))
+            })?;
+            let (read, written) = (input.pos(), output.pos());
+            self.inner.consume(read);
+            self.consumed += read as u64;
+            if remaining == 0 {
+                // Frame fully decoded and flushed; the next byte starts another
+                self.frame_start = None;
+                self.decoder.reinit()?;
+            }
+
+            if written > 0 {
+                return Ok(written);
+            }
+            if eof && self.frame_start.is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("frame at byte {} is truncated", frame_start),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_from_path() {
+        let expected = cfg!(feature = "gzip").then_some(Codec::Gzip);
+        assert_eq!(Codec::from_path(Path::new("data/words.txt.gz")), expected);
+        assert_eq!(Codec::from_path(Path::new("words.txt")), None);
+        assert_eq!(Codec::from_path(Path::new("gz")), None);
+        assert_eq!(Codec::parse("GZIP"), Some(Codec::Gzip));
+        assert_eq!(Codec::parse("xz"), None);
+        assert_eq!(Codec::names(), "gzip or zstd");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_truncated_gzip_names_path() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"b\na\n").unwrap();
+        let mut compressed = encoder.finish().unwrap();
+        compressed.truncate(compressed.len() - 4);
+
+        let reader = Box::new(io::Cursor::new(compressed));
+        let mut reader = decompress(reader, Codec::Gzip, Path::new("words.gz")).unwrap();
+        let err = RsortError::from(reader.read_to_end(&mut Vec::new()).unwrap_err());
+        assert!(matches!(err, RsortError::Decompress { .. }));
+        assert!(err.to_string().starts_with("words.gz: invalid gzip data: "), "{}", err);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_errors_give_frame_offset() {
+        let read = |compressed: Vec<u8>| {
+            let reader = Box::new(io::Cursor::new(compressed));
+            let mut reader = decompress(reader, Codec::Zstd, Path::new("words.zst")).unwrap();
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text).map(|_| text).map_err(RsortError::from)
+        };
+        let first = zstd::encode_all(&b"b\na\n"[..], 3).unwrap();
+        let second = zstd::encode_all(&b"c\n"[..], 3).unwrap();
+
+        let mut frames = first.clone();
+        frames.extend(&second);
+        assert_eq!(read(frames.clone()).unwrap(), b"b\na\nc\n");
+
+        frames.truncate(frames.len() - 2);
+        let err = read(frames).unwrap_err();
+        let expected = format!("words.zst: invalid zstd data: frame at byte {} is truncated", first.len());
+        assert_eq!(err.to_string(), expected);
+
+        let mut garbage = first.clone();
+        garbage.extend(b"not zstd");
+        let err = read(garbage).unwrap_err().to_string();
+        let expected = format!("words.zst: invalid zstd data: frame at byte {}: ", first.len());
+        assert!(err.starts_with(&expected), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_program_exit_status_is_an_error() {
+        let mut text = String::new();
+        let err = RsortError::from(
+            run_program("false", Path::new("/dev/null"))
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap_err(),
+        );
+        assert!(matches!(err, RsortError::ChildProcess { .. }));
+        assert_eq!(err.to_string(), "false failed on /dev/null: exit status: 1");
+
+        let err = run_program("/no/such/program", Path::new("/dev/null")).err().unwrap();
+        assert!(err.to_string().starts_with("cannot run --decompress-program '/no/such/program': "));
+    }
+}
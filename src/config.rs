@@ -1,47 +1,790 @@
+use std::path::{Path, PathBuf};
+
+use crate::check::{CheckFormat, CheckMode};
+#[cfg(feature = "cli")]
 use crate::cli::Args;
-use crate::error::Result;
-use crate::key::KeySpec;
+#[cfg(feature = "collate")]
+use crate::collate::Collation;
+use crate::compare::KeyCompare;
+use crate::compress::{self, Codec, Decompression};
+use crate::debug::DebugFormat;
+use crate::encoding::Encoding;
+use crate::error::{Result, RsortError};
+#[cfg(feature = "regex")]
+use crate::fields::SeparatorRegex;
+use crate::fields::{self, FieldSeparator, Splitter};
+use crate::input::{self, Framing};
+use crate::json::JsonPointer;
+use crate::key::{self, ByteRange, KeyKind, KeyModifiers, KeySpec, SortMode};
+use crate::stats::StatsFormat;
 
 /// Runtime configuration derived from CLI arguments
 #[derive(Clone, Debug)]
 pub struct Config {
     pub reverse: bool,
-    pub numeric: bool,
     pub fold_case: bool,
+    /// -h, -M, -n or -V: how keys without modifiers compare
+    pub sort_mode: SortMode,
     pub unique: bool,
     pub stable: bool,
-    pub debug: bool,
+    /// --debug: annotate each output record with its keys, in this format,
+    /// or explain the order of adjacent records
+    pub debug: Option<DebugFormat>,
+    /// -c / -C: check sortedness instead of sorting
+    pub check: Option<CheckMode>,
+    /// --format: how -c reports disorder
+    pub check_format: CheckFormat,
+    /// --verbose: with --format=json, report a sorted input too
+    pub check_verbose: bool,
+    /// -m: merge already sorted inputs
+    pub merge: bool,
+    /// --max-record-size: longest record accepted, in bytes
+    pub max_record_size: Option<u64>,
+    /// --record-size: records are this many bytes each, with no delimiter
+    pub record_size: Option<usize>,
+    /// --framing: length prefixes instead of delimiters, on input and output
+    pub framing: Framing,
+    /// --take: sort only the first N records read
+    pub take: Option<u64>,
+    /// --comment-char: records starting with this byte are output first, unsorted
+    pub comment_char: Option<u8>,
+    /// --header: leading records output first, as read
+    pub header: usize,
+    /// Byte that ends records; the last byte of a multi-byte delimiter
     pub record_delimiter: u8,
-    pub field_separator: Option<u8>,
+    /// Bytes before `record_delimiter` in a multi-byte delimiter, e.g. the
+    /// `\r` of `\r\n`; empty for a single-byte delimiter
+    pub record_delimiter_prefix: Vec<u8>,
+    /// --output-delimiter: byte written after each output record in place
+    /// of the record delimiter
+    pub output_delimiter: Option<u8>,
+    /// --force: write records containing `output_delimiter` anyway
+    pub force: bool,
+    /// --line-buffered: flush the output after every record
+    pub line_buffered: bool,
+    /// --group: record written between runs of records with equal keys
+    pub group: Option<Vec<u8>>,
+    /// --print-key: output each record's keys in place of the record
+    pub print_key: bool,
+    /// --explain: narrate how the two input records compare instead of
+    /// sorting
+    pub explain: bool,
+    /// --key-output-separator: joins the keys printed with --print-key
+    pub key_output_separator: Vec<u8>,
+    /// --number: put each record's input position and a tab before it
+    pub number: bool,
+    /// --stats: print what the run did to stderr, in this format
+    pub stats: Option<StatsFormat>,
+    /// --verify: read the output file back and check its order
+    pub verify: bool,
+    /// --preserve-final-newline: leave the delimiter off the last output
+    /// record when the input did not end with one (of several inputs, the
+    /// last one holding records)
+    pub preserve_final_newline: bool,
+    /// --crlf: ignore a trailing `\r` when comparing, keeping it in the output
+    pub crlf: bool,
+    /// --ignore-trailing-blanks: leave trailing blanks out of key
+    /// comparisons, but not the last resort
+    pub ignore_trailing_blanks: bool,
+    /// --decompress, --decompress-program: how inputs are decompressed
+    pub decompress: Decompression,
+    /// --encoding: how input files are decoded before records are split
+    pub encoding: Encoding,
+    /// --output-encoding: how the output is written
+    pub output_encoding: Encoding,
+    /// --strip-bom: drop a UTF-8 byte order mark from the start of the first input
+    pub strip_bom: bool,
+    /// --keep-bom: write a BOM dropped by `strip_bom` back ahead of the output
+    pub keep_bom: bool,
+    /// -t: None splits fields on blank runs
+    pub field_separator: Option<FieldSeparator>,
+    /// --csv: split fields as CSV, on `field_separator` or a comma
+    pub csv: bool,
+    /// --field-separator-regex: split fields on matches, instead of -t
+    #[cfg(feature = "regex")]
+    pub field_separator_regex: Option<SeparatorRegex>,
     pub keys: Vec<KeySpec>,
-    pub output_file: Option<String>,
-    pub input_files: Vec<String>,
+    /// --key-name: `NAME[:OPTS]` header columns, keyed after `keys`; see
+    /// `resolve_key_names`
+    pub key_names: Vec<String>,
+    /// --json-key: values in JSON Lines records, keyed after `keys` and
+    /// `key_names` under the global options
+    pub json_keys: Vec<JsonPointer>,
+    /// --key-bytes: byte positions, keyed after `json_keys` under the global
+    /// options
+    pub key_bytes: Vec<ByteRange>,
+    /// --locale: collation for text keys, in place of bytewise order
+    #[cfg(feature = "collate")]
+    pub locale: Option<Collation>,
+    /// Library-only replacement for the built-in key comparison
+    pub key_compare: Option<KeyCompare>,
+    /// --key-command: shell command printing a key line for each record,
+    /// compared in the record's place
+    pub key_command: Option<String>,
+    pub output_file: Option<PathBuf>,
+    /// Compress an output file named `*.gz` or `*.zst`; off with
+    /// --no-auto-compress
+    pub auto_compress: bool,
+    /// --compress-level: None for the codec's default
+    pub compress_level: Option<u32>,
+    /// --compress-output-program: pipe the output through this program
+    pub compress_output_program: Option<String>,
+    /// --append: add to the end of the output file instead of truncating it
+    pub append: bool,
+    /// --fsync: sync the output file to disk before exiting
+    pub fsync: bool,
+    /// --tee: files that also get a copy of the output
+    pub tee: Vec<PathBuf>,
+    /// --split-output: write this many files instead of one
+    pub split_output: Option<usize>,
+    /// --output-prefix: the --split-output files are this with `.000`,
+    /// `.001`, ... added
+    pub output_prefix: Option<PathBuf>,
+    /// --split-by-key: never divide records with equal keys between two
+    /// --split-output files
+    pub split_by_key: bool,
+    pub input_files: Vec<PathBuf>,
+}
+
+impl Default for Config {
+    /// GNU sort with no options: whole-line bytewise, newline-delimited, stdin to stdout
+    fn default() -> Self {
+        Config {
+            reverse: false,
+            fold_case: false,
+            sort_mode: SortMode::Text,
+            unique: false,
+            stable: false,
+            debug: None,
+            check: None,
+            check_format: CheckFormat::Text,
+            check_verbose: false,
+            merge: false,
+            max_record_size: None,
+            record_size: None,
+            framing: Framing::Delimited,
+            take: None,
+            comment_char: None,
+            header: 0,
+            record_delimiter: b'\n',
+            record_delimiter_prefix: Vec::new(),
+            output_delimiter: None,
+            force: false,
+            preserve_final_newline: false,
+            line_buffered: false,
+            group: None,
+            print_key: false,
+            explain: false,
+            key_output_separator: b"\t".to_vec(),
+            number: false,
+            stats: None,
+            verify: false,
+            crlf: false,
+            ignore_trailing_blanks: false,
+            decompress: Decompression::Auto,
+            encoding: Encoding::Utf8,
+            output_encoding: Encoding::Utf8,
+            strip_bom: false,
+            keep_bom: false,
+            field_separator: None,
+            csv: false,
+            #[cfg(feature = "regex")]
+            field_separator_regex: None,
+            keys: Vec::new(),
+            key_names: Vec::new(),
+            json_keys: Vec::new(),
+            key_bytes: Vec::new(),
+            #[cfg(feature = "collate")]
+            locale: None,
+            key_compare: None,
+            key_command: None,
+            output_file: None,
+            auto_compress: true,
+            compress_level: None,
+            compress_output_program: None,
+            append: false,
+            fsync: false,
+            tee: Vec::new(),
+            split_output: None,
+            output_prefix: None,
+            split_by_key: false,
+            input_files: Vec::new(),
+        }
+    }
 }
 
 impl Config {
+    /// Start building a configuration from the defaults
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
     /// Build configuration from parsed CLI arguments
+    ///
+    /// With the `profile` feature, options from `--profile FILE` (or
+    /// `./.rsort.toml`) are applied first and the command line overrides them.
+    #[cfg(feature = "cli")]
     pub fn from_args(args: &Args) -> Result<Self> {
-        let keys: Result<Vec<KeySpec>> = args
-            .keys
+        #[cfg(feature = "profile")]
+        let builder = match crate::profile::Profile::discover(args.profile.as_deref())? {
+            Some(profile) => profile.apply(Config::builder())?,
+            None => Config::builder(),
+        };
+        #[cfg(not(feature = "profile"))]
+        let builder = Config::builder();
+
+        builder.args(args)?.build()
+    }
+
+    /// Check cross-field invariants
+    ///
+    /// Shared by `ConfigBuilder::build` and `Config::from_args`.
+    pub fn validate(&self) -> Result<()> {
+        let separator = self.field_separator.as_ref().map(FieldSeparator::as_bytes);
+        if separator == Some(&[]) {
+            return Err(RsortError::Usage("empty field separator".to_string()));
+        }
+        let separator = separator.unwrap_or_default();
+        if separator.contains(&self.record_delimiter) && self.record_size.is_none() {
+            // The separator could never occur inside a record
+            let hint = if self.record_delimiter == b'\n' {
+                "; use -z if records are NUL-terminated and newlines separate fields"
+            } else {
+                ""
+            };
+            return Err(RsortError::Usage(format!(
+                "field separator is the same as the record delimiter{}",
+                hint
+            )));
+        }
+        #[cfg(feature = "regex")]
+        if self.field_separator_regex.is_some() && (self.field_separator.is_some() || self.csv) {
+            let other = if self.csv { "--csv" } else { "-t" };
+            return Err(RsortError::Usage(format!(
+                "options '--field-separator-regex' and '{}' are incompatible",
+                other
+            )));
+        }
+        let terminator = self.record_terminator();
+        if self.record_size == Some(0) {
+            return Err(RsortError::Usage("invalid --record-size argument '0'".to_string()));
+        }
+        if let Some(option) = self.undelimited_option() {
+            let other = match &terminator[..] {
+                _ if self.record_size.is_some() && self.framing != Framing::Delimited => Some("--framing"),
+                _ if self.crlf => Some("--crlf"),
+                _ if self.framing != Framing::Delimited && self.output_delimiter.is_some() => {
+                    Some("--output-delimiter")
+                }
+                _ if self.framing != Framing::Delimited && self.debug.is_some_and(DebugFormat::annotates) => {
+                    Some("--debug")
+                }
+                b"\n" => None,
+                b"\0" => Some("-z"),
+                _ => Some("--record-delimiter"),
+            };
+            if let Some(other) = other {
+                return Err(RsortError::Usage(format!(
+                    "options '{}' and '{}' are incompatible",
+                    option, other
+                )));
+            }
+        }
+        if self.crlf && terminator != b"\n" {
+            let option = if terminator == b"\0" { "-z" } else { "--record-delimiter" };
+            return Err(RsortError::Usage(format!(
+                "options '{}' and '--crlf' are incompatible",
+                option
+            )));
+        }
+        match &self.decompress {
+            Decompression::Codec(codec) if !codec.is_supported() => {
+                return Err(compress::unsupported(*codec));
+            }
+            Decompression::Program(program) if program.is_empty() => {
+                return Err(RsortError::Usage("empty --decompress-program".to_string()));
+            }
+            _ => {}
+        }
+        if self.compress_output_program.as_deref() == Some("") {
+            return Err(RsortError::Usage("empty --compress-output-program".to_string()));
+        }
+        if let Some(level) = self.compress_level {
+            for path in self.output_file.iter().chain(&self.tee) {
+                if let Some(codec) = self.output_codec(path) {
+                    codec.check_level(level)?;
+                }
+            }
+        }
+        if self.csv && separator.contains(&b'"') {
+            return Err(RsortError::Usage("--csv field separator cannot be '\"'".to_string()));
+        }
+        crate::input::check_stdin_once(&self.input_files)?;
+        if !self.key_names.is_empty()
+            && (self.header == 0 || Splitter::from_config(self) == Splitter::Blanks)
+        {
+            return Err(RsortError::Usage(
+                "--key-name requires --header and -t or --csv".to_string(),
+            ));
+        }
+        if self.check.is_some() || self.merge {
+            let mode = if self.merge { 'm' } else { 'c' };
+            for (set, option) in [
+                (self.take.is_some(), "--take"),
+                (self.comment_char.is_some(), "--comment-char"),
+                (self.header > 0, "--header"),
+                (self.preserve_final_newline, "--preserve-final-newline"),
+                (self.split_output.is_some(), "--split-output"),
+                (self.number, "--number"),
+                (self.stats.is_some(), "--stats"),
+            ] {
+                if set {
+                    return Err(RsortError::Usage(format!(
+                        "options '-{}' and '{}' are incompatible",
+                        mode, option
+                    )));
+                }
+            }
+        }
+        if self.verify {
+            // Output that is not the sorted records themselves
+            for (set, option) in [
+                (self.check.is_some(), "-c"),
+                (self.append, "--append"),
+                (self.compress_output_program.is_some(), "--compress-output-program"),
+                (self.output_delimiter.is_some(), "--output-delimiter"),
+                (self.header > 0, "--header"),
+                (self.comment_char.is_some(), "--comment-char"),
+                (self.group.is_some(), "--group"),
+                (self.print_key, "--print-key"),
+                (self.number, "--number"),
+                (self.debug.is_some_and(DebugFormat::annotates), "--debug"),
+            ] {
+                if set {
+                    return Err(RsortError::Usage(format!(
+                        "options '--verify' and '{}' are incompatible",
+                        option
+                    )));
+                }
+            }
+        }
+        if self.debug.is_some_and(DebugFormat::annotates) {
+            // Annotations the key marks would not line up under, or
+            // records of their own
+            for (set, option) in [
+                (self.print_key, "--print-key"),
+                (self.number, "--number"),
+                (self.output_delimiter.is_some(), "--output-delimiter"),
+            ] {
+                if set {
+                    return Err(RsortError::Usage(format!(
+                        "options '--debug' and '{}' are incompatible",
+                        option
+                    )));
+                }
+            }
+        }
+        if self.explain {
+            // Two records in, a narration out
+            for (set, option) in [
+                (self.check.is_some(), "-c"),
+                (self.merge, "-m"),
+                (self.header > 0, "--header"),
+                (self.output_file.is_some(), "-o"),
+                (self.debug.is_some(), "--debug"),
+            ] {
+                if set {
+                    return Err(RsortError::Usage(format!(
+                        "options '--explain' and '{}' are incompatible",
+                        option
+                    )));
+                }
+            }
+        }
+        if self.key_command.as_deref() == Some("") {
+            return Err(RsortError::Usage("empty --key-command".to_string()));
+        }
+        if self.key_command.is_some() {
+            // Keys exist only for the records being sorted, and only as
+            // they are sorted
+            for (set, option) in [
+                (self.check.is_some(), "-c"),
+                (self.merge, "-m"),
+                (self.explain, "--explain"),
+                (self.debug.is_some(), "--debug"),
+                (self.print_key, "--print-key"),
+                (self.group.is_some(), "--group"),
+                (self.split_by_key, "--split-by-key"),
+                (self.record_size.is_some(), "--record-size"),
+                (self.framing != Framing::Delimited, "--framing"),
+            ] {
+                if set {
+                    return Err(RsortError::Usage(format!(
+                        "options '--key-command' and '{}' are incompatible",
+                        option
+                    )));
+                }
+            }
+        }
+        if self.check_verbose && self.check_format != CheckFormat::Json {
+            return Err(RsortError::Usage("--verbose requires --format=json".to_string()));
+        }
+        if self.unique && self.group.is_some() {
+            return Err(RsortError::Usage("options '-u' and '--group' are incompatible".to_string()));
+        }
+        if let Some(chunks) = self.split_output {
+            if chunks == 0 {
+                return Err(RsortError::Usage("invalid --split-output argument '0'".to_string()));
+            }
+            if self.output_prefix.is_none() {
+                return Err(RsortError::Usage("--split-output requires --output-prefix".to_string()));
+            }
+            for (set, option) in [
+                (self.output_file.is_some(), "-o"),
+                (!self.tee.is_empty(), "--tee"),
+                (self.group.is_some(), "--group"),
+            ] {
+                if set {
+                    return Err(RsortError::Usage(format!(
+                        "options '--split-output' and '{}' are incompatible",
+                        option
+                    )));
+                }
+            }
+        }
+        if self.check.is_some() {
+            if self.output_file.is_some() {
+                return Err(RsortError::Usage("options '-co' are incompatible".to_string()));
+            }
+            if !self.tee.is_empty() {
+                return Err(RsortError::Usage("options '-c' and '--tee' are incompatible".to_string()));
+            }
+            if let Some(extra) = self.input_files.get(1) {
+                return Err(RsortError::Usage(format!(
+                    "extra operand '{}' not allowed with -c",
+                    extra.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Likely mistakes in the options, as GNU sort warns of with --debug
+    ///
+    /// Nothing here is an error: each message describes options that are
+    /// accepted but do not do what they seem to, phrased like GNU sort's
+    /// where it has the same warning.
+    pub fn lint(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let has_keys = !self.keys.is_empty() || !self.key_names.is_empty();
+
+        // Fields only matter to keys
+        #[cfg(feature = "regex")]
+        let regex = self.field_separator_regex.is_some();
+        #[cfg(not(feature = "regex"))]
+        let regex = false;
+        for (set, option) in [
+            (self.field_separator.is_some(), "-t"),
+            (self.csv, "--csv"),
+            (regex, "--field-separator-regex"),
+        ] {
+            if set && !has_keys {
+                warnings.push(format!("option '{}' is ignored without any key", option));
+            }
+        }
+
+        let separator = self.field_separator.as_ref().map(FieldSeparator::as_bytes);
+        let mut unused = self.modifiers();
+        let mut numeric_span = false;
+        for (keynum, spec) in (1..).zip(&self.keys) {
+            let modifiers = self.key_modifiers(spec);
+            if matches!(modifiers.key_kind(), KeyKind::Numeric | KeyKind::Human)
+                && spec.end_field.is_none_or(|end| end > spec.start_field)
+            {
+                warnings.push(format!("key {} is numeric and spans multiple fields", keynum));
+                numeric_span = true;
+            }
+            unused.fold_case &= !modifiers.fold_case;
+            if modifiers.mode == unused.mode {
+                unused.mode = SortMode::Text;
+            }
+            unused.reverse &= !modifiers.reverse;
+        }
+
+        // The separators inside such a key are read as part of the number
+        match separator {
+            Some(b".") if numeric_span => {
+                warnings.push("field separator '.' is treated as a decimal point in numbers".to_string())
+            }
+            Some(b"-") if numeric_span => {
+                warnings.push("field separator '-' is treated as a minus sign in numbers".to_string())
+            }
+            _ => {}
+        }
+
+        // Global options every key overrides with modifiers of its own;
+        // -r still reverses the last resort unless -s or -u drop it. A
+        // --json-key or --key-bytes has no modifiers, so it always uses them.
+        if !self.keys.is_empty() && self.json_keys.is_empty() && self.key_bytes.is_empty() {
+            let reverse_ignored = unused.reverse && !self.use_last_resort();
+            let ignored: String = [
+                (unused.fold_case, 'f'),
+                (unused.mode == SortMode::HumanNumeric, 'h'),
+                (unused.mode == SortMode::Month, 'M'),
+                (unused.mode == SortMode::Numeric, 'n'),
+                (reverse_ignored, 'r'),
+                (unused.mode == SortMode::Version, 'V'),
+            ]
             .iter()
-            .map(|s| KeySpec::parse(s))
+            .filter(|(set, _)| *set)
+            .map(|&(_, letter)| letter)
             .collect();
+            match ignored.len() {
+                0 => {}
+                1 => warnings.push(format!("option '-{}' is ignored", ignored)),
+                _ => warnings.push(format!("options '-{}' are ignored", ignored)),
+            }
+            if unused.reverse && self.use_last_resort() {
+                warnings.push("option '-r' only applies to last-resort comparison".to_string());
+            }
+        }
+        warnings
+    }
 
-        Ok(Config {
-            reverse: args.reverse,
-            numeric: args.numeric,
-            fold_case: args.fold_case,
-            unique: args.unique,
-            stable: args.stable,
-            debug: args.debug,
-            record_delimiter: args.record_delimiter(),
-            field_separator: args.field_separator()?,
-            keys: keys?,
-            output_file: args.output.clone(),
-            input_files: args.files.clone(),
+    /// What the options resolved to, as --debug reports it before sorting
+    ///
+    /// One line each for the text ordering (worded as in GNU sort), record
+    /// delimiter, field separator, each key with the modifiers it is
+    /// compared under, the last resort, and stability.
+    pub fn describe(&self) -> String {
+        let quoted = |bytes: &[u8]| format!("'{}'", crate::debug::escaped(bytes));
+        let mut lines = Vec::new();
+
+        #[cfg(feature = "collate")]
+        let collation = self.locale.as_ref().map(Collation::tag);
+        #[cfg(not(feature = "collate"))]
+        let collation: Option<&str> = None;
+        lines.push(match collation {
+            _ if self.key_compare.is_some() => "text ordering performed using a custom comparison".to_string(),
+            Some(tag) => format!("text ordering performed using '{}' collation rules", tag),
+            None => "text ordering performed using simple byte comparison".to_string(),
+        });
+
+        lines.push(match (self.record_size, self.framing) {
+            (Some(size), _) => format!("record delimiter: none, {}-byte records", size),
+            (None, Framing::Delimited) => format!("record delimiter: {}", quoted(&self.record_terminator())),
+            (None, framing) => format!("record delimiter: none, {} framing", framing.name()),
+        });
+        #[cfg(feature = "regex")]
+        let regex = self.field_separator_regex.as_ref().map(SeparatorRegex::as_str);
+        #[cfg(not(feature = "regex"))]
+        let regex: Option<&str> = None;
+        let separator = self.field_separator.as_ref().map(FieldSeparator::as_bytes);
+        lines.push(match (regex, separator) {
+            (Some(pattern), _) => format!("field separator: regex '{}'", pattern),
+            (None, separator) if self.csv => format!("field separator: CSV on {}", quoted(separator.unwrap_or(b","))),
+            (None, Some(separator)) => format!("field separator: {}", quoted(separator)),
+            (None, None) => "field separator: whitespace".to_string(),
+        });
+
+        if let Some(command) = &self.key_command {
+            lines.push(format!("keys: the lines --key-command '{}' prints, in place of the records", command));
+        }
+        let modifiers = self.modifiers();
+        let keyless = self.keys.is_empty() && self.key_names.is_empty();
+        if keyless && self.json_keys.is_empty() && self.key_bytes.is_empty() {
+            let options = KeyModifiers { reverse: false, ..modifiers };
+            if options.is_empty() {
+                lines.push("key: whole line".to_string());
+            } else {
+                lines.push(format!("key: whole line, -{}", options));
+            }
+        }
+        for (keynum, spec) in (1..).zip(&self.keys) {
+            let resolved = KeySpec {
+                modifiers: self.key_modifiers(spec),
+                ..spec.clone()
+            };
+            let inherited = if spec.modifiers.is_empty() && !modifiers.is_empty() {
+                " (global options)"
+            } else {
+                ""
+            };
+            lines.push(format!("key {}: -k {}{}", keynum, resolved, inherited));
+        }
+        for (keynum, name) in (self.keys.len() + 1..).zip(&self.key_names) {
+            lines.push(format!("key {}: column {}", keynum, name));
+        }
+        let named = self.keys.len() + self.key_names.len();
+        for (keynum, pointer) in (named + 1..).zip(&self.json_keys) {
+            if modifiers.is_empty() {
+                lines.push(format!("key {}: JSON value at '{}'", keynum, pointer));
+            } else {
+                lines.push(format!("key {}: JSON value at '{}', -{}", keynum, pointer, modifiers));
+            }
+        }
+        for (keynum, range) in (named + self.json_keys.len() + 1..).zip(&self.key_bytes) {
+            if modifiers.is_empty() {
+                lines.push(format!("key {}: bytes {}", keynum, range));
+            } else {
+                lines.push(format!("key {}: bytes {}, -{}", keynum, range, modifiers));
+            }
+        }
+
+        lines.push(match (self.unique, self.stable) {
+            (true, _) => "last-resort comparison: off (-u)".to_string(),
+            (false, true) => "last-resort comparison: off (-s)".to_string(),
+            (false, false) if self.reverse => "last-resort comparison: whole line, bytewise, reversed".to_string(),
+            (false, false) => "last-resort comparison: whole line, bytewise".to_string(),
+        });
+        lines.push(if self.use_stable_sort() {
+            "stable: yes, equal records keep their input order".to_string()
+        } else {
+            "stable: no, the last resort orders equal keys".to_string()
+        });
+        lines.join("\n")
+    }
+
+    /// The modifiers `spec` is compared under: its own, or else the global
+    /// ones, as in GNU sort
+    pub fn key_modifiers(&self, spec: &KeySpec) -> KeyModifiers {
+        if spec.modifiers.is_empty() {
+            self.modifiers()
+        } else {
+            spec.modifiers
+        }
+    }
+
+    /// The ordering keys without their own modifiers are compared under
+    pub fn key_kind(&self) -> KeyKind {
+        self.modifiers().key_kind()
+    }
+
+    /// The global ordering options, as inherited by keys without modifiers
+    pub fn modifiers(&self) -> KeyModifiers {
+        KeyModifiers {
+            blanks: false,
+            fold_case: self.fold_case,
+            mode: self.sort_mode,
+            reverse: self.reverse,
+        }
+    }
+
+    /// Turn `key_names` into keys using the header line, the first of `records`
+    ///
+    /// Returns None when there is nothing to resolve. Column names match
+    /// exactly, with surrounding double quotes removed; failing that, a
+    /// single ASCII case-insensitive match is used. The keys follow any -k
+    /// keys, in the order the names were given.
+    pub fn resolve_key_names<T: AsRef<[u8]>>(&self, records: &[T]) -> Result<Option<Config>> {
+        let header = match records.first() {
+            Some(header) if !self.key_names.is_empty() && self.header > 0 => header.as_ref(),
+            _ => return Ok(None),
+        };
+        let header = if self.crlf { input::strip_cr(header) } else { header };
+        let columns: Vec<Vec<u8>> = match Splitter::from_config(self) {
+            Splitter::Csv(separator) => fields::split_csv_fields(header, &separator)
+                .map(|field| unquote(&header[field.raw.0..field.raw.1]))
+                .collect(),
+            splitter => splitter
+                .spans(header)
+                .map(|(start, end)| unquote(&header[start..end]))
+                .collect(),
+        };
+
+        let mut resolved = self.clone();
+        for key_name in resolved.key_names.drain(..) {
+            // "NAME:nr"; a suffix that isn't modifiers is part of the name
+            let (name, modifiers) = match key_name.rsplit_once(':') {
+                Some((name, letters)) => match KeyModifiers::parse(letters) {
+                    Ok(modifiers) => (name, modifiers),
+                    Err(_) => (key_name.as_str(), KeyModifiers::default()),
+                },
+                None => (key_name.as_str(), KeyModifiers::default()),
+            };
+
+            let exact = columns.iter().position(|column| column == name.as_bytes());
+            let folded = || {
+                let mut matches = columns
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, column)| column.eq_ignore_ascii_case(name.as_bytes()));
+                match (matches.next(), matches.next()) {
+                    (Some((index, _)), None) => Some(index),
+                    _ => None,
+                }
+            };
+            let index = exact.or_else(folded).ok_or_else(|| {
+                let available: Vec<_> = columns.iter().map(|c| crate::debug::escaped(c)).collect();
+                RsortError::Usage(format!(
+                    "--key-name '{}': no such column in header (columns: {})",
+                    name,
+                    available.join(", ")
+                ))
+            })?;
+
+            let key = KeySpec::builder()
+                .start_field(index + 1)
+                .end_field(index + 1)
+                .modifiers(modifiers)
+                .build()?;
+            resolved.keys.push(key);
+        }
+        Ok(Some(resolved))
+    }
+
+    /// The full record delimiter, as written after each output record
+    pub fn record_terminator(&self) -> Vec<u8> {
+        let mut terminator = self.record_delimiter_prefix.clone();
+        terminator.push(self.record_delimiter);
+        terminator
+    }
+
+    /// The option reading records without a delimiter, if any: --record-size
+    /// or --framing
+    fn undelimited_option(&self) -> Option<&'static str> {
+        if self.record_size.is_some() {
+            Some("--record-size")
+        } else if self.framing != Framing::Delimited {
+            Some("--framing")
+        } else {
+            None
+        }
+    }
+
+    /// The delimiter written after each output record: `output_delimiter`,
+    /// or else the record delimiter
+    ///
+    /// Always a newline when --debug annotates the records, which are lines
+    /// whatever the input's delimiter, as in GNU sort. Nothing with
+    /// `record_size` or `framing` and no `output_delimiter`: fixed-size
+    /// records are written back to back, as read, and framed ones after
+    /// their length prefix.
+    pub fn output_terminator(&self) -> Vec<u8> {
+        match self.output_delimiter {
+            _ if self.debug.is_some_and(DebugFormat::annotates) => vec![b'\n'],
+            Some(delimiter) => vec![delimiter],
+            None if self.undelimited_option().is_some() => Vec::new(),
+            None => self.record_terminator(),
+        }
+    }
+
+    /// The byte no output record may contain: an `output_delimiter` other
+    /// than the record delimiter, or any with `record_size`, unless `force`
+    /// (`framing` rules out an `output_delimiter`)
+    pub fn forbidden_output_byte(&self) -> Option<u8> {
+        self.output_delimiter.filter(|&delimiter| {
+            !self.force && (self.undelimited_option().is_some() || [delimiter] != *self.record_terminator())
         })
     }
 
+    /// Whether the last output record gets a delimiter, given whether the
+    /// input ended with one: always, as in GNU sort, unless
+    /// `preserve_final_newline`
+    pub fn final_delimiter(&self, input_had_one: bool) -> bool {
+        input_had_one || !self.preserve_final_newline
+    }
+
     /// Whether last-resort comparison is enabled
     pub fn use_last_resort(&self) -> bool {
         !self.stable && !self.unique
@@ -51,4 +794,1559 @@ impl Config {
     pub fn use_stable_sort(&self) -> bool {
         self.stable || self.unique
     }
+
+    /// Codec an output file at `path` is compressed with, if any
+    ///
+    /// None with --compress-output-program, which does the compressing.
+    pub fn output_codec(&self, path: &Path) -> Option<Codec> {
+        let auto = self.auto_compress && self.compress_output_program.is_none();
+        auto.then(|| Codec::from_path(path)).flatten()
+    }
+}
+
+/// A CSV-style column name without its surrounding quotes, `""` unescaped
+fn unquote(column: &[u8]) -> Vec<u8> {
+    match column.strip_prefix(b"\"").and_then(|c| c.strip_suffix(b"\"")) {
+        Some(inner) => {
+            let mut name = Vec::with_capacity(inner.len());
+            let mut bytes = inner.iter();
+            while let Some(&b) = bytes.next() {
+                name.push(b);
+                if b == b'"' {
+                    // Skip the second quote of a doubled pair
+                    bytes.next();
+                }
+            }
+            name
+        }
+        None => column.to_vec(),
+    }
+}
+
+/// Parse a -t argument: one character, or a backslash escape such as `\t` or `\0`
+///
+/// A non-ASCII character separates fields by its UTF-8 encoding.
+pub fn parse_field_separator(s: &str) -> Result<FieldSeparator> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Err(RsortError::Usage("empty tab".to_string())),
+        (Some(c), None) => Ok(FieldSeparator::from(c)),
+        (Some('\\'), Some(_)) => parse_separator(s).map(FieldSeparator::Byte),
+        _ => Err(RsortError::Usage(format!("multi-character tab '{}'", s))),
+    }
+}
+
+/// Parse a single-byte argument: one byte, or a backslash escape
+///
+/// Escapes are `\t`, `\n`, `\r`, `\v`, `\f`, `\\`, `\xHH` (two hex digits)
+/// and `\NNN` (one to three octal digits, at most `\377`), so `\0` is NUL
+/// and `\x1f` or `\037` is the ASCII unit separator. Every option taking a
+/// byte (-t, --comment-char, ...) goes through here.
+pub fn parse_separator(s: &str) -> Result<u8> {
+    if s.len() == 1 {
+        return Ok(s.as_bytes()[0]);
+    }
+    let Some(escape) = s.strip_prefix('\\') else {
+        return Err(RsortError::InvalidDelimiter);
+    };
+    parse_escape(escape, s)
+}
+
+/// Parse a string of characters and escapes (as in `parse_separator`) into bytes
+///
+/// Used where several bytes are allowed, e.g. `--record-delimiter='\r\n'`.
+pub fn parse_escapes(s: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut rest = s;
+    while let Some(pos) = rest.find('\\') {
+        bytes.extend_from_slice(&rest.as_bytes()[..pos]);
+        let escape = &rest[pos + 1..];
+        // \xHH and \NNN are the only escapes longer than one character
+        let len = match escape.as_bytes() {
+            [] => {
+                // A trailing backslash stands for itself
+                bytes.push(b'\\');
+                return Ok(bytes);
+            }
+            [b'x', ..] => 1 + escape[1..].bytes().take(2).take_while(u8::is_ascii_hexdigit).count(),
+            _ => escape.bytes().take(3).take_while(|b| (b'0'..=b'7').contains(b)).count().max(1),
+        };
+        let len = escape.char_indices().nth(len).map_or(escape.len(), |(i, _)| i);
+        bytes.push(parse_escape(&escape[..len], &rest[pos..pos + 1 + len])?);
+        rest = &escape[len..];
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+    Ok(bytes)
+}
+
+/// Byte for `escape`, the text after a backslash; errors quote `whole`
+fn parse_escape(escape: &str, whole: &str) -> Result<u8> {
+    let invalid =
+        |reason: &str| RsortError::Usage(format!("invalid escape '{}'; {}", whole, reason));
+
+    match escape.as_bytes() {
+        b"t" => Ok(b'\t'),
+        b"n" => Ok(b'\n'),
+        b"r" => Ok(b'\r'),
+        b"v" => Ok(b'\x0B'), // vertical tab
+        b"f" => Ok(b'\x0C'), // form feed
+        b"\\" => Ok(b'\\'),
+        [b'x', hex @ ..] => match hex {
+            [hi, lo] if hi.is_ascii_hexdigit() && lo.is_ascii_hexdigit() => {
+                Ok(u8::from_str_radix(&escape[1..], 16).expect("two hex digits"))
+            }
+            _ => Err(invalid("\\x takes exactly two hex digits")),
+        },
+        octal if octal.iter().all(|b| (b'0'..=b'7').contains(b)) => {
+            if octal.len() > 3 {
+                return Err(invalid("octal escapes take at most three digits"));
+            }
+            u8::try_from(u32::from_str_radix(escape, 8).expect("octal digits"))
+                .map_err(|_| invalid("octal escapes go up to \\377"))
+        }
+        _ => Err(invalid("expected \\t, \\n, \\r, \\v, \\f, \\\\, \\xHH or octal \\NNN")),
+    }
+}
+
+/// Parse a size such as `64K` or `2G` into bytes
+///
+/// Suffixes are GNU sort's -S units: `b` for bytes, then `K`, `M`, `G`, `T`,
+/// `P`, `E`, `Z`, `Y` in powers of 1024. Unlike -S, a bare number is bytes.
+/// Returns None for malformed or overflowing sizes.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number: u64 = number.parse().ok()?;
+    let power = match suffix {
+        "" | "b" => 0,
+        "K" | "k" => 1,
+        "M" | "m" => 2,
+        "G" | "g" => 3,
+        "T" | "t" => 4,
+        "P" | "p" => 5,
+        "E" | "e" => 6,
+        "Z" | "z" => 7,
+        "Y" | "y" => 8,
+        _ => return None,
+    };
+    number.checked_mul(1024u64.checked_pow(power)?)
+}
+
+/// Builder for `Config` with validation on `build`
+///
+/// ```
+/// use rsort::Config;
+///
+/// let config = Config::builder().numeric(true).add_key("2,2").build()?;
+/// assert_eq!(config.sort_mode, rsort::key::SortMode::Numeric);
+/// # Ok::<(), rsort::RsortError>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+    /// First keydef or regex error, reported by `build`
+    error: Option<RsortError>,
+    /// Each of -h, -M, -n and -V switched on; `build` rejects more than one
+    modes: Vec<SortMode>,
+}
+
+impl ConfigBuilder {
+    /// Reverse the result of comparisons (-r)
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.config.reverse = reverse;
+        self
+    }
+
+    /// Compare according to numerical value (-n)
+    pub fn numeric(self, numeric: bool) -> Self {
+        self.sort_mode(SortMode::Numeric, numeric)
+    }
+
+    /// Fold lower case to upper case (-f)
+    pub fn fold_case(mut self, fold_case: bool) -> Self {
+        self.config.fold_case = fold_case;
+        self
+    }
+
+    /// Compare numbers with SI suffixes such as 2K or 1.5G (-h)
+    pub fn human_numeric(self, human_numeric: bool) -> Self {
+        self.sort_mode(SortMode::HumanNumeric, human_numeric)
+    }
+
+    /// Compare month abbreviations, unknown < JAN < ... < DEC (-M)
+    pub fn month(self, month: bool) -> Self {
+        self.sort_mode(SortMode::Month, month)
+    }
+
+    /// Compare version strings (-V)
+    pub fn version(self, version: bool) -> Self {
+        self.sort_mode(SortMode::Version, version)
+    }
+
+    /// Switch one of -h, -M, -n and -V on or off
+    fn sort_mode(mut self, mode: SortMode, on: bool) -> Self {
+        self.modes.retain(|&m| m != mode);
+        if on {
+            self.modes.push(mode);
+        }
+        self
+    }
+
+    /// Output only the first of each run of equal records (-u)
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.config.unique = unique;
+        self
+    }
+
+    /// Disable last-resort comparison (-s)
+    pub fn stable(mut self, stable: bool) -> Self {
+        self.config.stable = stable;
+        self
+    }
+
+    /// Annotate key spans (--debug)
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.config.debug = debug.then_some(DebugFormat::Text);
+        self
+    }
+
+    /// Annotate key spans in `format` (--debug=FORMAT)
+    pub fn debug_format(mut self, format: DebugFormat) -> Self {
+        self.config.debug = Some(format);
+        self
+    }
+
+    /// Check sortedness instead of sorting (-c / -C)
+    pub fn check(mut self, mode: impl Into<Option<CheckMode>>) -> Self {
+        self.config.check = mode.into();
+        self
+    }
+
+    /// Report disorder found by -c in `format` (--format)
+    pub fn check_format(mut self, format: CheckFormat) -> Self {
+        self.config.check_format = format;
+        self
+    }
+
+    /// Report a sorted input too, with --format=json (--verbose)
+    pub fn check_verbose(mut self, verbose: bool) -> Self {
+        self.config.check_verbose = verbose;
+        self
+    }
+
+    /// Merge already sorted inputs instead of sorting (-m)
+    pub fn merge(mut self, merge: bool) -> Self {
+        self.config.merge = merge;
+        self
+    }
+
+    /// Fail on records longer than `limit` bytes (--max-record-size)
+    pub fn max_record_size(mut self, limit: impl Into<Option<u64>>) -> Self {
+        self.config.max_record_size = limit.into();
+        self
+    }
+
+    /// Read records of exactly `size` bytes, with no delimiter (--record-size)
+    pub fn record_size(mut self, size: impl Into<Option<usize>>) -> Self {
+        self.config.record_size = size.into();
+        self
+    }
+
+    /// Frame records with length prefixes instead of delimiters, on input
+    /// and output (--framing)
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.config.framing = framing;
+        self
+    }
+
+    /// Sort only the first `count` records read, across all inputs (--take)
+    pub fn take(mut self, count: impl Into<Option<u64>>) -> Self {
+        self.config.take = count.into();
+        self
+    }
+
+    /// Output records starting with `c` first, in input order (--comment-char)
+    pub fn comment_char(mut self, c: impl Into<Option<u8>>) -> Self {
+        self.config.comment_char = c.into();
+        self
+    }
+
+    /// Output the first `lines` records first, unsorted (--header)
+    ///
+    /// They come from the start of the concatenated input, and count
+    /// towards `take`.
+    pub fn header(mut self, lines: usize) -> Self {
+        self.config.header = lines;
+        self
+    }
+
+    /// Byte that terminates records (newline by default, NUL for -z)
+    pub fn record_delimiter(mut self, delimiter: u8) -> Self {
+        self.config.record_delimiter = delimiter;
+        self.config.record_delimiter_prefix.clear();
+        self
+    }
+
+    /// Byte string that terminates records, e.g. `b"\r\n"` (--record-delimiter)
+    ///
+    /// An empty delimiter is reported by `build`.
+    pub fn record_delimiter_bytes(mut self, delimiter: impl AsRef<[u8]>) -> Self {
+        match delimiter.as_ref().split_last() {
+            Some((&last, prefix)) => {
+                self.config.record_delimiter = last;
+                self.config.record_delimiter_prefix = prefix.to_vec();
+            }
+            None => {
+                self.error
+                    .get_or_insert(RsortError::Usage("empty record delimiter".to_string()));
+            }
+        }
+        self
+    }
+
+    /// Write `delimiter` after each output record (--output-delimiter)
+    ///
+    /// Records are still read and compared with the record delimiter. A
+    /// record that contains `delimiter` is an error unless `force` is set,
+    /// as the output could not be split back into the same records.
+    pub fn output_delimiter(mut self, delimiter: u8) -> Self {
+        self.config.output_delimiter = Some(delimiter);
+        self
+    }
+
+    /// Flush the output after every record (--line-buffered)
+    ///
+    /// For -m feeding a pipeline, so each record reaches the next command
+    /// as soon as it is merged. The command line turns this on by itself
+    /// when stdout is a terminal.
+    pub fn line_buffered(mut self, line_buffered: bool) -> Self {
+        self.config.line_buffered = line_buffered;
+        self
+    }
+
+    /// Write `separator` as a record between runs of records with equal
+    /// keys (--group); an empty separator leaves a bare delimiter
+    ///
+    /// Keys are compared as for -u, without the last-resort comparison,
+    /// which this cannot be combined with.
+    pub fn group(mut self, separator: impl Into<Vec<u8>>) -> Self {
+        self.config.group = Some(separator.into());
+        self
+    }
+
+    /// Output the keys of each record instead of the record (--print-key)
+    ///
+    /// The keys are the bytes the comparison looks at, after `b` and
+    /// --ignore-trailing-blanks, joined by `key_output_separator`; without
+    /// keys that is the whole record. A missing key prints as nothing.
+    pub fn print_key(mut self, print_key: bool) -> Self {
+        self.config.print_key = print_key;
+        self
+    }
+
+    /// Narrate how the two input records compare instead of sorting
+    /// (--explain)
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.config.explain = explain;
+        self
+    }
+
+    /// Join the keys printed by `print_key` with `separator` instead of a
+    /// tab (--key-output-separator)
+    pub fn key_output_separator(mut self, separator: impl Into<Vec<u8>>) -> Self {
+        self.config.key_output_separator = separator.into();
+        self
+    }
+
+    /// Put the 1-based position each record had in the input, and a tab,
+    /// before it in the output (--number)
+    ///
+    /// Positions count across all inputs. With -u the position is that of
+    /// the record kept, the first of its run.
+    pub fn number(mut self, number: bool) -> Self {
+        self.config.number = number;
+        self
+    }
+
+    /// Print the run's `SortStats` to stderr once the output is written
+    /// (--stats)
+    ///
+    /// Also turns on comparison counting in `sort_files`. Only the command
+    /// line prints; library callers get the same counters returned.
+    pub fn stats(mut self, format: StatsFormat) -> Self {
+        self.config.stats = Some(format);
+        self
+    }
+
+    /// Read the output file back once written and check that it is sorted,
+    /// and unique with -u (--verify)
+    ///
+    /// A file out of order is renamed to `FILE.unverified` and the run
+    /// fails. Needs an output file: stdout cannot be read back.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.config.verify = verify;
+        self
+    }
+
+    /// End the output without a delimiter when the input does
+    /// (--preserve-final-newline)
+    ///
+    /// By default, as in GNU sort, every output record is terminated. Of
+    /// several inputs, the last one holding records decides. Sorting only;
+    /// -m and -c reject it.
+    pub fn preserve_final_newline(mut self, preserve: bool) -> Self {
+        self.config.preserve_final_newline = preserve;
+        self
+    }
+
+    /// Write records containing the --output-delimiter anyway (--force)
+    pub fn force(mut self, force: bool) -> Self {
+        self.config.force = force;
+        self
+    }
+
+    /// Field separator (-t): a byte, or a char matched by its UTF-8 bytes
+    ///
+    /// Without one, fields are split on blank runs.
+    pub fn field_separator(mut self, separator: impl Into<FieldSeparator>) -> Self {
+        self.config.field_separator = Some(separator.into());
+        self
+    }
+
+    /// Treat records as CRLF lines (--crlf)
+    ///
+    /// A trailing `\r` is left out of comparisons and keys but kept in the
+    /// output, so each line keeps its own ending.
+    pub fn crlf(mut self, crlf: bool) -> Self {
+        self.config.crlf = crlf;
+        self
+    }
+
+    /// Compare keys without their trailing blanks (--ignore-trailing-blanks)
+    ///
+    /// Applies to every key, or the whole line when there are none. The
+    /// last-resort comparison still sees the padding, so lines that differ
+    /// only by it are duplicates only under -s or -u.
+    pub fn ignore_trailing_blanks(mut self, ignore: bool) -> Self {
+        self.config.ignore_trailing_blanks = ignore;
+        self
+    }
+
+    /// Decompress every input with `codec` (--decompress)
+    ///
+    /// Without it, only files whose name ends in a codec's extension, such
+    /// as `.gz`, are decompressed. Applies to `sort_files` and the command
+    /// line, not to readers handed to `Sorter`.
+    pub fn decompress(mut self, codec: Codec) -> Self {
+        self.config.decompress = Decompression::Codec(codec);
+        self
+    }
+
+    /// Read every input through `program -d` (--decompress-program)
+    ///
+    /// The program reads the file on its stdin and writes the plain text to
+    /// its stdout, like `gzip -d` or `xz -d`; a nonzero exit is an error
+    /// naming the program and the file.
+    pub fn decompress_program(mut self, program: impl Into<String>) -> Self {
+        self.config.decompress = Decompression::Program(program.into());
+        self
+    }
+
+    /// Decode input files from `encoding` before sorting (--encoding)
+    ///
+    /// Keys and comparisons always see UTF-8. Applies to `sort_files` and
+    /// the command line, not to readers handed to `Sorter`.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.config.encoding = encoding;
+        self
+    }
+
+    /// Write the output in `encoding` (--output-encoding; UTF-8 by default)
+    pub fn output_encoding(mut self, encoding: Encoding) -> Self {
+        self.config.output_encoding = encoding;
+        self
+    }
+
+    /// Drop a UTF-8 byte order mark from the first record of the first input (--strip-bom)
+    pub fn strip_bom(mut self, strip_bom: bool) -> Self {
+        self.config.strip_bom = strip_bom;
+        self
+    }
+
+    /// Strip the BOM for sorting, then write it back ahead of the output (--keep-bom)
+    ///
+    /// Turns on `strip_bom` as well.
+    pub fn keep_bom(mut self, keep_bom: bool) -> Self {
+        self.config.keep_bom = keep_bom;
+        self.config.strip_bom |= keep_bom;
+        self
+    }
+
+    /// Split fields as CSV, honouring double quotes (--csv)
+    ///
+    /// The separator is `field_separator`, or a comma when that is unset.
+    pub fn csv(mut self, csv: bool) -> Self {
+        self.config.csv = csv;
+        self
+    }
+
+    /// Split fields on every occurrence of a byte string (--field-separator-str)
+    ///
+    /// Unlike -t, the separator may be several characters, e.g. `" | "`.
+    pub fn field_separator_str(mut self, separator: impl AsRef<[u8]>) -> Self {
+        let separator = separator.as_ref();
+        self.config.field_separator =
+            Some(FieldSeparator::new(separator).unwrap_or(FieldSeparator::Bytes(separator.into())));
+        self
+    }
+
+    /// Split fields on matches of `pattern` (--field-separator-regex)
+    ///
+    /// Invalid patterns, and patterns that can match the empty string, are
+    /// reported by `build`.
+    #[cfg(feature = "regex")]
+    pub fn field_separator_regex(mut self, pattern: &str) -> Self {
+        match SeparatorRegex::new(pattern) {
+            Ok(regex) => self.config.field_separator_regex = Some(regex),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Add a key at byte positions `START[,END]`, counting from 1
+    /// (--key-bytes); parse errors are reported by `build`
+    pub fn key_bytes(mut self, range: &str) -> Self {
+        match ByteRange::parse(range) {
+            Ok(range) => self.config.key_bytes.push(range),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Order text keys by the collation of the BCP 47 locale `tag` (--locale)
+    ///
+    /// Numeric, month and version keys are unaffected, and the last-resort
+    /// comparison stays bytewise. An invalid tag is reported by `build`.
+    #[cfg(feature = "collate")]
+    pub fn locale(mut self, tag: &str) -> Self {
+        match Collation::new(tag) {
+            Ok(collation) => self.config.locale = Some(collation),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Add an already-parsed key
+    pub fn key(mut self, key: KeySpec) -> Self {
+        self.config.keys.push(key);
+        self
+    }
+
+    /// Add a key in GNU keydef syntax (-k); parse errors are reported by `build`
+    pub fn add_key(mut self, keydef: &str) -> Self {
+        match KeySpec::parse(keydef) {
+            Ok(key) => self.config.keys.push(key),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Add a key by header column name, `NAME[:OPTS]` (--key-name)
+    pub fn add_key_name(mut self, name: &str) -> Self {
+        self.config.key_names.push(name.to_string());
+        self
+    }
+
+    /// Add a key from JSON Lines records, an RFC 6901 pointer such as
+    /// `/user/id` (--json-key); parse errors are reported by `build`
+    pub fn json_key(mut self, pointer: &str) -> Self {
+        match JsonPointer::parse(pointer) {
+            Ok(pointer) => self.config.json_keys.push(pointer),
+            Err(e) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Compare keys with `compare` instead of the -n/-f/bytewise choice
+    pub fn key_compare(mut self, compare: KeyCompare) -> Self {
+        self.config.key_compare = Some(compare);
+        self
+    }
+
+    /// Write output to this file (-o); "-" means stdout
+    ///
+    /// The output goes to a temporary file in the same directory that is
+    /// renamed over this one once complete, so the file may also be an
+    /// input. A file named `*.gz`, or `*.zst` with feature `zstd`, is
+    /// written compressed; see `auto_compress`.
+    pub fn output_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.output_file = Some(path.into());
+        self
+    }
+
+    /// Compress an output file whose extension names a codec (on by
+    /// default; --no-auto-compress turns it off)
+    pub fn auto_compress(mut self, auto_compress: bool) -> Self {
+        self.config.auto_compress = auto_compress;
+        self
+    }
+
+    /// Compression level for a compressed output file (--compress-level)
+    ///
+    /// gzip takes 0 to 9 (default 6), zstd 1 to 22 (default 3).
+    pub fn compress_level(mut self, level: u32) -> Self {
+        self.config.compress_level = Some(level);
+        self
+    }
+
+    /// Pipe the output through `program` (--compress-output-program)
+    ///
+    /// The program reads the sorted output on its stdin and writes to the
+    /// output file, or stdout; a nonzero exit is an error. It takes the
+    /// place of compressing by extension.
+    pub fn compress_output_program(mut self, program: impl Into<String>) -> Self {
+        self.config.compress_output_program = Some(program.into());
+        self
+    }
+
+    /// Order records by the key line `command` prints for each
+    /// (--key-command)
+    ///
+    /// The command runs once, through the shell, reading the records on its
+    /// stdin and printing their keys in order. -k, -t and the ordering
+    /// options apply to the key lines; the last resort still compares the
+    /// records. A nonzero exit, or a key count other than the record count,
+    /// is an error.
+    pub fn key_command(mut self, command: impl Into<String>) -> Self {
+        self.config.key_command = Some(command.into());
+        self
+    }
+
+    /// Add the output to the end of the output file (--append)
+    ///
+    /// The file is created if missing. Appends are not atomic: a run that
+    /// fails partway leaves what it wrote at the end of the file. Each run
+    /// stands alone, so -u removes duplicates within this output only.
+    /// Has no effect on stdout.
+    pub fn append(mut self, append: bool) -> Self {
+        self.config.append = append;
+        self
+    }
+
+    /// Sync the output file to disk once written (--fsync)
+    ///
+    /// A file renamed into place also has its directory synced, so the
+    /// rename survives a crash too. Does nothing for stdout or a pipe.
+    pub fn fsync(mut self, fsync: bool) -> Self {
+        self.config.fsync = fsync;
+        self
+    }
+
+    /// Also write the output to this file (--tee); may be given several times
+    ///
+    /// Each file gets the same bytes as stdout or the -o file, and is
+    /// written the way the -o file is: replaced once complete, compressed
+    /// by its extension, appended to with `append`.
+    pub fn tee(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.tee.push(path.into());
+        self
+    }
+
+    /// Write the output as `chunks` files named `prefix.000`, `prefix.001`,
+    /// ... (--split-output, --output-prefix)
+    ///
+    /// Each file holds the next range of the sorted order, of about the
+    /// same number of records; see `sort_files_split`. Replaces the output
+    /// file.
+    pub fn split_output(mut self, chunks: usize, prefix: impl Into<PathBuf>) -> Self {
+        self.config.split_output = Some(chunks);
+        self.config.output_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Move each --split-output boundary forward past records whose keys
+    /// equal the one before it (--split-by-key)
+    ///
+    /// All records with one key then land in one file, which may leave the
+    /// files less even, or the last ones empty.
+    pub fn split_by_key(mut self, split_by_key: bool) -> Self {
+        self.config.split_by_key = split_by_key;
+        self
+    }
+
+    /// Input files; empty reads stdin, "-" names stdin explicitly
+    pub fn input_files<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<PathBuf>,
+    {
+        self.config.input_files = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Layer CLI arguments over the options already set
+    ///
+    /// Flags can only switch options on; -t and -k replace earlier values
+    /// when given.
+    #[cfg(feature = "cli")]
+    fn args(mut self, args: &Args) -> Result<Self> {
+        for (set, mode) in [
+            (args.human_numeric, SortMode::HumanNumeric),
+            (args.month, SortMode::Month),
+            (args.numeric, SortMode::Numeric),
+            (args.version, SortMode::Version),
+        ] {
+            if set {
+                self = self.sort_mode(mode, true);
+            }
+        }
+        let config = &mut self.config;
+        config.reverse |= args.reverse;
+        config.fold_case |= args.fold_case;
+        config.unique |= args.unique;
+        config.stable |= args.stable;
+        if let Some(format) = &args.debug {
+            config.debug = Some(DebugFormat::parse(format).ok_or_else(|| {
+                RsortError::Usage(format!("invalid --debug argument '{}'; expected text, json or decisions", format))
+            })?);
+        }
+        config.check = if args.check_quiet {
+            Some(CheckMode::Quiet)
+        } else if args.check {
+            Some(CheckMode::Diagnose)
+        } else {
+            None
+        };
+        if let Some(format) = &args.format {
+            config.check_format = CheckFormat::parse(format).ok_or_else(|| {
+                RsortError::Usage(format!("invalid --format argument '{}'; expected text or json", format))
+            })?;
+        }
+        config.check_verbose |= args.verbose;
+        config.merge = args.merge;
+        if let Some(delimiter) = args.record_delimiter()? {
+            self = self.record_delimiter_bytes(delimiter);
+        }
+        let config = &mut self.config;
+        if let Some(size) = &args.max_record_size {
+            config.max_record_size = Some(parse_size(size).ok_or_else(|| {
+                RsortError::Usage(format!("invalid --max-record-size argument '{}'", size))
+            })?);
+        }
+        if let Some(size) = &args.record_size {
+            let bytes = parse_size(size).and_then(|bytes| usize::try_from(bytes).ok());
+            config.record_size = Some(bytes.ok_or_else(|| {
+                RsortError::Usage(format!("invalid --record-size argument '{}'", size))
+            })?);
+        }
+        if let Some(framing) = &args.framing {
+            config.framing = Framing::parse(framing).ok_or_else(|| {
+                RsortError::Usage(format!("invalid --framing argument '{}'; expected delimited or len32le", framing))
+            })?;
+        }
+        if args.take.is_some() {
+            config.take = args.take;
+        }
+        if let Some(header) = args.header {
+            config.header = header;
+        }
+        if let Some(delimiter) = &args.output_delimiter {
+            config.output_delimiter = Some(parse_separator(delimiter).map_err(|_| {
+                RsortError::Usage(format!("invalid --output-delimiter argument '{}'", delimiter))
+            })?);
+        }
+        config.force |= args.force;
+        config.preserve_final_newline |= args.preserve_final_newline;
+        config.line_buffered |= args.line_buffered;
+        if let Some(separator) = &args.group {
+            config.group = Some(separator.clone().into_bytes());
+        }
+        config.print_key |= args.print_key;
+        config.explain |= args.explain;
+        config.number |= args.number;
+        config.verify |= args.verify;
+        if let Some(format) = &args.stats {
+            config.stats = Some(StatsFormat::parse(format).ok_or_else(|| {
+                RsortError::Usage(format!("invalid --stats argument '{}'; expected text or json", format))
+            })?);
+        }
+        if let Some(separator) = &args.key_output_separator {
+            config.key_output_separator = separator.clone().into_bytes();
+        }
+        if let Some(c) = &args.comment_char {
+            config.comment_char = Some(parse_separator(c).map_err(|_| {
+                RsortError::Usage(format!("invalid --comment-char argument '{}'", c))
+            })?);
+        }
+        if let Some(separator) = args.field_separator()? {
+            config.field_separator = Some(separator);
+        }
+        if let Some(separator) = &args.field_separator_str {
+            self = self.field_separator_str(separator);
+        }
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = &args.field_separator_regex {
+            self = self.field_separator_regex(pattern);
+        }
+        #[cfg(feature = "collate")]
+        if let Some(tag) = &args.locale {
+            self = self.locale(tag);
+        }
+        let config = &mut self.config;
+        config.csv |= args.csv;
+        config.crlf |= args.crlf;
+        config.ignore_trailing_blanks |= args.ignore_trailing_blanks;
+        if let Some(program) = &args.decompress_program {
+            config.decompress = Decompression::Program(program.clone());
+        }
+        if let Some(name) = &args.decompress {
+            config.decompress = Decompression::Codec(Codec::parse(name).ok_or_else(|| {
+                RsortError::Usage(format!(
+                    "invalid --decompress argument '{}'; expected {}",
+                    name,
+                    Codec::names()
+                ))
+            })?);
+        }
+        for (arg, option, encoding) in [
+            (&args.encoding, "--encoding", &mut config.encoding),
+            (&args.output_encoding, "--output-encoding", &mut config.output_encoding),
+        ] {
+            if let Some(name) = arg {
+                *encoding = Encoding::parse(name).ok_or_else(|| {
+                    RsortError::Usage(format!(
+                        "invalid {} argument '{}'; expected utf-8, utf-16le or utf-16be",
+                        option, name
+                    ))
+                })?;
+            }
+        }
+        config.strip_bom |= args.strip_bom || args.keep_bom;
+        config.keep_bom |= args.keep_bom;
+        config.output_file = args.output.clone();
+        config.auto_compress &= !args.no_auto_compress;
+        if let Some(level) = args.compress_level {
+            config.compress_level = Some(level);
+        }
+        if let Some(program) = &args.compress_output_program {
+            config.compress_output_program = Some(program.clone());
+        }
+        if let Some(command) = &args.key_command {
+            config.key_command = Some(command.clone());
+        }
+        config.append |= args.append;
+        config.fsync |= args.fsync;
+        config.tee.extend(args.tee.iter().cloned());
+        if args.split_output.is_some() {
+            config.split_output = args.split_output;
+            config.output_prefix = args.output_prefix.clone();
+        }
+        config.split_by_key |= args.split_by_key;
+        config.input_files = args.files.clone();
+
+        if !args.key_names.is_empty() {
+            config.key_names = args.key_names.clone();
+        }
+        if !args.keys.is_empty() {
+            config.keys.clear();
+            for keydef in &args.keys {
+                self = self.add_key(keydef);
+            }
+        }
+        for pointer in &args.json_keys {
+            self = self.json_key(pointer);
+        }
+        for range in &args.key_bytes {
+            self = self.key_bytes(range);
+        }
+        Ok(self)
+    }
+
+    /// Validate and produce the configuration
+    pub fn build(mut self) -> Result<Config> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.config.sort_mode = match self.modes[..] {
+            [] => SortMode::Text,
+            [mode] => mode,
+            _ => return Err(key::incompatible_modes(self.config.fold_case, &self.modes)),
+        };
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert_eq!(config.record_delimiter, b'\n');
+        assert_eq!(config.field_separator, None);
+        assert!(config.keys.is_empty());
+        assert!(config.use_last_resort());
+    }
+
+    #[test]
+    fn test_builder_sets_options() {
+        let config = Config::builder()
+            .numeric(true)
+            .reverse(true)
+            .field_separator(b':')
+            .add_key("2,2")
+            .add_key("1")
+            .build()
+            .unwrap();
+        assert!(config.sort_mode == SortMode::Numeric && config.reverse);
+        assert_eq!(config.field_separator, Some(b':'.into()));
+        assert_eq!(config.keys.len(), 2);
+        assert_eq!(config.keys[0].start_field, 2);
+    }
+
+    #[test]
+    fn test_builder_reports_first_key_error() {
+        let err = Config::builder()
+            .add_key("0")
+            .add_key("x")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("field number must be >= 1"));
+    }
+
+    #[test]
+    fn test_separator_equal_to_delimiter_rejected() {
+        let err = Config::builder().field_separator(b'\n').build().unwrap_err();
+        assert!(matches!(err, RsortError::Usage(_)));
+        assert_eq!(err.exit_code(), 2);
+
+        let err = Config::builder()
+            .record_delimiter(0)
+            .field_separator(0)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, RsortError::Usage(_)));
+    }
+
+    #[test]
+    fn test_parse_field_separator() {
+        assert_eq!(parse_field_separator(",").unwrap(), FieldSeparator::Byte(b','));
+        assert_eq!(parse_field_separator("\\t").unwrap(), FieldSeparator::Byte(b'\t'));
+        assert_eq!(parse_field_separator("\\").unwrap(), FieldSeparator::Byte(b'\\'));
+        assert_eq!(parse_field_separator("¦").unwrap().as_bytes(), "¦".as_bytes());
+        assert_eq!(parse_field_separator("\u{2022}").unwrap().as_bytes(), b"\xe2\x80\xa2");
+
+        let err = parse_field_separator("ab").unwrap_err();
+        assert_eq!(err.to_string(), "multi-character tab 'ab'");
+        let err = parse_field_separator("§§").unwrap_err();
+        assert_eq!(err.to_string(), "multi-character tab '§§'");
+        let err = parse_field_separator("\\q").unwrap_err();
+        assert!(err.to_string().starts_with("invalid escape '\\q'; expected \\t"), "{}", err);
+        assert_eq!(parse_field_separator("").unwrap_err().to_string(), "empty tab");
+    }
+
+    #[test]
+    fn test_field_separator_str() {
+        let config = Config::builder().field_separator_str(" | ").build().unwrap();
+        assert_eq!(config.field_separator.unwrap().as_bytes(), b" | ");
+        let config = Config::builder().field_separator_str(":").build().unwrap();
+        assert_eq!(config.field_separator, Some(b':'.into()));
+
+        let err = Config::builder().field_separator_str("").build().unwrap_err();
+        assert_eq!(err.to_string(), "empty field separator");
+        let err = Config::builder().field_separator_str("a\nb").build().unwrap_err();
+        assert!(err.to_string().starts_with("field separator is the same as the record delimiter"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_field_separator_regex() {
+        let config = Config::builder().field_separator_regex(r"[,\s]+").build().unwrap();
+        assert_eq!(config.field_separator_regex.unwrap().as_str(), r"[,\s]+");
+
+        let err = Config::builder().field_separator_regex(",*").build().unwrap_err();
+        assert!(err.to_string().contains("can match the empty string"), "{}", err);
+
+        let err = Config::builder()
+            .field_separator_regex(",")
+            .field_separator(b';')
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "options '--field-separator-regex' and '-t' are incompatible"
+        );
+        let err = Config::builder().field_separator_regex(",").csv(true).build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "options '--field-separator-regex' and '--csv' are incompatible"
+        );
+    }
+
+    #[test]
+    fn test_parse_separator_escapes() {
+        let cases: [(&str, u8); 14] = [
+            ("\\t", b'\t'),
+            ("\\n", b'\n'),
+            ("\\r", b'\r'),
+            ("\\v", 0x0B),
+            ("\\f", 0x0C),
+            ("\\\\", b'\\'),
+            ("\\", b'\\'),
+            ("\\0", 0),
+            ("\0", 0),
+            ("\\x1f", 0x1F),
+            ("\\xFF", 0xFF),
+            ("\\37", 0x1F),
+            ("\\037", 0x1F),
+            ("\\377", 0xFF),
+        ];
+        for (escape, byte) in cases {
+            assert_eq!(parse_separator(escape).unwrap(), byte, "{}", escape);
+        }
+
+        let errors = [
+            ("\\x", "\\x takes exactly two hex digits"),
+            ("\\xZZ", "\\x takes exactly two hex digits"),
+            ("\\x1", "\\x takes exactly two hex digits"),
+            ("\\x123", "\\x takes exactly two hex digits"),
+            ("\\400", "octal escapes go up to \\377"),
+            ("\\0123", "octal escapes take at most three digits"),
+            ("\\8", "expected \\t, \\n, \\r, \\v, \\f, \\\\, \\xHH or octal \\NNN"),
+        ];
+        for (escape, reason) in errors {
+            let err = parse_separator(escape).unwrap_err();
+            assert_eq!(err.to_string(), format!("invalid escape '{}'; {}", escape, reason));
+        }
+        assert!(matches!(parse_separator("ab"), Err(RsortError::InvalidDelimiter)));
+    }
+
+    #[test]
+    fn test_crlf_needs_newline_records() {
+        let err = Config::builder().crlf(true).record_delimiter(0).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-z' and '--crlf' are incompatible");
+        let err = Config::builder().crlf(true).record_delimiter_bytes(b"\r\n").build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--record-delimiter' and '--crlf' are incompatible");
+        assert!(Config::builder().crlf(true).build().is_ok());
+    }
+
+    #[test]
+    fn test_record_size_excludes_delimiters() {
+        for (builder, other) in [
+            (Config::builder().record_delimiter(0), "-z"),
+            (Config::builder().record_delimiter_bytes(b"\x1e"), "--record-delimiter"),
+            (Config::builder().crlf(true), "--crlf"),
+        ] {
+            let err = builder.record_size(4).build().unwrap_err();
+            assert_eq!(err.to_string(), format!("options '--record-size' and '{}' are incompatible", other));
+        }
+        let err = Config::builder().record_size(0).build().unwrap_err();
+        assert_eq!(err.to_string(), "invalid --record-size argument '0'");
+
+        // Newlines are data, even as a field separator
+        let config = Config::builder().record_size(4).field_separator(b'\n').key_bytes("2").build().unwrap();
+        assert!(config.output_terminator().is_empty());
+        assert!(config.describe().contains("record delimiter: none, 4-byte records\nfield separator: '\\n'\n"));
+        assert!(config.describe().contains("key 1: bytes 2\n"));
+        assert_eq!(Config::builder().record_size(4).output_delimiter(b'\n').build().unwrap().output_terminator(), b"\n");
+    }
+
+    #[test]
+    fn test_framing_excludes_delimiters() {
+        for (builder, other) in [
+            (Config::builder().record_size(4), "--record-size"),
+            (Config::builder().record_delimiter(0), "-z"),
+            (Config::builder().output_delimiter(b'\n'), "--output-delimiter"),
+            (Config::builder().debug(true), "--debug"),
+        ] {
+            let err = builder.framing(Framing::Len32Le).build().unwrap_err();
+            let expected = if other == "--record-size" {
+                "options '--record-size' and '--framing' are incompatible".to_string()
+            } else {
+                format!("options '--framing' and '{}' are incompatible", other)
+            };
+            assert_eq!(err.to_string(), expected);
+        }
+        let config = Config::builder().framing(Framing::Len32Le).build().unwrap();
+        assert!(config.output_terminator().is_empty());
+        assert!(config.describe().contains("record delimiter: none, len32le framing\n"));
+    }
+
+    #[test]
+    fn test_key_names_with_crlf() {
+        let config = Config::builder()
+            .field_separator(b',')
+            .header(1)
+            .crlf(true)
+            .add_key_name("b")
+            .build()
+            .unwrap();
+        let resolved = config.resolve_key_names(&[b"a,b\r"]).unwrap().unwrap();
+        assert_eq!(resolved.keys[0].to_string(), "2,2");
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        assert_eq!(parse_escapes("\\r\\n").unwrap(), b"\r\n");
+        assert_eq!(parse_escapes("ab").unwrap(), b"ab");
+        assert_eq!(parse_escapes("--\\x1e\\0370\\\\").unwrap(), b"--\x1e\x1f0\\");
+        assert_eq!(parse_escapes("§\\").unwrap(), "§\\".as_bytes());
+        assert_eq!(parse_escapes("").unwrap(), b"");
+
+        let err = parse_escapes("a\\xZ").unwrap_err();
+        assert_eq!(err.to_string(), "invalid escape '\\x'; \\x takes exactly two hex digits");
+        let err = parse_escapes("\\q!").unwrap_err();
+        assert!(err.to_string().starts_with("invalid escape '\\q'; "), "{}", err);
+    }
+
+    #[test]
+    fn test_record_delimiter_bytes() {
+        let config = Config::builder().record_delimiter_bytes(b"\r\n").build().unwrap();
+        assert_eq!(config.record_delimiter, b'\n');
+        assert_eq!(config.record_terminator(), b"\r\n");
+
+        let config = Config::builder()
+            .record_delimiter_bytes(b"\r\n")
+            .record_delimiter(0)
+            .build()
+            .unwrap();
+        assert_eq!(config.record_terminator(), b"\0");
+
+        let err = Config::builder().record_delimiter_bytes(b"").build().unwrap_err();
+        assert_eq!(err.to_string(), "empty record delimiter");
+    }
+
+    #[test]
+    fn test_ordering_options_are_exclusive() {
+        let err = Config::builder().numeric(true).month(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-Mn' are incompatible");
+
+        let err = Config::builder()
+            .fold_case(true)
+            .version(true)
+            .human_numeric(true)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "options '-fhV' are incompatible");
+
+        let config = Config::builder().version(true).fold_case(true).build().unwrap();
+        assert_eq!(config.key_kind(), KeyKind::Version);
+
+        // Switching one off again leaves the other
+        let config = Config::builder().numeric(true).month(true).month(false).build().unwrap();
+        assert_eq!(config.sort_mode, SortMode::Numeric);
+    }
+
+    #[test]
+    fn test_check_rejects_output_and_extra_files() {
+        let err = Config::builder()
+            .check(CheckMode::Diagnose)
+            .output_file("out")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "options '-co' are incompatible");
+
+        let err = Config::builder()
+            .check(CheckMode::Quiet)
+            .input_files(["a", "b"])
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "extra operand 'b' not allowed with -c");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("0"), Some(0));
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("100b"), Some(100));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("2g"), Some(2 << 30));
+        assert_eq!(parse_size("1E"), Some(1 << 60));
+        assert_eq!(parse_size("1Z"), None);
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size("1KB"), None);
+        assert_eq!(parse_size("-1"), None);
+    }
+
+    #[test]
+    fn test_sort_only_options_rejected_with_check_and_merge() {
+        let err = Config::builder().take(5).merge(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-m' and '--take' are incompatible");
+
+        let err = Config::builder().take(0).check(CheckMode::Quiet).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-c' and '--take' are incompatible");
+
+        let err = Config::builder().comment_char(b'#').merge(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-m' and '--comment-char' are incompatible");
+
+        let err = Config::builder().header(1).merge(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-m' and '--header' are incompatible");
+
+        let err = Config::builder().number(true).merge(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-m' and '--number' are incompatible");
+        Config::builder().header(0).merge(true).build().unwrap();
+    }
+
+    #[test]
+    fn test_split_output_options() {
+        let err = Config::builder().split_output(0, "part").build().unwrap_err();
+        assert_eq!(err.to_string(), "invalid --split-output argument '0'");
+
+        let err = Config::builder().split_output(2, "part").output_file("out").build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--split-output' and '-o' are incompatible");
+
+        let err = Config::builder().split_output(2, "part").merge(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-m' and '--split-output' are incompatible");
+        Config::builder().split_output(2, "part").split_by_key(true).build().unwrap();
+    }
+
+    #[test]
+    fn test_verify_rejects_rewritten_output() {
+        let err = Config::builder().verify(true).number(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--verify' and '--number' are incompatible");
+        let err = Config::builder().verify(true).header(1).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--verify' and '--header' are incompatible");
+        Config::builder().verify(true).unique(true).reverse(true).build().unwrap();
+    }
+
+    fn lint(builder: ConfigBuilder) -> Vec<String> {
+        builder.build().unwrap().lint()
+    }
+
+    #[test]
+    fn test_lint_clean_config() {
+        assert!(lint(Config::builder()).is_empty());
+        assert!(lint(Config::builder().numeric(true).reverse(true)).is_empty());
+        assert!(lint(Config::builder().field_separator(b':').add_key("2,2n").add_key("1")).is_empty());
+        assert!(lint(Config::builder().reverse(true).add_key("1,1")).is_empty());
+    }
+
+    #[test]
+    fn test_lint_separator_without_keys() {
+        assert_eq!(lint(Config::builder().field_separator(b':')), ["option '-t' is ignored without any key"]);
+        assert!(lint(Config::builder().field_separator(b':').header(1).add_key_name("id")).is_empty());
+    }
+
+    #[test]
+    fn test_lint_numeric_key_spans_fields() {
+        assert_eq!(
+            lint(Config::builder().add_key("1,1").add_key("2n")),
+            ["key 2 is numeric and spans multiple fields"]
+        );
+        assert_eq!(
+            lint(Config::builder().field_separator(b'.').human_numeric(true).add_key("1").add_key("2,3")),
+            [
+                "key 1 is numeric and spans multiple fields",
+                "key 2 is numeric and spans multiple fields",
+                "field separator '.' is treated as a decimal point in numbers",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_global_options_overridden() {
+        assert_eq!(
+            lint(Config::builder().numeric(true).reverse(true).add_key("1,1f")),
+            ["option '-n' is ignored", "option '-r' only applies to last-resort comparison"]
+        );
+        assert_eq!(
+            lint(Config::builder().numeric(true).reverse(true).stable(true).add_key("1,1f")),
+            ["options '-nr' are ignored"]
+        );
+        // Inherited by the key without modifiers
+        assert!(lint(Config::builder().numeric(true).add_key("1,1f").add_key("2,2")).is_empty());
+    }
+
+    #[test]
+    fn test_describe_defaults() {
+        assert_eq!(
+            Config::default().describe(),
+            "text ordering performed using simple byte comparison\n\
+             record delimiter: '\\n'\n\
+             field separator: whitespace\n\
+             key: whole line\n\
+             last-resort comparison: whole line, bytewise\n\
+             stable: no, the last resort orders equal keys"
+        );
+    }
+
+    #[test]
+    fn test_describe_resolved_keys() {
+        let config = Config::builder()
+            .field_separator(b'\t')
+            .reverse(true)
+            .add_key("2,2n")
+            .add_key("1.2b")
+            .build()
+            .unwrap();
+        let description = config.describe();
+        assert!(description.contains("field separator: '\t'\n"), "{}", description);
+        assert!(description.contains("key 1: -k 2,2n\n"), "{}", description);
+        assert!(description.contains("key 2: -k 1.2b\n"), "{}", description);
+        assert!(description.contains("last-resort comparison: whole line, bytewise, reversed"), "{}", description);
+
+        let config = Config::builder().month(true).reverse(true).add_key("3").build().unwrap();
+        assert!(config.describe().contains("key 1: -k 3Mr (global options)"));
+
+        let config = Config::builder().numeric(true).add_key("1,1f").json_key("/a/0").build().unwrap();
+        assert!(config.describe().contains("key 2: JSON value at '/a/0', -n\n"));
+        assert!(config.lint().is_empty(), "{:?}", config.lint());
+    }
+
+    #[test]
+    fn test_describe_delimiters_and_stability() {
+        let config = Config::builder().record_delimiter(b'\0').csv(true).fold_case(true).unique(true).build().unwrap();
+        let description = config.describe();
+        assert!(description.contains("record delimiter: '\\0'\n"), "{}", description);
+        assert!(description.contains("field separator: CSV on ','\n"), "{}", description);
+        assert!(description.contains("key: whole line, -f\n"), "{}", description);
+        assert!(description.contains("last-resort comparison: off (-u)\n"), "{}", description);
+        assert!(description.ends_with("stable: yes, equal records keep their input order"), "{}", description);
+
+        let config = Config::builder().stable(true).build().unwrap();
+        assert!(config.describe().contains("last-resort comparison: off (-s)"));
+    }
+
+    #[test]
+    fn test_debug_rejects_rewritten_records() {
+        let err = Config::builder().debug(true).print_key(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--debug' and '--print-key' are incompatible");
+        let err = Config::builder().debug(true).output_delimiter(b'\0').build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--debug' and '--output-delimiter' are incompatible");
+        let config = Config::builder().debug(true).record_delimiter(b'\0').build().unwrap();
+        assert_eq!(config.output_terminator(), b"\n");
+    }
+
+    #[test]
+    fn test_explain_rejects_other_modes() {
+        let err = Config::builder().explain(true).check(CheckMode::Diagnose).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--explain' and '-c' are incompatible");
+        let err = Config::builder().explain(true).debug(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '--explain' and '--debug' are incompatible");
+    }
+
+    #[test]
+    fn test_group_rejects_unique() {
+        let err = Config::builder().group("").unique(true).build().unwrap_err();
+        assert_eq!(err.to_string(), "options '-u' and '--group' are incompatible");
+        Config::builder().group("--").stable(true).build().unwrap();
+    }
+
+    fn csv_config() -> ConfigBuilder {
+        Config::builder().field_separator(b',').header(1)
+    }
+
+    #[test]
+    fn test_key_names_resolve_against_header() {
+        let config = csv_config()
+            .add_key_name("amount:nr")
+            .add_key_name("Customer ID")
+            .build()
+            .unwrap();
+        let records: [&[u8]; 2] = [b"date,\"customer id\",\"amount\"", b"x,y,z"];
+        let resolved = config.resolve_key_names(&records).unwrap().unwrap();
+        assert_eq!(
+            resolved.keys,
+            vec![KeySpec::parse("3,3nr").unwrap(), KeySpec::parse("2,2").unwrap()]
+        );
+        assert!(resolved.key_names.is_empty());
+
+        let records: [&[u8]; 1] = [b"\"a \"\"quoted\"\" name\",b"];
+        let config = csv_config().add_key_name("a \"quoted\" name").build().unwrap();
+        let resolved = config.resolve_key_names(&records).unwrap().unwrap();
+        assert_eq!(resolved.keys, vec![KeySpec::parse("1,1").unwrap()]);
+    }
+
+    #[test]
+    fn test_key_names_with_csv() {
+        let config = Config::builder()
+            .csv(true)
+            .header(1)
+            .add_key_name("Total, USD")
+            .build()
+            .unwrap();
+        let records: [&[u8]; 1] = [b"name,\"Total, USD\""];
+        let resolved = config.resolve_key_names(&records).unwrap().unwrap();
+        assert_eq!(resolved.keys, vec![KeySpec::parse("2,2").unwrap()]);
+    }
+
+    #[test]
+    fn test_csv_rejects_quote_separator() {
+        let err = Config::builder().csv(true).field_separator(b'"').build().unwrap_err();
+        assert_eq!(err.to_string(), "--csv field separator cannot be '\"'");
+        Config::builder().field_separator(b'"').build().unwrap();
+    }
+
+    #[test]
+    fn test_key_names_follow_explicit_keys() {
+        let config = csv_config().add_key("2,2").add_key_name("a").build().unwrap();
+        let resolved = config.resolve_key_names(&[b"a,b"]).unwrap().unwrap();
+        assert_eq!(
+            resolved.keys,
+            vec![KeySpec::parse("2,2").unwrap(), KeySpec::parse("1,1").unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_key_name_errors() {
+        let config = csv_config().add_key_name("total").build().unwrap();
+        let err = config.resolve_key_names(&[b"id,\"name\",amount"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--key-name 'total': no such column in header (columns: id, name, amount)"
+        );
+
+        // Ambiguous without an exact match
+        let config = csv_config().add_key_name("ID").build().unwrap();
+        assert!(config.resolve_key_names(&[b"id,Id"]).is_err());
+
+        let err = Config::builder().add_key_name("a").header(1).build().unwrap_err();
+        assert_eq!(err.to_string(), "--key-name requires --header and -t or --csv");
+
+        // Nothing read, nothing to resolve
+        let config = csv_config().add_key_name("a").build().unwrap();
+        assert!(config.resolve_key_names::<&[u8]>(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_stdin_operand_allowed_once() {
+        let config = Config::builder().input_files(["a", "-", "b"]).build().unwrap();
+        assert_eq!(config.input_files.len(), 3);
+
+        let err = Config::builder().input_files(["-", "a", "-"]).build().unwrap_err();
+        assert_eq!(err.to_string(), "cannot read standard input more than once");
+    }
+
+    #[cfg(all(feature = "cli", feature = "profile"))]
+    fn args_with_profile(profile: &str, cli: &[&str]) -> Result<Config> {
+        use clap::Parser;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, profile.as_bytes()).unwrap();
+        let path = file.path().to_str().unwrap().to_string();
+        let argv = ["rsort", "--profile", path.as_str()].into_iter().chain(cli.iter().copied());
+        Config::from_args(&Args::parse_from(argv))
+    }
+
+    #[cfg(all(feature = "cli", feature = "profile"))]
+    #[test]
+    fn test_profile_overrides_defaults() {
+        let config = args_with_profile(
+            "numeric = true\nfield-separator = \",\"\nkeys = [\"2,2\"]\n",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.sort_mode, SortMode::Numeric);
+        assert!(!config.reverse);
+        assert_eq!(config.field_separator, Some(b','.into()));
+        assert_eq!(config.keys, vec![KeySpec::parse("2,2").unwrap()]);
+    }
+
+    #[cfg(all(feature = "cli", feature = "profile"))]
+    #[test]
+    fn test_cli_overrides_profile() {
+        let config = args_with_profile(
+            "numeric = true\nfield-separator = \",\"\nkeys = [\"2,2\"]\n",
+            &["-t", ":", "-k", "3", "-k", "1,1", "-r"],
+        )
+        .unwrap();
+        assert_eq!(config.sort_mode, SortMode::Numeric, "profile flag kept when CLI is silent");
+        assert!(config.reverse);
+        assert_eq!(config.field_separator, Some(b':'.into()));
+        assert_eq!(
+            config.keys,
+            vec![KeySpec::parse("3").unwrap(), KeySpec::parse("1,1").unwrap()]
+        );
+    }
+
+    #[cfg(all(feature = "cli", feature = "profile"))]
+    #[test]
+    fn test_cli_zero_terminated_overrides_profile() {
+        let config = args_with_profile("zero-terminated = false\n", &["-z"]).unwrap();
+        assert_eq!(config.record_delimiter, 0);
+        let config = args_with_profile("zero-terminated = true\n", &[]).unwrap();
+        assert_eq!(config.record_delimiter, 0);
+    }
+
+    #[cfg(all(feature = "cli", feature = "profile"))]
+    #[test]
+    fn test_missing_profile_is_error() {
+        use clap::Parser;
+
+        let args = Args::parse_from(["rsort", "--profile", "/nonexistent/team.toml"]);
+        let err = Config::from_args(&args).unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/team.toml"));
+    }
+
+    #[cfg(all(feature = "cli", feature = "profile"))]
+    #[test]
+    fn test_separator_and_delimiter_from_args() {
+        use clap::Parser;
+
+        let from_args = |argv: &[&str]| {
+            let argv = ["rsort", "--profile", "/dev/null"].iter().chain(argv);
+            Config::from_args(&Args::parse_from(argv))
+        };
+
+        let err = from_args(&["-t", "\\n"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "field separator is the same as the record delimiter; \
+             use -z if records are NUL-terminated and newlines separate fields"
+        );
+        let config = from_args(&["-z", "-t", "\\n"]).unwrap();
+        assert_eq!(config.field_separator, Some(b'\n'.into()));
+
+        let err = from_args(&["-z", "-t", "\\0"]).unwrap_err();
+        assert_eq!(err.to_string(), "field separator is the same as the record delimiter");
+        let config = from_args(&["-t", "\\0"]).unwrap();
+        assert_eq!(config.field_separator, Some(0.into()));
+    }
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn test_record_delimiter_arg() {
+        use clap::Parser;
+
+        let delimiter = |argv: &[&str]| {
+            Args::parse_from(["rsort"].iter().chain(argv)).record_delimiter()
+        };
+        assert_eq!(delimiter(&[]).unwrap(), None);
+        assert_eq!(delimiter(&["-z"]).unwrap(), Some(vec![0]));
+        assert_eq!(delimiter(&["--record-delimiter=;"]).unwrap(), Some(vec![b';']));
+        assert_eq!(delimiter(&["--record-delimiter=\\x1e"]).unwrap(), Some(vec![0x1E]));
+        assert_eq!(delimiter(&["-z", "--record-delimiter=\\0"]).unwrap(), Some(vec![0]));
+
+        let err = delimiter(&["-z", "--record-delimiter=;"]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "options '-z' and '--record-delimiter=;' are incompatible; -z means '\\0'"
+        );
+        assert_eq!(delimiter(&["--record-delimiter=\\r\\n"]).unwrap(), Some(b"\r\n".to_vec()));
+        let err = delimiter(&["--record-delimiter="]).unwrap_err();
+        assert_eq!(err.to_string(), "invalid --record-delimiter argument ''");
+    }
+
+    #[test]
+    fn test_newline_separator_with_nul_delimiter_allowed() {
+        let config = Config::builder()
+            .record_delimiter(0)
+            .field_separator(b'\n')
+            .build()
+            .unwrap();
+        assert_eq!(config.field_separator, Some(b'\n'.into()));
+    }
 }
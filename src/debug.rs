@@ -1,121 +1,446 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::io::{self, Write};
+use std::ops::Range;
 
+use crate::compare::{Comparator, Decision};
 use crate::config::Config;
-use crate::key::{extract_key, split_fields_with_positions};
+use crate::fields::{is_blank, Splitter};
+use crate::key::{FieldIndex, KeyExtractor, KeyKind, KeyModifiers, KeySpec};
+use crate::stats::write_json_string;
+
+/// Byte range of each configured key within `line`
+///
+/// One entry per `-k`, `--json-key` then `--key-bytes`, or a single
+/// whole-line entry without keys. The range is what the comparator
+/// compares; None when the key's start field, JSON value or start byte is
+/// missing. Ranges may be empty.
+pub fn key_spans(line: &[u8], config: &Config) -> Vec<Option<Range<usize>>> {
+    if keyless(config) {
+        return vec![Some(0..line.len())];
+    }
+
+    let splitter = Splitter::from_config(config);
+    let fields = FieldIndex::split(line, &splitter, None);
+    config
+        .keys
+        .iter()
+        .map(|spec| KeyExtractor::with_splitter(spec, splitter.clone()).span(line, &fields))
+        .chain(config.json_keys.iter().map(|pointer| pointer.span(line)))
+        .chain(config.key_bytes.iter().map(|range| range.span(line)))
+        .collect()
+}
+
+/// Whether the whole line is the key: no -k, --json-key or --key-bytes
+fn keyless(config: &Config) -> bool {
+    config.keys.is_empty() && config.json_keys.is_empty() && config.key_bytes.is_empty()
+}
 
 /// Debug output for a single line showing key spans
 ///
 /// Format matches GNU sort --debug:
-/// - Print the original line
-/// - Print underscores marking each key's span
+/// - Print the original line, with each tab shown as `>`
+/// - Print a line of underscores under the part of each key that is
+///   compared, or `^ no match for key` where a key with nothing to compare
+///   starts
+/// - Then a line under the whole record for the last-resort comparison,
+///   unless -s or -u turn it off. Without -k there is a key line only when
+///   ordering options (other than -r) make the key differ from the line.
+///
+/// The line is shown `escaped`, so a -z record's newlines and other
+/// control bytes stay on the one line, and each character shown is one
+/// column wide: the underlines stay under escapes and under multi-byte text
+/// such as a `§` separator.
+///
+/// With --field-separator-regex, a line of `^` marking each separator match
+/// comes first, showing where the fields were cut.
 pub fn debug_line<W: Write>(
     writer: &mut W,
     line: &[u8],
     config: &Config,
 ) -> io::Result<()> {
-    // Print original line
-    writer.write_all(line)?;
-    writeln!(writer)?;
-
-    if config.keys.is_empty() {
-        // No -k: whole line is the key
-        let underline = "_".repeat(line.len().max(1));
-        writeln!(writer, "{}", underline)?;
-    } else {
-        // Show each key's span
-        for key_spec in &config.keys {
-            let key = extract_key(line, key_spec, config.field_separator);
-
-            if key.is_empty() {
-                writeln!(writer, "^ no match for key")?;
-            } else {
-                // Find the full span of the key in the original line
-                if let Some((start, end)) = find_key_span(line, key_spec, config) {
-                    let indent = " ".repeat(start);
-                    let underline = "_".repeat((end - start).max(1));
-                    writeln!(writer, "{}{}", indent, underline)?;
-                } else {
-                    // Key extracted but position unclear
-                    let underline = "_".repeat(key.len());
-                    writeln!(writer, "{}", underline)?;
-                }
-            }
+    // A trailing \r would send the marks back to the start of the line
+    let line = if config.crlf { crate::input::strip_cr(line) } else { line };
+
+    // Print original line. A terminal would expand a tab to the next tab
+    // stop and leave the marks behind, so as in GNU sort it shows as `>`,
+    // one column wide like every other character.
+    writeln!(writer, "{}", escaped(line).replace('\t', ">"))?;
+
+    let width = |bytes: &[u8]| escaped(bytes).chars().count();
+
+    #[cfg(feature = "regex")]
+    if let Some(regex) = &config.field_separator_regex {
+        let mut marks = String::new();
+        for (start, end) in regex.matches(line) {
+            let indent = width(&line[..start]).saturating_sub(marks.len());
+            marks.push_str(&" ".repeat(indent));
+            marks.push_str(&"^".repeat(width(&line[start..end]).max(1)));
+        }
+        writeln!(writer, "{}", marks)?;
+    }
+
+    let mut marked = compared_spans(line, config);
+    let modifiers = config.modifiers();
+    if keyless(config)
+        && modifiers == (KeyModifiers { reverse: modifiers.reverse, ..KeyModifiers::default() })
+        && !config.ignore_trailing_blanks
+    {
+        // The key is the line itself, marked once as the last resort
+        marked.clear();
+    }
+    if marked.is_empty() || config.use_last_resort() {
+        marked.push(0..line.len());
+    }
+
+    for span in marked {
+        let indent = " ".repeat(width(&line[..span.start]));
+        if span.is_empty() {
+            writeln!(writer, "{}^ no match for key", indent)?;
+        } else {
+            let underline = "_".repeat(width(&line[span]));
+            writeln!(writer, "{}{}", indent, underline)?;
         }
     }
 
     Ok(())
 }
 
-/// Find the byte span (start, end) of a key within the line
-fn find_key_span(
-    line: &[u8],
-    key_spec: &crate::key::KeySpec,
-    config: &Config,
-) -> Option<(usize, usize)> {
-    let fields = split_fields_with_positions(line, config.field_separator);
+/// The part of each key of `line` that is compared, as --debug marks it
+///
+/// One range per -k, --json-key then --key-bytes, or one for the whole
+/// line without keys. An empty range is a key with nothing to compare,
+/// placed where it starts: past the end of the line when its field, value
+/// or start byte is missing.
+pub fn compared_spans(line: &[u8], config: &Config) -> Vec<Range<usize>> {
+    if keyless(config) {
+        return vec![compared(line, 0..line.len(), config.modifiers(), config)];
+    }
+    config
+        .keys
+        .iter()
+        .map(|spec| config.key_modifiers(spec))
+        .chain(config.json_keys.iter().map(|_| config.modifiers()))
+        .chain(config.key_bytes.iter().map(|_| config.modifiers()))
+        .zip(key_spans(line, config))
+        .map(|(modifiers, span)| match span {
+            Some(span) => compared(line, span, modifiers, config),
+            None => line.len()..line.len(),
+        })
+        .collect()
+}
+
+/// --debug=json's line for `record`, the `index`th (from 0) in the output,
+/// without its newline
+///
+/// ```text
+/// {"index":0,"record":"x 10","lossy":false,"keys":[{"key":1,"start":2,"end":4,"matched":true}]}
+/// ```
+///
+/// `keys` has `compared_spans` as byte offsets into the record (less a
+/// --crlf `\r`), keys numbered from 1. `record` is the record as UTF-8,
+/// with `lossy` true when invalid bytes had to be replaced by U+FFFD.
+pub fn json_line(record: &[u8], index: u64, config: &Config) -> Vec<u8> {
+    let line = if config.crlf { crate::input::strip_cr(record) } else { record };
+    let text = String::from_utf8_lossy(line);
+    let lossy = matches!(text, std::borrow::Cow::Owned(_));
+
+    let mut json = Vec::with_capacity(record.len() + 64);
+    write!(json, "{{\"index\":{},\"record\":", index).expect("writing to a Vec cannot fail");
+    write_json_string(&mut json, &text).expect("writing to a Vec cannot fail");
+    write!(json, ",\"lossy\":{},\"keys\":[", lossy).expect("writing to a Vec cannot fail");
+    for (key, span) in (1..).zip(compared_spans(line, config)) {
+        let comma = if key > 1 { "," } else { "" };
+        write!(
+            json,
+            "{}{{\"key\":{},\"start\":{},\"end\":{},\"matched\":{}}}",
+            comma,
+            key,
+            span.start,
+            span.end,
+            !span.is_empty()
+        )
+        .expect("writing to a Vec cannot fail");
+    }
+    json.extend_from_slice(b"]}");
+    json
+}
+
+/// `record` as text fit for a terminal, in --debug output and in messages
+/// that quote a record
+///
+/// Newline, carriage return and NUL show as `\n`, `\r` and `\0`, other
+/// control bytes (tab aside) and bytes that are not UTF-8 as `\xhh`.
+/// Everything else, backslashes included, is unchanged.
+pub fn escaped(record: &[u8]) -> String {
+    let mut shown = String::with_capacity(record.len());
+    for chunk in record.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => shown.push_str("\\n"),
+                '\r' => shown.push_str("\\r"),
+                '\0' => shown.push_str("\\0"),
+                '\t' => shown.push(c),
+                c if c.is_ascii_control() => shown.push_str(&format!("\\x{:02x}", c as u32)),
+                c => shown.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            shown.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    shown
+}
+
+/// How --debug shows the keys of each output record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugFormat {
+    /// The record over lines marking its keys, as GNU sort does
+    Text,
+    /// One JSON object per record (`json_line`)
+    Json,
+    /// The records unchanged, with what ordered each adjacent pair on
+    /// stderr (`decision_line`)
+    Decisions,
+}
+
+impl DebugFormat {
+    /// Parse a --debug argument: `text`, `json` or `decisions`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(DebugFormat::Text),
+            "json" => Some(DebugFormat::Json),
+            "decisions" => Some(DebugFormat::Decisions),
+            _ => None,
+        }
+    }
+
+    /// Whether the output records are replaced by their annotation
+    pub fn annotates(self) -> bool {
+        self != DebugFormat::Decisions
+    }
+}
 
-    let start_idx = key_spec.start_field.saturating_sub(1);
-    if start_idx >= fields.len() {
-        return None;
+/// Writes each record as --debug shows it in the output, counting them
+pub struct Annotator<'c> {
+    config: &'c Config,
+    format: DebugFormat,
+    /// Records annotated so far, the output index of the next
+    count: Cell<u64>,
+}
+
+impl<'c> Annotator<'c> {
+    /// The annotator for `config`, or None unless --debug annotates records
+    pub fn new(config: &'c Config) -> Option<Self> {
+        config.debug.filter(|format| format.annotates()).map(|format| Annotator {
+            config,
+            format,
+            count: Cell::new(0),
+        })
+    }
+
+    /// The next output record, `record`, as --debug writes it: without the
+    /// final newline, which the output terminator supplies
+    pub fn annotate(&self, record: &[u8]) -> Vec<u8> {
+        let index = self.count.replace(self.count.get() + 1);
+        match self.format {
+            DebugFormat::Text => {
+                let mut annotated = Vec::with_capacity(record.len() * 3);
+                debug_line(&mut annotated, record, self.config).expect("writing to a Vec cannot fail");
+                annotated.pop();
+                annotated
+            }
+            DebugFormat::Json => json_line(record, index, self.config),
+            DebugFormat::Decisions => unreachable!("--debug=decisions leaves records unannotated"),
+        }
     }
+}
 
-    let end_idx = key_spec
-        .end_field
-        .map(|f| f.saturating_sub(1).min(fields.len().saturating_sub(1)))
-        .unwrap_or(fields.len().saturating_sub(1));
+/// Reports on stderr what ordered each pair of adjacent output records
+/// (--debug=decisions)
+pub struct Explainer {
+    comparator: Comparator,
+    /// The last record output, and how many have been
+    previous: RefCell<Option<Vec<u8>>>,
+    count: Cell<u64>,
+}
 
-    let (first_start, first_end) = fields[start_idx];
-    let (last_start, last_end) = fields[end_idx];
+impl Explainer {
+    /// The explainer for `config`, or None without --debug=decisions
+    pub fn new(config: &Config) -> Option<Self> {
+        (config.debug == Some(DebugFormat::Decisions)).then(|| Explainer {
+            comparator: Comparator::new(config),
+            previous: RefCell::new(None),
+            count: Cell::new(0),
+        })
+    }
 
-    // Apply character offsets
-    let start_char_offset = key_spec.start_char.unwrap_or(1).saturating_sub(1);
-    let byte_start = (first_start + start_char_offset).min(first_end);
+    /// Note `record`, the next output record, explaining its place after
+    /// the one before
+    pub fn explain(&self, record: &[u8]) {
+        let line = self.count.replace(self.count.get() + 1);
+        let mut previous = self.previous.borrow_mut();
+        if let Some(previous) = previous.as_deref() {
+            let decision = decision_line(&self.comparator, previous, record);
+            // Diagnostics only: a closed stderr must not stop the output
+            let _ = writeln!(io::stderr().lock(), "rsort: lines {}-{}: {}", line, line + 1, decision);
+        }
+        let previous = previous.get_or_insert_with(Vec::new);
+        previous.clear();
+        previous.extend_from_slice(record);
+    }
+}
 
-    let byte_end = if let Some(ec) = key_spec.end_char {
-        (last_start + ec).min(last_end)
-    } else {
-        last_end
+/// What ordered `a` before `b` under `comparator`, in words
+///
+/// Names the deciding key (from 1) and the values it compared, the last
+/// resort and the two lines, or says they are equal: identical when the
+/// last resort is in effect, kept in input order when not (-s). A pair that
+/// is out of order, as in unsorted -m inputs, says so.
+pub fn decision_line(comparator: &Comparator, a: &[u8], b: &[u8]) -> String {
+    let (order, decision) = comparator.explain(a, b);
+    let placed = match order {
+        Ordering::Greater => "after",
+        Ordering::Less | Ordering::Equal => "before",
+    };
+    let quoted = |bytes: &[u8]| format!("'{}'", escaped(bytes));
+    let explanation = match decision {
+        Decision::Key(index) => format!(
+            "key {} puts {} {} {}",
+            index + 1,
+            quoted(&comparator.keys(a)[index]),
+            placed,
+            quoted(&comparator.keys(b)[index])
+        ),
+        Decision::LastResort => format!("keys equal; last resort puts {} {} {}", quoted(a), placed, quoted(b)),
+        Decision::Equal if comparator.is_stable() => return "equal; kept in input order".to_string(),
+        Decision::Equal => return "identical lines".to_string(),
     };
+    match order {
+        Ordering::Greater => format!("{}: out of order", explanation),
+        _ => explanation,
+    }
+}
+
+/// How `a` and `b` compare under `config`, step by step (--explain)
+///
+/// One line for each record, then for each key its bytes in both, how it
+/// compares them and its result, whether the last resort ran, and the
+/// final order. Keys after the deciding one are still shown, marked as
+/// not needed.
+pub fn explanation(a: &[u8], b: &[u8], config: &Config) -> String {
+    let comparator = Comparator::new(config);
+    let (order, decision) = comparator.explain(a, b);
+    let quoted = |bytes: &[u8]| format!("'{}'", escaped(bytes));
+    let mut lines = vec![format!("record 1: {}", quoted(a)), format!("record 2: {}", quoted(b))];
 
-    Some((byte_start, byte_end))
+    let missing = |record: &[u8]| -> Vec<bool> { key_spans(record, config).iter().map(Option::is_none).collect() };
+    let (missing_a, missing_b) = (missing(a), missing(b));
+    let mut keys: Vec<(String, KeyModifiers, &str)> = Vec::new();
+    for (keynum, spec) in (1..).zip(&config.keys) {
+        let modifiers = config.key_modifiers(spec);
+        let resolved = KeySpec { modifiers, ..spec.clone() };
+        keys.push((format!("key {} (-k {})", keynum, resolved), modifiers, "missing field"));
+    }
+    for (keynum, pointer) in (config.keys.len() + 1..).zip(&config.json_keys) {
+        keys.push((format!("key {} (--json-key {})", keynum, pointer), config.modifiers(), "missing value"));
+    }
+    for (keynum, range) in (config.keys.len() + config.json_keys.len() + 1..).zip(&config.key_bytes) {
+        keys.push((format!("key {} (--key-bytes {})", keynum, range), config.modifiers(), "missing bytes"));
+    }
+    if keys.is_empty() {
+        keys.push(("key (whole line)".to_string(), config.modifiers(), ""));
+    }
+    for (index, ((key_a, key_b, result), (label, modifiers, missing))) in comparator.compare_each_key(a, b).into_iter().zip(keys).enumerate() {
+        let mut line = format!(
+            "{}: {} vs {}, {}: {}",
+            label,
+            if missing_a[index] { missing.to_string() } else { quoted(&key_a) },
+            if missing_b[index] { missing.to_string() } else { quoted(&key_b) },
+            comparison(modifiers, config),
+            placement(result)
+        );
+        if modifiers.reverse && result.is_ne() {
+            line.push_str(" (reversed by r)");
+        }
+        if decision != Decision::Key(index) && result.is_ne() {
+            line.push_str("; not needed");
+        }
+        lines.push(line);
+    }
+
+    lines.push(match decision {
+        Decision::Key(index) => format!("last resort: not needed, key {} decided", index + 1),
+        _ if !config.use_last_resort() => {
+            format!("last resort: off ({}); equal keys keep their input order", if config.unique { "-u" } else { "-s" })
+        }
+        Decision::LastResort if config.reverse => {
+            format!("last resort: whole line, bytewise: {} (reversed by -r)", placement(order))
+        }
+        Decision::LastResort => format!("last resort: whole line, bytewise: {}", placement(order)),
+        Decision::Equal => "last resort: whole line, bytewise: equal".to_string(),
+    });
+    lines.push(match order {
+        Ordering::Less => "result: record 1 sorts before record 2".to_string(),
+        Ordering::Greater => "result: record 1 sorts after record 2".to_string(),
+        Ordering::Equal => "result: equal; they keep their input order".to_string(),
+    });
+    lines.join("\n")
 }
 
-/// Emit debug output for all input lines during the read phase
-pub fn debug_input<W: Write>(
-    writer: &mut W,
-    records: &[Vec<u8>],
-    config: &Config,
-) -> io::Result<()> {
-    for record in records {
-        debug_line(writer, record, config)?;
+/// How a key with `modifiers` is compared, in words
+fn comparison(modifiers: KeyModifiers, config: &Config) -> String {
+    if config.key_compare.is_some() {
+        return "custom comparison".to_string();
+    }
+    let kind = match modifiers.key_kind() {
+        KeyKind::Bytes => "bytewise",
+        KeyKind::FoldCase => "bytewise, case folded",
+        KeyKind::Numeric => "numeric",
+        KeyKind::Human => "human numeric",
+        KeyKind::Month => "by month",
+        KeyKind::Version => "by version",
+    };
+    #[cfg(feature = "collate")]
+    if let Some(collation) = &config.locale {
+        if matches!(modifiers.key_kind(), KeyKind::Bytes | KeyKind::FoldCase) {
+            return format!("by '{}' collation", collation.tag());
+        }
+    }
+    kind.to_string()
+}
+
+/// Where record 1 goes relative to record 2 for `order`
+fn placement(order: Ordering) -> &'static str {
+    match order {
+        Ordering::Less => "before",
+        Ordering::Equal => "equal",
+        Ordering::Greater => "after",
     }
-    Ok(())
+}
+
+/// The part of the key at `span` in `line` that its ordering compares
+///
+/// Numbers and months are only as long as the text read as one, and
+/// --ignore-trailing-blanks trims trailing blanks from text.
+fn compared(line: &[u8], span: Range<usize>, modifiers: KeyModifiers, config: &Config) -> Range<usize> {
+    let key = &line[span.clone()];
+    let kind = modifiers.key_kind();
+    let mut read = kind.read_range(key);
+    if matches!(kind, KeyKind::Bytes | KeyKind::FoldCase | KeyKind::Version) && config.ignore_trailing_blanks {
+        let trailing = key.iter().rev().take_while(|&&b| is_blank(b)).count();
+        read.end -= trailing;
+    }
+    span.start + read.start..span.start + read.end
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::key::KeySpec;
-
-    fn test_config() -> Config {
-        Config {
-            reverse: false,
-            numeric: false,
-            fold_case: false,
-            unique: false,
-            stable: false,
-            debug: true,
-            record_delimiter: b'\n',
-            field_separator: None,
-            keys: vec![],
-            output_file: None,
-            input_files: vec![],
-        }
-    }
 
     #[test]
     fn test_debug_whole_line() {
-        let config = test_config();
+        let config = Config::default();
         let mut output = Vec::new();
         debug_line(&mut output, b"hello", &config).unwrap();
         let output_str = String::from_utf8(output).unwrap();
@@ -125,8 +450,7 @@ mod tests {
 
     #[test]
     fn test_debug_with_key() {
-        let mut config = test_config();
-        config.keys = vec![KeySpec::parse("2,2").unwrap()];
+        let config = Config::builder().add_key("2,2").build().unwrap();
         let mut output = Vec::new();
         debug_line(&mut output, b"foo bar baz", &config).unwrap();
         let output_str = String::from_utf8(output).unwrap();
@@ -134,4 +458,286 @@ mod tests {
         // Should have underscores for "bar"
         assert!(output_str.contains("___"));
     }
+
+    fn spans(line: &[u8], config: Config) -> Vec<Option<Range<usize>>> {
+        key_spans(line, &config)
+    }
+
+    #[test]
+    fn test_key_spans_whole_line() {
+        assert_eq!(spans(b"hello", Config::default()), vec![Some(0..5)]);
+        assert_eq!(spans(b"", Config::default()), vec![Some(0..0)]);
+    }
+
+    #[test]
+    fn test_key_spans_whitespace_fields() {
+        let config = Config::builder().add_key("2,2").add_key("1").build().unwrap();
+        assert_eq!(spans(b"foo  bar baz", config), vec![Some(5..8), Some(0..12)]);
+    }
+
+    #[test]
+    fn test_key_spans_separator() {
+        let config = Config::builder()
+            .field_separator(b',')
+            .add_key("2,3")
+            .add_key("3,3")
+            .build()
+            .unwrap();
+        assert_eq!(spans(b"a,bb,,d", config), vec![Some(2..5), Some(5..5)]);
+    }
+
+    #[test]
+    fn test_key_spans_char_offsets() {
+        let config = Config::builder().add_key("1.2,1.3").add_key("2.9").build().unwrap();
+        assert_eq!(spans(b"abcd xy", config), vec![Some(1..3), Some(7..7)]);
+    }
+
+    #[test]
+    fn test_key_spans_missing_field() {
+        let config = Config::builder().add_key("3").build().unwrap();
+        assert_eq!(spans(b"a b", config), vec![None]);
+
+        let config = Config::builder().field_separator(b':').add_key("2").build().unwrap();
+        assert_eq!(spans(b"a", config.clone()), vec![None]);
+        assert_eq!(spans(b"a:", config), vec![Some(2..2)]);
+    }
+
+    #[test]
+    fn test_debug_multibyte_separator() {
+        let config = Config::builder().field_separator('§').add_key("2,3").build().unwrap();
+        assert_eq!(spans("ab§c§dé".as_bytes(), config.clone()), vec![Some(4..10)]);
+
+        let mut output = Vec::new();
+        debug_line(&mut output, "ab§c§dé".as_bytes(), &config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "ab§c§dé\n   ____\n_______\n");
+
+        // Not UTF-8: the stray byte is escaped, four columns wide
+        let mut output = Vec::new();
+        debug_line(&mut output, b"\xff\xc2\xa7x", &Config::default()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\\xff§x\n______\n");
+    }
+
+    #[test]
+    fn test_key_spans_string_separator() {
+        let config = Config::builder()
+            .field_separator_str(" | ")
+            .add_key("2,3")
+            .add_key("2,2")
+            .build()
+            .unwrap();
+        assert_eq!(spans(b"web |  | vacuum", config), vec![Some(6..15), Some(6..6)]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_debug_marks_regex_separators() {
+        let config = Config::builder()
+            .field_separator_regex(r"[,\s]+")
+            .add_key("2,2")
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+        debug_line(&mut output, b"a, b  c", &config).unwrap();
+        debug_line(&mut output, b"abc", &config).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a, b  c\n ^^ ^^\n   _\n_______\nabc\n\n   ^ no match for key\n___\n"
+        );
+    }
+
+    #[test]
+    fn test_debug_renders_missing_key() {
+        let config = Config::builder().add_key("3").add_key("1").build().unwrap();
+        let mut output = Vec::new();
+        debug_line(&mut output, b"a b", &config).unwrap();
+        assert_eq!(output, b"a b\n   ^ no match for key\n___\n___\n");
+    }
+
+    #[test]
+    fn test_debug_empty_field_is_not_missing() {
+        // An empty field is marked where it is, a missing one past the end
+        // of the line; GNU sort words both as no match
+        let config = Config::builder().field_separator(b':').add_key("2,2").stable(true).build().unwrap();
+        assert_eq!(key_spans(b"a::c", &config), vec![Some(2..2)]);
+        assert_eq!(key_spans(b"a", &config), vec![None]);
+        let mut output = Vec::new();
+        debug_line(&mut output, b"a::c", &config).unwrap();
+        debug_line(&mut output, b"a", &config).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a::c\n  ^ no match for key\na\n ^ no match for key\n"
+        );
+
+        // Blank-run fields: a trailing blank starts no field
+        let config = Config::builder().add_key("2,2").stable(true).build().unwrap();
+        let mut output = Vec::new();
+        debug_line(&mut output, b"a ", &config).unwrap();
+        debug_line(&mut output, b"a  b", &config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "a \n  ^ no match for key\na  b\n   _\n");
+    }
+
+    #[test]
+    fn test_debug_crlf_leaves_out_cr() {
+        let config = Config::builder().crlf(true).add_key("2").build().unwrap();
+        let mut output = Vec::new();
+        debug_line(&mut output, b"a bc\r", &config).unwrap();
+        assert_eq!(output, b"a bc\n  __\n____\n");
+    }
+
+    #[test]
+    fn test_debug_tightens_typed_keys() {
+        let mut output = Vec::new();
+        let config = Config::builder().numeric(true).build().unwrap();
+        debug_line(&mut output, b" 10 z", &config).unwrap();
+        debug_line(&mut output, b"abc", &config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), " 10 z\n __\n_____\nabc\n^ no match for key\n___\n");
+
+        let mut output = Vec::new();
+        let config = Config::builder().add_key("2,2M").add_key("1,1b").stable(true).build().unwrap();
+        debug_line(&mut output, b"x\t Feb 1", &config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "x> Feb 1\n   ___\n_\n");
+    }
+
+    #[test]
+    fn test_debug_last_resort_line() {
+        let mut output = Vec::new();
+        debug_line(&mut output, b"ab", &Config::builder().reverse(true).build().unwrap()).unwrap();
+        debug_line(&mut output, b"ab", &Config::builder().fold_case(true).build().unwrap()).unwrap();
+        debug_line(&mut output, b"ab", &Config::builder().fold_case(true).unique(true).build().unwrap()).unwrap();
+        debug_line(&mut output, b"", &Config::default()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "ab\n__\nab\n__\n__\nab\n__\n\n^ no match for key\n");
+    }
+
+    #[test]
+    fn test_debug_last_resort_follows_stable() {
+        // GNU sort 9.1: printf '09\n' | sort --debug -n [-s]
+        let mut output = Vec::new();
+        debug_line(&mut output, b"09", &Config::builder().numeric(true).build().unwrap()).unwrap();
+        debug_line(&mut output, b"09", &Config::builder().numeric(true).stable(true).build().unwrap()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "09\n__\n__\n09\n__\n");
+    }
+
+    #[test]
+    fn test_debug_tabs_keep_marks_aligned() {
+        // GNU sort 9.1: printf 'a1\tbob smith\t 42\n' | sort --debug -t'\t' -k2,2 -k3,3n
+        let config = Config::builder()
+            .field_separator(b'\t')
+            .add_key("2,2")
+            .add_key("3,3n")
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+        debug_line(&mut output, b"a1\tbob smith\t 42", &config).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a1>bob smith> 42\n   _________\n              __\n________________\n"
+        );
+    }
+
+    #[test]
+    fn test_escaped() {
+        assert_eq!(escaped(b"a\nb\r\0"), "a\\nb\\r\\0");
+        assert_eq!(escaped(b"\x1b[1m\x7f\t"), "\\x1b[1m\\x7f\t");
+        assert_eq!(escaped("é\\".as_bytes()), "é\\");
+        assert_eq!(escaped(b"\xffa\xc3"), "\\xffa\\xc3");
+    }
+
+    #[test]
+    fn test_debug_escapes_control_bytes() {
+        // Each underline is as long as the escaped key it marks
+        let config = Config::builder().record_delimiter(b'\0').field_separator(b':').add_key("2,2").build().unwrap();
+        for (record, shown, key) in [
+            (&b"a:x\ny"[..], "a:x\\ny", "x\\ny"),
+            (b"\r:\rz", "\\r:\\rz", "\\rz"),
+            (b"\0:\0", "\\0:\\0", "\\0"),
+            (b"\x01:\x1b", "\\x01:\\x1b", "\\x1b"),
+            (b"\xfe:\xff!", "\\xfe:\\xff!", "\\xff!"),
+        ] {
+            let mut output = Vec::new();
+            debug_line(&mut output, record, &config).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            let lines: Vec<&str> = output.lines().collect();
+            assert_eq!(lines[0], shown);
+            let indent = shown.len() - key.len();
+            assert_eq!(lines[1], format!("{}{}", " ".repeat(indent), "_".repeat(key.len())));
+            assert_eq!(lines[2], "_".repeat(shown.len()));
+        }
+    }
+
+    #[test]
+    fn test_annotate_leaves_off_the_last_newline() {
+        let config = Config::builder().debug(true).add_key("2").build().unwrap();
+        let annotator = Annotator::new(&config).unwrap();
+        assert_eq!(annotator.annotate(b"a\tb"), b"a>b\n  _\n___");
+    }
+
+    #[test]
+    fn test_json_line() {
+        let config = Config::builder().add_key("2,2n").add_key("3").build().unwrap();
+        assert_eq!(
+            String::from_utf8(json_line(b"x  10", 4, &config)).unwrap(),
+            r#"{"index":4,"record":"x  10","lossy":false,"keys":[{"key":1,"start":3,"end":5,"matched":true},{"key":2,"start":5,"end":5,"matched":false}]}"#
+        );
+        let json = json_line(b"\xff \"q\"", 0, &Config::default());
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"{"index":0,"record":"� \"q\"","lossy":true,"keys":[{"key":1,"start":0,"end":5,"matched":true}]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_annotator_counts_records() {
+        let config = Config::builder().debug_format(DebugFormat::Json).build().unwrap();
+        let annotator = Annotator::new(&config).unwrap();
+        assert!(annotator.annotate(b"b").starts_with(br#"{"index":0,"#));
+        assert!(annotator.annotate(b"a").starts_with(br#"{"index":1,"#));
+    }
+
+    #[test]
+    fn test_decision_line() {
+        let config = Config::builder().field_separator(b':').add_key("1,1").add_key("2,2n").build().unwrap();
+        let comparator = Comparator::new(&config);
+        let decide = |a: &[u8], b: &[u8]| decision_line(&comparator, a, b);
+        assert_eq!(decide(b"a:2", b"b:1"), "key 1 puts 'a' before 'b'");
+        assert_eq!(decide(b"a:9", b"a:10"), "key 2 puts '9' before '10'");
+        assert_eq!(decide(b"a:10", b"a:9"), "key 2 puts '10' after '9': out of order");
+        assert_eq!(decide(b"a:09", b"a:9"), "keys equal; last resort puts 'a:09' before 'a:9'");
+        assert_eq!(decide(b"a:9", b"a:9"), "identical lines");
+        assert_eq!(decide(b"a\x1b:1", b"c:1"), "key 1 puts 'a\\x1b' before 'c'");
+
+        let config = Config::builder().numeric(true).stable(true).build().unwrap();
+        assert_eq!(decision_line(&Comparator::new(&config), b"09", b"9"), "equal; kept in input order");
+    }
+
+    #[test]
+    fn test_decisions_leave_records_unannotated() {
+        let config = Config::builder().debug_format(DebugFormat::Decisions).build().unwrap();
+        assert!(Annotator::new(&config).is_none());
+        assert!(Explainer::new(&config).is_some());
+        let config = Config::builder().debug_format(DebugFormat::Decisions).record_delimiter(b'\0').build().unwrap();
+        assert_eq!(config.output_terminator(), b"\0");
+    }
+
+    #[test]
+    fn test_explanation() {
+        let config = Config::builder().field_separator(b':').add_key("1,1r").add_key("2,2n").build().unwrap();
+        assert_eq!(
+            explanation(b"a:1", b"b:1", &config),
+            "record 1: 'a:1'\n\
+             record 2: 'b:1'\n\
+             key 1 (-k 1,1r): 'a' vs 'b', bytewise: after (reversed by r)\n\
+             key 2 (-k 2,2n): '1' vs '1', numeric: equal\n\
+             last resort: not needed, key 1 decided\n\
+             result: record 1 sorts after record 2"
+        );
+        let config = Config::builder().unique(true).build().unwrap();
+        assert_eq!(
+            explanation(b"a", b"a", &config),
+            "record 1: 'a'\n\
+             record 2: 'a'\n\
+             key (whole line): 'a' vs 'a', bytewise: equal\n\
+             last resort: off (-u); equal keys keep their input order\n\
+             result: equal; they keep their input order"
+        );
+    }
 }
@@ -0,0 +1,314 @@
+//! Input and output text encodings (`--encoding`, `--output-encoding`)
+//!
+//! Sorting always works on UTF-8 bytes: UTF-16 input is decoded before
+//! records are split, and output can be encoded back to UTF-16 on the way
+//! out.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// A text encoding rsort can read or write
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Bytes as they are; the default
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Parse `utf-8`, `utf-16le` or `utf-16be`, ignoring case
+    pub fn parse(name: &str) -> Option<Self> {
+        [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The name `parse` accepts
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+        }
+    }
+}
+
+/// `reader` decoded to UTF-8 from `encoding`
+pub fn decode<'a>(reader: Box<dyn BufRead + 'a>, encoding: Encoding) -> Box<dyn BufRead + 'a> {
+    match encoding {
+        Encoding::Utf8 => reader,
+        Encoding::Utf16Le => Box::new(BufReader::new(Utf16Reader::new(reader, false))),
+        Encoding::Utf16Be => Box::new(BufReader::new(Utf16Reader::new(reader, true))),
+    }
+}
+
+/// `writer` taking UTF-8 and writing `encoding`
+pub fn encode<'a>(writer: Box<dyn Write + 'a>, encoding: Encoding) -> Box<dyn Write + 'a> {
+    match encoding {
+        Encoding::Utf8 => writer,
+        Encoding::Utf16Le => Box::new(Utf16Writer::new(writer, false)),
+        Encoding::Utf16Be => Box::new(Utf16Writer::new(writer, true)),
+    }
+}
+
+/// Reads UTF-16 from `inner` as UTF-8, dropping a leading byte order mark
+///
+/// An odd number of bytes or an unpaired surrogate is an `InvalidData` error.
+pub struct Utf16Reader<R> {
+    inner: R,
+    big_endian: bool,
+    /// Bytes read but not decoded: an odd byte or a high surrogate whose
+    /// pair is still to come
+    raw: Vec<u8>,
+    /// Decoded UTF-8 not yet returned, from `pos`
+    decoded: Vec<u8>,
+    pos: usize,
+    at_start: bool,
+    eof: bool,
+}
+
+impl<R: Read> Utf16Reader<R> {
+    pub fn new(inner: R, big_endian: bool) -> Self {
+        Utf16Reader {
+            inner,
+            big_endian,
+            raw: Vec::new(),
+            decoded: Vec::new(),
+            pos: 0,
+            at_start: true,
+            eof: false,
+        }
+    }
+
+    /// Decode the next chunk of `inner` into `decoded`
+    fn fill(&mut self) -> io::Result<()> {
+        self.decoded.clear();
+        self.pos = 0;
+
+        let mut chunk = [0u8; 8192];
+        let n = loop {
+            match self.inner.read(&mut chunk) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if n == 0 {
+            self.eof = true;
+            return match self.raw.len() {
+                0 => Ok(()),
+                2 => Err(invalid_data("truncated UTF-16 input: unpaired surrogate at end")),
+                _ => Err(invalid_data("truncated UTF-16 input: odd number of bytes")),
+            };
+        }
+        self.raw.extend_from_slice(&chunk[..n]);
+
+        let mut units: Vec<u16> = self
+            .raw
+            .chunks_exact(2)
+            .map(|pair| {
+                let pair = [pair[0], pair[1]];
+                if self.big_endian {
+                    u16::from_be_bytes(pair)
+                } else {
+                    u16::from_le_bytes(pair)
+                }
+            })
+            .collect();
+        // A high surrogate at the end waits for its pair
+        if units.last().is_some_and(|unit| (0xD800..0xDC00).contains(unit)) {
+            units.pop();
+        }
+        self.raw.drain(..units.len() * 2);
+
+        for c in char::decode_utf16(units) {
+            let c = c.map_err(|e| {
+                invalid_data(&format!(
+                    "invalid UTF-16 input: unpaired surrogate {:#06x}",
+                    e.unpaired_surrogate()
+                ))
+            })?;
+            if self.at_start {
+                self.at_start = false;
+                if c == '\u{FEFF}' {
+                    continue;
+                }
+            }
+            let mut utf8 = [0u8; 4];
+            self.decoded.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Utf16Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.eof {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Writes UTF-8 given to it as UTF-16 to `inner`, without a byte order mark
+///
+/// Invalid UTF-8 is an `InvalidData` error, as is a character left
+/// incomplete when the writer is flushed.
+pub struct Utf16Writer<W> {
+    inner: W,
+    big_endian: bool,
+    /// Start of a UTF-8 sequence split across writes
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Utf16Writer<W> {
+    pub fn new(inner: W, big_endian: bool) -> Self {
+        Utf16Writer {
+            inner,
+            big_endian,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Utf16Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let done = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // A character cut off at the end waits for the next write
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                self.pending.truncate(self.pending.len() - buf.len());
+                return Err(invalid_data("output is not valid UTF-8; cannot encode it as UTF-16"));
+            }
+        };
+        let valid = std::str::from_utf8(&self.pending[..done]).expect("checked above");
+        let mut encoded = Vec::with_capacity(done * 2);
+        for unit in valid.encode_utf16() {
+            let bytes = if self.big_endian {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            };
+            encoded.extend_from_slice(&bytes);
+        }
+        self.inner.write_all(&encoded)?;
+        self.pending.drain(..done);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            return Err(invalid_data(
+                "output ends inside a UTF-8 character; cannot encode it as UTF-16",
+            ));
+        }
+        self.inner.flush()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Hands out one byte per read, to split every code unit
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.split_first() {
+                Some((&byte, rest)) if !buf.is_empty() => {
+                    buf[0] = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut writer = Utf16Writer::new(Vec::new(), big_endian);
+        writer.write_all(text.as_bytes()).unwrap();
+        writer.flush().unwrap();
+        writer.inner
+    }
+
+    fn decode_all(reader: impl Read) -> io::Result<String> {
+        let mut text = String::new();
+        BufReader::new(reader).read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Encoding::parse("UTF-16LE"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::parse("utf-8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::parse("latin1"), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "zé\n𝄞 clef\nabc\n";
+        for big_endian in [false, true] {
+            let bytes = utf16(text, big_endian);
+            assert_eq!(bytes.len(), text.encode_utf16().count() * 2);
+            let decoded = decode_all(Utf16Reader::new(Cursor::new(&bytes), big_endian)).unwrap();
+            assert_eq!(decoded, text);
+            let decoded = decode_all(Utf16Reader::new(Trickle(&bytes), big_endian)).unwrap();
+            assert_eq!(decoded, text);
+        }
+        assert_eq!(utf16("é", false), [0xE9, 0x00]);
+        assert_eq!(utf16("é", true), [0x00, 0xE9]);
+    }
+
+    #[test]
+    fn test_leading_bom_is_dropped() {
+        let decoded = decode_all(Utf16Reader::new(Trickle(b"\xFF\xFEa\x00"), false)).unwrap();
+        assert_eq!(decoded, "a");
+        let decoded = decode_all(Utf16Reader::new(&b"\xFE\xFF\x00a\xFE\xFF"[..], true)).unwrap();
+        assert_eq!(decoded, "a\u{FEFF}");
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let err = decode_all(Utf16Reader::new(&b"a\x00b"[..], false)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "truncated UTF-16 input: odd number of bytes");
+
+        let err = decode_all(Utf16Reader::new(&b"a\x00\x34\xD8"[..], false)).unwrap_err();
+        assert_eq!(err.to_string(), "truncated UTF-16 input: unpaired surrogate at end");
+
+        let err = decode_all(Utf16Reader::new(&b"\x00\xDCa\x00"[..], false)).unwrap_err();
+        assert_eq!(err.to_string(), "invalid UTF-16 input: unpaired surrogate 0xdc00");
+    }
+
+    #[test]
+    fn test_writer_joins_split_characters() {
+        let mut writer = Utf16Writer::new(Vec::new(), false);
+        for byte in "é𝄞".as_bytes() {
+            writer.write_all(&[*byte]).unwrap();
+        }
+        writer.flush().unwrap();
+        assert_eq!(writer.inner, utf16("é𝄞", false));
+
+        let mut writer = Utf16Writer::new(Vec::new(), false);
+        writer.write_all(b"\xC3").unwrap();
+        assert_eq!(writer.flush().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let err = Utf16Writer::new(Vec::new(), false).write(b"a\xFF").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,15 +1,347 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
 use thiserror::Error;
 
+use crate::compress::Codec;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum RsortError {
-    #[error("I/O error: {0}")]
-    Io(#[from] std::io::Error),
+    #[error("I/O error: {}", os_message(.0))]
+    Io(io::Error),
+
+    #[error("cannot read: {}: {}", path.display(), os_message(source))]
+    OpenInput { path: PathBuf, source: io::Error },
+
+    #[error("read failed: {}: {}", path.display(), os_message(source))]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("open failed: {}: {}", path.display(), os_message(source))]
+    OpenOutput { path: PathBuf, source: io::Error },
+
+    #[error("write failed: {}: {}", output_name(path.as_deref()), os_message(source))]
+    Write {
+        /// None for standard output
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
 
     #[error("Invalid key specification: {0}")]
     InvalidKey(String),
 
     #[error("Invalid field delimiter: must be a single byte")]
     InvalidDelimiter,
+
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("invalid profile {}{}: {message}", path.display(), line_suffix(*line))]
+    Profile {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+
+    #[error("{}: invalid {codec} data: {}", path.display(), os_message(source))]
+    Decompress {
+        path: PathBuf,
+        codec: Codec,
+        source: io::Error,
+    },
+
+    #[error("{program} failed{}: {status}", on_path(path.as_deref()))]
+    ChildProcess {
+        program: String,
+        /// The file it was working on, if any
+        path: Option<PathBuf>,
+        status: ExitStatus,
+    },
+
+    #[error(
+        "memory exhausted: could not allocate {needed} more bytes with {used} bytes buffered; \
+         input is too large to sort in memory without external sorting"
+    )]
+    MemoryExhausted { needed: usize, used: usize },
+
+    #[error(
+        "{}record starting at byte {offset} is longer than --max-record-size ({limit} bytes); \
+         binary input? use -z for NUL-delimited records",
+        path_prefix(path.as_deref())
+    )]
+    RecordTooLarge {
+        /// None until `reading` names the input
+        path: Option<PathBuf>,
+        offset: u64,
+        limit: u64,
+    },
+
+    #[error(
+        "{}trailing partial record at byte {offset}: {len} bytes, short of --record-size ({size} bytes)",
+        path_prefix(path.as_deref())
+    )]
+    PartialRecord {
+        /// None until `reading` names the input
+        path: Option<PathBuf>,
+        offset: u64,
+        /// Bytes left at the end of the input
+        len: u64,
+        size: u64,
+    },
+
+    #[error("{}record at byte {offset}: {problem}", path_prefix(path.as_deref()))]
+    BadFrame {
+        /// None until `reading` names the input
+        path: Option<PathBuf>,
+        offset: u64,
+        problem: String,
+    },
+
+    #[error(
+        "--verify: {}:{line}: disorder: {record}; output moved to {}",
+        path.display(),
+        moved.display()
+    )]
+    Unverified {
+        path: PathBuf,
+        /// 1-based number of the first record out of order
+        line: u64,
+        record: String,
+        /// Where the bad output was renamed to
+        moved: PathBuf,
+    },
+}
+
+impl RsortError {
+    /// Process exit status for this error
+    ///
+    /// GNU sort exits 2 for every failure; 1 is reserved for `-c` disorder.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RsortError::Usage(_)
+            | RsortError::InvalidKey(_)
+            | RsortError::InvalidDelimiter
+            | RsortError::Profile { .. } => 2,
+            RsortError::Io(_)
+            | RsortError::OpenInput { .. }
+            | RsortError::Read { .. }
+            | RsortError::OpenOutput { .. }
+            | RsortError::Write { .. }
+            | RsortError::Decompress { .. }
+            | RsortError::ChildProcess { .. }
+            | RsortError::MemoryExhausted { .. }
+            | RsortError::RecordTooLarge { .. }
+            | RsortError::PartialRecord { .. }
+            | RsortError::BadFrame { .. }
+            | RsortError::Unverified { .. } => 2,
+        }
+    }
+
+    /// Whatever read standard output stopped reading it, as `head -1` does
+    ///
+    /// Not a failure: the reader got everything it wanted. Only seen where
+    /// SIGPIPE does not end the process first (Windows, or SIGPIPE blocked).
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(
+            self,
+            RsortError::Write { path: None, source } if source.kind() == io::ErrorKind::BrokenPipe
+        )
+    }
+
+    /// Carry this error through an `io::Error`, for `Read` implementations
+    ///
+    /// Converting the `io::Error` back gives this error unchanged.
+    pub fn into_io(self) -> io::Error {
+        io::Error::other(self)
+    }
+
+    /// Attach an input path to an error raised while reading it
+    pub fn reading(self, path: impl AsRef<Path>) -> Self {
+        match self {
+            RsortError::Io(source) => RsortError::Read {
+                path: path.as_ref().to_path_buf(),
+                source,
+            },
+            RsortError::RecordTooLarge { path: None, offset, limit } => RsortError::RecordTooLarge {
+                path: Some(path.as_ref().to_path_buf()),
+                offset,
+                limit,
+            },
+            RsortError::PartialRecord { path: None, offset, len, size } => RsortError::PartialRecord {
+                path: Some(path.as_ref().to_path_buf()),
+                offset,
+                len,
+                size,
+            },
+            RsortError::BadFrame { path: None, offset, problem } => RsortError::BadFrame {
+                path: Some(path.as_ref().to_path_buf()),
+                offset,
+                problem,
+            },
+            other => other,
+        }
+    }
+
+    /// Attach the output (None for stdout) to an error raised while writing
+    pub fn writing(self, path: Option<&Path>) -> Self {
+        match self {
+            RsortError::Io(source) => RsortError::Write {
+                path: path.map(Path::to_path_buf),
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+impl From<io::Error> for RsortError {
+    /// An `RsortError` carried by the I/O error (see `into_io`) comes back
+    /// out as itself
+    fn from(err: io::Error) -> Self {
+        if !err.get_ref().is_some_and(|inner| inner.is::<RsortError>()) {
+            return RsortError::Io(err);
+        }
+        *err.into_inner()
+            .and_then(|inner| inner.downcast().ok())
+            .expect("checked to be an RsortError")
+    }
+}
+
+/// How GNU names an output in diagnostics
+fn output_name(path: Option<&Path>) -> String {
+    match path {
+        Some(path) => path.display().to_string(),
+        None => "'standard output'".to_string(),
+    }
+}
+
+/// An I/O error as GNU prints it: "No such file or directory", without
+/// Rust's " (os error 2)" suffix
+pub(crate) fn os_message(err: &io::Error) -> String {
+    let message = err.to_string();
+    match err.raw_os_error() {
+        Some(code) => {
+            let suffix = format!(" (os error {})", code);
+            message.strip_suffix(&suffix).unwrap_or(&message).to_string()
+        }
+        None => message,
+    }
+}
+
+/// "PATH: " when the input is known (merge inputs are not)
+fn path_prefix(path: Option<&Path>) -> String {
+    path.map(|path| format!("{}: ", path.display())).unwrap_or_default()
+}
+
+/// " on PATH" when the file is known
+fn on_path(path: Option<&Path>) -> String {
+    path.map(|path| format!(" on {}", path.display())).unwrap_or_default()
+}
+
+/// ":LINE" when the line is known
+fn line_suffix(line: Option<usize>) -> String {
+    line.map(|line| format!(":{}", line)).unwrap_or_default()
 }
 
 pub type Result<T> = std::result::Result<T, RsortError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn not_found() -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, "No such file or directory")
+    }
+
+    #[test]
+    fn test_messages_name_the_path() {
+        let err = RsortError::OpenInput {
+            path: PathBuf::from("missing.txt"),
+            source: not_found(),
+        };
+        assert_eq!(err.to_string(), "cannot read: missing.txt: No such file or directory");
+
+        let err = RsortError::OpenOutput {
+            path: PathBuf::from("/no/such/dir/out"),
+            source: not_found(),
+        };
+        assert!(err.to_string().contains("/no/such/dir/out"));
+
+        let err = RsortError::Write {
+            path: Some(PathBuf::from("out.txt")),
+            source: io::Error::other("No space left on device"),
+        };
+        assert_eq!(err.to_string(), "write failed: out.txt: No space left on device");
+    }
+
+    #[test]
+    fn test_os_errors_drop_code_suffix() {
+        let err = RsortError::OpenInput {
+            path: PathBuf::from("missing.txt"),
+            source: io::Error::from_raw_os_error(2),
+        };
+        let message = err.to_string();
+        assert!(message.starts_with("cannot read: missing.txt: "), "{}", message);
+        assert!(!message.contains("os error"), "{}", message);
+    }
+
+    #[test]
+    fn test_stdout_write_error_names_standard_output() {
+        let err = RsortError::Write {
+            path: None,
+            source: io::Error::other("No space left on device"),
+        };
+        assert!(err.to_string().contains("'standard output'"));
+    }
+
+    #[test]
+    fn test_reading_adds_path_to_io_errors_only() {
+        let err = RsortError::Io(not_found()).reading("in.txt");
+        assert!(matches!(err, RsortError::Read { ref path, .. } if path == Path::new("in.txt")));
+        assert!(err.to_string().contains("in.txt"));
+
+        let err = RsortError::MemoryExhausted { needed: 1, used: 2 }.reading("in.txt");
+        assert!(matches!(err, RsortError::MemoryExhausted { .. }));
+
+        let err = RsortError::RecordTooLarge { path: None, offset: 10, limit: 5 }.reading("in.txt");
+        assert!(err.to_string().starts_with("in.txt: record starting at byte 10 "), "{}", err);
+
+        let err = RsortError::PartialRecord { path: None, offset: 8, len: 3, size: 4 }.reading("in.txt");
+        assert!(err.to_string().starts_with("in.txt: trailing partial record at byte 8: "), "{}", err);
+    }
+
+    #[test]
+    fn test_writing_keeps_carried_errors() {
+        let err = RsortError::from(not_found()).writing(Some(Path::new("out.txt")));
+        assert!(matches!(err, RsortError::Write { path: Some(_), .. }));
+
+        let carried = RsortError::Usage("bad".to_string()).into_io();
+        let err = RsortError::from(carried).writing(None);
+        assert!(matches!(err, RsortError::Usage(_)));
+    }
+
+    #[test]
+    fn test_exit_codes_match_gnu() {
+        assert_eq!(RsortError::Usage("bad".to_string()).exit_code(), 2);
+        assert_eq!(RsortError::InvalidKey("0".to_string()).exit_code(), 2);
+        assert_eq!(RsortError::Io(not_found()).exit_code(), 2);
+        assert_eq!(RsortError::MemoryExhausted { needed: 1, used: 0 }.exit_code(), 2);
+        let unverified = RsortError::Unverified {
+            path: PathBuf::from("out"),
+            line: 2,
+            record: "a".to_string(),
+            moved: PathBuf::from("out.unverified"),
+        };
+        assert_eq!(unverified.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_broken_pipe_only_on_stdout() {
+        let broken = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert!(RsortError::Io(broken()).writing(None).is_broken_pipe());
+        assert!(!RsortError::Io(broken()).writing(Some(Path::new("out"))).is_broken_pipe());
+        assert!(!RsortError::Io(broken()).reading("in").is_broken_pipe());
+        assert!(!RsortError::Io(not_found()).writing(None).is_broken_pipe());
+    }
+}
@@ -0,0 +1,568 @@
+//! Field splitting shared by key extraction, comparison and --debug
+//!
+//! Contract, for a record and an optional `-t` separator:
+//!
+//! - With a separator, every separator ends a field (a multi-byte
+//!   separator or --field-separator-str matches leftmost first, without
+//!   overlapping), so a record with `n`
+//!   separators has `n + 1` fields, some possibly empty. An empty record is
+//!   one empty field.
+//! - Without one, fields are the maximal runs of non-blank bytes (see
+//!   `is_blank`). A record with no such run is one empty field at 0.
+//! - With a separator regex (`--field-separator-regex`, feature `regex`),
+//!   every match ends a field, by the same rules. Patterns that can match
+//!   the empty string are rejected when compiled.
+//! - In CSV mode (`--csv`), fields follow RFC 4180 on a single record: a
+//!   field starting with `"` runs to its closing quote, `""` inside it is an
+//!   escaped quote, and separators inside quotes don't split. A quote
+//!   anywhere else is an ordinary byte. Keys see a quoted field's content,
+//!   without the quotes (escaped quotes stay doubled). Quoted newlines are
+//!   not supported: records are split on the delimiter first.
+//!
+//! Spans are `(start, end)` byte offsets into the record, end exclusive.
+
+use crate::config::Config;
+#[cfg(feature = "regex")]
+use crate::error::{Result, RsortError};
+
+/// A `-t` field separator
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FieldSeparator {
+    /// One byte, the common case
+    Byte(u8),
+    /// Several bytes matched as a unit: the UTF-8 encoding of a char like
+    /// `§`, or a --field-separator-str string like `" | "`
+    Bytes(Box<[u8]>),
+}
+
+impl FieldSeparator {
+    /// Separator from its bytes, None when empty
+    pub fn new(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [] => None,
+            [byte] => Some(FieldSeparator::Byte(*byte)),
+            _ => Some(FieldSeparator::Bytes(bytes.into())),
+        }
+    }
+
+    /// The bytes that separate fields
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            FieldSeparator::Byte(byte) => std::slice::from_ref(byte),
+            FieldSeparator::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+impl From<u8> for FieldSeparator {
+    fn from(byte: u8) -> Self {
+        FieldSeparator::Byte(byte)
+    }
+}
+
+impl From<char> for FieldSeparator {
+    /// The UTF-8 encoding of `c`
+    fn from(c: char) -> Self {
+        let mut buf = [0; 4];
+        FieldSeparator::new(c.encode_utf8(&mut buf).as_bytes())
+            .expect("a char is at least one byte")
+    }
+}
+
+/// A compiled --field-separator-regex, matching bytes so any input works
+#[cfg(feature = "regex")]
+#[derive(Clone, Debug)]
+pub struct SeparatorRegex(regex::bytes::Regex);
+
+#[cfg(feature = "regex")]
+impl SeparatorRegex {
+    /// Compile `pattern`, rejecting patterns that can match the empty string
+    pub fn new(pattern: &str) -> Result<Self> {
+        let invalid = |reason: &dyn std::fmt::Display| {
+            RsortError::Usage(format!("invalid --field-separator-regex '{}': {}", pattern, reason))
+        };
+        let hir = regex_syntax::Parser::new().parse(pattern).map_err(|e| invalid(&e))?;
+        if hir.properties().minimum_len() == Some(0) {
+            return Err(invalid(&"it can match the empty string"));
+        }
+        regex::bytes::Regex::new(pattern).map(SeparatorRegex).map_err(|e| invalid(&e))
+    }
+
+    /// The pattern as given
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Span of each separator match in `record`
+    pub fn matches<'a>(&'a self, record: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.0.find_iter(record).map(|m| (m.start(), m.end()))
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PartialEq for SeparatorRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for SeparatorRegex {}
+
+/// How records are cut into fields
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Splitter {
+    /// Runs of blanks (no -t)
+    Blanks,
+    /// Every occurrence of the separator (-t)
+    Separator(FieldSeparator),
+    /// CSV fields between separators (--csv)
+    Csv(FieldSeparator),
+    /// Every match of a regex (--field-separator-regex)
+    #[cfg(feature = "regex")]
+    Regex(SeparatorRegex),
+}
+
+impl Splitter {
+    /// Blank runs for None, otherwise the separator
+    pub fn from_separator(separator: Option<&FieldSeparator>) -> Self {
+        separator.map_or(Splitter::Blanks, |sep| Splitter::Separator(sep.clone()))
+    }
+
+    /// The splitter `config` asks for; --csv without -t splits on commas
+    pub fn from_config(config: &Config) -> Self {
+        #[cfg(feature = "regex")]
+        if let Some(regex) = &config.field_separator_regex {
+            return Splitter::Regex(regex.clone());
+        }
+        if config.csv {
+            let separator = config.field_separator.clone();
+            Splitter::Csv(separator.unwrap_or(FieldSeparator::Byte(b',')))
+        } else {
+            Splitter::from_separator(config.field_separator.as_ref())
+        }
+    }
+
+    /// Iterate over the span keys see in each field of `record`
+    ///
+    /// That is the whole field, except for quoted CSV fields.
+    pub fn spans<'a>(&'a self, record: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        match self {
+            Splitter::Blanks => Spans::Plain(split(record, None)),
+            Splitter::Separator(sep) => Spans::Plain(split(record, Some(sep))),
+            Splitter::Csv(sep) => Spans::Csv(split_csv(record, sep)),
+            #[cfg(feature = "regex")]
+            Splitter::Regex(regex) => Spans::Regex(RegexFields {
+                record,
+                matches: regex.0.find_iter(record),
+                pos: 0,
+                done: false,
+            }),
+        }
+    }
+}
+
+/// Either kind of field iterator, without boxing
+enum Spans<'a> {
+    Plain(Fields<'a>),
+    Csv(CsvFields<'a>),
+    #[cfg(feature = "regex")]
+    Regex(RegexFields<'a>),
+}
+
+impl Iterator for Spans<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        match self {
+            Spans::Plain(fields) => fields.next(),
+            Spans::Csv(fields) => fields.next().map(|field| field.content),
+            #[cfg(feature = "regex")]
+            Spans::Regex(fields) => fields.next(),
+        }
+    }
+}
+
+/// Iterate over the `(start, end)` span of each field of `record`
+///
+/// Nothing is allocated; use `collect_fields` when random access is needed.
+pub fn split_fields_with_positions<'a>(
+    record: &'a [u8],
+    separator: Option<&'a FieldSeparator>,
+) -> impl Iterator<Item = (usize, usize)> + 'a {
+    split(record, separator)
+}
+
+fn split<'a>(record: &'a [u8], separator: Option<&'a FieldSeparator>) -> Fields<'a> {
+    Fields {
+        record,
+        separator: separator.map(FieldSeparator::as_bytes),
+        pos: 0,
+        yielded: false,
+        done: false,
+    }
+}
+
+/// One CSV field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CsvField {
+    /// The field as written, quotes included
+    pub raw: (usize, usize),
+    /// What keys compare: inside the quotes for a quoted field, else `raw`
+    pub content: (usize, usize),
+}
+
+/// Iterate over the CSV fields of `record` (see the module docs)
+pub fn split_csv_fields<'a>(
+    record: &'a [u8],
+    separator: &'a FieldSeparator,
+) -> impl Iterator<Item = CsvField> + 'a {
+    split_csv(record, separator)
+}
+
+fn split_csv<'a>(record: &'a [u8], separator: &'a FieldSeparator) -> CsvFields<'a> {
+    CsvFields {
+        record,
+        separator: separator.as_bytes(),
+        pos: 0,
+        done: false,
+    }
+}
+
+/// Every field span of `record`, collected
+pub fn collect_fields(record: &[u8], separator: Option<&FieldSeparator>) -> Vec<(usize, usize)> {
+    split_fields_with_positions(record, separator).collect()
+}
+
+/// Blanks in the C locale, plus newline (which only occurs inside -z
+/// records), as GNU sort counts them for splitting fields and for b
+pub(crate) fn is_blank(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n')
+}
+
+/// Offset of the first `separator` in `haystack`
+fn find(haystack: &[u8], separator: &[u8]) -> Option<usize> {
+    match separator {
+        [byte] => memchr::memchr(*byte, haystack),
+        // Never matches, rather than matching everywhere
+        [] => None,
+        _ => memchr::memmem::find(haystack, separator),
+    }
+}
+
+struct Fields<'a> {
+    record: &'a [u8],
+    separator: Option<&'a [u8]>,
+    /// Where the next field search starts
+    pos: usize,
+    /// Whether any field was produced (blank mode's empty-record rule)
+    yielded: bool,
+    done: bool,
+}
+
+impl Iterator for Fields<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.done {
+            return None;
+        }
+        let rest = &self.record[self.pos..];
+
+        let span = match self.separator {
+            Some(sep) => {
+                let start = self.pos;
+                match find(rest, sep) {
+                    Some(len) => {
+                        self.pos = start + len + sep.len();
+                        (start, start + len)
+                    }
+                    None => {
+                        self.done = true;
+                        (start, self.record.len())
+                    }
+                }
+            }
+            None => match rest.iter().position(|&b| !is_blank(b)) {
+                Some(skip) => {
+                    let start = self.pos + skip;
+                    let len = self.record[start..]
+                        .iter()
+                        .position(|&b| is_blank(b))
+                        .unwrap_or(self.record.len() - start);
+                    self.pos = start + len;
+                    (start, start + len)
+                }
+                None => {
+                    self.done = true;
+                    if self.yielded {
+                        return None;
+                    }
+                    (0, 0)
+                }
+            },
+        };
+
+        self.yielded = true;
+        Some(span)
+    }
+}
+
+struct CsvFields<'a> {
+    record: &'a [u8],
+    separator: &'a [u8],
+    /// Where the next field starts
+    pos: usize,
+    done: bool,
+}
+
+impl Iterator for CsvFields<'_> {
+    type Item = CsvField;
+
+    fn next(&mut self) -> Option<CsvField> {
+        if self.done {
+            return None;
+        }
+        let record = self.record;
+        let start = self.pos;
+
+        // A quoted field's content ends at the first quote not doubled;
+        // anything after it, up to the separator, is still this field
+        let (content, rest) = if record.get(start) == Some(&b'"') {
+            let mut i = start + 1;
+            loop {
+                match record.get(i) {
+                    Some(b'"') if record.get(i + 1) == Some(&b'"') => i += 2,
+                    Some(b'"') => break ((start + 1, i), i + 1),
+                    Some(_) => i += 1,
+                    // Unterminated: the quote runs to the end
+                    None => break ((start + 1, record.len()), record.len()),
+                }
+            }
+        } else {
+            ((start, start), start)
+        };
+
+        let end = match find(&record[rest..], self.separator) {
+            Some(len) => {
+                self.pos = rest + len + self.separator.len();
+                rest + len
+            }
+            None => {
+                self.done = true;
+                record.len()
+            }
+        };
+        let content = if rest == start { (start, end) } else { content };
+        Some(CsvField {
+            raw: (start, end),
+            content,
+        })
+    }
+}
+
+/// Fields between regex matches; like `Fields` with a separator
+#[cfg(feature = "regex")]
+struct RegexFields<'a> {
+    record: &'a [u8],
+    matches: regex::bytes::Matches<'a, 'a>,
+    /// Where the next field starts
+    pos: usize,
+    done: bool,
+}
+
+#[cfg(feature = "regex")]
+impl Iterator for RegexFields<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        if self.done {
+            return None;
+        }
+        let start = self.pos;
+        match self.matches.next() {
+            Some(m) => {
+                self.pos = m.end();
+                Some((start, m.start()))
+            }
+            None => {
+                self.done = true;
+                Some((start, self.record.len()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(record: &[u8], separator: Option<u8>) -> Vec<(usize, usize)> {
+        collect_fields(record, separator.map(FieldSeparator::Byte).as_ref())
+    }
+
+    #[test]
+    fn test_blank_separated() {
+        assert_eq!(spans(b"foo bar\tbaz", None), vec![(0, 3), (4, 7), (8, 11)]);
+        assert_eq!(spans(b"  a  b", None), vec![(2, 3), (5, 6)]);
+        assert_eq!(spans(b"a\nb", None), vec![(0, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn test_explicit_separator() {
+        assert_eq!(spans(b"a,bc,", Some(b',')), vec![(0, 1), (2, 4), (5, 5)]);
+        assert_eq!(spans(b"a,,b", Some(b',')), vec![(0, 1), (2, 2), (3, 4)]);
+    }
+
+    #[test]
+    fn test_empty_record() {
+        assert_eq!(spans(b"", None), vec![(0, 0)]);
+        assert_eq!(spans(b"", Some(b',')), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_all_separators() {
+        assert_eq!(spans(b" \t ", None), vec![(0, 0)]);
+        assert_eq!(spans(b",,", Some(b',')), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_trailing_separator() {
+        assert_eq!(spans(b"a b  ", None), vec![(0, 1), (2, 3)]);
+        assert_eq!(spans(b"a:b:", Some(b':')), vec![(0, 1), (2, 3), (4, 4)]);
+    }
+
+    #[test]
+    fn test_iterator_is_lazy() {
+        let colon = FieldSeparator::Byte(b':');
+        let mut fields = split_fields_with_positions(b"a:b:c", Some(&colon));
+        assert_eq!(fields.nth(1), Some((2, 3)));
+        assert_eq!(fields.next(), Some((4, 5)));
+        assert_eq!(fields.next(), None);
+        assert_eq!(fields.next(), None);
+    }
+
+    fn csv(record: &[u8]) -> Vec<(&[u8], &[u8])> {
+        split_csv_fields(record, &FieldSeparator::Byte(b','))
+            .map(|f| (&record[f.raw.0..f.raw.1], &record[f.content.0..f.content.1]))
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_quoted_separator() {
+        assert_eq!(
+            csv(b"\"Smith, John\",42"),
+            vec![(&b"\"Smith, John\""[..], &b"Smith, John"[..]), (b"42", b"42")]
+        );
+    }
+
+    #[test]
+    fn test_csv_empty_fields() {
+        assert_eq!(csv(b""), vec![(&b""[..], &b""[..])]);
+        assert_eq!(
+            csv(b"\"\",,\"\""),
+            vec![(&b"\"\""[..], &b""[..]), (b"", b""), (b"\"\"", b"")]
+        );
+    }
+
+    #[test]
+    fn test_csv_escaped_and_stray_quotes() {
+        // Doubled quotes don't close the field
+        assert_eq!(
+            csv(b"\"say \"\"hi, there\"\"\",x"),
+            vec![(&b"\"say \"\"hi, there\"\"\""[..], &b"say \"\"hi, there\"\""[..]), (b"x", b"x")]
+        );
+        // A quote mid-field is an ordinary byte and does not protect commas
+        assert_eq!(csv(b"ab\"c,d\""), vec![(&b"ab\"c"[..], &b"ab\"c"[..]), (b"d\"", b"d\"")]);
+        // Text after the closing quote belongs to the raw field only
+        assert_eq!(csv(b"\"a\"b,c"), vec![(&b"\"a\"b"[..], &b"a"[..]), (b"c", b"c")]);
+        // Unterminated quote runs to the end of the record
+        assert_eq!(csv(b"\"a,b"), vec![(&b"\"a,b"[..], &b"a,b"[..])]);
+    }
+
+    #[test]
+    fn test_multibyte_separator() {
+        let section = FieldSeparator::from('§');
+        assert_eq!(section.as_bytes(), "§".as_bytes());
+        let record = "a§§bc§".as_bytes();
+        assert_eq!(collect_fields(record, Some(&section)), vec![(0, 1), (3, 3), (5, 7), (9, 9)]);
+
+        // Three bytes, with a lone first byte in the data that must not match
+        let bullet = FieldSeparator::from('\u{2022}');
+        let record = [b"x\xe2y".as_slice(), "\u{2022}".as_bytes(), b"z"].concat();
+        assert_eq!(collect_fields(&record, Some(&bullet)), vec![(0, 3), (6, 7)]);
+
+        // Leftmost, non-overlapping
+        let sep = FieldSeparator::new(b"aa").unwrap();
+        assert_eq!(collect_fields(b"aaab", Some(&sep)), vec![(0, 0), (2, 4)]);
+        assert_eq!(FieldSeparator::new(b""), None);
+        assert_eq!(FieldSeparator::new(b","), Some(FieldSeparator::Byte(b',')));
+    }
+
+    #[test]
+    fn test_string_separator() {
+        let pipe = FieldSeparator::new(b" | ").unwrap();
+        assert_eq!(spans_str(b"a | b |  | c", &pipe), [&b"a"[..], b"b", b"", b"c"]);
+        assert_eq!(spans_str(b" | a | ", &pipe), [&b""[..], b"a", b""]);
+        assert_eq!(spans_str(b"a|b", &pipe), [b"a|b"]);
+
+        // The separator shares a prefix with the data
+        let ab = FieldSeparator::new(b"ab").unwrap();
+        assert_eq!(spans_str(b"aab", &ab), [&b"a"[..], b""]);
+        assert_eq!(spans_str(b"aabab", &ab), [&b"a"[..], b"", b""]);
+        assert_eq!(spans_str(b"abab", &ab), [&b""[..], b"", b""]);
+    }
+
+    fn spans_str<'a>(record: &'a [u8], separator: &FieldSeparator) -> Vec<&'a [u8]> {
+        collect_fields(record, Some(separator))
+            .into_iter()
+            .map(|(start, end)| &record[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn test_splitter_spans() {
+        let record = b"\"b,1\",a";
+        let comma = FieldSeparator::Byte(b',');
+        let spans: Vec<_> = Splitter::Csv(comma.clone()).spans(record).collect();
+        assert_eq!(spans, vec![(1, 4), (6, 7)]);
+        let spans: Vec<_> = Splitter::Separator(comma.clone()).spans(record).collect();
+        assert_eq!(spans, collect_fields(record, Some(&comma)));
+        let spans: Vec<_> = Splitter::Blanks.spans(b" a b").collect();
+        assert_eq!(spans, collect_fields(b" a b", None));
+    }
+
+    #[cfg(feature = "regex")]
+    fn regex_fields<'a>(record: &'a [u8], pattern: &str) -> Vec<&'a [u8]> {
+        let splitter = Splitter::Regex(SeparatorRegex::new(pattern).unwrap());
+        splitter.spans(record).map(|(start, end)| &record[start..end]).collect()
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_separator() {
+        // Runs of blanks collapse into one separator
+        assert_eq!(regex_fields(b"a   b\t c", r"\s+"), [b"a", b"b", b"c"]);
+        // Alternation, and invalid UTF-8 in the data
+        assert_eq!(regex_fields(b"a, b;\xffc", r"[,;]\s*"), [&b"a"[..], b"b", b"\xffc"]);
+        // Matches at the ends make empty first and last fields
+        assert_eq!(regex_fields(b",,a,,", ",+"), [&b""[..], b"a", b""]);
+        assert_eq!(regex_fields(b"", ",+"), [b""]);
+        assert_eq!(regex_fields(b"abc", ",+"), [b"abc"]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_separator_errors() {
+        for pattern in ["a*", r"\b", "^", "x|"] {
+            let err = SeparatorRegex::new(pattern).unwrap_err();
+            let expected = "it can match the empty string";
+            assert_eq!(
+                err.to_string(),
+                format!("invalid --field-separator-regex '{}': {}", pattern, expected)
+            );
+        }
+        let err = SeparatorRegex::new("(").unwrap_err();
+        assert!(err.to_string().starts_with("invalid --field-separator-regex '(': "), "{}", err);
+    }
+}
@@ -1,11 +1,79 @@
-use std::io::{self, BufRead};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::arena::Arena;
+use crate::compress::{self, Codec, Decompression};
+use crate::error::{Result, RsortError};
+use crate::platform;
+use crate::trace::trace_event;
+
+/// UTF-8 byte order mark, as written by some Windows tools
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// How records are marked out in the input and output (--framing)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Ended by the record delimiter
+    #[default]
+    Delimited,
+    /// Each preceded by its length, a 4-byte little-endian integer
+    Len32Le,
+}
+
+impl Framing {
+    const ALL: [Framing; 2] = [Framing::Delimited, Framing::Len32Le];
+
+    /// Parse a --framing argument such as `len32le`
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|framing| framing.name() == name)
+    }
+
+    /// The name `parse` accepts
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::Delimited => "delimited",
+            Framing::Len32Le => "len32le",
+        }
+    }
+
+    /// Write what goes before a record of `len` bytes: its length prefix,
+    /// if any
+    pub fn write_prefix<W: Write + ?Sized>(self, writer: &mut W, len: usize) -> io::Result<()> {
+        match self {
+            Framing::Delimited => Ok(()),
+            Framing::Len32Le => {
+                let len = u32::try_from(len).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "record too long for a 32-bit length prefix")
+                })?;
+                writer.write_all(&len.to_le_bytes())
+            }
+        }
+    }
+}
 
 /// Reads records from input, splitting on the specified delimiter
 pub struct RecordReader<R> {
     reader: R,
+    /// Last byte of the delimiter, the one searched for
     delimiter: u8,
+    /// Bytes that must precede `delimiter` for it to end a record
+    prefix: Vec<u8>,
     buffer: Vec<u8>,
     last_had_delimiter: bool,
+    /// Longest record accepted, delimiter excluded
+    max_record_size: Option<u64>,
+    /// Fixed record length, in place of the delimiter (`--record-size`)
+    record_size: Option<usize>,
+    /// Length prefixes, in place of the delimiter, unless `Delimited`
+    framing: Framing,
+    /// Bytes consumed so far
+    offset: u64,
+    /// Records left before reporting EOF early (`--take`)
+    remaining: Option<u64>,
+    /// Drop a `UTF8_BOM` starting the first record
+    strip_bom: bool,
+    stripped_bom: bool,
 }
 
 impl<R: BufRead> RecordReader<R> {
@@ -13,48 +81,373 @@ impl<R: BufRead> RecordReader<R> {
         Self {
             reader,
             delimiter,
+            prefix: Vec::new(),
             buffer: Vec::new(),
             last_had_delimiter: true,
+            max_record_size: None,
+            record_size: None,
+            framing: Framing::Delimited,
+            offset: 0,
+            remaining: None,
+            strip_bom: false,
+            stripped_bom: false,
         }
     }
 
+    /// Drop a UTF-8 byte order mark from the start of the first record
+    ///
+    /// A BOM anywhere else is data. Check `stripped_bom` afterwards to know
+    /// whether there was one.
+    pub fn strip_bom(mut self, strip: bool) -> Self {
+        self.strip_bom = strip;
+        self
+    }
+
+    /// Whether a byte order mark was dropped (see `strip_bom`)
+    pub fn stripped_bom(&self) -> bool {
+        self.stripped_bom
+    }
+
+    /// Require `prefix` before the delimiter byte, for multi-byte delimiters
+    ///
+    /// With `b"\r"` and `b'\n'`, records end at `\r\n` only; a lone `\r` or
+    /// `\n` stays in the record.
+    pub fn delimiter_prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefix = prefix.to_vec();
+        self
+    }
+
+    /// Stop after `limit` records, leaving the rest of the input unread
+    pub fn take_records(mut self, limit: Option<u64>) -> Self {
+        self.remaining = limit;
+        self
+    }
+
+    /// Fail with `RecordTooLarge` on records longer than `limit` bytes
+    pub fn max_record_size(mut self, limit: Option<u64>) -> Self {
+        self.max_record_size = limit;
+        self
+    }
+
+    /// Read records of exactly `size` bytes, ignoring the delimiter
+    ///
+    /// Nothing is stripped from the records, and `strip_bom` and
+    /// `max_record_size` do not apply. Input ending part way through a
+    /// record fails with `PartialRecord`.
+    pub fn record_size(mut self, size: Option<usize>) -> Self {
+        self.record_size = size;
+        self
+    }
+
+    /// Read records framed by length prefixes instead of delimited ones
+    ///
+    /// The prefix is not part of the record. `max_record_size` limits the
+    /// length a prefix may declare; `strip_bom` does not apply. Input ending
+    /// inside a prefix or before the declared length fails with `BadFrame`.
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
     /// Read the next record, returning None at EOF
-    pub fn read_record(&mut self) -> io::Result<Option<&[u8]>> {
+    ///
+    /// Equivalent to `read_until`, but grows the buffer with `try_reserve` so an
+    /// oversized record reports `MemoryExhausted` instead of aborting.
+    pub fn read_record(&mut self) -> Result<Option<&[u8]>> {
         self.buffer.clear();
-        let bytes_read = self.reader.read_until(self.delimiter, &mut self.buffer)?;
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        if let Some(size) = self.record_size {
+            return self.read_fixed(size);
+        }
+        if self.framing == Framing::Len32Le {
+            return self.read_framed();
+        }
+        let start = self.offset;
+        let delimiter_len = self.prefix.len() + 1;
+        let mut terminated = false;
+
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if available.is_empty() {
+                break;
+            }
+
+            let mut from = 0;
+            let (chunk_len, found) = loop {
+                match memchr::memchr(self.delimiter, &available[from..]) {
+                    Some(i) if ends_with(&self.buffer, &available[..from + i], &self.prefix) => {
+                        break (from + i + 1, true);
+                    }
+                    Some(i) => from += i + 1,
+                    None => break (available.len(), false),
+                }
+            };
+            if let Some(limit) = self.max_record_size {
+                let len = self.buffer.len() + chunk_len;
+                // Until the delimiter turns up, the tail may still be part of it
+                let record_len = if found {
+                    len - delimiter_len
+                } else {
+                    len.saturating_sub(self.prefix.len())
+                };
+                if record_len as u64 > limit {
+                    return Err(RsortError::RecordTooLarge {
+                        path: None,
+                        offset: start,
+                        limit,
+                    });
+                }
+            }
+            self.buffer
+                .try_reserve(chunk_len)
+                .map_err(|_| RsortError::MemoryExhausted {
+                    needed: chunk_len,
+                    used: self.buffer.len(),
+                })?;
+            self.buffer.extend_from_slice(&available[..chunk_len]);
+            self.reader.consume(chunk_len);
+            self.offset += chunk_len as u64;
 
-        if bytes_read == 0 {
+            if found {
+                terminated = true;
+                break;
+            }
+        }
+
+        if self.strip_bom && start == 0 && self.buffer.starts_with(UTF8_BOM) {
+            self.buffer.drain(..UTF8_BOM.len());
+            self.stripped_bom = true;
+        }
+        // A file holding only a BOM has no records
+        if self.buffer.is_empty() {
             return Ok(None);
         }
+        if let Some(limit) = self.max_record_size {
+            if !terminated && self.buffer.len() as u64 > limit {
+                return Err(RsortError::RecordTooLarge {
+                    path: None,
+                    offset: start,
+                    limit,
+                });
+            }
+        }
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
 
         // Track and strip delimiter if present at end
-        self.last_had_delimiter = self.buffer.last() == Some(&self.delimiter);
-        if self.last_had_delimiter {
-            self.buffer.pop();
+        self.last_had_delimiter = terminated;
+        if terminated {
+            self.buffer.truncate(self.buffer.len() - delimiter_len);
         }
 
         Ok(Some(&self.buffer))
     }
 
+    /// `read_record` for records of `size` bytes (`record_size`)
+    fn read_fixed(&mut self, size: usize) -> Result<Option<&[u8]>> {
+        let start = self.offset;
+        self.fill(size)?;
+        match self.buffer.len() {
+            0 => Ok(None),
+            len if len < size => Err(RsortError::PartialRecord {
+                path: None,
+                offset: start,
+                len: len as u64,
+                size: size as u64,
+            }),
+            _ => {
+                if let Some(remaining) = &mut self.remaining {
+                    *remaining -= 1;
+                }
+                Ok(Some(&self.buffer))
+            }
+        }
+    }
+
+    /// `read_record` for records after a 4-byte length (`framing`)
+    fn read_framed(&mut self) -> Result<Option<&[u8]>> {
+        let start = self.offset;
+        let bad_frame = |problem: String| RsortError::BadFrame {
+            path: None,
+            offset: start,
+            problem,
+        };
+        match self.fill(4)? {
+            0 => return Ok(None),
+            4 => {}
+            len => return Err(bad_frame(format!("length prefix cut short after {} of 4 bytes", len))),
+        }
+        let prefix: [u8; 4] = self.buffer[..].try_into().expect("the prefix is 4 bytes");
+        let declared = u32::from_le_bytes(prefix);
+        if let Some(limit) = self.max_record_size.filter(|&limit| u64::from(declared) > limit) {
+            return Err(bad_frame(format!(
+                "declares {} bytes, more than --max-record-size ({} bytes); corrupted length prefix?",
+                declared, limit
+            )));
+        }
+
+        self.buffer.clear();
+        let len = self.fill(declared as usize)?;
+        if len < declared as usize {
+            return Err(bad_frame(format!(
+                "declares {} bytes, but the input ends after {}; corrupted length prefix?",
+                declared, len
+            )));
+        }
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        Ok(Some(&self.buffer))
+    }
+
+    /// Append up to `len` more bytes to the buffer, fewer only at EOF;
+    /// returns how many were appended
+    ///
+    /// The buffer grows as bytes arrive, so a bogus length stops at EOF
+    /// rather than allocating it all up front.
+    fn fill(&mut self, len: usize) -> Result<usize> {
+        let mut appended = 0;
+        while appended < len {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            if available.is_empty() {
+                break;
+            }
+            let chunk_len = available.len().min(len - appended);
+            self.buffer
+                .try_reserve(chunk_len)
+                .map_err(|_| RsortError::MemoryExhausted {
+                    needed: chunk_len,
+                    used: self.buffer.len(),
+                })?;
+            self.buffer.extend_from_slice(&available[..chunk_len]);
+            self.reader.consume(chunk_len);
+            self.offset += chunk_len as u64;
+            appended += chunk_len;
+        }
+        Ok(appended)
+    }
+
     /// Returns whether the last read record had a trailing delimiter
     pub fn last_had_delimiter(&self) -> bool {
         self.last_had_delimiter
     }
+
+    /// Append every remaining record to `arena`, as `read_records_into` does
+    ///
+    /// Unlike that function, the reader stays available, e.g. for `stripped_bom`.
+    /// Returns whether the last record read had its delimiter (true when
+    /// there were none).
+    pub fn read_into(&mut self, arena: &mut Arena) -> Result<bool> {
+        loop {
+            let record = match self.read_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                // Report everything buffered so far, not just the partial record
+                Err(RsortError::MemoryExhausted { needed, used }) => {
+                    return Err(RsortError::MemoryExhausted {
+                        needed,
+                        used: used + arena.bytes_used(),
+                    })
+                }
+                Err(e) => return Err(e),
+            };
+            arena.try_push(record)?;
+        }
+        Ok(self.last_had_delimiter)
+    }
 }
 
-/// Read all records from a reader into a Vec
-/// Returns (records, had_trailing_delimiter)
-/// Note: GNU sort always adds trailing delimiter, so we always return true
-pub fn read_all_records<R: BufRead>(reader: R, delimiter: u8) -> io::Result<(Vec<Vec<u8>>, bool)> {
-    let mut records = Vec::new();
-    let mut rec_reader = RecordReader::new(reader, delimiter);
+/// `record` without the `\r` that ends a CRLF line (--crlf)
+pub fn strip_cr(record: &[u8]) -> &[u8] {
+    record.strip_suffix(b"\r").unwrap_or(record)
+}
+
+/// Whether `head` followed by `tail` ends with `suffix`
+fn ends_with(head: &[u8], tail: &[u8], suffix: &[u8]) -> bool {
+    match suffix.len().checked_sub(tail.len()) {
+        None | Some(0) => tail.ends_with(suffix),
+        Some(split) => tail == &suffix[split..] && head.ends_with(&suffix[..split]),
+    }
+}
+
+/// Open an input operand for reading; "-" is stdin
+///
+/// Stdin is locked for as long as the reader lives, so callers open it at
+/// most once (see `check_stdin_once`).
+pub fn open_input(path: impl AsRef<Path>) -> Result<Box<dyn BufRead>> {
+    let path = path.as_ref();
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(platform::long_path(path)).map_err(|source| RsortError::OpenInput {
+        path: path.into(),
+        source,
+    })?;
+    trace_event!(path = %path.display(), "opened input");
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// `open_input`, then decompressed as `decompression` says
+///
+/// By default only files whose name ends in a codec's extension (e.g. `.gz`)
+/// are decompressed, never stdin.
+pub fn open_decompressed(path: impl AsRef<Path>, decompression: &Decompression) -> Result<Box<dyn BufRead>> {
+    let path = path.as_ref();
+    let codec = match decompression {
+        Decompression::Auto => Codec::from_path(path),
+        Decompression::Codec(codec) => Some(*codec),
+        Decompression::Program(program) => return compress::run_program(program, path),
+    };
+    let reader = open_input(path)?;
+    match codec {
+        Some(codec) => compress::decompress(reader, codec, path),
+        None => Ok(reader),
+    }
+}
 
-    while let Some(record) = rec_reader.read_record()? {
-        records.push(record.to_vec());
+/// Reject input operands naming stdin ("-") more than once
+///
+/// A second read of stdin would see only EOF, so GNU-style, it is an error
+/// rather than an empty input.
+pub fn check_stdin_once<P: AsRef<Path>>(paths: &[P]) -> Result<()> {
+    let stdin_operands = paths.iter().filter(|path| path.as_ref() == Path::new("-"));
+    if stdin_operands.count() > 1 {
+        return Err(RsortError::Usage(
+            "cannot read standard input more than once".to_string(),
+        ));
     }
+    Ok(())
+}
 
-    // GNU sort always adds trailing delimiter to output
-    Ok((records, true))
+/// Read all records from a reader into a Vec
+/// Returns (records, had_trailing_delimiter): whether the input ended with
+/// the delimiter, or was empty
+pub fn read_all_records<R: BufRead>(reader: R, delimiter: u8) -> Result<(Vec<Vec<u8>>, bool)> {
+    let mut arena = Arena::new();
+    let had_trailing = read_into_arena(reader, delimiter, &mut arena)?;
+    Ok((arena.into_records(), had_trailing))
+}
+
+/// Read all records from a reader, appending them to an arena
+/// Returns had_trailing_delimiter, as `read_all_records` does
+pub fn read_into_arena<R: BufRead>(reader: R, delimiter: u8, arena: &mut Arena) -> Result<bool> {
+    read_records_into(RecordReader::new(reader, delimiter), arena)
+}
+
+/// `read_into_arena` for a configured `RecordReader`
+pub fn read_records_into<R: BufRead>(mut rec_reader: RecordReader<R>, arena: &mut Arena) -> Result<bool> {
+    rec_reader.read_into(arena)
 }
 
 #[cfg(test)]
@@ -62,6 +455,13 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    #[test]
+    fn test_open_input_error_names_path() {
+        let err = open_input("no-such-input.txt").err().unwrap();
+        assert!(matches!(err, RsortError::OpenInput { .. }));
+        assert!(err.to_string().contains("no-such-input.txt"));
+    }
+
     #[test]
     fn test_read_records_newline() {
         let input = b"a\nb\nc\n";
@@ -72,8 +472,12 @@ mod tests {
     #[test]
     fn test_read_records_no_trailing_newline() {
         let input = b"a\nb\nc";
-        let (records, _) = read_all_records(Cursor::new(input), b'\n').unwrap();
+        let (records, had_trailing) = read_all_records(Cursor::new(input), b'\n').unwrap();
         assert_eq!(records, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert!(!had_trailing);
+
+        assert!(read_all_records(Cursor::new(b"a\nb\n"), b'\n').unwrap().1);
+        assert!(read_all_records(Cursor::new(b""), b'\n').unwrap().1);
     }
 
     #[test]
@@ -92,4 +496,207 @@ mod tests {
             vec![b"".to_vec(), b"".to_vec(), b"a".to_vec(), b"".to_vec()]
         );
     }
+
+    #[test]
+    fn test_read_large_record_across_buffer_refills() {
+        let mut input = vec![b'x'; 20_000];
+        input.extend_from_slice(b"\ny\n");
+        let reader = io::BufReader::with_capacity(16, Cursor::new(input));
+        let (records, _) = read_all_records(reader, b'\n').unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].len(), 20_000);
+        assert_eq!(records[1], b"y");
+    }
+
+    fn read_crlf(input: &[u8], capacity: usize) -> Vec<Vec<u8>> {
+        let reader = io::BufReader::with_capacity(capacity, Cursor::new(input));
+        let mut reader = RecordReader::new(reader, b'\n').delimiter_prefix(b"\r");
+        let mut records = Vec::new();
+        while let Some(record) = reader.read_record().unwrap() {
+            records.push(record.to_vec());
+        }
+        records
+    }
+
+    #[test]
+    fn test_read_records_multibyte_delimiter() {
+        let input = b"b\r\na\rx\r\nc\nd\r\n\r\ne";
+        let expected = [&b"b"[..], b"a\rx", b"c\nd", b"", b"e"];
+        // Every capacity splits "\r\n" across refills somewhere
+        for capacity in 1..=input.len() {
+            assert_eq!(read_crlf(input, capacity), expected, "capacity {}", capacity);
+        }
+        assert_eq!(read_crlf(b"\r\r\n\n", 2), [&b"\r"[..], b"\n"]);
+
+        let mut reader = RecordReader::new(Cursor::new(b"a\r\nb\r"), b'\n').delimiter_prefix(b"\r");
+        assert_eq!(reader.read_record().unwrap(), Some(&b"a"[..]));
+        assert!(reader.last_had_delimiter());
+        assert_eq!(reader.read_record().unwrap(), Some(&b"b\r"[..]));
+        assert!(!reader.last_had_delimiter());
+    }
+
+    #[test]
+    fn test_max_record_size_multibyte_delimiter() {
+        let reader = io::BufReader::with_capacity(4, Cursor::new(b"abc\r\nabcd"));
+        let mut reader = RecordReader::new(reader, b'\n')
+            .delimiter_prefix(b"\r")
+            .max_record_size(Some(3));
+        assert_eq!(reader.read_record().unwrap(), Some(&b"abc"[..]));
+        let err = reader.read_record().unwrap_err();
+        assert!(matches!(err, RsortError::RecordTooLarge { offset: 5, limit: 3, .. }));
+    }
+
+    #[test]
+    fn test_record_size() {
+        let reader = io::BufReader::with_capacity(3, Cursor::new(b"ab\ncd\0\xEF\xBB\xBF"));
+        let mut reader = RecordReader::new(reader, b'\n').record_size(Some(3)).strip_bom(true);
+        let mut arena = Arena::new();
+        assert!(reader.read_into(&mut arena).unwrap());
+        assert_eq!(arena.into_records(), [&b"ab\n"[..], b"cd\0", b"\xEF\xBB\xBF"]);
+        assert!(!reader.stripped_bom());
+
+        let mut reader = RecordReader::new(Cursor::new(b"abcdefg"), b'\n').record_size(Some(3));
+        assert_eq!(reader.read_record().unwrap(), Some(&b"abc"[..]));
+        assert_eq!(reader.read_record().unwrap(), Some(&b"def"[..]));
+        let err = reader.read_record().unwrap_err();
+        assert!(matches!(err, RsortError::PartialRecord { offset: 6, len: 1, size: 3, .. }));
+    }
+
+    #[test]
+    fn test_len32le_framing() {
+        let input = b"\x03\0\0\0a\nb\0\0\0\0\x01\0\0\0\0";
+        for capacity in 1..=input.len() {
+            let reader = io::BufReader::with_capacity(capacity, Cursor::new(input));
+            let mut reader = RecordReader::new(reader, b'\n').framing(Framing::Len32Le);
+            let mut arena = Arena::new();
+            assert!(reader.read_into(&mut arena).unwrap());
+            assert_eq!(arena.into_records(), [&b"a\nb"[..], b"", b"\0"], "capacity {}", capacity);
+        }
+
+        let frame_error = |input: &[u8], limit: Option<u64>| {
+            let mut reader = RecordReader::new(Cursor::new(input), b'\n')
+                .framing(Framing::Len32Le)
+                .max_record_size(limit);
+            while reader.read_record()?.is_some() {}
+            Ok::<_, RsortError>(())
+        };
+        let err = frame_error(b"\x01\0\0\0a\xff\xff\xff\x7fabc", None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "record at byte 5: declares 2147483647 bytes, but the input ends after 3; corrupted length prefix?"
+        );
+        let err = frame_error(b"\x01\0\0\0a\x01\0", None).unwrap_err();
+        assert_eq!(err.to_string(), "record at byte 5: length prefix cut short after 2 of 4 bytes");
+        let err = frame_error(b"\x01\0\0\0a\x05\0\0\0abcde", Some(4)).unwrap_err();
+        assert!(matches!(err, RsortError::BadFrame { offset: 5, .. }));
+        assert!(frame_error(b"\x04\0\0\0abcd", Some(4)).is_ok());
+    }
+
+    fn read_stripping_bom(input: &[u8], delimiter: u8) -> (Vec<Vec<u8>>, bool) {
+        let mut reader = RecordReader::new(Cursor::new(input), delimiter).strip_bom(true);
+        let mut arena = Arena::new();
+        reader.read_into(&mut arena).unwrap();
+        (arena.into_records(), reader.stripped_bom())
+    }
+
+    #[test]
+    fn test_strip_bom() {
+        let (records, stripped) = read_stripping_bom(b"\xEF\xBB\xBFb\na\n", b'\n');
+        assert_eq!(records, [&b"b"[..], b"a"]);
+        assert!(stripped);
+
+        // Only at the very start: a later BOM is data
+        let (records, stripped) = read_stripping_bom(b"b\n\xEF\xBB\xBFa\n", b'\n');
+        assert_eq!(records, [&b"b"[..], b"\xEF\xBB\xBFa"]);
+        assert!(!stripped);
+
+        let (records, stripped) = read_stripping_bom(b"\xEF\xBB\xBF", b'\n');
+        assert!(records.is_empty());
+        assert!(stripped);
+        let (records, _) = read_stripping_bom(b"\xEF\xBB\xBF\n", b'\n');
+        assert_eq!(records, [b""]);
+
+        let (records, stripped) = read_stripping_bom(b"\xEF\xBB\xBFb\0\xEF\xBB\xBFa\0", 0);
+        assert_eq!(records, [&b"b"[..], b"\xEF\xBB\xBFa"]);
+        assert!(stripped);
+
+        let (records, _) = read_all_records(Cursor::new(b"\xEF\xBB\xBFb\n"), b'\n').unwrap();
+        assert_eq!(records, [b"\xEF\xBB\xBFb"]);
+    }
+
+    #[test]
+    fn test_arena_budget_exhausted() {
+        let mut arena = Arena::with_budget(4);
+        let err = read_into_arena(Cursor::new(b"ab\ncd\nef\n"), b'\n', &mut arena).unwrap_err();
+        match err {
+            RsortError::MemoryExhausted { needed, used } => {
+                assert_eq!(needed, 2);
+                assert_eq!(used, 4);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        assert_eq!(arena.len(), 2);
+    }
+
+    #[test]
+    fn test_arena_budget_not_reached() {
+        let mut arena = Arena::with_budget(6);
+        read_into_arena(Cursor::new(b"ab\ncd\nef\n"), b'\n', &mut arena).unwrap();
+        assert_eq!(arena.len(), 3);
+        assert_eq!(arena.bytes_used(), 6);
+    }
+
+    #[test]
+    fn test_max_record_size() {
+        let mut reader = RecordReader::new(Cursor::new(b"abc\nabcd\nab"), b'\n').max_record_size(Some(3));
+        assert_eq!(reader.read_record().unwrap(), Some(&b"abc"[..]));
+        match reader.read_record().unwrap_err() {
+            RsortError::RecordTooLarge { path, offset, limit } => {
+                assert_eq!(path, None);
+                assert_eq!(offset, 4);
+                assert_eq!(limit, 3);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_max_record_size_across_buffer_refills() {
+        let mut input = vec![b'x'; 100];
+        input.push(b'\n');
+        let reader = io::BufReader::with_capacity(16, Cursor::new(&input));
+        let mut arena = Arena::new();
+        read_records_into(RecordReader::new(reader, b'\n').max_record_size(Some(100)), &mut arena).unwrap();
+        assert_eq!(arena.len(), 1);
+
+        let reader = io::BufReader::with_capacity(16, Cursor::new(&input));
+        let err = read_records_into(RecordReader::new(reader, b'\n').max_record_size(Some(99)), &mut arena)
+            .unwrap_err();
+        assert!(matches!(err, RsortError::RecordTooLarge { offset: 0, limit: 99, .. }));
+    }
+
+    /// Fails the test if read past the data it was built with
+    struct Tripwire(Cursor<&'static [u8]>);
+
+    impl io::Read for Tripwire {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match io::Read::read(&mut self.0, buf)? {
+                0 => Err(io::Error::other("read past the cutoff")),
+                n => Ok(n),
+            }
+        }
+    }
+
+    #[test]
+    fn test_take_records_stops_reading() {
+        let reader = BufReader::new(Tripwire(Cursor::new(b"b\na\n")));
+        let mut reader = RecordReader::new(reader, b'\n').take_records(Some(2));
+        assert_eq!(reader.read_record().unwrap(), Some(&b"b"[..]));
+        assert_eq!(reader.read_record().unwrap(), Some(&b"a"[..]));
+        assert_eq!(reader.read_record().unwrap(), None);
+        assert_eq!(reader.read_record().unwrap(), None);
+
+        let mut reader = RecordReader::new(Cursor::new(b"a\n"), b'\n').take_records(Some(0));
+        assert_eq!(reader.read_record().unwrap(), None);
+    }
 }
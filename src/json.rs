@@ -0,0 +1,428 @@
+//! Keys from JSON Lines records (--json-key)
+//!
+//! Contract, for a record and an RFC 6901 JSON pointer:
+//!
+//! - The record is scanned, not parsed into a tree: members and elements
+//!   off the way to the pointed-to value are stepped over, not kept. The
+//!   scan still covers the whole record, so a record broken after the
+//!   value, or with more after its closing bracket, has no key.
+//! - The key is a string's content with its escapes undone (`\u` escapes
+//!   as UTF-8), or any other value's bytes as written: `12.5`, `true`, or a
+//!   whole object or array.
+//! - A missing member, an index past the end of an array, a pointer going
+//!   through a number or string, and a record that is not JSON give no
+//!   key, which compares as an empty one.
+//!
+//! Spans are byte ranges of the value in the record, inside the quotes
+//! for a string.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+use crate::error::{Result, RsortError};
+
+/// An RFC 6901 JSON pointer, such as `/user/id`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPointer {
+    /// As given, for messages
+    text: String,
+    /// Member names or array indices, `~1` and `~0` undone
+    tokens: Vec<Vec<u8>>,
+}
+
+impl JsonPointer {
+    /// Parse `pointer`: empty for the whole record, otherwise `/` before
+    /// each token, with `~1` for a `/` and `~0` for a `~` inside one
+    pub fn parse(pointer: &str) -> Result<Self> {
+        let invalid = |why: &str| {
+            RsortError::Usage(format!("invalid --json-key argument '{}'; {}", pointer, why))
+        };
+        let tokens = match pointer.strip_prefix('/') {
+            None if !pointer.is_empty() => return Err(invalid("a JSON pointer starts with '/'")),
+            None => Vec::new(),
+            Some(rest) => rest
+                .split('/')
+                .map(|token| {
+                    let mut unescaped = Vec::with_capacity(token.len());
+                    let mut bytes = token.bytes();
+                    while let Some(byte) = bytes.next() {
+                        if byte != b'~' {
+                            unescaped.push(byte);
+                            continue;
+                        }
+                        match bytes.next() {
+                            Some(b'0') => unescaped.push(b'~'),
+                            Some(b'1') => unescaped.push(b'/'),
+                            _ => return Err(invalid("'~' must be followed by 0 or 1")),
+                        }
+                    }
+                    Ok(unescaped)
+                })
+                .collect::<Result<_>>()?,
+        };
+        Ok(JsonPointer {
+            text: pointer.to_string(),
+            tokens,
+        })
+    }
+
+    /// The pointer as given
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Byte range of the pointed-to value in `record`, None when there is
+    /// none (see the module docs)
+    pub fn span(&self, record: &[u8]) -> Option<Range<usize>> {
+        self.locate(record).map(|(span, _)| span)
+    }
+
+    /// The key of `record`: the pointed-to value, empty when there is none
+    ///
+    /// Borrowed from the record unless a string's escapes had to be undone.
+    pub fn extract<'a>(&self, record: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.locate(record) {
+            Some((span, true)) => unescape(&record[span]).unwrap_or(Cow::Borrowed(&[])),
+            Some((span, false)) => Cow::Borrowed(&record[span]),
+            None => Cow::Borrowed(&[]),
+        }
+    }
+
+    /// The value's span, and whether it is a string
+    fn locate(&self, record: &[u8]) -> Option<(Range<usize>, bool)> {
+        let mut scanner = Scanner { record, pos: 0 };
+        scanner.skip_blanks();
+        // The opening bracket of each container on the way to the value
+        let mut opened = Vec::with_capacity(self.tokens.len());
+        for token in &self.tokens {
+            let open = scanner.peek()?;
+            match open {
+                b'{' => scanner.member(token)?,
+                b'[' => scanner.element(token)?,
+                _ => return None,
+            }
+            opened.push(open);
+        }
+        let start = scanner.pos;
+        let end = scanner.value()?;
+        for &open in opened.iter().rev() {
+            scanner.finish(open)?;
+        }
+        scanner.skip_blanks();
+        if scanner.pos != record.len() {
+            return None;
+        }
+        if record[start] == b'"' {
+            return Some((start + 1..end - 1, true));
+        }
+        Some((start..end, false))
+    }
+}
+
+impl fmt::Display for JsonPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// A position in a record being scanned, always at a value or token after
+/// skipping blanks
+struct Scanner<'a> {
+    record: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.record.get(self.pos).copied()
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Step past `byte` and the blanks after it, or fail
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.peek()? == byte).then_some(())?;
+        self.pos += 1;
+        self.skip_blanks();
+        Some(())
+    }
+
+    /// Move from an object's `{` to the value of member `name`
+    fn member(&mut self, name: &[u8]) -> Option<()> {
+        self.expect(b'{')?;
+        if self.peek()? == b'}' {
+            return None;
+        }
+        loop {
+            let start = self.pos;
+            let end = self.string()?;
+            let key = unescape(&self.record[start + 1..end - 1])?;
+            self.skip_blanks();
+            self.expect(b':')?;
+            if *key == *name {
+                return Some(());
+            }
+            self.value()?;
+            self.skip_blanks();
+            match self.peek()? {
+                b',' => self.expect(b',')?,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Move from an array's `[` to element `index`, a decimal without
+    /// leading zeros
+    fn element(&mut self, index: &[u8]) -> Option<()> {
+        let canonical = !index.is_empty() && index.iter().all(u8::is_ascii_digit) && (index == b"0" || index[0] != b'0');
+        let index: usize = std::str::from_utf8(index).ok().filter(|_| canonical)?.parse().ok()?;
+        self.expect(b'[')?;
+        if self.peek()? == b']' {
+            return None;
+        }
+        for _ in 0..index {
+            self.value()?;
+            self.skip_blanks();
+            match self.peek()? {
+                b',' => self.expect(b',')?,
+                _ => return None,
+            }
+        }
+        Some(())
+    }
+
+    /// Step past the rest of the container opened by `open` once one of its
+    /// values ends here: any further members or elements, and the closing
+    /// bracket
+    fn finish(&mut self, open: u8) -> Option<()> {
+        let close = if open == b'{' { b'}' } else { b']' };
+        loop {
+            self.skip_blanks();
+            match self.peek()? {
+                b',' => {
+                    self.expect(b',')?;
+                    if open == b'{' {
+                        self.name()?;
+                    }
+                    self.value()?;
+                }
+                byte if byte == close => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Step past a member's name and its `:`, to the value
+    fn name(&mut self) -> Option<()> {
+        self.string()?;
+        self.skip_blanks();
+        self.expect(b':')
+    }
+
+    /// Step past the string starting here, returning where it ends (after
+    /// the closing quote)
+    fn string(&mut self) -> Option<usize> {
+        (self.peek()? == b'"').then_some(())?;
+        let mut i = self.pos + 1;
+        loop {
+            match self.record.get(i)? {
+                b'"' => break,
+                b'\\' => i += 2,
+                _ => i += 1,
+            }
+        }
+        self.pos = i + 1;
+        Some(self.pos)
+    }
+
+    /// Step past the value starting here, returning where it ends, or fail
+    /// if it is not JSON
+    fn value(&mut self) -> Option<usize> {
+        // The containers still open, innermost last: a stack rather than
+        // recursion, so deep nesting cannot overflow
+        let mut opened = Vec::new();
+        loop {
+            match self.peek()? {
+                open @ (b'{' | b'[') => {
+                    self.expect(open)?;
+                    let close = if open == b'{' { b'}' } else { b']' };
+                    if self.peek()? != close {
+                        opened.push(open);
+                        if open == b'{' {
+                            self.name()?;
+                        }
+                        continue;
+                    }
+                    self.pos += 1;
+                }
+                b'"' => {
+                    self.string()?;
+                }
+                _ => self.literal()?,
+            }
+            // A value ended: step to the next one in its container, or past
+            // the containers it closes
+            loop {
+                let Some(&open) = opened.last() else {
+                    return Some(self.pos);
+                };
+                self.skip_blanks();
+                match self.peek()? {
+                    b',' => {
+                        self.expect(b',')?;
+                        if open == b'{' {
+                            self.name()?;
+                        }
+                        break;
+                    }
+                    b'}' if open == b'{' => {}
+                    b']' if open == b'[' => {}
+                    _ => return None,
+                }
+                self.pos += 1;
+                opened.pop();
+            }
+        }
+    }
+
+    /// Step past the number or literal starting here, which runs to the
+    /// next delimiter
+    fn literal(&mut self) -> Option<()> {
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some(b',' | b'}' | b']' | b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+        let token = &self.record[start..self.pos];
+        let number = token.iter().all(|b| b.is_ascii_digit() || b"+-.eE".contains(b));
+        (number && token.iter().any(u8::is_ascii_digit) || matches!(token, b"true" | b"false" | b"null")).then_some(())
+    }
+}
+
+/// The content of a JSON string with its escapes undone, None if one is
+/// invalid
+fn unescape(content: &[u8]) -> Option<Cow<'_, [u8]>> {
+    if !content.contains(&b'\\') {
+        return Some(Cow::Borrowed(content));
+    }
+    let mut unescaped = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().copied();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            unescaped.push(byte);
+            continue;
+        }
+        let c = match bytes.next()? {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let unit = hex4(&mut bytes)?;
+                let code = if (0xd800..0xdc00).contains(&unit) {
+                    // A surrogate pair, written as two escapes
+                    (bytes.next()? == b'\\' && bytes.next()? == b'u').then_some(())?;
+                    let low = hex4(&mut bytes).filter(|low| (0xdc00..0xe000).contains(low))?;
+                    0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    unit
+                };
+                char::from_u32(code)?
+            }
+            _ => return None,
+        };
+        unescaped.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    Some(Cow::Owned(unescaped))
+}
+
+/// The four hex digits of a `\u` escape
+fn hex4(bytes: &mut impl Iterator<Item = u8>) -> Option<u32> {
+    let digits: Vec<u8> = bytes.take(4).collect();
+    let digits = std::str::from_utf8(&digits).ok().filter(|digits| digits.len() == 4)?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(pointer: &str, record: &[u8]) -> Vec<u8> {
+        JsonPointer::parse(pointer).unwrap().extract(record).into_owned()
+    }
+
+    #[test]
+    fn test_parse_pointer() {
+        assert_eq!(JsonPointer::parse("").unwrap().tokens, Vec::<Vec<u8>>::new());
+        assert_eq!(JsonPointer::parse("/a~1b/~0c/0").unwrap().tokens, [&b"a/b"[..], b"~c", b"0"]);
+        assert_eq!(JsonPointer::parse("/").unwrap().tokens, [b""]);
+        let err = JsonPointer::parse("user").unwrap_err();
+        assert_eq!(err.to_string(), "invalid --json-key argument 'user'; a JSON pointer starts with '/'");
+        assert!(JsonPointer::parse("/a~2").is_err());
+    }
+
+    #[test]
+    fn test_nested_members_and_elements() {
+        let record = br#"{"id": 7, "user": {"name": "bo", "tags": ["x", {"k": [1, 2.5]}]}, "n": null}"#;
+        assert_eq!(key("/id", record), b"7");
+        assert_eq!(key("/user/name", record), b"bo");
+        assert_eq!(key("/user/tags/0", record), b"x");
+        assert_eq!(key("/user/tags/1/k/1", record), b"2.5");
+        assert_eq!(key("/user/tags/1", record), br#"{"k": [1, 2.5]}"#);
+        assert_eq!(key("/n", record), b"null");
+        assert_eq!(key("", b" [1] "), b"[1]");
+    }
+
+    #[test]
+    fn test_strings_are_unescaped() {
+        let record = br#"{"a\"b": "x\"y", "s": "\u00e9\n\ud83d\ude00\/", "skip": "}\\", "t": 1}"#;
+        assert_eq!(key("/a\"b", record), b"x\"y");
+        assert_eq!(key("/s", record), "é\n😀/".as_bytes());
+        assert_eq!(key("/t", record), b"1");
+        assert_eq!(key("/s", br#"{"s": "\q"}"#), b"");
+    }
+
+    #[test]
+    fn test_missing_and_malformed() {
+        for (pointer, record) in [
+            ("/b", &br#"{"a": 1}"#[..]),
+            ("/a/b", br#"{"a": 1}"#),
+            ("/2", b"[1, 2]"),
+            ("/01", b"[1, 2]"),
+            ("/-", b"[1, 2]"),
+            ("/a", b"not json"),
+            ("/a", br#"{"a" 1}"#),
+            ("/b", br#"{"a": tru, "b": 1}"#),
+            ("/a", br#"{"a": "unterminated}"#),
+            ("/a", b""),
+            // Broken after the value
+            ("/a", br#"{"a":"x", oops"#),
+            ("/a", br#"{"a":"y""#),
+            ("/a", br#"{"a":"x"} trailing"#),
+            ("/a/0", br#"{"a": [1, {"b": ]}, "c": 2}"#),
+            ("/a", br#"{"a": 1, "b": [1,]}"#),
+            ("/a", br#"{"a": 1}}"#),
+        ] {
+            assert_eq!(JsonPointer::parse(pointer).unwrap().span(record), None, "{} in {:?}", pointer, record);
+        }
+        assert_eq!(key("/a", br#"{"a": 1, "b": [{}, [], {"c": [null]}]} "#), b"1");
+    }
+
+    #[test]
+    fn test_span() {
+        let pointer = JsonPointer::parse("/a").unwrap();
+        assert_eq!(pointer.span(br#"{"a": "x\ty"}"#), Some(7..11));
+        assert_eq!(pointer.span(br#"{"a":[1, 2]}"#), Some(5..11));
+    }
+}
@@ -1,7 +1,16 @@
+use std::fmt;
+use std::ops::Range;
+
 use crate::error::{Result, RsortError};
+use crate::fields::{self, FieldSeparator, Splitter};
+
+mod value;
+
+pub use value::{compare_versions, KeyKind, KeyValue, Number};
+pub(crate) use value::compare_versions_folded;
 
 /// Parsed key specification from -k argument
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct KeySpec {
     /// Starting field (1-indexed)
     pub start_field: usize,
@@ -11,13 +20,15 @@ pub struct KeySpec {
     pub end_field: Option<usize>,
     /// Ending character within field (1-indexed, optional)
     pub end_char: Option<usize>,
-    // Future: per-key modifiers (b, d, f, i, n, r)
+    /// Per-key options, e.g. the `nr` of `2,2nr`
+    pub modifiers: KeyModifiers,
 }
 
 impl KeySpec {
-    /// Parse key specification like "1", "1,2", "2.3,2.5", "1,1"
+    /// Parse key specification like "1", "1,2", "2.3,2.5", "1,1n"
     ///
-    /// Format: FIELD[.CHAR][,FIELD[.CHAR]]
+    /// Format: FIELD[.CHAR][OPTS][,FIELD[.CHAR][OPTS]], OPTS being letters
+    /// from `bfhMnrV` (see `KeyModifiers`)
     pub fn parse(s: &str) -> Result<Self> {
         let parts: Vec<&str> = s.split(',').collect();
 
@@ -25,7 +36,85 @@ impl KeySpec {
             return Err(RsortError::InvalidKey(s.to_string()));
         }
 
-        let (start_field, start_char) = parse_field_char(parts[0])?;
+        let (start_field, start_char, mut modifiers) = parse_field_char(parts[0])?;
+        let mut builder = KeySpec::builder().start_field(start_field);
+        if let Some(c) = start_char {
+            builder = builder.start_char(c);
+        }
+
+        if parts.len() > 1 {
+            let (end_field, end_char, end_modifiers) = parse_field_char(parts[1])?;
+            builder = builder.end_field(end_field);
+            if let Some(c) = end_char {
+                builder = builder.end_char(c);
+            }
+            // A trailing b only shifts where an end char counts from in GNU
+            // sort; the key's own leading blanks stay
+            let end_modifiers = KeyModifiers {
+                blanks: false,
+                ..end_modifiers
+            };
+            modifiers = modifiers.union(end_modifiers)?;
+        }
+
+        builder.modifiers(modifiers).build()
+    }
+
+    /// Start building a key from typed positions instead of a keydef string
+    pub fn builder() -> KeySpecBuilder {
+        KeySpecBuilder::default()
+    }
+}
+
+/// Builder for `KeySpec`; the single place key validation lives
+///
+/// ```
+/// use rsort::key::KeySpec;
+///
+/// let spec = KeySpec::builder().start_field(2).start_char(3).end_field(2).end_char(5).build()?;
+/// assert_eq!(spec.to_string(), "2.3,2.5");
+/// # Ok::<(), rsort::RsortError>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct KeySpecBuilder {
+    spec: KeySpec,
+}
+
+impl KeySpecBuilder {
+    /// First field of the key (1-indexed)
+    pub fn start_field(mut self, field: usize) -> Self {
+        self.spec.start_field = field;
+        self
+    }
+
+    /// Character within the start field where the key begins (1-indexed)
+    pub fn start_char(mut self, char_pos: usize) -> Self {
+        self.spec.start_char = Some(char_pos);
+        self
+    }
+
+    /// Last field of the key (1-indexed); unset means end of line
+    pub fn end_field(mut self, field: usize) -> Self {
+        self.spec.end_field = Some(field);
+        self
+    }
+
+    /// Last character within the end field (1-indexed); requires `end_field`
+    pub fn end_char(mut self, char_pos: usize) -> Self {
+        self.spec.end_char = Some(char_pos);
+        self
+    }
+
+    /// Per-key options; any modifier stops the key inheriting global ones
+    pub fn modifiers(mut self, modifiers: KeyModifiers) -> Self {
+        self.spec.modifiers = modifiers;
+        self
+    }
+
+    /// Validate and produce the key
+    pub fn build(self) -> Result<KeySpec> {
+        let spec = self.spec;
+        let start_field = spec.start_field;
 
         if start_field == 0 {
             return Err(RsortError::InvalidKey(
@@ -33,15 +122,8 @@ impl KeySpec {
             ));
         }
 
-        let (end_field, end_char) = if parts.len() > 1 {
-            let (f, c) = parse_field_char(parts[1])?;
-            (Some(f), c)
-        } else {
-            (None, None)
-        };
-
         // Validate end_field
-        if let Some(ef) = end_field {
+        if let Some(ef) = spec.end_field {
             if ef == 0 {
                 return Err(RsortError::InvalidKey(
                     "end field must be >= 1".to_string(),
@@ -49,21 +131,25 @@ impl KeySpec {
             }
             if ef < start_field {
                 return Err(RsortError::InvalidKey(format!(
-                    "end field {} < start field {}",
-                    ef, start_field
+                    "{}: end field {} < start field {}",
+                    spec, ef, start_field
                 )));
             }
+        } else if spec.end_char.is_some() {
+            return Err(RsortError::InvalidKey(
+                "end char requires an end field".to_string(),
+            ));
         }
 
         // Validate character positions
-        if let Some(sc) = start_char {
+        if let Some(sc) = spec.start_char {
             if sc == 0 {
                 return Err(RsortError::InvalidKey(
                     "start char must be >= 1".to_string(),
                 ));
             }
         }
-        if let Some(ec) = end_char {
+        if let Some(ec) = spec.end_char {
             if ec == 0 {
                 return Err(RsortError::InvalidKey(
                     "end char must be >= 1".to_string(),
@@ -72,30 +158,225 @@ impl KeySpec {
         }
 
         // Same field: validate char ordering
-        if end_field == Some(start_field) {
-            if let (Some(sc), Some(ec)) = (start_char, end_char) {
+        if spec.end_field == Some(start_field) {
+            if let (Some(sc), Some(ec)) = (spec.start_char, spec.end_char) {
                 if ec < sc {
                     return Err(RsortError::InvalidKey(format!(
-                        "end char {} < start char {}",
-                        ec, sc
+                        "{}: end char {} < start char {}",
+                        spec, ec, sc
                     )));
                 }
             }
         }
 
-        Ok(KeySpec {
-            start_field,
-            start_char,
-            end_field,
-            end_char,
+        Ok(spec)
+    }
+}
+
+/// Canonical GNU keydef syntax, e.g. `2.3,4.1`; `KeySpec::parse` reads it back
+///
+/// An `end_char` without an `end_field` has no keydef spelling and is omitted.
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start_field)?;
+        if let Some(c) = self.start_char {
+            write!(f, ".{}", c)?;
+        }
+        // b belongs to the start position; the rest may go at the end
+        if self.modifiers.blanks {
+            write!(f, "b")?;
+        }
+        let rest = KeyModifiers {
+            blanks: false,
+            ..self.modifiers
+        };
+        if let Some(end_field) = self.end_field {
+            write!(f, ",{}", end_field)?;
+            if let Some(c) = self.end_char {
+                write!(f, ".{}", c)?;
+            }
+        }
+        write!(f, "{}", rest)
+    }
+}
+
+/// Which of the mutually exclusive ordering options is in effect
+///
+/// -h, -M, -n and -V each replace the text comparison and cannot be
+/// combined, so one value holds whichever was given, for all keys or for
+/// one. -f is separate: it goes with text and version comparison.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SortMode {
+    /// Bytewise, or case-folded with -f
+    #[default]
+    Text,
+    /// h: human-readable numbers
+    HumanNumeric,
+    /// M: month abbreviations
+    Month,
+    /// n: numeric
+    Numeric,
+    /// V: version strings
+    Version,
+}
+
+impl SortMode {
+    /// The option letter, None for `Text`
+    pub fn letter(self) -> Option<char> {
+        match self {
+            SortMode::Text => None,
+            SortMode::HumanNumeric => Some('h'),
+            SortMode::Month => Some('M'),
+            SortMode::Numeric => Some('n'),
+            SortMode::Version => Some('V'),
+        }
+    }
+
+    fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'h' => Some(SortMode::HumanNumeric),
+            'M' => Some(SortMode::Month),
+            'n' => Some(SortMode::Numeric),
+            'V' => Some(SortMode::Version),
+            _ => None,
+        }
+    }
+}
+
+/// GNU's error for `modes` given together, naming them (and f, if
+/// `fold_case`) in its letter order
+pub(crate) fn incompatible_modes(fold_case: bool, modes: &[SortMode]) -> RsortError {
+    let mut opts = String::new();
+    if fold_case {
+        opts.push('f');
+    }
+    for mode in [SortMode::HumanNumeric, SortMode::Month, SortMode::Numeric, SortMode::Version] {
+        if modes.contains(&mode) {
+            opts.extend(mode.letter());
+        }
+    }
+    RsortError::Usage(format!("options '-{}' are incompatible", opts))
+}
+
+/// Ordering options attached to one key (`-k2,2nr`)
+///
+/// As in GNU sort, a key with any modifier ignores the global ordering
+/// options and -r; a key with none inherits them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyModifiers {
+    /// b: ignore leading blanks in the key
+    pub blanks: bool,
+    /// f: fold lower case to upper case
+    pub fold_case: bool,
+    /// h, M, n or V
+    pub mode: SortMode,
+    /// r: reverse this key
+    pub reverse: bool,
+}
+
+impl KeyModifiers {
+    /// Parse modifier letters such as `nr`, rejecting more than one of h,
+    /// M, n and V with GNU's message
+    pub fn parse(letters: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::default();
+        let mut modes = Vec::new();
+        for letter in letters.chars() {
+            let flag = match letter {
+                'b' => &mut modifiers.blanks,
+                'f' => &mut modifiers.fold_case,
+                'r' => &mut modifiers.reverse,
+                'd' | 'g' | 'i' | 'R' => {
+                    return Err(RsortError::InvalidKey(format!(
+                        "modifier '{}' is not supported",
+                        letter
+                    )))
+                }
+                _ => match SortMode::from_letter(letter) {
+                    Some(mode) => {
+                        if !modes.contains(&mode) {
+                            modes.push(mode);
+                        }
+                        continue;
+                    }
+                    None => {
+                        return Err(RsortError::InvalidKey(format!(
+                            "invalid modifier '{}'",
+                            letter
+                        )))
+                    }
+                },
+            };
+            *flag = true;
+        }
+        match modes[..] {
+            [] => {}
+            [mode] => modifiers.mode = mode,
+            _ => return Err(incompatible_modes(modifiers.fold_case, &modes)),
+        }
+        Ok(modifiers)
+    }
+
+    /// Whether no modifier is set
+    pub fn is_empty(&self) -> bool {
+        *self == KeyModifiers::default()
+    }
+
+    /// Every modifier set in either; an error if they set different modes
+    pub fn union(self, other: KeyModifiers) -> Result<Self> {
+        let fold_case = self.fold_case || other.fold_case;
+        let mode = match (self.mode, other.mode) {
+            (SortMode::Text, mode) | (mode, SortMode::Text) => mode,
+            (a, b) if a == b => a,
+            (a, b) => return Err(incompatible_modes(fold_case, &[a, b])),
+        };
+        Ok(KeyModifiers {
+            blanks: self.blanks || other.blanks,
+            fold_case,
+            mode,
+            reverse: self.reverse || other.reverse,
         })
     }
+
+    /// The ordering the key is compared under
+    ///
+    /// -f only matters for text and version comparison.
+    pub fn key_kind(&self) -> KeyKind {
+        match self.mode {
+            SortMode::Text if self.fold_case => KeyKind::FoldCase,
+            SortMode::Text => KeyKind::Bytes,
+            SortMode::HumanNumeric => KeyKind::Human,
+            SortMode::Month => KeyKind::Month,
+            SortMode::Numeric => KeyKind::Numeric,
+            SortMode::Version => KeyKind::Version,
+        }
+    }
 }
 
-/// Parse "FIELD" or "FIELD.CHAR" into (field, optional_char)
-fn parse_field_char(s: &str) -> Result<(usize, Option<usize>)> {
-    // Strip any trailing modifier letters (for future compatibility)
-    let s = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+/// The modifier letters, in `bfhMnrV` order
+impl fmt::Display for KeyModifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (on, letter) in [
+            (self.blanks, 'b'),
+            (self.fold_case, 'f'),
+            (self.mode == SortMode::HumanNumeric, 'h'),
+            (self.mode == SortMode::Month, 'M'),
+            (self.mode == SortMode::Numeric, 'n'),
+            (self.reverse, 'r'),
+            (self.mode == SortMode::Version, 'V'),
+        ] {
+            if on {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse "FIELD[.CHAR][OPTS]" into (field, optional_char, modifiers)
+fn parse_field_char(s: &str) -> Result<(usize, Option<usize>, KeyModifiers)> {
+    let position_len = s.trim_end_matches(|c: char| c.is_ascii_alphabetic()).len();
+    let (s, letters) = s.split_at(position_len);
+    let modifiers = KeyModifiers::parse(letters)?;
 
     let parts: Vec<&str> = s.split('.').collect();
 
@@ -117,102 +398,273 @@ fn parse_field_char(s: &str) -> Result<(usize, Option<usize>)> {
         None
     };
 
-    Ok((field, char_pos))
+    Ok((field, char_pos, modifiers))
 }
 
 /// Extract key bytes from a record based on KeySpec
 /// For multi-field keys, preserves original bytes (including separators) from the record
-pub fn extract_key(record: &[u8], spec: &KeySpec, field_separator: Option<u8>) -> Vec<u8> {
-    let fields_with_pos = split_fields_with_positions(record, field_separator);
+pub fn extract_key<'a>(
+    record: &'a [u8],
+    spec: &KeySpec,
+    field_separator: Option<&FieldSeparator>,
+) -> &'a [u8] {
+    let extractor = KeyExtractor::new(spec, field_separator);
+    extractor.extract(record, &FieldIndex::new(record, field_separator))
+}
+
+/// Split record into fields, returning (start_pos, end_pos) for each
+#[deprecated(
+    since = "0.1.0",
+    note = "use `fields::collect_fields`, or `fields::split_fields_with_positions` to iterate"
+)]
+pub fn split_fields_with_positions(record: &[u8], separator: Option<u8>) -> Vec<(usize, usize)> {
+    fields::collect_fields(record, separator.map(FieldSeparator::from).as_ref())
+}
 
-    // Convert to 0-indexed
-    let start_idx = spec.start_field.saturating_sub(1);
+/// Field positions of one record, computed once and shared by every key
+#[derive(Clone, Debug)]
+pub struct FieldIndex {
+    fields: Vec<(usize, usize)>,
+}
 
-    // Field doesn't exist: return empty
-    if start_idx >= fields_with_pos.len() {
-        return Vec::new();
+impl FieldIndex {
+    /// Split a record into fields
+    pub fn new(record: &[u8], separator: Option<&FieldSeparator>) -> Self {
+        FieldIndex {
+            fields: fields::collect_fields(record, separator),
+        }
     }
 
-    let end_idx = spec
-        .end_field
-        .map(|f| f.saturating_sub(1).min(fields_with_pos.len().saturating_sub(1)))
-        .unwrap_or(fields_with_pos.len().saturating_sub(1));
+    /// Split a record with `splitter`, keeping at most `max_fields` fields
+    ///
+    /// The limit has the same caveat as `with_limit`.
+    pub fn split(record: &[u8], splitter: &Splitter, max_fields: Option<usize>) -> Self {
+        let spans = splitter.spans(record);
+        FieldIndex {
+            fields: match max_fields {
+                Some(limit) => spans.take(limit).collect(),
+                None => spans.collect(),
+            },
+        }
+    }
 
-    let (first_start, first_end) = fields_with_pos[start_idx];
-    let (last_start, last_end) = fields_with_pos[end_idx];
+    /// Split only the first `max_fields` fields of a record
+    ///
+    /// Only valid for keys with an explicit end field: the last indexed field
+    /// is not necessarily the last field of the record.
+    pub fn with_limit(
+        record: &[u8],
+        separator: Option<&FieldSeparator>,
+        max_fields: usize,
+    ) -> Self {
+        FieldIndex {
+            fields: fields::split_fields_with_positions(record, separator)
+                .take(max_fields)
+                .collect(),
+        }
+    }
 
-    // Apply character offsets
-    let start_char_offset = spec.start_char.unwrap_or(1).saturating_sub(1);
-    let byte_start = (first_start + start_char_offset).min(first_end);
+    /// Number of fields in the record
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
 
-    let byte_end = if let Some(ec) = spec.end_char {
-        // end_char applies to the last field
-        (last_start + ec).min(last_end)
-    } else {
-        last_end
-    };
+    /// Whether the record has no fields
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
 
-    // For single field, just slice
-    if start_idx == end_idx {
-        return record.get(byte_start..byte_end).unwrap_or(&[]).to_vec();
+    /// Byte span (start, end) of a field (0-indexed)
+    pub fn get(&self, idx: usize) -> Option<(usize, usize)> {
+        self.fields.get(idx).copied()
     }
+}
 
-    // For multiple fields, copy the entire span from record (preserving original separators)
-    record.get(byte_start..byte_end).unwrap_or(&[]).to_vec()
+/// Key extraction plan compiled once from a KeySpec and field splitter
+#[derive(Clone, Debug)]
+pub struct KeyExtractor {
+    /// Splitter the FieldIndex must be built with
+    splitter: Splitter,
+    /// Starting field (0-indexed)
+    start_idx: usize,
+    /// Ending field (0-indexed), None for end of line
+    end_idx: Option<usize>,
+    /// b: skip the starting field's leading blanks before the offset
+    skip_blanks: bool,
+    /// Byte offset into the starting field (0 when no start char given)
+    start_offset: usize,
+    /// Ending character within the last field
+    end_char: Option<usize>,
 }
 
-/// Split record into fields, returning (start_pos, end_pos) for each
-pub fn split_fields_with_positions(record: &[u8], separator: Option<u8>) -> Vec<(usize, usize)> {
-    match separator {
-        Some(sep) => {
-            let mut fields = Vec::new();
-            let mut start = 0;
-
-            for (i, &b) in record.iter().enumerate() {
-                if b == sep {
-                    fields.push((start, i));
-                    start = i + 1;
-                }
-            }
-            fields.push((start, record.len()));
-            fields
+impl KeyExtractor {
+    pub fn new(spec: &KeySpec, field_separator: Option<&FieldSeparator>) -> Self {
+        KeyExtractor::with_splitter(spec, Splitter::from_separator(field_separator))
+    }
+
+    /// Extractor for fields cut by `splitter` (e.g. CSV)
+    pub fn with_splitter(spec: &KeySpec, splitter: Splitter) -> Self {
+        KeyExtractor {
+            splitter,
+            start_idx: spec.start_field.saturating_sub(1),
+            end_idx: spec.end_field.map(|f| f.saturating_sub(1)),
+            skip_blanks: spec.modifiers.blanks,
+            start_offset: spec.start_char.unwrap_or(1).saturating_sub(1),
+            end_char: spec.end_char,
         }
-        None => {
-            // Whitespace-delimited
-            let mut fields = Vec::new();
-            let mut in_field = false;
-            let mut start = 0;
-
-            for (i, &b) in record.iter().enumerate() {
-                let is_space = b == b' ' || b == b'\t';
-
-                if is_space && in_field {
-                    fields.push((start, i));
-                    in_field = false;
-                } else if !is_space && !in_field {
-                    start = i;
-                    in_field = true;
-                }
-            }
+    }
 
-            if in_field {
-                fields.push((start, record.len()));
-            }
+    /// Splitter to build the shared FieldIndex with
+    pub fn splitter(&self) -> &Splitter {
+        &self.splitter
+    }
 
-            if fields.is_empty() {
-                fields.push((0, 0));
-            }
+    /// Number of leading fields this key can look at (None if it runs to end of line)
+    pub fn fields_needed(&self) -> Option<usize> {
+        self.end_idx.map(|idx| idx + 1)
+    }
+
+    /// Byte range of the key within the record, None when the start field is missing
+    ///
+    /// The range may be empty (e.g. a start char beyond the end of the record).
+    pub fn span(&self, record: &[u8], fields: &FieldIndex) -> Option<Range<usize>> {
+        // Field doesn't exist: no key
+        let (first_start, first_end) = fields.get(self.start_idx)?;
+
+        let last_idx = fields.len() - 1;
+        let end_idx = self.end_idx.map_or(last_idx, |idx| idx.min(last_idx));
+        let (last_start, last_end) = if end_idx == self.start_idx {
+            (first_start, first_end)
+        } else {
+            fields.get(end_idx)?
+        };
+
+        Some(self.char_range(record, first_start, last_start, last_end))
+    }
+
+    /// `span` computed straight from the record, without a FieldIndex
+    ///
+    /// Cheaper when a single key looks at the record; several keys should
+    /// share one `FieldIndex` instead.
+    pub fn span_in(&self, record: &[u8]) -> Option<Range<usize>> {
+        let mut fields = self.splitter.spans(record);
+        let (first_start, first_end) = fields.nth(self.start_idx)?;
+        let (last_start, last_end) = match self.end_idx {
+            Some(end_idx) => fields.take(end_idx.saturating_sub(self.start_idx)).last(),
+            None => fields.last(),
+        }
+        .unwrap_or((first_start, first_end));
+
+        Some(self.char_range(record, first_start, last_start, last_end))
+    }
+
+    /// Apply the character offsets to the first and last field of the key
+    ///
+    /// As in GNU sort, b skips blanks first and the start character counts
+    /// from the first byte after them. Character offsets run past the end
+    /// of their field if need be, stopping only at the end of the record.
+    fn char_range(&self, record: &[u8], first_start: usize, last_start: usize, last_end: usize) -> Range<usize> {
+        let first_start = if self.skip_blanks {
+            first_start + record[first_start..].iter().take_while(|&&b| fields::is_blank(b)).count()
+        } else {
+            first_start
+        };
+        // Saturating: keydefs like -k1.18446744073709551615 are valid
+        let byte_start = first_start.saturating_add(self.start_offset).min(record.len());
+        let byte_end = match self.end_char {
+            // end_char applies to the last field
+            Some(ec) => last_start.saturating_add(ec).min(record.len()),
+            None => last_end,
+        };
+        byte_start..byte_end.max(byte_start)
+    }
+
+    /// `extract` without a FieldIndex (see `span_in`)
+    pub fn extract_in<'a>(&self, record: &'a [u8]) -> &'a [u8] {
+        self.span_in(record)
+            .and_then(|range| record.get(range))
+            .unwrap_or(&[])
+    }
+
+    /// Extract the key bytes, empty when the start field is missing
+    ///
+    /// For multi-field keys the slice covers the original bytes, separators included.
+    pub fn extract<'a>(&self, record: &'a [u8], fields: &FieldIndex) -> &'a [u8] {
+        self.span(record, fields)
+            .and_then(|range| record.get(range))
+            .unwrap_or(&[])
+    }
+}
 
-            fields
+/// Byte positions of a key, for records with no fields (--key-bytes)
+///
+/// Parsed from `START[,END]`, 1-based and inclusive, END defaulting to the
+/// end of the record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    /// First byte (0-indexed)
+    start: usize,
+    /// One past the last byte, None for the end of the record
+    end: Option<usize>,
+}
+
+impl ByteRange {
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || {
+            RsortError::Usage(format!(
+                "invalid --key-bytes argument '{}'; expected START[,END], byte positions counting from 1",
+                s
+            ))
+        };
+        let position = |part: &str| part.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(invalid);
+        let (start, end) = match s.split_once(',') {
+            Some((start, end)) => (position(start)?, Some(position(end)?)),
+            None => (position(s)?, None),
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err(invalid());
         }
+        Ok(ByteRange { start: start - 1, end })
+    }
+
+    /// Byte range of the key in `record`, cut short at its end; None when
+    /// the record ends before START
+    pub fn span(&self, record: &[u8]) -> Option<Range<usize>> {
+        if self.start >= record.len() {
+            return None;
+        }
+        let end = self.end.map_or(record.len(), |end| end.min(record.len()));
+        Some(self.start..end)
+    }
+
+    /// The key's bytes in `record`, empty when `span` is None
+    pub fn extract<'a>(&self, record: &'a [u8]) -> &'a [u8] {
+        self.span(record).map_or(&[], |span| &record[span])
     }
 }
 
+/// `START[,END]`, as `ByteRange::parse` reads it
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start + 1)?;
+        if let Some(end) = self.end {
+            write!(f, ",{}", end)?;
+        }
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_split_fields_with_positions() {
+        assert_eq!(split_fields_with_positions(b"a:bc::d", Some(b':')), [(0, 1), (2, 4), (5, 5), (6, 7)]);
+        assert_eq!(split_fields_with_positions(b" a  b", None), [(1, 2), (4, 5)]);
+    }
+
     #[test]
     fn test_parse_simple_field() {
         let spec = KeySpec::parse("2").unwrap();
@@ -237,6 +689,112 @@ mod tests {
         assert_eq!(spec.end_char, Some(5));
     }
 
+    #[test]
+    fn test_parse_modifiers() {
+        let spec = KeySpec::parse("2,2nr").unwrap();
+        assert!(spec.modifiers.mode == SortMode::Numeric && spec.modifiers.reverse);
+        assert!(!spec.modifiers.fold_case);
+        // GNU accepts modifiers on either position; b only counts on the start
+        assert_eq!(KeySpec::parse("2bf,3").unwrap(), KeySpec::parse("2b,3f").unwrap());
+        assert!(!KeySpec::parse("2,3b").unwrap().modifiers.blanks);
+        assert!(KeySpec::parse("1").unwrap().modifiers.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_modifiers() {
+        let err = KeySpec::parse("1,1x").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid key specification: invalid modifier 'x'");
+        let err = KeySpec::parse("1d").unwrap_err();
+        assert!(err.to_string().contains("modifier 'd' is not supported"));
+        let err = KeySpec::parse("1,1nM").unwrap_err();
+        assert_eq!(err.to_string(), "options '-Mn' are incompatible");
+    }
+
+    #[test]
+    fn test_mode_conflict_matrix() {
+        // Every combination of f, h, M, n and V: at most one of the last four
+        for bits in 0u8..32 {
+            let letters: String = ['f', 'h', 'M', 'n', 'V']
+                .iter()
+                .enumerate()
+                .filter(|&(bit, _)| bits & (1 << bit) != 0)
+                .map(|(_, &letter)| letter)
+                .collect();
+            let modes = (bits >> 1).count_ones();
+            match KeyModifiers::parse(&letters) {
+                Ok(modifiers) => {
+                    assert!(modes <= 1, "{} accepted", letters);
+                    assert_eq!(modifiers.to_string(), letters);
+                }
+                Err(err) => {
+                    assert!(modes > 1, "{} rejected", letters);
+                    assert_eq!(err.to_string(), format!("options '-{}' are incompatible", letters));
+                }
+            }
+        }
+        // Across the two positions too, whichever carries f
+        let err = KeySpec::parse("1n,1fV").unwrap_err();
+        assert_eq!(err.to_string(), "options '-fnV' are incompatible");
+        assert_eq!(KeySpec::parse("1n,1n").unwrap(), KeySpec::parse("1,1n").unwrap());
+    }
+
+    #[test]
+    fn test_display_canonical() {
+        for keydef in ["2", "2.3", "1,2", "2.3,4.1", "3,3.7", "2,2nr", "1.2bf", "2b,3n"] {
+            assert_eq!(KeySpec::parse(keydef).unwrap().to_string(), keydef);
+        }
+    }
+
+    #[test]
+    fn test_invalid_order_names_key() {
+        let err = KeySpec::parse("3.2,1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid key specification: 3.2,1: end field 1 < start field 3"
+        );
+        let err = KeySpec::parse("2.5,2.3").unwrap_err();
+        assert!(err.to_string().contains("2.5,2.3: end char 3 < start char 5"));
+    }
+
+    #[test]
+    fn test_builder_matches_parse() {
+        let built = KeySpec::builder()
+            .start_field(2)
+            .start_char(3)
+            .end_field(4)
+            .end_char(1)
+            .build()
+            .unwrap();
+        assert_eq!(built, KeySpec::parse("2.3,4.1").unwrap());
+        assert_eq!(KeySpec::builder().start_field(5).build().unwrap(), KeySpec::parse("5").unwrap());
+    }
+
+    #[test]
+    fn test_builder_validation_matches_parse() {
+        let cases = [
+            ("0", KeySpec::builder().start_field(0)),
+            ("1,0", KeySpec::builder().start_field(1).end_field(0)),
+            ("3.2,1", KeySpec::builder().start_field(3).start_char(2).end_field(1)),
+            ("1.0", KeySpec::builder().start_field(1).start_char(0)),
+            ("1,2.0", KeySpec::builder().start_field(1).end_field(2).end_char(0)),
+            (
+                "2.5,2.3",
+                KeySpec::builder().start_field(2).start_char(5).end_field(2).end_char(3),
+            ),
+        ];
+        for (keydef, builder) in cases {
+            let parsed = KeySpec::parse(keydef).unwrap_err().to_string();
+            let built = builder.build().unwrap_err().to_string();
+            assert_eq!(parsed, built, "keydef {}", keydef);
+        }
+    }
+
+    #[test]
+    fn test_builder_rejects_end_char_without_end_field() {
+        let err = KeySpec::builder().start_field(1).end_char(2).build().unwrap_err();
+        assert!(matches!(err, RsortError::InvalidKey(_)));
+    }
+
     #[test]
     fn test_extract_key_whitespace() {
         let record = b"apple banana cherry";
@@ -249,7 +807,7 @@ mod tests {
     fn test_extract_key_delimiter() {
         let record = b"a:b:c";
         let spec = KeySpec::parse("2,2").unwrap();
-        let key = extract_key(record, &spec, Some(b':'));
+        let key = extract_key(record, &spec, Some(&FieldSeparator::Byte(b':')));
         assert_eq!(key, b"b");
     }
 
@@ -268,4 +826,121 @@ mod tests {
         let key = extract_key(record, &spec, None);
         assert_eq!(key, b"bcd");
     }
+
+    #[test]
+    fn test_extract_key_multi_field_preserves_separators() {
+        let record = b"a:b:c:d";
+        let spec = KeySpec::parse("2,3").unwrap();
+        assert_eq!(extract_key(record, &spec, Some(&FieldSeparator::Byte(b':'))), b"b:c");
+
+        let record = "a¦b¦c¦d".as_bytes();
+        let key = extract_key(record, &spec, Some(&FieldSeparator::from('¦')));
+        assert_eq!(key, "b¦c".as_bytes());
+    }
+
+    #[test]
+    fn test_extract_key_to_end_of_line() {
+        let record = b"one  two three";
+        let spec = KeySpec::parse("2").unwrap();
+        assert_eq!(extract_key(record, &spec, None), b"two three");
+    }
+
+    #[test]
+    fn test_extract_key_chars_beyond_field() {
+        // As in GNU sort, offsets run on into the next fields
+        let record = b"ab cd";
+        let spec = KeySpec::parse("1.5,1.6").unwrap();
+        assert_eq!(extract_key(record, &spec, None), b"d");
+        let spec = KeySpec::parse("1.7").unwrap();
+        assert_eq!(extract_key(record, &spec, None), b"");
+    }
+
+    #[test]
+    fn test_extractor_span() {
+        let record = b"foo bar baz";
+        let fields = FieldIndex::new(record, None);
+
+        let extractor = KeyExtractor::new(&KeySpec::parse("2,2").unwrap(), None);
+        assert_eq!(extractor.span(record, &fields), Some(4..7));
+
+        let extractor = KeyExtractor::new(&KeySpec::parse("2.2,3.1").unwrap(), None);
+        assert_eq!(extractor.span(record, &fields), Some(5..9));
+
+        let extractor = KeyExtractor::new(&KeySpec::parse("4,4").unwrap(), None);
+        assert_eq!(extractor.span(record, &fields), None);
+
+        // b skips the blanks before the start character is counted
+        let record = b"x,  ba";
+        let comma = Some(&FieldSeparator::Byte(b','));
+        let fields = FieldIndex::new(record, comma);
+        let extractor = KeyExtractor::new(&KeySpec::parse("2.2b,2").unwrap(), comma);
+        assert_eq!(extractor.span(record, &fields), Some(5..6));
+        let extractor = KeyExtractor::new(&KeySpec::parse("2.2,2").unwrap(), comma);
+        assert_eq!(extractor.span(record, &fields), Some(3..6));
+    }
+
+    #[test]
+    fn test_extractors_share_field_index() {
+        let record = b"x,10,y";
+        let comma = Some(&FieldSeparator::Byte(b','));
+        let fields = FieldIndex::new(record, comma);
+        let first = KeyExtractor::new(&KeySpec::parse("3,3").unwrap(), comma);
+        let second = KeyExtractor::new(&KeySpec::parse("2,2").unwrap(), comma);
+        assert_eq!(first.extract(record, &fields), b"y");
+        assert_eq!(second.extract(record, &fields), b"10");
+    }
+
+    #[test]
+    fn test_field_index_with_limit() {
+        let record = b"a:b:c:d";
+        let fields = FieldIndex::with_limit(record, Some(&FieldSeparator::Byte(b':')), 2);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields.get(1), Some((2, 3)));
+
+        let fields = FieldIndex::with_limit(b"a b c", None, 2);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields.get(1), Some((2, 3)));
+    }
+
+    #[test]
+    fn test_span_in_matches_span() {
+        let records: [&[u8]; 5] = [b"a b c d", b"  x", b"", b"a:b::d:", b"one"];
+        for keydef in ["1", "2", "2,3", "2.2,3.1", "1.3,4", "5", "2.2b,3"] {
+            let spec = KeySpec::parse(keydef).unwrap();
+            for separator in [None, Some(FieldSeparator::Byte(b':'))] {
+                let extractor = KeyExtractor::new(&spec, separator.as_ref());
+                for record in records {
+                    let fields = FieldIndex::new(record, separator.as_ref());
+                    assert_eq!(extractor.span_in(record), extractor.span(record, &fields));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_key_huge_char_offsets() {
+        // Found reviewing the span math for the fuzz targets, not by the
+        // fuzzer: offsets near usize::MAX overflowed it
+        let spec = KeySpec::parse("1,1.18446744073709551615").unwrap();
+        assert_eq!(extract_key(b"  abc", &spec, None), b"abc");
+
+        let spec = KeySpec::parse("2.18446744073709551615").unwrap();
+        assert_eq!(extract_key(b"a  abc", &spec, None), b"");
+    }
+
+    #[test]
+    fn test_byte_range() {
+        let range = ByteRange::parse("3,4").unwrap();
+        assert_eq!(range.to_string(), "3,4");
+        assert_eq!(range.extract(b"ab\x00\x07xyz"), b"\x00\x07");
+        assert_eq!(range.span(b"abc"), Some(2..3));
+        assert_eq!(range.span(b"ab"), None);
+        assert_eq!(range.extract(b"ab"), b"");
+        assert_eq!(ByteRange::parse("2").unwrap().extract(b"abc"), b"bc");
+
+        for bad in ["0", "2,1", "", "1,", "x", "-1"] {
+            let err = ByteRange::parse(bad).unwrap_err();
+            assert!(err.to_string().starts_with("invalid --key-bytes argument"), "{}", bad);
+        }
+    }
 }
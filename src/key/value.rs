@@ -0,0 +1,497 @@
+//! Parsed comparison keys for the typed orderings (-f, -n, -h, -M, -V)
+//!
+//! `KeyValue::parse(key, kind)` reads a key the way the matching option
+//! does, and `Ord` on the result is the ordering the binary uses. Values
+//! borrow from the key bytes.
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+use crate::fields::is_blank;
+
+/// Which ordering a key is compared under
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyKind {
+    /// Unsigned bytewise (the default)
+    #[default]
+    Bytes,
+    /// Case-insensitive (-f): ASCII only, or Unicode with feature `unicode`
+    FoldCase,
+    /// Decimal number (-n)
+    Numeric,
+    /// Number with an SI suffix such as 2K or 1.5G (-h)
+    Human,
+    /// Month abbreviation, JAN..DEC (-M)
+    Month,
+    /// Version string (-V)
+    Version,
+}
+
+impl KeyKind {
+    /// The bytes of `key` that parsing it as this kind reads
+    ///
+    /// A number (with its -h suffix) or month name after the leading
+    /// blanks, or an empty range there when the key has none; the whole key
+    /// for the other kinds. This is what --debug underlines.
+    pub fn read_range(self, key: &[u8]) -> Range<usize> {
+        let start = key.iter().take_while(|&&b| is_blank(b)).count();
+        match self {
+            KeyKind::Numeric | KeyKind::Human => {
+                let (_, end) = Number::parse_prefix(key);
+                if !key[start..end].iter().any(u8::is_ascii_digit) {
+                    return start..start;
+                }
+                let unit = self == KeyKind::Human && key.get(end).is_some_and(|&b| unit_rank(b) > 0);
+                start..end + usize::from(unit)
+            }
+            KeyKind::Month if month(key) > 0 => start..start + 3,
+            KeyKind::Month => start..start,
+            KeyKind::Bytes | KeyKind::FoldCase | KeyKind::Version => 0..key.len(),
+        }
+    }
+}
+
+/// A key parsed for comparison under one `KeyKind`
+///
+/// Values of different kinds order by kind; a single sort never mixes them.
+#[derive(Clone, Copy, Debug)]
+pub enum KeyValue<'a> {
+    Bytes(&'a [u8]),
+    Folded(&'a [u8]),
+    Numeric(Number<'a>),
+    /// `unit` is the suffix rank (K=1 .. Y=8), negated for negative numbers
+    /// and 0 for zero or no suffix
+    Human { unit: i8, number: Number<'a> },
+    /// 1-12 for JAN-DEC, 0 for anything else
+    Month(u8),
+    Version(&'a [u8]),
+}
+
+impl<'a> KeyValue<'a> {
+    /// Parse `key` as the given kind; never fails, like the CLI orderings
+    pub fn parse(key: &'a [u8], kind: KeyKind) -> Self {
+        match kind {
+            KeyKind::Bytes => KeyValue::Bytes(key),
+            KeyKind::FoldCase => KeyValue::Folded(key),
+            KeyKind::Numeric => KeyValue::Numeric(Number::parse(key)),
+            KeyKind::Human => {
+                let (number, end) = Number::parse_prefix(key);
+                let unit = if number.is_zero() {
+                    0
+                } else {
+                    let rank = key.get(end).map_or(0, |&b| unit_rank(b));
+                    if number.negative {
+                        -rank
+                    } else {
+                        rank
+                    }
+                };
+                KeyValue::Human { unit, number }
+            }
+            KeyKind::Month => KeyValue::Month(month(key)),
+            KeyKind::Version => KeyValue::Version(key),
+        }
+    }
+
+    pub fn kind(&self) -> KeyKind {
+        match self {
+            KeyValue::Bytes(_) => KeyKind::Bytes,
+            KeyValue::Folded(_) => KeyKind::FoldCase,
+            KeyValue::Numeric(_) => KeyKind::Numeric,
+            KeyValue::Human { .. } => KeyKind::Human,
+            KeyValue::Month(_) => KeyKind::Month,
+            KeyValue::Version(_) => KeyKind::Version,
+        }
+    }
+}
+
+impl Ord for KeyValue<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (KeyValue::Bytes(a), KeyValue::Bytes(b)) => a.cmp(b),
+            #[cfg(feature = "unicode")]
+            (KeyValue::Folded(a), KeyValue::Folded(b)) => crate::casefold::compare_folded(a, b),
+            #[cfg(not(feature = "unicode"))]
+            (KeyValue::Folded(a), KeyValue::Folded(b)) => {
+                let fold = |b: &u8| b.to_ascii_uppercase();
+                a.iter().map(fold).cmp(b.iter().map(fold))
+            }
+            (KeyValue::Numeric(a), KeyValue::Numeric(b)) => a.cmp(b),
+            (
+                KeyValue::Human { unit: ua, number: na },
+                KeyValue::Human { unit: ub, number: nb },
+            ) => ua.cmp(ub).then_with(|| na.cmp(nb)),
+            (KeyValue::Month(a), KeyValue::Month(b)) => a.cmp(b),
+            (KeyValue::Version(a), KeyValue::Version(b)) => compare_versions(a, b),
+            _ => (self.kind() as u8).cmp(&(other.kind() as u8)),
+        }
+    }
+}
+
+impl PartialOrd for KeyValue<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for KeyValue<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for KeyValue<'_> {}
+
+/// A decimal number as -n reads it, compared exactly on its digits
+///
+/// Leading blanks are skipped, then an optional `-`, digits, and an optional
+/// `.` fraction. `+`, exponents and anything else end the number; a key with
+/// no digits is zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Number<'a> {
+    negative: bool,
+    /// Integer digits without leading zeros
+    integer: &'a [u8],
+    /// Fraction digits without trailing zeros
+    fraction: &'a [u8],
+}
+
+impl<'a> Number<'a> {
+    pub fn parse(key: &'a [u8]) -> Self {
+        Self::parse_prefix(key).0
+    }
+
+    /// Parse the leading number, also returning the index just past it
+    ///
+    /// The index counts the leading blanks, sign and digits read, so
+    /// `key[..end]` is what -n compares (--debug underlines it without the
+    /// blanks); it is past any lone `-` or `.` even with no digits.
+    pub fn parse_prefix(key: &'a [u8]) -> (Self, usize) {
+        let mut pos = key.iter().take_while(|&&b| is_blank(b)).count();
+        let negative = key.get(pos) == Some(&b'-');
+        if negative {
+            pos += 1;
+        }
+
+        let int_start = pos;
+        pos += digits_at(key, pos);
+        let mut integer = &key[int_start..pos];
+
+        let mut fraction: &[u8] = &[];
+        if key.get(pos) == Some(&b'.') {
+            pos += 1;
+            let frac_start = pos;
+            pos += digits_at(key, pos);
+            fraction = &key[frac_start..pos];
+        }
+
+        while let [b'0', rest @ ..] = integer {
+            integer = rest;
+        }
+        while let [rest @ .., b'0'] = fraction {
+            fraction = rest;
+        }
+
+        let number = Number {
+            negative: negative && !(integer.is_empty() && fraction.is_empty()),
+            integer,
+            fraction,
+        };
+        (number, pos)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.integer.is_empty() && self.fraction.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Compare absolute values
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        self.integer
+            .len()
+            .cmp(&other.integer.len())
+            .then_with(|| self.integer.cmp(other.integer))
+            .then_with(|| self.fraction.cmp(other.fraction))
+    }
+}
+
+impl Ord for Number<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+}
+
+impl PartialOrd for Number<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn digits_at(key: &[u8], pos: usize) -> usize {
+    key[pos..].iter().take_while(|b| b.is_ascii_digit()).count()
+}
+
+/// Rank of an SI suffix for -h
+fn unit_rank(b: u8) -> i8 {
+    match b {
+        b'K' | b'k' => 1,
+        b'M' => 2,
+        b'G' => 3,
+        b'T' => 4,
+        b'P' => 5,
+        b'E' => 6,
+        b'Z' => 7,
+        b'Y' => 8,
+        _ => 0,
+    }
+}
+
+const MONTHS: [&[u8; 3]; 12] = [
+    b"JAN", b"FEB", b"MAR", b"APR", b"MAY", b"JUN", b"JUL", b"AUG", b"SEP", b"OCT", b"NOV", b"DEC",
+];
+
+/// Month number for a key starting (after blanks) with a month abbreviation
+fn month(key: &[u8]) -> u8 {
+    let start = key.iter().take_while(|&&b| is_blank(b)).count();
+    let key = &key[start..];
+    MONTHS
+        .iter()
+        .position(|name| key.len() >= 3 && key[..3].eq_ignore_ascii_case(&name[..]))
+        .map_or(0, |i| i as u8 + 1)
+}
+
+/// Version ordering of -V (gnulib `filevercmp`)
+///
+/// Digit runs compare numerically and everything else bytewise, except that
+/// `~` sorts before anything, even the end of the string. Trailing
+/// file-suffix-like parts (`.tar.gz`) only break ties.
+pub fn compare_versions(a: &[u8], b: &[u8]) -> Ordering {
+    compare_versions_by(a, b, |c| c)
+}
+
+/// -V -f: versions compared as if both were in upper case
+pub(crate) fn compare_versions_folded(a: &[u8], b: &[u8]) -> Ordering {
+    compare_versions_by(a, b, |c| c.to_ascii_uppercase())
+}
+
+/// `compare_versions` with each byte replaced by `map(byte)` where its value
+/// matters; `map` must keep digits, `.` and `~` as they are
+fn compare_versions_by(a: &[u8], b: &[u8], map: impl Fn(u8) -> u8 + Copy) -> Ordering {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        (false, false) => {}
+    }
+
+    // "." first, then "..", then other dot-names, then the rest
+    match (a[0] == b'.', b[0] == b'.') {
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        (true, true) => {
+            for special in [&b"."[..], b".."] {
+                match (a == special, b == special) {
+                    (true, true) => return Ordering::Equal,
+                    (true, false) => return Ordering::Less,
+                    (false, true) => return Ordering::Greater,
+                    (false, false) => {}
+                }
+            }
+        }
+        (false, false) => {}
+    }
+
+    let a_prefix = file_prefix_len(a);
+    let b_prefix = file_prefix_len(b);
+    let result = verrevcmp(&a[..a_prefix], &b[..b_prefix], map);
+    if result != Ordering::Equal || (a_prefix == a.len() && b_prefix == b.len()) {
+        return result;
+    }
+    verrevcmp(a, b, map)
+}
+
+/// Length of `s` without its longest suffix matching `(\.[A-Za-z~][A-Za-z0-9~]*)*$`
+fn file_prefix_len(s: &[u8]) -> usize {
+    let n = s.len();
+    let mut prefix = 0;
+    let mut i = 0;
+    loop {
+        // A suffix may start anywhere, the first byte included
+        while i + 1 < n && s[i] == b'.' && (s[i + 1].is_ascii_alphabetic() || s[i + 1] == b'~') {
+            i += 2;
+            while i < n && (s[i].is_ascii_alphanumeric() || s[i] == b'~') {
+                i += 1;
+            }
+        }
+        if i >= n {
+            return prefix;
+        }
+        i += 1;
+        prefix = i;
+    }
+}
+
+/// Weight of the byte at `pos` in the non-digit parts of a version
+fn order(s: &[u8], pos: usize, map: impl Fn(u8) -> u8) -> i32 {
+    match s.get(pos).map(|&c| map(c)) {
+        None => -1,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(c),
+        Some(b'~') => -2,
+        Some(c) => i32::from(c) + 256,
+    }
+}
+
+/// Debian version comparison (dpkg `verrevcmp`)
+fn verrevcmp(a: &[u8], b: &[u8], map: impl Fn(u8) -> u8 + Copy) -> Ordering {
+    let is_digit_at = |s: &[u8], pos: usize| s.get(pos).is_some_and(u8::is_ascii_digit);
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        // Non-digit run
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let (ca, cb) = (order(a, i, map), order(b, j, map));
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        // Digit run, compared numerically
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while is_digit_at(a, i) && is_digit_at(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if is_digit_at(a, i) {
+            return Ordering::Greater;
+        }
+        if is_digit_at(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmp(a: &[u8], b: &[u8], kind: KeyKind) -> Ordering {
+        KeyValue::parse(a, kind).cmp(&KeyValue::parse(b, kind))
+    }
+
+    #[test]
+    fn test_number_exact() {
+        let n = |a: &[u8], b: &[u8]| cmp(a, b, KeyKind::Numeric);
+        assert_eq!(n(b"12345678901234567890", b"12345678901234567891"), Ordering::Less);
+        assert_eq!(n(b"0.1", b"0.10"), Ordering::Equal);
+        assert_eq!(n(b"-0", b"0"), Ordering::Equal);
+        assert_eq!(n(b"-.5", b"-0.4"), Ordering::Less);
+        assert_eq!(n(b".5", b"0.49"), Ordering::Greater);
+        assert_eq!(n(b"+5", b"0"), Ordering::Equal);
+        assert_eq!(n(b"1e3", b"2"), Ordering::Less);
+        assert_eq!(n(b" \t\n7", b"7"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_number_parse() {
+        let n = Number::parse(b"  -007.250x");
+        assert!(n.is_negative());
+        assert_eq!((n.integer, n.fraction), (&b"7"[..], &b"25"[..]));
+        assert!(Number::parse(b"-.000").is_zero());
+        assert!(!Number::parse(b"-.000").is_negative());
+    }
+
+    #[test]
+    fn test_number_parse_prefix_end() {
+        assert_eq!(Number::parse_prefix(b"  -12.5kg").1, 7);
+        assert_eq!(Number::parse_prefix(b"1,234").1, 1);
+        assert_eq!(Number::parse_prefix(b"5.").1, 2);
+        assert_eq!(Number::parse_prefix(b"abc").1, 0);
+        assert_eq!(Number::parse_prefix(b"").1, 0);
+    }
+
+    #[test]
+    fn test_human_units() {
+        let h = |a: &[u8], b: &[u8]| cmp(a, b, KeyKind::Human);
+        assert_eq!(h(b"2K", b"1M"), Ordering::Less);
+        assert_eq!(h(b"1023", b"1k"), Ordering::Less);
+        assert_eq!(h(b"1.5G", b"1G"), Ordering::Greater);
+        assert_eq!(h(b"-1M", b"-2K"), Ordering::Less);
+        assert_eq!(h(b"0K", b"1"), Ordering::Less);
+        assert_eq!(h(b"1Q", b"1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_month() {
+        assert!(matches!(KeyValue::parse(b"  feb", KeyKind::Month), KeyValue::Month(2)));
+        assert!(matches!(KeyValue::parse(b"DECEMBER", KeyKind::Month), KeyValue::Month(12)));
+        assert!(matches!(KeyValue::parse(b"JA", KeyKind::Month), KeyValue::Month(0)));
+        assert_eq!(cmp(b"xyz", b"jan", KeyKind::Month), Ordering::Less);
+    }
+
+    #[test]
+    fn test_read_range() {
+        assert_eq!(KeyKind::Numeric.read_range(b" -3.5kx"), 1..5);
+        assert_eq!(KeyKind::Human.read_range(b" -3.5kx"), 1..6);
+        assert_eq!(KeyKind::Numeric.read_range(b"  -x"), 2..2);
+        assert_eq!(KeyKind::Numeric.read_range(b"5."), 0..2);
+        assert_eq!(KeyKind::Numeric.read_range(b"  -12.5kg"), 2..7);
+        assert_eq!(KeyKind::Human.read_range(b"  -12.5kg"), 2..8);
+        assert_eq!(KeyKind::Numeric.read_range(b"abc"), 0..0);
+        assert_eq!(KeyKind::Numeric.read_range(b""), 0..0);
+        assert_eq!(KeyKind::Month.read_range(b"\tfebruary"), 1..4);
+        assert_eq!(KeyKind::Month.read_range(b" fe"), 1..1);
+        assert_eq!(KeyKind::Bytes.read_range(b" ab "), 0..4);
+    }
+
+    #[test]
+    fn test_versions() {
+        let v = |a: &[u8], b: &[u8]| cmp(a, b, KeyKind::Version);
+        assert_eq!(v(b"1.2", b"1.10"), Ordering::Less);
+        assert_eq!(v(b"1.0~rc1", b"1.0"), Ordering::Less);
+        assert_eq!(v(b"a-1.9.tar.gz", b"a-1.10.tar.gz"), Ordering::Less);
+        assert_eq!(v(b"01", b"1"), Ordering::Equal);
+        assert_eq!(v(b".hidden", b"a"), Ordering::Less);
+        assert_eq!(v(b"", b"0"), Ordering::Less);
+        assert_eq!(v(b"1a", b"1#"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_file_prefix_len() {
+        assert_eq!(file_prefix_len(b"foo-1.2.tar.gz"), 7);
+        assert_eq!(file_prefix_len(b"foo"), 3);
+        assert_eq!(file_prefix_len(b".a"), 0);
+        assert_eq!(file_prefix_len(b".a1.b"), 0);
+        assert_eq!(file_prefix_len(b".5.a"), 2);
+    }
+
+    #[test]
+    fn test_kinds_do_not_mix() {
+        let bytes = KeyValue::parse(b"9", KeyKind::Bytes);
+        let number = KeyValue::parse(b"1", KeyKind::Numeric);
+        assert_eq!(bytes.cmp(&number), Ordering::Less);
+    }
+}
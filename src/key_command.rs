@@ -0,0 +1,89 @@
+//! Keys computed by an external command (--key-command)
+//!
+//! The command runs once, through the shell, for all the records to sort.
+//! It reads them on its stdin, each ended by the record delimiter, and must
+//! print one key per record, in the same order and ended the same way.
+//! Records are written from a second thread while the keys are read, so a
+//! command printing as it reads never stalls on a full pipe.
+
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::config::Config;
+use crate::error::{os_message, Result, RsortError};
+use crate::input::RecordReader;
+
+/// The key `command` prints for each of `records`
+///
+/// Fails if the command cannot be started, exits with a nonzero status, or
+/// prints more or fewer keys than there are records.
+pub fn keys(command: &str, records: &[&[u8]], config: &Config) -> Result<Vec<Vec<u8>>> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            RsortError::Usage(format!("cannot run --key-command '{}': {}", command, os_message(&e)))
+        })?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let terminator = config.record_terminator();
+
+    let (keys, written) = thread::scope(|scope| {
+        let writer = scope.spawn(|| -> io::Result<()> {
+            let mut stdin = BufWriter::new(stdin);
+            for record in records {
+                stdin.write_all(record)?;
+                stdin.write_all(&terminator)?;
+            }
+            stdin.flush()
+        });
+        let mut reader = RecordReader::new(BufReader::new(stdout), config.record_delimiter)
+            .delimiter_prefix(&config.record_delimiter_prefix);
+        let mut keys = Vec::with_capacity(records.len());
+        let read = loop {
+            match reader.read_record() {
+                Ok(Some(key)) => keys.push(key.to_vec()),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        let written = writer.join().expect("the writer thread does not panic");
+        read.map(|()| (keys, written))
+    })?;
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(RsortError::ChildProcess {
+            program: command.to_string(),
+            path: None,
+            status,
+        });
+    }
+    // A command that stopped reading early is caught by the count
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    if keys.len() != records.len() {
+        return Err(RsortError::Usage(format!(
+            "--key-command '{}' printed {} keys for {} records",
+            command,
+            keys.len(),
+            records.len()
+        )));
+    }
+    Ok(keys)
+}
+
+/// `command` run by the shell, as `sh -c` or `cmd /C`
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let (shell, flag) = ("cmd", "/C");
+    #[cfg(not(windows))]
+    let (shell, flag) = ("sh", "-c");
+    let mut shell = Command::new(shell);
+    shell.arg(flag).arg(command);
+    shell
+}
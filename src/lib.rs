@@ -1,10 +1,79 @@
+//! rsort: GNU-compatible sorting (`LC_ALL=C` semantics) as a library
+//!
+//! [`Sorter`] is the supported entry point for embedding:
+//!
+//! ```
+//! use rsort::Sorter;
+//!
+//! let sorted = Sorter::new().fold_case(true).unique(true).sort_bytes(b"b\nA\na\nB\n")?;
+//! assert_eq!(sorted, b"A\nb\n");
+//! # Ok::<(), rsort::RsortError>(())
+//! ```
+//!
+//! [`sort_file`] and [`sort_files`] run the whole file-to-file pipeline, as
+//! the binary does.
+//!
+//! Records already coming from an iterator can use [`SortedExt`] instead:
+//! `records.rsorted(&config)` or `records.rsorted_unique(&config)`.
+//!
+//! # Module stability
+//!
+//! - [`compare`], [`key`], [`fields`], [`json`], and [`config`] are public so callers can build
+//!   comparators and key specs directly. They follow semver but may gain
+//!   fields and variants as GNU options are added.
+//! - [`input`], [`output`], [`compress`], [`sort`], [`arena`], and [`debug`] are the
+//!   building blocks the binary is assembled from; prefer [`Sorter`].
+//! - [`check`] and [`merge`] are the engines behind `-c`/`-C` and `-m`.
+//! - [`platform`] names files the way the OS can open them (Windows long paths).
+//! - `aio` (feature `async`) reads and merges tokio `AsyncBufRead` sources.
+//! - `capi` (feature `capi`) is the C ABI declared in `include/rsort.h`.
+//! - `cli` (feature `cli`, on by default) exists for the `rsort` binary and
+//!   is not part of the API. With `default-features = false` the library
+//!   builds without clap; construct [`Config`] with [`Config::builder`].
+
+// New unsafe code needs an explicit, reviewed `allow` (see `capi`)
+#![deny(unsafe_code)]
+
+#[cfg(feature = "async")]
+pub mod aio;
 pub mod arena;
+#[cfg(feature = "capi")]
+#[allow(unsafe_code)]
+pub mod capi;
+#[cfg(feature = "unicode")]
+pub mod casefold;
+pub mod check;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
 pub mod cli;
+#[cfg(feature = "collate")]
+pub mod collate;
 pub mod compare;
+pub mod compress;
 pub mod config;
 pub mod debug;
+pub mod encoding;
 pub mod error;
+pub mod fields;
 pub mod input;
+pub mod json;
 pub mod key;
+mod key_command;
+pub mod merge;
 pub mod output;
+mod pipeline;
+pub mod platform;
+#[cfg(feature = "profile")]
+pub mod profile;
 pub mod sort;
+mod sorter;
+mod stats;
+mod trace;
+
+pub use config::{Config, ConfigBuilder};
+pub use error::{Result, RsortError};
+pub use fields::FieldSeparator;
+pub use pipeline::{sort_file, sort_files, sort_files_split};
+pub use sort::SortedExt;
+pub use sorter::Sorter;
+pub use stats::{SortStats, StatsFormat};
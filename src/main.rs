@@ -1,85 +1,280 @@
-mod arena;
-mod cli;
-mod compare;
-mod config;
-mod debug;
-mod error;
-mod input;
-mod key;
-mod output;
-mod sort;
-
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+#![deny(unsafe_code)]
+
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 
-use cli::Args;
-use config::Config;
-use error::Result;
+use rsort::cli::Args;
+use rsort::config::Config;
+use rsort::error::{Result, RsortError};
+use rsort::check::{self, CheckFormat, CheckMode, CheckResult};
+use rsort::{debug, encoding, input, merge, output, platform, SortStats};
 
 /// Set up SIGPIPE handling for Unix systems
 /// This prevents "broken pipe" errors when output is piped to commands like `head`
-#[cfg(unix)]
+#[cfg(all(unix, not(target_os = "wasi")))]
+#[allow(unsafe_code)] // FFI call with constant arguments
 fn setup_sigpipe() {
     unsafe {
         libc::signal(libc::SIGPIPE, libc::SIG_DFL);
     }
 }
 
-#[cfg(not(unix))]
+#[cfg(not(all(unix, not(target_os = "wasi"))))]
 fn setup_sigpipe() {
-    // Windows doesn't have SIGPIPE
+    // Windows and WASI don't have SIGPIPE
 }
 
+/// Ignore SIGPIPE again, so a --compress-output-program or --key-command
+/// that exits early shows up as EPIPE and is reported with its exit status
+#[cfg(all(unix, not(target_os = "wasi")))]
+#[allow(unsafe_code)] // FFI call with constant arguments
+fn ignore_sigpipe() {
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_IGN);
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "wasi"))))]
+fn ignore_sigpipe() {}
+
+/// -c / -C found the input out of order
+const EXIT_DISORDER: i32 = 1;
+
+/// Exit statuses follow GNU sort: 0 on success (standard output closed
+/// early included), `EXIT_DISORDER` from -c/-C, and `RsortError::exit_code`
+/// (2) for every error, including clap's usage errors
 fn main() {
     setup_sigpipe();
 
-    if let Err(e) = run() {
-        eprintln!("rsort: {}", e);
-        std::process::exit(1);
+    match run() {
+        Ok(0) => {}
+        Ok(code) => std::process::exit(code),
+        // `rsort | head -1` where SIGPIPE did not stop us: done, not failed
+        Err(e) if e.is_broken_pipe() => {}
+        Err(e) => {
+            eprintln!("rsort: {}", e);
+            std::process::exit(e.exit_code());
+        }
     }
 }
 
-fn run() -> Result<()> {
-    let args = Args::parse();
-    let config = Config::from_args(&args)?;
-
-    // Read records from files or stdin
-    let (mut records, had_trailing) = if config.input_files.is_empty() {
-        let stdin = io::stdin();
-        let reader = BufReader::new(stdin.lock());
-        input::read_all_records(reader, config.record_delimiter)?
-    } else {
-        let mut all_records = Vec::new();
-        let mut last_had_trailing = true;
-        for path in &config.input_files {
-            let reader: Box<dyn BufRead> = if path == "-" {
-                Box::new(BufReader::new(io::stdin().lock()))
-            } else {
-                Box::new(BufReader::new(File::open(path)?))
-            };
-            let (mut file_records, had_trailing) = input::read_all_records(reader, config.record_delimiter)?;
-            all_records.append(&mut file_records);
-            last_had_trailing = had_trailing;
+/// Run rsort, returning the exit status
+fn run() -> Result<i32> {
+    let args = Args::parse_from(rsort::cli::with_env_options(
+        std::env::args_os(),
+        std::env::var_os("RSORT_OPTS").as_deref(),
+    )?);
+    if let Some(shell) = args.generate_completions {
+        rsort::cli::write_completions(shell, &mut io::stdout());
+        return Ok(0);
+    }
+    if args.generate_man {
+        rsort::cli::write_man(&mut io::stdout().lock())?;
+        return Ok(0);
+    }
+    #[cfg(feature = "trace")]
+    install_logging(args.log_level.as_deref())?;
+    let mut config = Config::from_args(&args)?;
+    // No shell expands `*.txt` for us there
+    #[cfg(windows)]
+    {
+        let literal = rsort::cli::operands_after_separator(std::env::args_os());
+        config.input_files = rsort::cli::expand_wildcards(&config.input_files, literal)?;
+        // -c takes one operand, and so on
+        config.validate()?;
+    }
+    // Like grep, show each record as it comes when a person is watching
+    if output::output_path(&config).is_none() && config.split_output.is_none() && io::stdout().is_terminal() {
+        config.line_buffered = true;
+    }
+    if config.compress_output_program.is_some() || config.key_command.is_some() {
+        ignore_sigpipe();
+    }
+    if config.input_files.is_empty() {
+        terminal_hint();
+    }
+    if config.debug.is_some() {
+        for line in config.describe().lines() {
+            eprintln!("rsort: {}", line);
+        }
+        for warning in config.lint() {
+            eprintln!("rsort: {}", warning);
         }
-        (all_records, last_had_trailing)
+    }
+
+    if config.explain {
+        return run_explain(&config);
+    }
+    if let Some(mode) = config.check {
+        return run_check(&config, mode);
+    }
+    if config.merge {
+        return run_merge(&config);
+    }
+
+    let stats = run_sort(&config)?;
+    if let Some(format) = config.stats {
+        stats.write_summary(io::stderr().lock(), format)?;
+    }
+    Ok(0)
+}
+
+/// Log to stderr at `--log-level`, or else at RUST_LOG when that is a
+/// plain level such as `debug`; without either nothing is logged
+#[cfg(feature = "trace")]
+fn install_logging(level: Option<&str>) -> Result<()> {
+    let level = match level {
+        Some(level) => level.parse::<tracing::Level>().map_err(|_| {
+            RsortError::Usage(format!(
+                "invalid --log-level argument '{}'; expected error, warn, info, debug or trace",
+                level
+            ))
+        })?,
+        None => match std::env::var("RUST_LOG").ok().and_then(|level| level.parse().ok()) {
+            Some(level) => level,
+            None => return Ok(()),
+        },
     };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .with_writer(io::stderr)
+        .init();
+    Ok(())
+}
 
-    // Debug output: show key spans before sorting
-    if config.debug {
-        let stderr = io::stderr();
-        let mut stderr = stderr.lock();
-        debug::debug_input(&mut stderr, &records, &config)?;
-        stderr.flush()?;
+/// Explain the wait when stdin is a terminal, so rsort doesn't look hung
+///
+/// Only printed when stderr is a terminal too; redirecting stderr silences it.
+fn terminal_hint() {
+    if io::stdin().is_terminal() && io::stderr().is_terminal() {
+        eprintln!("rsort: reading from terminal; press Ctrl-D to end input, or pass a FILE operand");
     }
+}
 
-    // Sort records
-    sort::sort_records(&mut records, &config);
+/// Default mode: read every input, sort, and write
+fn run_sort(config: &Config) -> Result<SortStats> {
+    let inputs: Vec<PathBuf> = input_paths(config).map(PathBuf::from).collect();
+    if let (Some(chunks), Some(prefix)) = (config.split_output, &config.output_prefix) {
+        return rsort::sort_files_split(&inputs, prefix, chunks, config);
+    }
+    let output = config.output_file.as_deref().unwrap_or(Path::new("-"));
+    rsort::sort_files(&inputs, output, config)
+}
 
-    // Write output
-    let mut out = output::open_output(&config)?;
-    output::write_records(&mut out, &records, &config, had_trailing)?;
+/// Input operands, with no operands meaning stdin
+fn input_paths(config: &Config) -> impl Iterator<Item = &Path> {
+    let stdin_only = config.input_files.is_empty().then_some(Path::new("-"));
+    stdin_only
+        .into_iter()
+        .chain(config.input_files.iter().map(PathBuf::as_path))
+}
 
-    Ok(())
+/// -c / -C: exit 1 on the first out-of-order record, like GNU sort
+///
+/// -c --format=json reports it on stdout instead, and with --verbose a
+/// sorted input too.
+fn run_check(config: &Config, mode: CheckMode) -> Result<i32> {
+    let path = config.input_files.first().map_or(Path::new("-"), PathBuf::as_path);
+    let reader = encoding::decode(input::open_decompressed(path, &config.decompress)?, config.encoding);
+
+    let result = check::check_sorted(reader, config).map_err(|e| e.reading(path))?;
+    if mode == CheckMode::Diagnose && config.check_format == CheckFormat::Json {
+        if !result.is_sorted() || config.check_verbose {
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", result.to_json(path, config))?;
+            stdout.flush()?;
+        }
+        return Ok(if result.is_sorted() { 0 } else { EXIT_DISORDER });
+    }
+    match result {
+        CheckResult::Sorted { .. } => Ok(0),
+        CheckResult::Disorder { record_index, current, .. } => {
+            if mode == CheckMode::Diagnose {
+                let stderr = io::stderr();
+                let mut stderr = stderr.lock();
+                write!(stderr, "rsort: {}:{}: disorder: ", path.display(), record_index + 1)?;
+                stderr.write_all(debug::escaped(&current).as_bytes())?;
+                stderr.write_all(&config.record_terminator())?;
+                stderr.flush()?;
+            }
+            Ok(EXIT_DISORDER)
+        }
+    }
+}
+
+/// --explain: narrate how the two input records compare
+///
+/// The records may come from one input or be spread over several. Exits 0
+/// whichever way they compare.
+fn run_explain(config: &Config) -> Result<i32> {
+    let mut records = Vec::new();
+    for path in input_paths(config) {
+        let reader = encoding::decode(input::open_decompressed(path, &config.decompress)?, config.encoding);
+        let mut reader = input::RecordReader::new(reader, config.record_delimiter)
+            .delimiter_prefix(&config.record_delimiter_prefix)
+            .max_record_size(config.max_record_size)
+            .record_size(config.record_size)
+            .framing(config.framing)
+            .strip_bom(config.strip_bom);
+        while let Some(record) = reader.read_record().map_err(|e| e.reading(path))? {
+            records.push(record.to_vec());
+        }
+    }
+    let [a, b] = &records[..] else {
+        return Err(RsortError::Usage(format!(
+            "--explain needs exactly two records; read {}",
+            records.len()
+        )));
+    };
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", debug::explanation(a, b, config))?;
+    stdout.flush()?;
+    Ok(0)
+}
+
+/// -m: merge already sorted inputs
+fn run_merge(config: &Config) -> Result<i32> {
+    // An input that is also an --append target, by any name (a symlink or
+    // hard link), is read up front, so the merge never reads its own
+    // output; a replaced output is written aside and renamed at the end,
+    // so `-m -o merged.txt merged.txt new.txt` folds in place, as in GNU
+    let appended: Vec<platform::FileId> = if config.append {
+        output::output_path(config)
+            .iter()
+            .chain(&config.tee)
+            .filter_map(|path| platform::FileId::of(path))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let output_path = output::output_path(config);
+    let verified = check::verify_target(output_path.as_deref(), config)?;
+    // Opened first, so an unwritable output fails before any reading
+    let mut out = output::open_output(config)?;
+    let paths: Vec<&Path> = input_paths(config).collect();
+    let mut sources: Vec<Box<dyn BufRead>> = Vec::new();
+    for &path in &paths {
+        if path.as_os_str() != "-" && platform::FileId::of(path).is_some_and(|file| appended.contains(&file)) {
+            let mut contents = Vec::new();
+            input::open_decompressed(path, &config.decompress)?
+                .read_to_end(&mut contents)
+                .map_err(|e| RsortError::from(e).reading(path))?;
+            sources.push(encoding::decode(Box::new(io::Cursor::new(contents)), config.encoding));
+        } else {
+            sources.push(encoding::decode(input::open_decompressed(path, &config.decompress)?, config.encoding));
+        }
+    }
+
+    // The merge names the inputs in its errors, but not the output
+    let writer = output::named_output(out.encoded(config.output_encoding), output_path.as_deref());
+    merge::merge_named(sources, &paths, config, writer)?;
+    out.finish().map_err(|e| output::write_error(config, e))?;
+    if let Some(path) = verified {
+        check::verify_output(path, config)?;
+    }
+    Ok(0)
 }
@@ -0,0 +1,283 @@
+//! k-way merge of already-sorted inputs (-m)
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+
+use crate::compare::Comparator;
+use crate::config::Config;
+use crate::error::{Result, RsortError};
+use crate::input::{RecordReader, UTF8_BOM};
+use crate::output::{self, Grouper, KeyPrinter};
+use crate::trace::{trace_event, trace_span};
+
+/// Counters describing what a merge did
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Number of input streams
+    pub sources: usize,
+    /// Records read across all sources
+    pub records_read: u64,
+    /// Records written (fewer than read when -u removed duplicates)
+    pub records_written: u64,
+}
+
+/// Current front record of one source
+pub(crate) struct Head<'c> {
+    pub(crate) record: Vec<u8>,
+    pub(crate) source: usize,
+    pub(crate) comparator: &'c Comparator,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap pops the maximum; reverse so the smallest record wins,
+        // with ties going to the earlier source
+        self.comparator
+            .cmp(&self.record, &other.record)
+            .then(self.source.cmp(&other.source))
+            .reverse()
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head<'_> {}
+
+/// Merge sorted `sources` into `writer`
+///
+/// Each source must already be sorted under `config`; they are not checked.
+/// Equal records come out in source order, and with `unique` only the first
+/// of each run of equal records is written; with `group` the separator is
+/// written between runs of equal keys instead. Every output record is
+/// terminated by the delimiter, like GNU sort.
+pub fn merge_streams<W: Write>(
+    sources: Vec<Box<dyn BufRead + '_>>,
+    config: &Config,
+    writer: W,
+) -> Result<MergeStats> {
+    merge_with(sources, &Comparator::new(config), config, writer)
+}
+
+/// `merge_streams`, with errors reading `sources[i]` naming `paths[i]`
+pub fn merge_named<W: Write>(
+    sources: Vec<Box<dyn BufRead + '_>>,
+    paths: &[&Path],
+    config: &Config,
+    writer: W,
+) -> Result<MergeStats> {
+    merge_sources(sources, paths, &Comparator::new(config), config, writer)
+}
+
+/// Merge with a prebuilt comparator
+pub fn merge_with<W: Write>(
+    sources: Vec<Box<dyn BufRead + '_>>,
+    comparator: &Comparator,
+    config: &Config,
+    writer: W,
+) -> Result<MergeStats> {
+    merge_sources(sources, &[], comparator, config, writer)
+}
+
+/// The merge, naming `paths[i]`, if there is one, in errors reading
+/// `sources[i]`
+fn merge_sources<W: Write>(
+    sources: Vec<Box<dyn BufRead + '_>>,
+    paths: &[&Path],
+    comparator: &Comparator,
+    config: &Config,
+    writer: W,
+) -> Result<MergeStats> {
+    let reading = |source: usize| {
+        move |err: RsortError| match paths.get(source) {
+            Some(path) => err.reading(path),
+            None => err,
+        }
+    };
+    trace_span!("merge", fan_in = sources.len());
+    let terminator = config.output_terminator();
+    let forbidden = config.forbidden_output_byte();
+    let mut stats = MergeStats {
+        sources: sources.len(),
+        ..MergeStats::default()
+    };
+    let mut readers: Vec<_> = sources
+        .into_iter()
+        .enumerate()
+        .map(|(i, source)| {
+            RecordReader::new(source, config.record_delimiter)
+                .delimiter_prefix(&config.record_delimiter_prefix)
+                .max_record_size(config.max_record_size)
+                .record_size(config.record_size)
+                .framing(config.framing)
+                .strip_bom(config.strip_bom && i == 0)
+        })
+        .collect();
+
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    for (source, reader) in readers.iter_mut().enumerate() {
+        if let Some(record) = reader.read_record().map_err(reading(source))? {
+            stats.records_read += 1;
+            heap.push(Head {
+                record: record.to_vec(),
+                source,
+                comparator,
+            });
+        }
+    }
+
+    let mut writer = BufWriter::new(writer);
+    if config.keep_bom && readers.first().is_some_and(RecordReader::stripped_bom) {
+        writer.write_all(UTF8_BOM)?;
+    }
+    let mut last: Option<Vec<u8>> = None;
+    let printer = KeyPrinter::new(config);
+    let mut grouper = config.group.as_deref().map(|separator| (Grouper::new(config), separator));
+
+    while let Some(mut head) = heap.pop() {
+        let is_dup = config.unique
+            && last
+                .as_ref()
+                .is_some_and(|prev| comparator.cmp(prev, &head.record) == Ordering::Equal);
+        if !is_dup {
+            let record = output::printed(head.record.as_slice(), printer.as_ref());
+            output::check_output_record(record.as_ref(), forbidden)?;
+            if let Some((grouper, separator)) = &mut grouper {
+                if grouper.starts_group(&head.record) {
+                    output::check_output_record(separator, forbidden)?;
+                    config.framing.write_prefix(&mut writer, separator.len())?;
+                    writer.write_all(separator)?;
+                    writer.write_all(&terminator)?;
+                }
+            }
+            config.framing.write_prefix(&mut writer, record.as_ref().len())?;
+            writer.write_all(record.as_ref())?;
+            writer.write_all(&terminator)?;
+            if config.line_buffered {
+                writer.flush()?;
+            }
+            stats.records_written += 1;
+            if config.unique {
+                let prev = last.get_or_insert_with(Vec::new);
+                prev.clear();
+                prev.extend_from_slice(&head.record);
+            }
+        }
+
+        if let Some(record) = readers[head.source].read_record().map_err(reading(head.source))? {
+            stats.records_read += 1;
+            head.record.clear();
+            head.record.extend_from_slice(record);
+            heap.push(head);
+        }
+    }
+
+    writer.flush()?;
+    trace_event!(
+        records_read = stats.records_read,
+        records_written = stats.records_written,
+        "merged"
+    );
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn merge(inputs: &[&'static [u8]], config: &Config) -> (Vec<u8>, MergeStats) {
+        let sources: Vec<Box<dyn BufRead>> = inputs
+            .iter()
+            .map(|input| Box::new(Cursor::new(*input)) as Box<dyn BufRead>)
+            .collect();
+        let mut output = Vec::new();
+        let stats = merge_streams(sources, config, &mut output).unwrap();
+        (output, stats)
+    }
+
+    #[test]
+    fn test_zero_sources() {
+        let (output, stats) = merge(&[], &Config::default());
+        assert!(output.is_empty());
+        assert_eq!(stats, MergeStats::default());
+    }
+
+    #[test]
+    fn test_one_source_passes_through() {
+        let (output, stats) = merge(&[b"a\nb\nc\n"], &Config::default());
+        assert_eq!(output, b"a\nb\nc\n");
+        assert_eq!(stats.records_read, 3);
+        assert_eq!(stats.records_written, 3);
+    }
+
+    #[test]
+    fn test_interleaves_sources() {
+        let (output, stats) = merge(&[b"a\nd\n", b"b\ne\n", b"c\nf\n"], &Config::default());
+        assert_eq!(output, b"a\nb\nc\nd\ne\nf\n");
+        assert_eq!(stats.sources, 3);
+    }
+
+    #[test]
+    fn test_missing_trailing_delimiter() {
+        let (output, _) = merge(&[b"a\nc", b"b\nd"], &Config::default());
+        assert_eq!(output, b"a\nb\nc\nd\n");
+    }
+
+    #[test]
+    fn test_nul_delimited() {
+        let config = Config::builder().record_delimiter(0).build().unwrap();
+        let (output, _) = merge(&[b"a\nx\0c\0", b"b\0"], &config);
+        assert_eq!(output, b"a\nx\0b\0c\0");
+    }
+
+    #[test]
+    fn test_ties_keep_source_order() {
+        let config = Config::builder().fold_case(true).stable(true).build().unwrap();
+        let (output, _) = merge(&[b"a\nB\n", b"A\nb\n"], &config);
+        assert_eq!(output, b"a\nA\nB\nb\n");
+    }
+
+    #[test]
+    fn test_unique_keeps_first_of_run() {
+        let config = Config::builder().fold_case(true).unique(true).build().unwrap();
+        let (output, stats) = merge(&[b"A\nb\n", b"a\nB\n"], &config);
+        assert_eq!(output, b"A\nb\n");
+        assert_eq!(stats.records_read, 4);
+        assert_eq!(stats.records_written, 2);
+    }
+
+    #[test]
+    fn test_honors_key_options() {
+        let config = Config::builder().numeric(true).add_key("2,2").build().unwrap();
+        let (output, _) = merge(&[b"x 1\nx 10\n", b"y 2\ny 3\n"], &config);
+        assert_eq!(output, b"x 1\ny 2\ny 3\nx 10\n");
+    }
+
+    #[test]
+    fn test_named_read_errors_name_source() {
+        let config = Config::builder().max_record_size(3).build().unwrap();
+        let sources: Vec<Box<dyn BufRead>> =
+            vec![Box::new(Cursor::new(&b"a\nb\n"[..])), Box::new(Cursor::new(&b"c\ntoolong\n"[..]))];
+        let paths = [Path::new("one.txt"), Path::new("two.txt")];
+        let err = merge_named(sources, &paths, &config, Vec::new()).unwrap_err();
+        match err {
+            RsortError::RecordTooLarge { path, offset, limit } => {
+                assert_eq!(path.as_deref(), Some(Path::new("two.txt")));
+                assert_eq!((offset, limit), (2, 3));
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+}
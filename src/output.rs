@@ -1,127 +1,1014 @@
 use std::cmp::Ordering;
-use std::fs::File;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 
-use crate::compare::compare_records;
+use tempfile::TempPath;
+
+use crate::compare::Comparator;
+use crate::compress::{self, Compressor, ProgramWriter};
 use crate::config::Config;
+use crate::debug::{Annotator, Explainer};
+use crate::encoding::{self, Encoding};
+use crate::input::Framing;
+use crate::platform;
+use crate::error::{Result, RsortError};
 
 /// Write records to output with optional deduplication
 /// add_trailing controls whether to add delimiter after the last record
+/// Returns the number of records written
 pub fn write_records<W: Write>(
     writer: W,
     records: &[Vec<u8>],
     config: &Config,
     add_trailing: bool,
-) -> io::Result<()> {
+) -> io::Result<u64> {
+    write_records_iter(writer, records, config, add_trailing)
+}
+
+/// Streaming form of `write_records` for producers that never hold every record
+pub fn write_records_iter<W, I>(
+    writer: W,
+    records: I,
+    config: &Config,
+    add_trailing: bool,
+) -> io::Result<u64>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let records = records.into_iter().map(Unnumbered);
+    write_records_after(writer, std::iter::empty(), records, config, add_trailing)
+}
+
+/// A record to output, which may carry the input position --number prints
+pub trait OutputRecord: AsRef<[u8]> {
+    /// The 1-based position of the record among all input records
+    fn number(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl OutputRecord for &[u8] {}
+
+/// A record with its position among the input records (--number)
+#[derive(Clone, Copy, Debug)]
+pub struct Numbered<T> {
+    pub number: u64,
+    pub record: T,
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Numbered<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.record.as_ref()
+    }
+}
+
+impl<T: AsRef<[u8]>> OutputRecord for Numbered<T> {
+    fn number(&self) -> Option<u64> {
+        Some(self.number)
+    }
+}
+
+/// A record whose position is unknown, as `write_records_iter` gets them
+struct Unnumbered<T>(T);
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Unnumbered<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<T: AsRef<[u8]>> OutputRecord for Unnumbered<T> {}
+
+/// `write_records_iter`, after `preserved` records that -u never removes
+/// and --group never separates (--header, --comment-char)
+pub fn write_records_after<W, P, I>(
+    writer: W,
+    preserved: P,
+    records: I,
+    config: &Config,
+    add_trailing: bool,
+) -> io::Result<u64>
+where
+    W: Write,
+    P: IntoIterator<Item = I::Item>,
+    I: IntoIterator,
+    I::Item: OutputRecord,
+{
     let mut writer = BufWriter::new(writer);
-    let delimiter = config.record_delimiter;
+    let delimiter = config.output_terminator();
+    let forbidden = config.forbidden_output_byte();
+
+    let printer = KeyPrinter::new(config);
+    let print = |record| printed(record, printer.as_ref());
 
+    let written = if config.unique {
+        let comparator = Comparator::new(config);
+        let records = preserved.into_iter().chain(unique(records, &comparator)).map(print);
+        write_all(&mut writer, records, &delimiter, forbidden, config.line_buffered, add_trailing, config.framing)?
+    } else if let Some(separator) = &config.group {
+        let mut records = grouped(records, Grouper::new(config), separator);
+        let preserved = preserved.into_iter().map(GroupItem::Record);
+        let items = preserved.chain(&mut records).map(|item| match item {
+            GroupItem::Record(record) => GroupItem::Record(print(record)),
+            GroupItem::Separator(separator) => GroupItem::Separator(separator),
+        });
+        let written = write_all(&mut writer, items, &delimiter, forbidden, config.line_buffered, add_trailing, config.framing)?;
+        written - records.separators
+    } else {
+        let records = preserved.into_iter().chain(records).map(print);
+        write_all(&mut writer, records, &delimiter, forbidden, config.line_buffered, add_trailing, config.framing)?
+    };
+
+    writer.flush()?;
+    Ok(written)
+}
+
+/// `write_records_after` across `writers`, each taking the next contiguous
+/// range of the output (--split-output)
+///
+/// The records left after -u are divided by count, earlier writers taking
+/// one more when they do not divide evenly, and writers past the last
+/// record get nothing. Every record but the last is followed by the
+/// delimiter, so the writers' output put end to end is the single output.
+/// With `config.split_by_key`, a range is extended rather than end between
+/// two sorted records with equal keys.
+pub fn write_split<W, P, I>(
+    writers: &mut [W],
+    preserved: P,
+    records: I,
+    config: &Config,
+    add_trailing: bool,
+) -> io::Result<u64>
+where
+    W: Write,
+    P: IntoIterator<Item = I::Item>,
+    I: IntoIterator,
+    I::Item: OutputRecord,
+{
+    let mut all: Vec<I::Item> = preserved.into_iter().collect();
+    let sorted_from = all.len();
     if config.unique {
-        write_unique(&mut writer, records, config, delimiter, add_trailing)?;
+        all.extend(unique(records, &Comparator::new(config)));
     } else {
-        write_all(&mut writer, records, delimiter, add_trailing)?;
+        all.extend(records);
     }
+    let by_key = config.split_by_key.then(|| key_comparator(config));
+    let delimiter = config.output_terminator();
+    let forbidden = config.forbidden_output_byte();
+    let printer = KeyPrinter::new(config);
 
-    writer.flush()
+    let ends = split_points(&all, sorted_from, by_key.as_ref(), writers.len());
+    let mut start = 0;
+    for (writer, end) in writers.iter_mut().zip(ends) {
+        let mut writer = BufWriter::new(writer);
+        let add_trailing = end < all.len() || add_trailing;
+        let records = all[start..end].iter().map(|record| printed(Ref(record), printer.as_ref()));
+        write_all(&mut writer, records, &delimiter, forbidden, config.line_buffered, add_trailing, config.framing)?;
+        writer.flush()?;
+        start = end;
+    }
+    Ok(all.len() as u64)
 }
 
-/// Write all records without deduplication
-fn write_all<W: Write>(
-    writer: &mut W,
-    records: &[Vec<u8>],
-    delimiter: u8,
-    add_trailing: bool,
-) -> io::Result<()> {
-    let len = records.len();
-    for (i, record) in records.iter().enumerate() {
-        writer.write_all(record)?;
-        // Add delimiter between records, and after last only if add_trailing
-        if i < len - 1 || add_trailing {
-            writer.write_all(&[delimiter])?;
+/// Where each of `chunks` ranges of `records` ends, for `write_split`
+///
+/// With `by_key`, an end moves forward past records equal to the one before
+/// it, as long as both are among the sorted records from `sorted_from` on.
+fn split_points<T: AsRef<[u8]>>(
+    records: &[T],
+    sorted_from: usize,
+    by_key: Option<&Comparator>,
+    chunks: usize,
+) -> Vec<usize> {
+    let (each, extra) = (records.len() / chunks, records.len() % chunks);
+    let mut ends = Vec::with_capacity(chunks);
+    let mut end = 0;
+    for i in 1..=chunks {
+        end = end.max(i * each + i.min(extra));
+        if let Some(comparator) = by_key {
+            while end > sorted_from
+                && end < records.len()
+                && comparator.cmp(records[end - 1].as_ref(), records[end].as_ref()) == Ordering::Equal
+            {
+                end += 1;
+            }
         }
+        ends.push(end);
     }
-    Ok(())
+    ends
 }
 
-/// Write unique records only (first among equals by key comparison)
-fn write_unique<W: Write>(
+/// Write records separated by the delimiter
+///
+/// The delimiter goes before every record but the first, so no lookahead is
+/// needed; the last record gets one only if add_trailing. A record
+/// containing `forbidden` is an error (see `check_output_record`). With
+/// `line_buffered` each record is flushed as written, its delimiter with
+/// the next one. A record's --number goes before it, with a tab, and its
+/// `framing` prefix before both.
+fn write_all<W, I>(
     writer: &mut W,
-    records: &[Vec<u8>],
-    config: &Config,
-    delimiter: u8,
+    records: I,
+    delimiter: &[u8],
+    forbidden: Option<u8>,
+    line_buffered: bool,
     add_trailing: bool,
-) -> io::Result<()> {
-    let mut prev: Option<&Vec<u8>> = None;
-    let mut unique_records: Vec<&Vec<u8>> = Vec::new();
-
+    framing: Framing,
+) -> io::Result<u64>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: OutputRecord,
+{
+    let mut written = 0u64;
     for record in records {
-        let is_dup = prev
-            .map(|p| compare_for_unique(p, record, config) == Ordering::Equal)
-            .unwrap_or(false);
+        check_output_record(record.as_ref(), forbidden)?;
+        if written > 0 {
+            writer.write_all(delimiter)?;
+        }
+        let number = record.number().map(|number| format!("{}\t", number)).unwrap_or_default();
+        framing.write_prefix(writer, number.len() + record.as_ref().len())?;
+        writer.write_all(number.as_bytes())?;
+        writer.write_all(record.as_ref())?;
+        written += 1;
+        if line_buffered {
+            writer.flush()?;
+        }
+    }
+    if written > 0 && add_trailing {
+        writer.write_all(delimiter)?;
+    }
+    Ok(written)
+}
+
+/// Refuse a record containing `forbidden`, `Config::forbidden_output_byte`
+///
+/// The error is a usage error carried by the `io::Error`.
+pub fn check_output_record(record: &[u8], forbidden: Option<u8>) -> io::Result<()> {
+    match forbidden {
+        Some(byte) if memchr::memchr(byte, record).is_some() => Err(RsortError::Usage(format!(
+            "a record contains the --output-delimiter '{}'; use --force to write it anyway",
+            byte.escape_ascii()
+        ))
+        .into_io()),
+        _ => Ok(()),
+    }
+}
+
+/// Keep only the first of each run of equal records (-u)
+///
+/// -u disables last-resort, so the comparator reports key-equal records as Equal.
+pub fn unique<I>(records: I, comparator: &Comparator) -> Unique<'_, I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    Unique {
+        records: records.into_iter(),
+        comparator,
+        prev: None,
+    }
+}
+
+/// Iterator returned by `unique`; holds a copy of the last emitted record only
+pub struct Unique<'c, I> {
+    records: I,
+    comparator: &'c Comparator,
+    prev: Option<Vec<u8>>,
+}
+
+impl<I> Iterator for Unique<'_, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        for record in self.records.by_ref() {
+            let bytes = record.as_ref();
+            let is_dup = self
+                .prev
+                .as_ref()
+                .is_some_and(|prev| self.comparator.cmp(prev, bytes) == Ordering::Equal);
+            if !is_dup {
+                let prev = self.prev.get_or_insert_with(Vec::new);
+                prev.clear();
+                prev.extend_from_slice(bytes);
+                return Some(record);
+            }
+        }
+        None
+    }
+}
+
+/// Turns each record into its keys for --print-key, or into its --debug
+/// annotation, explaining its order with --debug=decisions
+pub struct KeyPrinter<'c> {
+    comparator: Comparator,
+    separator: &'c [u8],
+    annotator: Option<Annotator<'c>>,
+    explainer: Option<Explainer>,
+    print_key: bool,
+}
+
+impl<'c> KeyPrinter<'c> {
+    /// The printer for `config`, or None without --print-key or --debug
+    pub fn new(config: &'c Config) -> Option<Self> {
+        (config.print_key || config.debug.is_some()).then(|| KeyPrinter {
+            comparator: Comparator::new(config),
+            separator: &config.key_output_separator,
+            annotator: Annotator::new(config),
+            explainer: Explainer::new(config),
+            print_key: config.print_key,
+        })
+    }
+
+    /// The keys of `record`, joined by --key-output-separator, or with
+    /// --debug its annotation; None to output the record itself
+    pub fn print(&self, record: &[u8]) -> Option<Vec<u8>> {
+        if let Some(explainer) = &self.explainer {
+            explainer.explain(record);
+        }
+        match &self.annotator {
+            Some(annotator) => Some(annotator.annotate(record)),
+            None => self.print_key.then(|| self.comparator.keys(record).join(self.separator)),
+        }
+    }
+}
+
+/// An output record: the record itself, or its keys and --number with
+/// --print-key (its annotation with --debug)
+pub enum Printed<T> {
+    Record(T),
+    Keys(Vec<u8>, Option<u64>),
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Printed<T> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Printed::Record(record) => record.as_ref(),
+            Printed::Keys(keys, _) => keys,
+        }
+    }
+}
+
+impl<T: OutputRecord> OutputRecord for Printed<T> {
+    fn number(&self) -> Option<u64> {
+        match self {
+            Printed::Record(record) => record.number(),
+            Printed::Keys(_, number) => *number,
+        }
+    }
+}
+
+/// `record` as output, through `printer` if there is one
+pub fn printed<T: OutputRecord>(record: T, printer: Option<&KeyPrinter>) -> Printed<T> {
+    match printer.and_then(|printer| printer.print(record.as_ref())) {
+        Some(keys) => Printed::Keys(keys, record.number()),
+        None => Printed::Record(record),
+    }
+}
+
+/// A borrowed output record, itself an output record
+struct Ref<'a, T>(&'a T);
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for Ref<'_, T> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<T: OutputRecord> OutputRecord for Ref<'_, T> {
+    fn number(&self) -> Option<u64> {
+        self.0.number()
+    }
+}
+
+/// A comparator that reports records with equal keys as Equal, whatever
+/// the last-resort comparison would say (--group, --split-by-key)
+fn key_comparator(config: &Config) -> Comparator {
+    let mut keys_only = config.clone();
+    keys_only.stable = true;
+    Comparator::new(&keys_only)
+}
+
+/// Tells where each run of records with equal keys starts (--group)
+pub struct Grouper {
+    comparator: Comparator,
+    /// The first record of the current run
+    first: Option<Vec<u8>>,
+}
+
+impl Grouper {
+    /// A grouper for `config`'s keys
+    pub fn new(config: &Config) -> Self {
+        Grouper {
+            comparator: key_comparator(config),
+            first: None,
+        }
+    }
+
+    /// Whether `record`, the next one output, starts a new run after an
+    /// earlier one, so the group separator goes before it
+    pub fn starts_group(&mut self, record: &[u8]) -> bool {
+        let starts = match &self.first {
+            Some(first) => self.comparator.cmp(first, record) != Ordering::Equal,
+            None => {
+                self.first = Some(record.to_vec());
+                return false;
+            }
+        };
+        if starts {
+            self.first = Some(record.to_vec());
+        }
+        starts
+    }
+}
+
+/// A record, or the --group separator written between runs as a record
+pub enum GroupItem<'s, T> {
+    Record(T),
+    Separator(&'s [u8]),
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for GroupItem<'_, T> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            GroupItem::Record(record) => record.as_ref(),
+            GroupItem::Separator(separator) => separator,
+        }
+    }
+}
+
+impl<T: OutputRecord> OutputRecord for GroupItem<'_, T> {
+    fn number(&self) -> Option<u64> {
+        match self {
+            GroupItem::Record(record) => record.number(),
+            GroupItem::Separator(_) => None,
+        }
+    }
+}
+
+/// Put `separator` before each record that starts a new run of equal keys
+/// (--group); there is none before the first record or after the last
+pub fn grouped<I>(records: I, grouper: Grouper, separator: &[u8]) -> Grouped<'_, I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    Grouped {
+        records: records.into_iter(),
+        grouper,
+        separator,
+        pending: None,
+        separators: 0,
+    }
+}
+
+/// Iterator returned by `grouped`
+pub struct Grouped<'s, I: Iterator> {
+    records: I,
+    grouper: Grouper,
+    separator: &'s [u8],
+    /// The record to return after the separator just returned
+    pending: Option<I::Item>,
+    /// How many separators have been returned
+    pub separators: u64,
+}
+
+impl<'s, I> Iterator for Grouped<'s, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = GroupItem<'s, I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.pending.take() {
+            return Some(GroupItem::Record(record));
+        }
+        let record = self.records.next()?;
+        if self.grouper.starts_group(record.as_ref()) {
+            self.pending = Some(record);
+            self.separators += 1;
+            return Some(GroupItem::Separator(self.separator));
+        }
+        Some(GroupItem::Record(record))
+    }
+}
+
+/// Where the output goes: stdout, or a file that may be compressed
+///
+/// Call `finish` after the last write. A regular file is written to a
+/// temporary file beside it that `finish` renames into place, so the
+/// original stays whole until the new output is complete, even when it is
+/// also an input; an output dropped before `finish` succeeds leaves nothing
+/// behind. --append writes in place.
+///
+/// Every --tee file gets a copy of each write, made through an `Output` of
+/// its own.
+pub struct Output {
+    writer: OutputWriter,
+    target: Target,
+    /// --fsync: the regular file to sync once written
+    synced: Option<File>,
+    tees: Vec<Tee>,
+}
+
+/// A --tee file; its errors name it, not the main output
+struct Tee {
+    path: PathBuf,
+    output: Output,
+}
+
+/// An error writing to `path`, one of several outputs, carried past the
+/// naming of the main output's errors
+fn named_error(path: &Path, source: io::Error) -> io::Error {
+    RsortError::from(source).writing(Some(path)).into_io()
+}
+
+/// A writer whose errors name `path` (None for stdout), for one of several
+/// outputs, or for a caller that cannot tell its write errors from its reads
+pub struct Named<'a, W> {
+    writer: W,
+    path: Option<&'a Path>,
+}
+
+/// Name `path` in the errors of `writer` (see `Named`)
+pub fn named<W: Write>(writer: W, path: &Path) -> Named<'_, W> {
+    Named { writer, path: Some(path) }
+}
+
+/// Name the main output `path`, None for stdout, in the errors of `writer`,
+/// as `write_error` does
+pub fn named_output<'a, W: Write>(writer: W, path: Option<&'a Path>) -> Named<'a, W> {
+    Named { writer, path }
+}
+
+impl<W: Write> Write for Named<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf).map_err(|err| RsortError::from(err).writing(self.path).into_io())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().map_err(|err| RsortError::from(err).writing(self.path).into_io())
+    }
+}
+
+enum OutputWriter {
+    Plain(Box<dyn Write>),
+    Stdout(Box<dyn Write>),
+    /// Stdout after its reader went away, while --tee files still want
+    /// the rest of the output
+    Closed,
+    Compressed(Compressor<'static>),
+    Program(ProgramWriter),
+}
+
+/// What `finish` does once everything is written
+enum Target {
+    /// Nothing: stdout, or a file written in place
+    Direct,
+    /// Rename `temp` over `path`, after giving it the mode and owner of
+    /// the `original` file if there was one; `temp` is removed if dropped
+    Replace {
+        temp: TempPath,
+        path: PathBuf,
+        original: Option<Box<fs::Metadata>>,
+    },
+}
+
+impl Output {
+    /// A writer taking UTF-8 and writing `encoding` to this output
+    pub fn encoded(&mut self, encoding: Encoding) -> Box<dyn Write + '_> {
+        encoding::encode(Box::new(self), encoding)
+    }
+
+    /// Flush everything, ending a compressed stream, and put a file in place
+    ///
+    /// With --fsync the file, and the directory it was renamed in, are
+    /// synced to disk before this returns.
+    pub fn finish(self) -> io::Result<()> {
+        self.finish_with(&mut OsSync)
+    }
+
+    fn finish_with(self, sync: &mut dyn SyncHook) -> io::Result<()> {
+        let Output {
+            writer,
+            target,
+            synced,
+            tees,
+        } = self;
+        match writer {
+            OutputWriter::Plain(mut writer) | OutputWriter::Stdout(mut writer) => writer.flush()?,
+            OutputWriter::Closed => {}
+            OutputWriter::Compressed(compressor) => compressor.finish()?,
+            OutputWriter::Program(program) => program.finish()?,
+        }
+        if let Some(file) = &synced {
+            sync.sync_file(file)?;
+        }
+        if let Target::Replace { temp, path, original } = target {
+            if let Some(original) = original {
+                copy_attributes(&temp, &original)?;
+            }
+            temp.persist(&path).map_err(|err| err.error)?;
+            if synced.is_some() {
+                sync.sync_dir(parent_dir(&path))?;
+            }
+        }
+        for tee in tees {
+            let Tee { path, output } = tee;
+            output.finish_with(sync).map_err(|err| named_error(&path, err))?;
+        }
+        Ok(())
+    }
+}
+
+/// How --fsync makes the output durable; tests substitute their own
+trait SyncHook {
+    fn sync_file(&mut self, file: &File) -> io::Result<()>;
+    /// Sync the directory entry of a file just renamed into `dir`
+    fn sync_dir(&mut self, dir: &Path) -> io::Result<()>;
+}
+
+/// fsync(2) for real
+struct OsSync;
+
+impl SyncHook for OsSync {
+    fn sync_file(&mut self, file: &File) -> io::Result<()> {
+        file.sync_all()
+    }
+
+    fn sync_dir(&mut self, dir: &Path) -> io::Result<()> {
+        // Only Unix can open a directory to sync it
+        #[cfg(unix)]
+        File::open(dir)?.sync_all()?;
+        #[cfg(not(unix))]
+        let _ = dir;
+        Ok(())
+    }
+}
+
+impl Output {
+    /// Whether `err` is stdout's reader going away (as `head -1` does)
+    /// with --tee files still to write, and if so stop writing stdout
+    ///
+    /// The tees then get the rest of the output and are finished as usual;
+    /// with no tees the error stands, and the caller ends quietly.
+    fn stdout_closed(&mut self, err: &io::Error) -> bool {
+        let closed = matches!(self.writer, OutputWriter::Stdout(_))
+            && err.kind() == io::ErrorKind::BrokenPipe
+            && !self.tees.is_empty();
+        if closed {
+            self.writer = OutputWriter::Closed;
+        }
+        closed
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = match &mut self.writer {
+            OutputWriter::Plain(writer) => writer.write(buf),
+            OutputWriter::Stdout(writer) => writer.write(buf),
+            OutputWriter::Closed => Ok(buf.len()),
+            OutputWriter::Compressed(compressor) => compressor.write(buf),
+            OutputWriter::Program(program) => program.write(buf),
+        };
+        let written = match written {
+            Err(err) if self.stdout_closed(&err) => buf.len(),
+            written => written?,
+        };
+        for tee in &mut self.tees {
+            tee.output.write_all(&buf[..written]).map_err(|err| named_error(&tee.path, err))?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let flushed = match &mut self.writer {
+            OutputWriter::Plain(writer) | OutputWriter::Stdout(writer) => writer.flush(),
+            OutputWriter::Closed => Ok(()),
+            OutputWriter::Compressed(compressor) => compressor.flush(),
+            OutputWriter::Program(program) => program.flush(),
+        };
+        match flushed {
+            Err(err) if self.stdout_closed(&err) => {}
+            flushed => flushed?,
+        }
+        for tee in &mut self.tees {
+            tee.output.flush().map_err(|err| named_error(&tee.path, err))?;
+        }
+        Ok(())
+    }
+}
+
+/// Open the -o file, or stdout; "-" is stdout (GNU convention)
+///
+/// Write through `Output::encoded` to get `config.output_encoding`.
+pub fn open_output(config: &Config) -> Result<Output> {
+    create_output(output_path(config).as_deref(), config)
+}
+
+/// Start writing `path`, or stdout for None
+///
+/// `path` is replaced when `Output::finish` succeeds, or added to with
+/// `config.append`. A file named `*.gz` or `*.zst` is compressed at
+/// `config.compress_level` unless `config.auto_compress` is off. With
+/// `config.compress_output_program`, that program writes the output instead.
+/// Each of `config.tee` is opened the same way and gets a copy.
+pub fn create_output(path: Option<&Path>, config: &Config) -> Result<Output> {
+    let mut output = create_sink(path, config)?;
+    for path in &config.tee {
+        output.tees.push(Tee {
+            path: path.clone(),
+            output: create_sink(Some(path), config)?,
+        });
+    }
+    Ok(output)
+}
+
+fn create_sink(path: Option<&Path>, config: &Config) -> Result<Output> {
+    let Some(path) = path else {
+        let writer = match &config.compress_output_program {
+            Some(program) => OutputWriter::Program(compress::spawn_program(program, Stdio::inherit(), None)?),
+            None => OutputWriter::Stdout(stdout()),
+        };
+        return Ok(Output {
+            writer,
+            target: Target::Direct,
+            synced: None,
+            tees: Vec::new(),
+        });
+    };
+    let codec = config.output_codec(path);
+    if let (Some(codec), Some(level)) = (codec, config.compress_level) {
+        // Before anything is created
+        codec.check_level(level)?;
+    }
+    let (file, target) = open_target(path, config.append)?;
+    let synced = if config.fsync { synced_file(&file, &target) } else { None };
+    let writer = if let Some(program) = &config.compress_output_program {
+        OutputWriter::Program(compress::spawn_program(program, Stdio::from(file), Some(path))?)
+    } else if let Some(codec) = codec {
+        OutputWriter::Compressed(compress::compress(Box::new(file), codec, config.compress_level)?)
+    } else {
+        OutputWriter::Plain(Box::new(file))
+    };
+    Ok(Output {
+        writer,
+        target,
+        synced,
+        tees: Vec::new(),
+    })
+}
+
+/// Standard output, as bytes unless it is a Windows console
+///
+/// Redirected to a file or pipe, stdout gets every byte as is, on Windows
+/// too. A Windows console takes only UTF-8, and std fails the write for
+/// anything else, so there `ConsoleText` shows those bytes as U+FFFD.
+fn stdout() -> Box<dyn Write> {
+    #[cfg(windows)]
+    {
+        use std::io::IsTerminal;
+
+        if io::stdout().is_terminal() {
+            return Box::new(ConsoleText::new(io::stdout()));
+        }
+    }
+    Box::new(io::stdout())
+}
 
-        if !is_dup {
-            unique_records.push(record);
-            prev = Some(record);
+/// Text for a console, with each byte that is not part of valid UTF-8
+/// replaced by U+FFFD
+///
+/// A sequence cut short at the end of one write is held for the next, so
+/// only bytes that are really invalid are replaced.
+#[cfg(any(windows, test))]
+struct ConsoleText<W: Write> {
+    inner: W,
+    /// The start of a UTF-8 sequence that the last write ended in
+    pending: Vec<u8>,
+}
+
+#[cfg(any(windows, test))]
+impl<W: Write> ConsoleText<W> {
+    const REPLACEMENT: &'static [u8] = "\u{FFFD}".as_bytes();
+
+    fn new(inner: W) -> Self {
+        ConsoleText { inner, pending: Vec::new() }
+    }
+}
+
+#[cfg(any(windows, test))]
+impl<W: Write> Write for ConsoleText<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let mut text = Vec::with_capacity(self.pending.len());
+        let mut rest = self.pending.as_slice();
+        loop {
+            let err = match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.extend_from_slice(valid.as_bytes());
+                    rest = &[];
+                    break;
+                }
+                Err(err) => err,
+            };
+            let (valid, after) = rest.split_at(err.valid_up_to());
+            text.extend_from_slice(valid);
+            rest = after;
+            // None: cut short, and the next write may finish it
+            let Some(invalid) = err.error_len() else { break };
+            text.extend_from_slice(Self::REPLACEMENT);
+            rest = &rest[invalid..];
         }
+        let held = rest.len();
+        self.pending.drain(..self.pending.len() - held);
+        self.inner.write_all(&text)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
     }
+}
 
-    let len = unique_records.len();
-    for (i, record) in unique_records.iter().enumerate() {
-        writer.write_all(record)?;
-        if i < len - 1 || add_trailing {
-            writer.write_all(&[delimiter])?;
+#[cfg(any(windows, test))]
+impl<W: Write> Drop for ConsoleText<W> {
+    /// Output that ended inside a sequence ends with one replacement
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            let _ = self.inner.write_all(Self::REPLACEMENT);
+            let _ = self.inner.flush();
         }
     }
+}
 
-    Ok(())
+/// A handle on `file` to sync, if it is a regular file; devices and pipes
+/// are not synced
+fn synced_file(file: &File, target: &Target) -> Option<File> {
+    let regular = matches!(target, Target::Replace { .. }) || file.metadata().is_ok_and(|meta| meta.is_file());
+    regular.then(|| file.try_clone().ok()).flatten()
 }
 
-/// Comparison for -u deduplication
+/// The file to write `path` through: a temporary file beside it, or `path`
+/// itself when appending or when it is not a regular file
 ///
-/// Uses key comparison only (no last-resort) since -u disables last-resort.
-fn compare_for_unique(a: &[u8], b: &[u8], config: &Config) -> Ordering {
-    // compare_records already handles -u by disabling last-resort
-    compare_records(a, b, config)
+/// A long path is opened, and its temporary file named, in the form
+/// `platform::long_path` gives.
+fn open_target(path: &Path, append: bool) -> Result<(File, Target)> {
+    let open_error = |source| RsortError::OpenOutput {
+        path: path.to_path_buf(),
+        source,
+    };
+    let long = platform::long_path(path);
+    let replaced = if append { None } else { replaced_path(&long) };
+    let Some((replaced, original)) = replaced else {
+        let mut options = OpenOptions::new();
+        if append {
+            options.append(true);
+        } else {
+            options.write(true).truncate(true);
+        }
+        let file = options.create(true).open(&long).map_err(open_error)?;
+        return Ok((file, Target::Direct));
+    };
+
+    let (file, temp) = create_temp(&replaced).map_err(open_error)?;
+    let target = Target::Replace {
+        temp,
+        path: replaced,
+        original,
+    };
+    Ok((file, target))
+}
+
+/// The file a rename should replace to write `path`, with its metadata if
+/// it exists, or None to write `path` in place
+///
+/// A regular file is replaced, through any symlinks to it as GNU sort
+/// follows them, and so is a path that does not exist yet. Devices, FIFOs
+/// and sockets (`/dev/null`, `/dev/stdout`, a named pipe) are written in
+/// place, as is a dangling symlink, which creates the file it names.
+fn replaced_path(path: &Path) -> Option<(PathBuf, Option<Box<fs::Metadata>>)> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_file() => {
+            let resolved = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+            Some((resolved, Some(Box::new(meta))))
+        }
+        Ok(_) => None,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !path.is_symlink() => {
+            Some((path.to_path_buf(), None))
+        }
+        // Any other error comes back from the open
+        Err(_) => None,
+    }
 }
 
-/// Open output file or return stdout
-/// Handles "-" as stdout (GNU convention)
-pub fn open_output(config: &Config) -> io::Result<Box<dyn Write>> {
-    match &config.output_file {
-        Some(path) if path == "-" => Ok(Box::new(io::stdout())),
-        Some(path) => {
-            let file = File::create(path)?;
-            Ok(Box::new(file))
+/// Give the file at `temp` the permissions of the `original` it replaces
+/// (the read-only attribute on Windows), and its owner and group as far as
+/// this user may: only root can give a file away, but the group of a
+/// setgid directory's file is usually one of the user's own
+fn copy_attributes(temp: &Path, original: &fs::Metadata) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{chown, MetadataExt};
+        if chown(temp, Some(original.uid()), Some(original.gid())).is_err() {
+            let _ = chown(temp, None, Some(original.gid()));
         }
-        None => Ok(Box::new(io::stdout())),
+    }
+    // After chown, which may clear setuid and setgid bits
+    fs::set_permissions(temp, original.permissions())
+}
+
+/// Create a new file in the directory of `path`, to rename over it later
+///
+/// Like `File::create`, the file gets mode 0666 less the umask.
+fn create_temp(path: &Path) -> io::Result<(File, TempPath)> {
+    let dir = parent_dir(path);
+    let mut attempt = 0u32;
+    loop {
+        let temp = dir.join(format!(".rsort-{}-{}", std::process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((file, TempPath::try_from_path(temp)?)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// The directory holding `path`
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
     }
 }
 
+/// The -o file, or None when writing to stdout
+pub fn output_path(config: &Config) -> Option<PathBuf> {
+    config
+        .output_file
+        .as_deref()
+        .filter(|path| path.as_os_str() != "-")
+        .map(PathBuf::from)
+}
+
+/// The --split-output files for `prefix`: `prefix.000` to `prefix.{chunks - 1}`,
+/// with more digits when there are over a thousand
+pub fn split_paths(prefix: &Path, chunks: usize) -> Vec<PathBuf> {
+    let width = (chunks.saturating_sub(1)).to_string().len().max(3);
+    (0..chunks)
+        .map(|i| {
+            let mut path = prefix.as_os_str().to_os_string();
+            path.push(format!(".{:0width$}", i, width = width));
+            PathBuf::from(path)
+        })
+        .collect()
+}
+
+/// Attach the output name to an error raised while writing
+pub fn write_error(config: &Config, source: io::Error) -> RsortError {
+    RsortError::from(source).writing(output_path(config).as_deref())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::key::KeySpec;
 
-    fn test_config() -> Config {
-        Config {
-            reverse: false,
-            numeric: false,
-            fold_case: false,
-            unique: false,
-            stable: false,
-            debug: false,
-            record_delimiter: b'\n',
-            field_separator: None,
-            keys: vec![],
-            output_file: None,
-            input_files: vec![],
-        }
+    #[test]
+    fn test_split_points() {
+        let records = ["a", "a", "a", "b", "c", "c", "d"];
+        assert_eq!(split_points(&records, 0, None, 3), [3, 5, 7]);
+        assert_eq!(split_points(&records, 0, None, 9), [1, 2, 3, 4, 5, 6, 7, 7, 7]);
+
+        let comparator = Comparator::new(&Config::default());
+        assert_eq!(split_points(&records, 0, Some(&comparator), 4), [3, 4, 6, 7]);
+        assert_eq!(split_points(&records, 0, Some(&comparator), 7), [3, 3, 3, 4, 6, 6, 7]);
+        // Preserved records are never compared
+        assert_eq!(split_points(&records, 2, Some(&comparator), 7), [1, 2, 3, 4, 6, 6, 7]);
+    }
+
+    #[test]
+    fn test_split_paths() {
+        let names = |chunks| split_paths(Path::new("dir/out"), chunks);
+        assert_eq!(names(2), [PathBuf::from("dir/out.000"), PathBuf::from("dir/out.001")]);
+        assert_eq!(names(1001)[1000], Path::new("dir/out.1000"));
+        assert_eq!(names(1000)[999], Path::new("dir/out.999"));
     }
 
     #[test]
     fn test_write_all() {
         let records: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
-        let config = test_config();
+        let config = Config::default();
         let mut output = Vec::new();
         write_records(&mut output, &records, &config, true).unwrap();
         assert_eq!(output, b"a\nb\nc\n");
@@ -136,8 +1023,7 @@ mod tests {
             b"b".to_vec(),
             b"c".to_vec(),
         ];
-        let mut config = test_config();
-        config.unique = true;
+        let config = Config::builder().unique(true).build().unwrap();
         let mut output = Vec::new();
         write_records(&mut output, &records, &config, true).unwrap();
         assert_eq!(output, b"a\nb\nc\n");
@@ -151,21 +1037,181 @@ mod tests {
             b"a 2".to_vec(),
             b"b 1".to_vec(),
         ];
-        let mut config = test_config();
-        config.unique = true;
-        config.keys = vec![KeySpec::parse("1,1").unwrap()];
+        let config = Config::builder().unique(true).add_key("1,1").build().unwrap();
         let mut output = Vec::new();
         write_records(&mut output, &records, &config, true).unwrap();
         assert_eq!(output, b"a 1\nb 1\n");
     }
 
+    #[test]
+    fn test_console_text_replaces_invalid_utf8() {
+        let mut out = Vec::new();
+        let mut console = ConsoleText::new(&mut out);
+        console.write_all(b"a\xffb\n\xc3").unwrap();
+        // The rest of the é started by the last write
+        console.write_all(b"\xa9\n\0z\xe2\x82").unwrap();
+        drop(console);
+        assert_eq!(String::from_utf8(out).unwrap(), "a\u{FFFD}b\né\n\0z\u{FFFD}");
+    }
+
+    #[test]
+    fn test_open_output_error_names_path() {
+        let config = Config::builder()
+            .output_file("/nonexistent-dir/out.txt")
+            .build()
+            .unwrap();
+        let err = open_output(&config).err().unwrap();
+        assert!(matches!(err, RsortError::OpenOutput { .. }));
+        assert!(err.to_string().contains("/nonexistent-dir/out.txt"));
+    }
+
+    #[test]
+    fn test_write_error_names_output() {
+        let err = write_error(&Config::default(), io::ErrorKind::BrokenPipe.into());
+        assert!(err.to_string().contains("'standard output'"));
+
+        let config = Config::builder().output_file("out.txt").build().unwrap();
+        let err = write_error(&config, io::ErrorKind::BrokenPipe.into());
+        assert!(err.to_string().contains("out.txt"));
+    }
+
+    /// Takes every write, then fails to flush
+    struct FailingFlush;
+
+    impl Write for FailingFlush {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::other("No space left on device"))
+        }
+    }
+
+    #[test]
+    fn test_failed_finish_keeps_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.gz");
+        fs::write(&path, b"original").unwrap();
+
+        let failing = |writer| {
+            let (_, target) = open_target(&path, false).unwrap();
+            let mut output = Output {
+                writer,
+                target,
+                synced: None,
+                tees: Vec::new(),
+            };
+            output.encoded(Encoding::Utf8).write_all(b"a\nb\n").unwrap();
+            assert!(output.finish().is_err());
+            assert_eq!(fs::read(&path).unwrap(), b"original");
+            assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1, "temporary file left");
+        };
+        failing(OutputWriter::Plain(Box::new(FailingFlush)));
+        #[cfg(feature = "gzip")]
+        failing(OutputWriter::Compressed(compress::compress(Box::new(FailingFlush), compress::Codec::Gzip, None).unwrap()));
+    }
+
+    /// Records --fsync calls, failing file syncs when `fail` is set
+    #[derive(Default)]
+    struct RecordingSync {
+        files: usize,
+        dirs: Vec<PathBuf>,
+        fail: bool,
+    }
+
+    impl SyncHook for RecordingSync {
+        fn sync_file(&mut self, _file: &File) -> io::Result<()> {
+            self.files += 1;
+            if self.fail {
+                return Err(io::Error::from_raw_os_error(5));
+            }
+            Ok(())
+        }
+
+        fn sync_dir(&mut self, dir: &Path) -> io::Result<()> {
+            self.dirs.push(dir.to_path_buf());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fsync_syncs_file_then_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        let finish = |config: &Config, path: Option<&Path>, sync: &mut RecordingSync| {
+            let mut output = create_output(path, config).unwrap();
+            output.write_all(b"a\n").unwrap();
+            output.finish_with(sync)
+        };
+
+        let config = Config::builder().fsync(true).build().unwrap();
+        let mut sync = RecordingSync::default();
+        finish(&config, Some(&path), &mut sync).unwrap();
+        assert_eq!(sync.files, 1);
+        assert_eq!(sync.dirs, [fs::canonicalize(dir.path()).unwrap()]);
+
+        // Off by default, and nothing to sync for stdout
+        let mut sync = RecordingSync::default();
+        finish(&Config::default(), Some(&path), &mut sync).unwrap();
+        finish(&config, None, &mut sync).unwrap();
+        assert_eq!((sync.files, sync.dirs.len()), (0, 0));
+
+        // A failed sync is a write error on the output, which is not replaced
+        let config = Config { output_file: Some(path.clone()), ..config };
+        fs::write(&path, b"original").unwrap();
+        let mut sync = RecordingSync { fail: true, ..Default::default() };
+        let err = write_error(&config, finish(&config, Some(&path), &mut sync).unwrap_err());
+        assert_eq!(err.to_string(), format!("write failed: {}: Input/output error", path.display()));
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+    }
+
     #[test]
     fn test_write_nul_delimiter() {
         let records: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec()];
-        let mut config = test_config();
-        config.record_delimiter = 0u8;
+        let config = Config::builder().record_delimiter(0u8).build().unwrap();
         let mut output = Vec::new();
         write_records(&mut output, &records, &config, true).unwrap();
         assert_eq!(output, b"a\0b\0");
     }
+
+    #[test]
+    fn test_write_no_trailing() {
+        let records: Vec<Vec<u8>> = vec![b"a".to_vec(), b"b".to_vec()];
+        let mut output = Vec::new();
+        write_records(&mut output, &records, &Config::default(), false).unwrap();
+        assert_eq!(output, b"a\nb");
+
+        let mut output = Vec::new();
+        write_records(&mut output, &[], &Config::default(), true).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_write_iter_from_lazy_generator() {
+        let records = (0..5u8).map(|i| vec![b'a' + i / 2]);
+        let mut output = Vec::new();
+        let written = write_records_iter(&mut output, records, &Config::default(), true).unwrap();
+        assert_eq!(written, 5);
+        assert_eq!(output, b"a\na\nb\nb\nc\n");
+    }
+
+    #[test]
+    fn test_write_iter_unique_streams() {
+        let config = Config::builder().unique(true).build().unwrap();
+        let records = (0..6u8).map(|i| vec![b'a' + i / 3]);
+        let mut output = Vec::new();
+        let written = write_records_iter(&mut output, records, &config, false).unwrap();
+        assert_eq!(written, 2);
+        assert_eq!(output, b"a\nb");
+    }
+
+    #[test]
+    fn test_unique_adaptor_borrows() {
+        let data: [&[u8]; 4] = [b"A", b"a", b"b", b"B"];
+        let config = Config::builder().unique(true).fold_case(true).build().unwrap();
+        let comparator = Comparator::new(&config);
+        let kept: Vec<&[u8]> = unique(data, &comparator).collect();
+        assert_eq!(kept, vec![&b"A"[..], b"b"]);
+    }
 }
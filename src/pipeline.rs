@@ -0,0 +1,203 @@
+//! Whole-file sorting: read every input, sort, write one output
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::arena::Arena;
+use crate::check;
+use crate::config::Config;
+use crate::encoding;
+use crate::error::{Result, RsortError};
+use crate::input::{self, RecordReader};
+use crate::output;
+use crate::sorter::Sorter;
+use crate::stats::SortStats;
+use crate::trace::{trace_event, trace_span};
+
+/// Sort the file `input` into the file `output`
+///
+/// As on the command line, `-` names stdin or stdout, and `output` may be
+/// one of the inputs: it is only replaced once the sorted output is complete.
+/// `config`'s own input and output files are ignored.
+///
+/// ```no_run
+/// let config = rsort::Config::builder().numeric(true).build()?;
+/// let stats = rsort::sort_file("scores.txt", "scores.sorted", &config)?;
+/// println!("{} records", stats.records_written);
+/// # Ok::<(), rsort::RsortError>(())
+/// ```
+pub fn sort_file(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    config: &Config,
+) -> Result<SortStats> {
+    sort_files(&[input.as_ref().to_path_buf()], output, config)
+}
+
+/// Sort the concatenation of `inputs` into `output`, like `rsort -o OUTPUT INPUTS...`
+///
+/// `-` may appear among `inputs` at most once.
+pub fn sort_files(inputs: &[PathBuf], output: impl AsRef<Path>, config: &Config) -> Result<SortStats> {
+    input::check_stdin_once(inputs)?;
+    // Opened first, so an unwritable output fails before any reading
+    let output = output.as_ref();
+    trace_span!("sort_files", inputs = inputs.len(), output = %output.display());
+    let output = (output != Path::new("-")).then_some(output);
+    let verified = check::verify_target(output, config)?;
+    let mut out = output::create_output(output, config)?;
+
+    let mut loaded = read_inputs(inputs, config)?;
+    let resolved = config.resolve_key_names(&loaded.records)?;
+    let config = resolved.as_ref().unwrap_or(config);
+
+    let mut encoded = out.encoded(config.output_encoding);
+    let write_error = |source: io::Error| RsortError::from(source).writing(output);
+    let bom = loaded.bom(config);
+    encoded.write_all(bom).map_err(write_error)?;
+    let sorter = Sorter::from_config(config.clone()).collect_stats(config.stats.is_some());
+    let mut stats = sorter
+        .sort_and_write(&mut loaded.records, encoded, config.final_delimiter(loaded.had_trailing))
+        .map_err(write_error)?;
+    out.finish().map_err(write_error)?;
+    if let Some(output) = verified {
+        check::verify_output(output, config)?;
+    }
+    stats.bytes_written += bom.len() as u64;
+    loaded.fill_in(&mut stats);
+    Ok(stats)
+}
+
+/// Sort the concatenation of `inputs` into `chunks` files, like
+/// `rsort --split-output=CHUNKS --output-prefix=PREFIX INPUTS...`
+///
+/// The files are named by `output::split_paths`; each holds the next range
+/// of the sorted order, so read in turn they are `sort_files`' output.
+/// `config.split_by_key` keeps equal keys in one file. A BOM kept with
+/// `keep_bom` starts the first file.
+pub fn sort_files_split(
+    inputs: &[PathBuf],
+    prefix: impl AsRef<Path>,
+    chunks: usize,
+    config: &Config,
+) -> Result<SortStats> {
+    input::check_stdin_once(inputs)?;
+    trace_span!("sort_files_split", inputs = inputs.len(), chunks);
+    check::verify_target(None, config)?;
+    if chunks == 0 {
+        return Err(RsortError::Usage("invalid --split-output argument '0'".to_string()));
+    }
+    let paths = output::split_paths(prefix.as_ref(), chunks);
+    let mut outs = paths
+        .iter()
+        .map(|path| output::create_output(Some(path), config))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut loaded = read_inputs(inputs, config)?;
+    let resolved = config.resolve_key_names(&loaded.records)?;
+    let config = resolved.as_ref().unwrap_or(config);
+
+    let mut writers: Vec<_> = outs
+        .iter_mut()
+        .zip(&paths)
+        .map(|(out, path)| output::named(out.encoded(config.output_encoding), path))
+        .collect();
+    let bom = loaded.bom(config);
+    writers[0].write_all(bom)?;
+    let sorter = Sorter::from_config(config.clone()).collect_stats(config.stats.is_some());
+    let mut stats = sorter.sort_and_split(
+        &mut loaded.records,
+        &mut writers,
+        config.final_delimiter(loaded.had_trailing),
+    )?;
+    drop(writers);
+    for (out, path) in outs.into_iter().zip(&paths) {
+        out.finish().map_err(|source| RsortError::from(source).writing(Some(path)))?;
+    }
+    stats.bytes_written += bom.len() as u64;
+    loaded.fill_in(&mut stats);
+    Ok(stats)
+}
+
+/// Every record of the inputs, as `read_inputs` found them
+struct Loaded {
+    records: Vec<Vec<u8>>,
+    /// Whether the inputs, one after another, end with a delimiter: the
+    /// last input with records decides (--preserve-final-newline)
+    had_trailing: bool,
+    stripped_bom: bool,
+    /// Records read from each input
+    input_records: Vec<(PathBuf, u64)>,
+    /// Record bytes held once everything was read
+    arena_bytes: u64,
+    read_time: Duration,
+}
+
+impl Loaded {
+    /// The BOM to start the output with: --keep-bom's, if one was stripped
+    fn bom(&self, config: &Config) -> &'static [u8] {
+        if config.keep_bom && self.stripped_bom {
+            input::UTF8_BOM
+        } else {
+            &[]
+        }
+    }
+
+    /// Add what only reading knows to the stats of sorting and writing
+    fn fill_in(self, stats: &mut SortStats) {
+        stats.input_records = self.input_records;
+        stats.arena_bytes = self.arena_bytes;
+        stats.read_time = self.read_time;
+    }
+}
+
+/// Read `inputs` one after another, stopping at --take
+fn read_inputs(inputs: &[PathBuf], config: &Config) -> Result<Loaded> {
+    trace_span!("read");
+    let started = Instant::now();
+    let mut arena = Arena::new();
+    let mut had_trailing = true;
+    // --take: records still wanted; later inputs are not even opened
+    let mut remaining = config.take;
+    let mut stripped_bom = false;
+    let mut input_records = Vec::new();
+    for (i, path) in inputs.iter().enumerate() {
+        if remaining == Some(0) {
+            break;
+        }
+        let reader = encoding::decode(input::open_decompressed(path, &config.decompress)?, config.encoding);
+        let mut reader = RecordReader::new(reader, config.record_delimiter)
+            .delimiter_prefix(&config.record_delimiter_prefix)
+            .max_record_size(config.max_record_size)
+            .record_size(config.record_size)
+            .framing(config.framing)
+            .take_records(remaining)
+            .strip_bom(config.strip_bom && i == 0);
+        let before = arena.len();
+        #[cfg(feature = "trace")]
+        let bytes_before = arena.bytes_used();
+        let terminated = reader.read_into(&mut arena).map_err(|e| e.reading(path))?;
+        if arena.len() > before {
+            had_trailing = terminated;
+        }
+        stripped_bom |= reader.stripped_bom();
+        let read = (arena.len() - before) as u64;
+        trace_event!(
+            path = %path.display(),
+            records = read,
+            bytes = arena.bytes_used() - bytes_before,
+            "read input"
+        );
+        input_records.push((path.clone(), read));
+        remaining = remaining.map(|n| n - read);
+    }
+    trace_event!(records = arena.len(), elapsed = ?started.elapsed(), "read done");
+    Ok(Loaded {
+        arena_bytes: arena.bytes_used() as u64,
+        records: arena.into_records(),
+        had_trailing,
+        stripped_bom,
+        input_records,
+        read_time: started.elapsed(),
+    })
+}
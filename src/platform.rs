@@ -0,0 +1,122 @@
+//! Operating system differences in how files are named and told apart
+
+use std::borrow::Cow;
+use std::path::Path;
+#[cfg(not(unix))]
+use std::path::PathBuf;
+
+/// Paths at least this long are opened through the `\\?\` prefix on
+/// Windows: MAX_PATH (260) less room for the `.rsort-PID-N` temporary file
+/// written beside an output
+#[cfg(windows)]
+const LONG_PATH: usize = 240;
+
+/// `path` in a form the OS opens however long it is
+///
+/// Windows refuses a path of MAX_PATH (260) characters or more unless it
+/// is absolute and starts with `\\?\` (`\\?\UNC\server\share\...` for a
+/// share), so a long path is made absolute and prefixed. Other paths, and
+/// every path elsewhere, come back unchanged.
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        if path.as_os_str().encode_wide().count() >= LONG_PATH {
+            let absolute = std::path::absolute(path).ok();
+            if let Some(long) = absolute.as_deref().and_then(Path::to_str).and_then(verbatim) {
+                return Cow::Owned(PathBuf::from(long));
+            }
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+/// What makes two paths name the same file: device and inode numbers on
+/// Unix, so hard links match too, and the canonical path elsewhere
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+/// What makes two paths name the same file, without inode numbers to go by
+#[cfg(not(unix))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileId {
+    canonical: PathBuf,
+}
+
+impl FileId {
+    /// The file `path` names, following symlinks; None if it cannot be
+    /// looked at (e.g. it does not exist)
+    pub fn of(path: &Path) -> Option<Self> {
+        let path = long_path(path);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let meta = std::fs::metadata(&path).ok()?;
+            Some(FileId {
+                dev: meta.dev(),
+                ino: meta.ino(),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            let canonical = std::fs::canonicalize(&path).ok()?;
+            Some(FileId { canonical })
+        }
+    }
+}
+
+/// The `\\?\` form of an absolute Windows path, None if it already has one
+#[cfg(any(windows, test))]
+fn verbatim(absolute: &str) -> Option<String> {
+    if absolute.starts_with(r"\\?\") || absolute.starts_with(r"\\.\") {
+        return None;
+    }
+    match absolute.strip_prefix(r"\\") {
+        Some(share) => Some(format!(r"\\?\UNC\{}", share)),
+        None => Some(format!(r"\\?\{}", absolute)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbatim() {
+        assert_eq!(verbatim(r"C:\data\in.txt").unwrap(), r"\\?\C:\data\in.txt");
+        assert_eq!(verbatim(r"\\server\share\in.txt").unwrap(), r"\\?\UNC\server\share\in.txt");
+        assert_eq!(verbatim(r"\\?\C:\data\in.txt"), None);
+        assert_eq!(verbatim(r"\\.\pipe\sorted"), None);
+    }
+
+    #[test]
+    fn test_short_paths_are_unchanged() {
+        assert!(matches!(long_path(Path::new("in.txt")), Cow::Borrowed(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_id_sees_through_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt");
+        std::fs::write(&file, b"a\n").unwrap();
+        let (hard, soft) = (dir.path().join("hard"), dir.path().join("soft"));
+        std::fs::hard_link(&file, &hard).unwrap();
+        std::os::unix::fs::symlink(&file, &soft).unwrap();
+
+        let id = FileId::of(&file).unwrap();
+        assert_eq!(FileId::of(&hard).unwrap(), id);
+        assert_eq!(FileId::of(&soft).unwrap(), id);
+        assert_eq!(FileId::of(&dir.path().join(".").join("data.txt")).unwrap(), id);
+
+        std::fs::write(dir.path().join("copy.txt"), b"a\n").unwrap();
+        assert_ne!(FileId::of(&dir.path().join("copy.txt")).unwrap(), id);
+        assert_eq!(FileId::of(&dir.path().join("missing")), None);
+    }
+}
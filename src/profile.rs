@@ -0,0 +1,165 @@
+//! Default options from a TOML profile (`--profile FILE` or `./.rsort.toml`)
+//!
+//! ```toml
+//! field-separator = ","
+//! keys = ["2,2", "1"]
+//! numeric = true
+//! ```
+//!
+//! Command-line flags override the profile field by field.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::config::{parse_field_separator, ConfigBuilder};
+use crate::error::{Result, RsortError};
+
+/// Options a profile may set; anything else in the file is an error
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub reverse: Option<bool>,
+    pub numeric: Option<bool>,
+    pub fold_case: Option<bool>,
+    pub human_numeric: Option<bool>,
+    pub month: Option<bool>,
+    pub version: Option<bool>,
+    pub unique: Option<bool>,
+    pub stable: Option<bool>,
+    pub zero_terminated: Option<bool>,
+    /// Same syntax as -t, including escapes like `\t`
+    pub field_separator: Option<String>,
+    /// GNU keydefs, as for -k
+    pub keys: Option<Vec<String>>,
+}
+
+impl Profile {
+    /// Loaded from the current directory when no --profile is given
+    pub const DEFAULT_FILE: &'static str = ".rsort.toml";
+
+    /// Parse a profile; `path` is only used in error messages
+    pub fn from_toml(text: &str, path: &Path) -> Result<Self> {
+        toml::from_str(text).map_err(|e| RsortError::Profile {
+            path: path.to_path_buf(),
+            line: e.span().map(|span| text[..span.start].matches('\n').count() + 1),
+            // Keep the diagnostic on one line
+            message: e.message().trim_end().replace('\n', ": "),
+        })
+    }
+
+    /// Read and parse a profile file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|source| RsortError::OpenInput {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml(&text, path)
+    }
+
+    /// The explicit profile if given, else `./.rsort.toml` if it exists
+    pub fn discover(explicit: Option<&str>) -> Result<Option<Self>> {
+        let path = match explicit {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let path = PathBuf::from(Self::DEFAULT_FILE);
+                if !path.is_file() {
+                    return Ok(None);
+                }
+                path
+            }
+        };
+        Self::load(&path).map(Some)
+    }
+
+    /// Set every option the profile mentions on `builder`
+    pub fn apply(&self, mut builder: ConfigBuilder) -> Result<ConfigBuilder> {
+        if let Some(reverse) = self.reverse {
+            builder = builder.reverse(reverse);
+        }
+        if let Some(numeric) = self.numeric {
+            builder = builder.numeric(numeric);
+        }
+        if let Some(fold_case) = self.fold_case {
+            builder = builder.fold_case(fold_case);
+        }
+        if let Some(human_numeric) = self.human_numeric {
+            builder = builder.human_numeric(human_numeric);
+        }
+        if let Some(month) = self.month {
+            builder = builder.month(month);
+        }
+        if let Some(version) = self.version {
+            builder = builder.version(version);
+        }
+        if let Some(unique) = self.unique {
+            builder = builder.unique(unique);
+        }
+        if let Some(stable) = self.stable {
+            builder = builder.stable(stable);
+        }
+        if let Some(zero_terminated) = self.zero_terminated {
+            builder = builder.record_delimiter(if zero_terminated { 0 } else { b'\n' });
+        }
+        if let Some(separator) = &self.field_separator {
+            builder = builder.field_separator(parse_field_separator(separator)?);
+        }
+        for keydef in self.keys.iter().flatten() {
+            builder = builder.add_key(keydef);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn parse(text: &str) -> Result<Profile> {
+        Profile::from_toml(text, Path::new("team.toml"))
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let profile = parse("numeric = true\nfield-separator = \"\\\\t\"\nkeys = [\"2,2\"]\n").unwrap();
+        assert_eq!(profile.numeric, Some(true));
+        assert_eq!(profile.reverse, None);
+
+        let config = profile.apply(Config::builder()).unwrap().build().unwrap();
+        assert_eq!(config.sort_mode, crate::key::SortMode::Numeric);
+        assert_eq!(config.field_separator, Some(b'\t'.into()));
+        assert_eq!(config.keys[0].to_string(), "2,2");
+    }
+
+    #[test]
+    fn test_empty_profile_changes_nothing() {
+        assert_eq!(parse("").unwrap(), Profile::default());
+    }
+
+    #[test]
+    fn test_unknown_key_is_error() {
+        let err = parse("numeric = true\nnumric-sort = true\n").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("numric-sort"), "{}", message);
+        assert!(message.contains("team.toml:2"), "{}", message);
+    }
+
+    #[test]
+    fn test_malformed_profile_names_path_and_line() {
+        let err = parse("reverse = true\n\nkeys = [\"1\"\n").unwrap_err();
+        assert!(matches!(err, RsortError::Profile { line: Some(_), .. }));
+        assert!(err.to_string().starts_with("invalid profile team.toml:"));
+
+        let err = parse("reverse = \"yes\"\n").unwrap_err();
+        assert!(err.to_string().contains("team.toml:1:"), "{}", err);
+    }
+
+    #[test]
+    fn test_bad_keydef_reported_at_build() {
+        let profile = parse("keys = [\"0\"]").unwrap();
+        let err = profile.apply(Config::builder()).unwrap().build().unwrap_err();
+        assert!(matches!(err, RsortError::InvalidKey(_)));
+    }
+}
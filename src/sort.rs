@@ -1,43 +1,186 @@
-use crate::compare::compare_records;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::vec;
+
+use crate::compare::Comparator;
 use crate::config::Config;
+use crate::output;
 
 /// Sort records according to configuration
 ///
+/// Works over any byte-slice representation: `Vec<u8>`, `&[u8]`, `Box<[u8]>`, ...
+pub fn sort_records<T: AsRef<[u8]>>(records: &mut [T], config: &Config) {
+    sort_with(records, &Comparator::new(config));
+}
+
+/// Sort records with a prebuilt comparator
+///
 /// Uses stable sort when -s or -u is specified (to preserve input order for equals).
 /// Otherwise uses unstable sort (faster, no scratch allocation).
-pub fn sort_records(records: &mut [Vec<u8>], config: &Config) {
-    if config.use_stable_sort() {
-        records.sort_by(|a, b| compare_records(a, b, config));
+pub fn sort_with<T: AsRef<[u8]>>(records: &mut [T], comparator: &Comparator) {
+    if comparator.is_stable() {
+        records.sort_by(|a, b| comparator.cmp(a.as_ref(), b.as_ref()));
+    } else {
+        records.sort_unstable_by(|a, b| comparator.cmp(a.as_ref(), b.as_ref()));
+    }
+}
+
+/// `sort_with` that also returns how many comparisons were made
+pub fn sort_counting<T: AsRef<[u8]>>(records: &mut [T], comparator: &Comparator) -> u64 {
+    let mut comparisons = 0u64;
+    let mut cmp = |a: &T, b: &T| {
+        comparisons += 1;
+        comparator.cmp(a.as_ref(), b.as_ref())
+    };
+    if comparator.is_stable() {
+        records.sort_by(&mut cmp);
+    } else {
+        records.sort_unstable_by(&mut cmp);
+    }
+    comparisons
+}
+
+/// Return the sorted order of `records` as indices, leaving the records in place
+///
+/// `records[perm[0]]` is the first record in sorted order.
+///
+/// # Panics
+///
+/// Panics if there are more than `u32::MAX` records.
+pub fn sort_indices<T: AsRef<[u8]>>(records: &[T], config: &Config) -> Vec<u32> {
+    indices_with(records, &Comparator::new(config))
+}
+
+/// Index-sorting variant of `sort_with`
+pub fn indices_with<T: AsRef<[u8]>>(records: &[T], comparator: &Comparator) -> Vec<u32> {
+    let len = u32::try_from(records.len()).expect("more than u32::MAX records");
+    let mut perm: Vec<u32> = (0..len).collect();
+    let key = |i: &u32| records[*i as usize].as_ref();
+    if comparator.is_stable() {
+        perm.sort_by(|a, b| comparator.cmp(key(a), key(b)));
+    } else {
+        perm.sort_unstable_by(|a, b| comparator.cmp(key(a), key(b)));
+    }
+    perm
+}
+
+/// `sort_counting` ordering each record by its line in `keys` instead of
+/// its own bytes (see `Comparator::cmp_keyed`; --key-command)
+///
+/// With `unique`, only the first record of each run of equal keys is kept:
+/// the rest are moved past the returned count, which is how many were
+/// kept. Also returns how many comparisons were made.
+///
+/// # Panics
+///
+/// Panics if `keys` and `records` differ in length.
+pub fn sort_keyed<T: AsRef<[u8]>>(
+    records: &mut [T],
+    keys: &[Vec<u8>],
+    comparator: &Comparator,
+    unique: bool,
+) -> (usize, u64) {
+    assert_eq!(records.len(), keys.len(), "one key per record");
+    let comparisons = Cell::new(0u64);
+    let cmp = |&i: &usize, &j: &usize| {
+        comparisons.set(comparisons.get() + 1);
+        comparator.cmp_keyed(records[i].as_ref(), &keys[i], records[j].as_ref(), &keys[j])
+    };
+    let mut order: Vec<usize> = (0..records.len()).collect();
+    if comparator.is_stable() {
+        order.sort_by(cmp);
     } else {
-        records.sort_unstable_by(|a, b| compare_records(a, b, config));
+        order.sort_unstable_by(cmp);
+    }
+
+    let mut kept = order.len();
+    if unique {
+        let mut dropped = Vec::new();
+        let mut last = None;
+        order.retain(|&i| {
+            let duplicate = last.is_some_and(|j| cmp(&j, &i) == Ordering::Equal);
+            if duplicate {
+                dropped.push(i);
+            } else {
+                last = Some(i);
+            }
+            !duplicate
+        });
+        kept = order.len();
+        order.extend(dropped);
+    }
+    permute(records, order);
+    (kept, comparisons.get())
+}
+
+/// Reorder `items` so that position k holds what was at `order[k]`
+fn permute<T>(items: &mut [T], mut order: Vec<usize>) {
+    for start in 0..items.len() {
+        // Follow the cycle through `start`, marking each position done
+        let mut current = start;
+        loop {
+            let next = std::mem::replace(&mut order[current], current);
+            if next == start || next == current {
+                break;
+            }
+            items.swap(current, next);
+            current = next;
+        }
+    }
+}
+
+/// `.rsorted()` and `.rsorted_unique()` for any iterator of records
+///
+/// ```
+/// use rsort::{Config, SortedExt};
+///
+/// let config = Config::builder().fold_case(true).build()?;
+/// let words = ["b", "A", "a", "B"].into_iter().rsorted_unique(&config);
+/// assert_eq!(words.collect::<Vec<_>>(), ["A", "b"]);
+/// # Ok::<(), rsort::RsortError>(())
+/// ```
+pub trait SortedExt: Iterator + Sized
+where
+    Self::Item: AsRef<[u8]>,
+{
+    /// Collect and sort the records as `sort_records` would
+    fn rsorted(self, config: &Config) -> vec::IntoIter<Self::Item> {
+        let mut records: Vec<Self::Item> = self.collect();
+        sort_records(&mut records, config);
+        records.into_iter()
+    }
+
+    /// Like `rsorted`, then keep the first of each run of equal records (-u)
+    ///
+    /// `config.unique` is implied, so the last-resort comparison is off.
+    fn rsorted_unique(self, config: &Config) -> vec::IntoIter<Self::Item> {
+        let config = Config {
+            unique: true,
+            ..config.clone()
+        };
+        let comparator = Comparator::new(&config);
+        let mut records: Vec<Self::Item> = self.collect();
+        sort_with(&mut records, &comparator);
+        let kept: Vec<Self::Item> = output::unique(records, &comparator).collect();
+        kept.into_iter()
     }
 }
 
+impl<I> SortedExt for I
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::key::KeySpec;
-
-    fn test_config() -> Config {
-        Config {
-            reverse: false,
-            numeric: false,
-            fold_case: false,
-            unique: false,
-            stable: false,
-            debug: false,
-            record_delimiter: b'\n',
-            field_separator: None,
-            keys: vec![],
-            output_file: None,
-            input_files: vec![],
-        }
-    }
 
     #[test]
     fn test_basic_sort() {
         let mut records: Vec<Vec<u8>> = vec![b"c".to_vec(), b"a".to_vec(), b"b".to_vec()];
-        let config = test_config();
+        let config = Config::default();
         sort_records(&mut records, &config);
         assert_eq!(records, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
     }
@@ -45,8 +188,7 @@ mod tests {
     #[test]
     fn test_numeric_sort() {
         let mut records: Vec<Vec<u8>> = vec![b"10".to_vec(), b"2".to_vec(), b"1".to_vec()];
-        let mut config = test_config();
-        config.numeric = true;
+        let config = Config::builder().numeric(true).build().unwrap();
         sort_records(&mut records, &config);
         assert_eq!(records, vec![b"1".to_vec(), b"2".to_vec(), b"10".to_vec()]);
     }
@@ -54,8 +196,7 @@ mod tests {
     #[test]
     fn test_reverse_sort() {
         let mut records: Vec<Vec<u8>> = vec![b"a".to_vec(), b"c".to_vec(), b"b".to_vec()];
-        let mut config = test_config();
-        config.reverse = true;
+        let config = Config::builder().reverse(true).build().unwrap();
         sort_records(&mut records, &config);
         assert_eq!(records, vec![b"c".to_vec(), b"b".to_vec(), b"a".to_vec()]);
     }
@@ -63,8 +204,7 @@ mod tests {
     #[test]
     fn test_fold_case_sort() {
         let mut records: Vec<Vec<u8>> = vec![b"B".to_vec(), b"a".to_vec(), b"A".to_vec(), b"b".to_vec()];
-        let mut config = test_config();
-        config.fold_case = true;
+        let config = Config::builder().fold_case(true).build().unwrap();
         sort_records(&mut records, &config);
         // With -f and last-resort: A < B < a < b (uppercase before lowercase)
         // Because A == a on key, last-resort sees A (0x41) < a (0x61)
@@ -81,13 +221,114 @@ mod tests {
             b"y 1".to_vec(),
             b"z 2".to_vec(),
         ];
-        let mut config = test_config();
-        config.numeric = true;
-        config.keys = vec![KeySpec::parse("2,2").unwrap()];
+        let config = Config::builder().numeric(true).add_key("2,2").build().unwrap();
         sort_records(&mut records, &config);
         assert_eq!(
             records,
             vec![b"y 1".to_vec(), b"z 2".to_vec(), b"x 3".to_vec()]
         );
     }
+
+    #[test]
+    fn test_sort_borrowed_slices() {
+        let data = b"b 2\na 3\nc 1".to_vec();
+        let mut records: Vec<&[u8]> = data.split(|&b| b == b'\n').collect();
+        let config = Config::builder().numeric(true).add_key("2,2").build().unwrap();
+        sort_records(&mut records, &config);
+        assert_eq!(records, vec![&b"c 1"[..], b"b 2", b"a 3"]);
+    }
+
+    #[test]
+    fn test_sort_boxed_slices() {
+        let mut records: Vec<Box<[u8]>> = vec![b"b".as_slice().into(), b"a".as_slice().into()];
+        sort_records(&mut records, &Config::default());
+        assert_eq!(&*records[0], b"a");
+        assert_eq!(&*records[1], b"b");
+    }
+
+    #[test]
+    fn test_sort_counting_matches_sort_with() {
+        let records: Vec<Vec<u8>> = (0..50u8).rev().map(|i| vec![i]).collect();
+        let mut counted = records.clone();
+        let comparisons = sort_counting(&mut counted, &Comparator::new(&Config::default()));
+        let mut sorted = records;
+        sort_records(&mut sorted, &Config::default());
+        assert_eq!(counted, sorted);
+        assert!(comparisons >= 49);
+    }
+
+    #[test]
+    fn test_sort_indices_matches_in_place() {
+        let records: Vec<Vec<u8>> = vec![
+            b"B 2".to_vec(),
+            b"a 10".to_vec(),
+            b"b 1".to_vec(),
+            b"A 2".to_vec(),
+            b"a 10".to_vec(),
+        ];
+        for config in [
+            Config::default(),
+            Config::builder().fold_case(true).build().unwrap(),
+            Config::builder().numeric(true).add_key("2,2").stable(true).build().unwrap(),
+        ] {
+            let perm = sort_indices(&records, &config);
+            let mut sorted = records.clone();
+            sort_records(&mut sorted, &config);
+            let permuted: Vec<Vec<u8>> =
+                perm.iter().map(|&i| records[i as usize].clone()).collect();
+            assert_eq!(permuted, sorted);
+            assert_eq!(records[0], b"B 2"); // untouched
+        }
+    }
+
+    #[test]
+    fn test_sort_indices_stable_keeps_input_order() {
+        let records = [b"x 1".as_slice(), b"y 0", b"z 1"];
+        let config = Config::builder().add_key("2,2").stable(true).build().unwrap();
+        assert_eq!(sort_indices(&records, &config), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_rsorted_adaptor() {
+        let config = Config::builder().numeric(true).build().unwrap();
+        let sorted: Vec<Vec<u8>> = [b"10".to_vec(), b"9".to_vec(), b"-1".to_vec()]
+            .into_iter()
+            .rsorted(&config)
+            .collect();
+        assert_eq!(sorted, vec![b"-1".to_vec(), b"9".to_vec(), b"10".to_vec()]);
+    }
+
+    #[test]
+    fn test_rsorted_unique_by_key() {
+        let config = Config::builder().add_key("1,1").build().unwrap();
+        let records = [&b"b 1"[..], b"a 2", b"b 0", b"a 1"];
+        let kept: Vec<&[u8]> = records.into_iter().rsorted_unique(&config).collect();
+        // First of each run in input order, as -u keeps
+        assert_eq!(kept, vec![&b"a 2"[..], b"b 1"]);
+    }
+
+    #[test]
+    fn test_sort_keyed() {
+        let keys: Vec<Vec<u8>> = [&b"3"[..], b"1", b"2", b"1", b"0"].iter().map(|key| key.to_vec()).collect();
+        let comparator = Comparator::new(&Config::default());
+        let mut records = [&b"c"[..], b"b", b"e", b"a", b"d"];
+        let (kept, comparisons) = sort_keyed(&mut records, &keys, &comparator, false);
+        // Equal keys fall back to the records
+        assert_eq!(records, [&b"d"[..], b"a", b"b", b"e", b"c"]);
+        assert_eq!(kept, 5);
+        assert!(comparisons > 0);
+
+        let comparator = Comparator::new(&Config::builder().unique(true).build().unwrap());
+        let mut records = [&b"c"[..], b"b", b"e", b"a", b"d"];
+        let (kept, _) = sort_keyed(&mut records, &keys, &comparator, true);
+        assert_eq!(records[..kept], [&b"d"[..], b"b", b"e", b"c"]);
+        assert_eq!(records[kept..], [&b"a"[..]]);
+    }
+
+    #[test]
+    fn test_permute() {
+        let mut items = ['a', 'b', 'c', 'd', 'e', 'f'];
+        permute(&mut items, vec![2, 0, 1, 3, 5, 4]);
+        assert_eq!(items, ['c', 'a', 'b', 'd', 'f', 'e']);
+    }
 }
@@ -0,0 +1,482 @@
+//! Builder-style entry point for embedding rsort in other programs
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::io::{self, BufRead, Write};
+use std::time::Instant;
+
+use crate::arena::Arena;
+use crate::compare::{Comparator, KeyCompare};
+use crate::config::Config;
+use crate::error::{Result, RsortError};
+use crate::fields::FieldSeparator;
+use crate::input::{self, RecordReader};
+use crate::json::JsonPointer;
+use crate::key::{self, ByteRange, KeySpec, SortMode};
+use crate::key_command;
+use crate::output::{self, Numbered, OutputRecord};
+use crate::sort;
+use crate::stats::{CountingWriter, SortStats};
+use crate::trace::{trace_event, trace_span};
+
+/// Sorts records with GNU sort semantics (`LC_ALL=C`)
+///
+/// Switching on more than one of `numeric`, `human_numeric`, `month` and
+/// `version` makes the sort calls fail, as GNU sort rejects `-nM`.
+///
+/// ```
+/// use rsort::Sorter;
+///
+/// let sorter = Sorter::new()
+///     .numeric(true)
+///     .key("2,2")?
+///     .field_separator(b',')
+///     .reverse(true);
+///
+/// let sorted = sorter.sort_bytes(b"a,1\nb,10\nc,2\n")?;
+/// assert_eq!(sorted, b"b,10\nc,2\na,1\n");
+/// # Ok::<(), rsort::RsortError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Sorter {
+    config: Config,
+    /// Count comparisons into SortStats
+    collect_stats: bool,
+    /// Each of -h, -M, -n and -V switched on; the sort calls reject more
+    /// than one, as `ConfigBuilder::build` does
+    modes: Vec<SortMode>,
+}
+
+impl Sorter {
+    /// Plain bytewise sort of newline-delimited records
+    pub fn new() -> Self {
+        Sorter {
+            config: Config::default(),
+            collect_stats: false,
+            modes: Vec::new(),
+        }
+    }
+
+    /// Use an existing configuration (e.g. one built from CLI arguments)
+    pub fn from_config(config: Config) -> Self {
+        let modes = match config.sort_mode {
+            SortMode::Text => Vec::new(),
+            mode => vec![mode],
+        };
+        Sorter {
+            config,
+            collect_stats: false,
+            modes,
+        }
+    }
+
+    /// Configuration the sorter runs with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Reverse the result of comparisons (-r)
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.config.reverse = reverse;
+        self
+    }
+
+    /// Compare according to numerical value (-n)
+    pub fn numeric(self, numeric: bool) -> Self {
+        self.sort_mode(SortMode::Numeric, numeric)
+    }
+
+    /// Fold lower case to upper case (-f)
+    pub fn fold_case(mut self, fold_case: bool) -> Self {
+        self.config.fold_case = fold_case;
+        self
+    }
+
+    /// Compare numbers with SI suffixes such as 2K or 1.5G (-h)
+    pub fn human_numeric(self, human_numeric: bool) -> Self {
+        self.sort_mode(SortMode::HumanNumeric, human_numeric)
+    }
+
+    /// Compare month abbreviations (-M)
+    pub fn month(self, month: bool) -> Self {
+        self.sort_mode(SortMode::Month, month)
+    }
+
+    /// Compare version strings (-V)
+    pub fn version(self, version: bool) -> Self {
+        self.sort_mode(SortMode::Version, version)
+    }
+
+    /// Switch one of -h, -M, -n and -V on or off
+    ///
+    /// Switching on a second one is not an error until sorting, which fails
+    /// as GNU does for `-nM`.
+    fn sort_mode(mut self, mode: SortMode, on: bool) -> Self {
+        self.modes.retain(|&m| m != mode);
+        if on {
+            self.modes.push(mode);
+        }
+        self.config.sort_mode = self.modes.last().copied().unwrap_or(SortMode::Text);
+        self
+    }
+
+    /// Fail if more than one of -h, -M, -n and -V is on
+    fn check_modes(&self) -> Result<()> {
+        match self.modes[..] {
+            [] | [_] => Ok(()),
+            _ => Err(key::incompatible_modes(self.config.fold_case, &self.modes)),
+        }
+    }
+
+    /// Output only the first of each run of equal records (-u)
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.config.unique = unique;
+        self
+    }
+
+    /// Disable last-resort comparison (-s)
+    pub fn stable(mut self, stable: bool) -> Self {
+        self.config.stable = stable;
+        self
+    }
+
+    /// Byte that terminates records (newline by default, NUL for -z)
+    pub fn record_delimiter(mut self, delimiter: u8) -> Self {
+        self.config.record_delimiter = delimiter;
+        self.config.record_delimiter_prefix.clear();
+        self
+    }
+
+    /// Read records of exactly `size` bytes instead of delimited ones
+    /// (--record-size), writing them back to back
+    pub fn record_size(mut self, size: usize) -> Self {
+        self.config.record_size = Some(size);
+        self
+    }
+
+    /// Split fields on this byte or char instead of blank runs (-t)
+    pub fn field_separator(mut self, separator: impl Into<FieldSeparator>) -> Self {
+        self.config.field_separator = Some(separator.into());
+        self
+    }
+
+    /// Add a sort key in GNU keydef syntax (-k), e.g. `"2,2"` or `"1.3,1.5"`
+    pub fn key(mut self, keydef: &str) -> Result<Self> {
+        self.config.keys.push(KeySpec::parse(keydef)?);
+        Ok(self)
+    }
+
+    /// Add a key from JSON Lines records (--json-key): the value at an
+    /// RFC 6901 pointer such as `"/user/id"`, after any `key`s
+    pub fn json_key(mut self, pointer: &str) -> Result<Self> {
+        self.config.json_keys.push(JsonPointer::parse(pointer)?);
+        Ok(self)
+    }
+
+    /// Add a key at byte positions (--key-bytes), `"START[,END]"` counting
+    /// from 1, after any `key`s and `json_key`s
+    pub fn key_bytes(mut self, range: &str) -> Result<Self> {
+        self.config.key_bytes.push(ByteRange::parse(range)?);
+        Ok(self)
+    }
+
+    /// Compare keys (or whole lines, with no keys) using `compare`
+    ///
+    /// Replaces the -n/-f/bytewise key comparison only: keys are still
+    /// extracted and tried in order, -r still reverses, ties still fall back
+    /// to the bytewise last-resort unless -s or -u, and -u drops records the
+    /// closure calls equal. The same comparison is used when the config is
+    /// passed to `check` or `merge`.
+    pub fn compare_keys_with<F>(mut self, compare: F) -> Self
+    where
+        F: Fn(&[u8], &[u8]) -> Ordering + Send + Sync + 'static,
+    {
+        self.config.key_compare = Some(KeyCompare::new(compare));
+        self
+    }
+
+    /// Count comparisons in the returned `SortStats` (off by default)
+    ///
+    /// The other counters and timings are always filled in; counting
+    /// comparisons wraps the comparator, so it is opt-in.
+    pub fn collect_stats(mut self, collect_stats: bool) -> Self {
+        self.collect_stats = collect_stats;
+        self
+    }
+
+    /// Sort records in place
+    pub fn sort_records<T: AsRef<[u8]>>(&self, records: &mut [T]) -> Result<()> {
+        self.check_modes()?;
+        sort::sort_with(records, &Comparator::new(&self.config));
+        Ok(())
+    }
+
+    /// Sorted order of `records` as indices, without moving them
+    pub fn sort_indices<T: AsRef<[u8]>>(&self, records: &[T]) -> Result<Vec<u32>> {
+        self.check_modes()?;
+        Ok(sort::indices_with(records, &Comparator::new(&self.config)))
+    }
+
+    /// Write sorted records, applying -u, and return how many were written
+    pub fn write_records<W: Write>(&self, records: &[Vec<u8>], writer: W) -> Result<u64> {
+        Ok(output::write_records(writer, records, &self.config, true)?)
+    }
+
+    /// Sort a delimited in-memory buffer, returning the sorted buffer
+    ///
+    /// Like GNU sort, every output record is terminated by the delimiter,
+    /// unless `preserve_final_newline` is set and `input` lacks a final one.
+    /// `max_record_size` is not applied: the input is already in memory.
+    /// Fails on input that cannot be split into records (a partial record
+    /// with `record_size`, broken `framing`), a `--key-name` column missing
+    /// from the header, or a record the output cannot hold, such as one
+    /// containing the `output_delimiter`.
+    pub fn sort_bytes(&self, input: &[u8]) -> Result<Vec<u8>> {
+        self.check_modes()?;
+        let mut sorted = Vec::with_capacity(input.len() + 1);
+        let mut arena = Arena::new();
+        let mut reader = RecordReader::new(input, self.config.record_delimiter)
+            .delimiter_prefix(&self.config.record_delimiter_prefix)
+            .record_size(self.config.record_size)
+            .framing(self.config.framing)
+            .take_records(self.config.take)
+            .strip_bom(self.config.strip_bom);
+        let had_trailing = reader.read_into(&mut arena)?;
+        if self.config.keep_bom && reader.stripped_bom() {
+            sorted.extend_from_slice(input::UTF8_BOM);
+        }
+        let sorter = self.with_key_names(arena.records_mut())?;
+        let final_delimiter = self.config.final_delimiter(had_trailing);
+        sorter.sort_and_write(arena.records_mut(), &mut sorted, final_delimiter)?;
+        Ok(sorted)
+    }
+
+    /// Read all records from `reader`, sort them, and write them to `writer`
+    pub fn sort_reader_to_writer<R: BufRead, W: Write>(
+        &self,
+        reader: R,
+        mut writer: W,
+    ) -> Result<SortStats> {
+        self.check_modes()?;
+        let started = Instant::now();
+        let mut arena = Arena::new();
+        let mut reader = RecordReader::new(reader, self.config.record_delimiter)
+            .delimiter_prefix(&self.config.record_delimiter_prefix)
+            .max_record_size(self.config.max_record_size)
+            .record_size(self.config.record_size)
+            .framing(self.config.framing)
+            .take_records(self.config.take)
+            .strip_bom(self.config.strip_bom);
+        let had_trailing = reader.read_into(&mut arena)?;
+        let mut records = arena.into_records();
+        let read_time = started.elapsed();
+
+        let bom = if self.config.keep_bom && reader.stripped_bom() { input::UTF8_BOM } else { &[] };
+        writer.write_all(bom)?;
+        let final_delimiter = self.config.final_delimiter(had_trailing);
+        let mut stats = self
+            .with_key_names(&records)?
+            .sort_and_write(&mut records, writer, final_delimiter)?;
+        stats.bytes_written += bom.len() as u64;
+        stats.read_time = read_time;
+        Ok(stats)
+    }
+
+    /// This sorter with `key_names` resolved against the header of `records`
+    fn with_key_names<T: AsRef<[u8]>>(&self, records: &[T]) -> Result<Cow<'_, Sorter>> {
+        Ok(match self.config.resolve_key_names(records)? {
+            Some(config) => Cow::Owned(Sorter {
+                config,
+                collect_stats: self.collect_stats,
+                modes: self.modes.clone(),
+            }),
+            None => Cow::Borrowed(self),
+        })
+    }
+
+    /// Sort records already in memory and write them, applying -u
+    ///
+    /// Fills every `SortStats` field except the read time, which only the
+    /// caller knows. `key_names` are not looked at; resolve them first with
+    /// `Config::resolve_key_names`.
+    pub fn sort_and_write<T: AsRef<[u8]>, W: Write>(
+        &self,
+        records: &mut [T],
+        writer: W,
+        add_trailing: bool,
+    ) -> io::Result<SortStats> {
+        if self.config.number {
+            let mut numbered = numbered(records);
+            return self.sort_and_write_as(&mut numbered, |record| *record, writer, add_trailing);
+        }
+        self.sort_and_write_as(records, |record| record.as_ref(), writer, add_trailing)
+    }
+
+    /// `sort_and_write`, with the output divided across `writers` as
+    /// `output::write_split` does (--split-output)
+    pub fn sort_and_split<T: AsRef<[u8]>, W: Write>(
+        &self,
+        records: &mut [T],
+        writers: &mut [W],
+        add_trailing: bool,
+    ) -> io::Result<SortStats> {
+        if self.config.number {
+            let mut numbered = numbered(records);
+            return self.sort_and_split_as(&mut numbered, |record| *record, writers, add_trailing);
+        }
+        self.sort_and_split_as(records, |record| record.as_ref(), writers, add_trailing)
+    }
+
+    /// `sort_and_write`, writing each record as `item` makes it
+    fn sort_and_write_as<'a, T, R, W>(
+        &self,
+        records: &'a mut [T],
+        item: impl Fn(&'a T) -> R,
+        writer: W,
+        add_trailing: bool,
+    ) -> io::Result<SortStats>
+    where
+        T: AsRef<[u8]>,
+        R: OutputRecord,
+        W: Write,
+    {
+        let (mut stats, preserved, end) = self.arrange(records)?;
+
+        trace_span!("write");
+        let started = Instant::now();
+        let mut writer = CountingWriter::new(writer);
+        let (preserved, records) = records[..end].split_at(preserved);
+        let preserved = preserved.iter().map(&item);
+        let records = records.iter().map(&item);
+        stats.records_written =
+            output::write_records_after(&mut writer, preserved, records, &self.output_config(), add_trailing)?;
+        stats.bytes_written = writer.bytes;
+        stats.write_time = started.elapsed();
+        trace_event!(
+            records = stats.records_written,
+            bytes = stats.bytes_written,
+            elapsed = ?stats.write_time,
+            "written"
+        );
+
+        stats.duplicates_removed = stats.records_read - stats.records_written;
+        Ok(stats)
+    }
+
+    /// `sort_and_split`, writing each record as `item` makes it
+    fn sort_and_split_as<'a, T, R, W>(
+        &self,
+        records: &'a mut [T],
+        item: impl Fn(&'a T) -> R,
+        writers: &mut [W],
+        add_trailing: bool,
+    ) -> io::Result<SortStats>
+    where
+        T: AsRef<[u8]>,
+        R: OutputRecord,
+        W: Write,
+    {
+        let (mut stats, preserved, end) = self.arrange(records)?;
+
+        trace_span!("write", chunks = writers.len());
+        let started = Instant::now();
+        let mut writers: Vec<_> = writers.iter_mut().map(CountingWriter::new).collect();
+        let (preserved, records) = records[..end].split_at(preserved);
+        let preserved = preserved.iter().map(&item);
+        let records = records.iter().map(&item);
+        stats.records_written =
+            output::write_split(&mut writers, preserved, records, &self.output_config(), add_trailing)?;
+        stats.bytes_written = writers.iter().map(|writer| writer.bytes).sum();
+        stats.write_time = started.elapsed();
+        trace_event!(
+            records = stats.records_written,
+            bytes = stats.bytes_written,
+            elapsed = ?stats.write_time,
+            "written"
+        );
+
+        stats.duplicates_removed = stats.records_read - stats.records_written;
+        Ok(stats)
+    }
+
+    /// Put `records` in output order, returning the stats so far, how
+    /// many leading records -u must leave alone, and how many to write
+    ///
+    /// --header records stay in place, then --comment-char records follow
+    /// in input order; the rest are sorted. With --key-command, -u is
+    /// applied here, on the command's keys, and the duplicates are left
+    /// past the records to write.
+    fn arrange<T: AsRef<[u8]>>(&self, records: &mut [T]) -> io::Result<(SortStats, usize, usize)> {
+        self.check_modes().map_err(RsortError::into_io)?;
+        trace_span!("sort", records = records.len());
+        let comparator = Comparator::new(&self.config);
+        let mut stats = SortStats {
+            records_read: records.len() as u64,
+            bytes_read: records.iter().map(|r| r.as_ref().len() as u64 + 1).sum(),
+            ..SortStats::default()
+        };
+
+        let started = Instant::now();
+        let header = self.config.header.min(records.len());
+        let records = &mut records[header..];
+        let comments = match self.config.comment_char {
+            Some(c) => {
+                let is_comment = |record: &T| record.as_ref().first() == Some(&c);
+                records.sort_by_key(|record| !is_comment(record));
+                records.iter().take_while(|record| is_comment(record)).count()
+            }
+            None => 0,
+        };
+        let preserved = header + comments;
+        let records = &mut records[comments..];
+        if let Some(command) = &self.config.key_command {
+            let lines: Vec<&[u8]> = records.iter().map(AsRef::as_ref).collect();
+            let keys = key_command::keys(command, &lines, &self.config).map_err(RsortError::into_io)?;
+            let (kept, comparisons) = sort::sort_keyed(records, &keys, &comparator, self.config.unique);
+            stats.comparisons = if self.collect_stats { comparisons } else { 0 };
+            stats.sort_time = started.elapsed();
+            trace_event!(header, comments, elapsed = ?stats.sort_time, "sorted by --key-command");
+            return Ok((stats, preserved, preserved + kept));
+        }
+        if self.collect_stats {
+            stats.comparisons = sort::sort_counting(records, &comparator);
+        } else {
+            sort::sort_with(records, &comparator);
+        }
+        stats.sort_time = started.elapsed();
+        // Sorting is in memory: nothing is spilled to disk
+        trace_event!(header, comments, elapsed = ?stats.sort_time, "sorted");
+        Ok((stats, preserved, preserved + records.len()))
+    }
+
+    /// The config records are written with: with --key-command, without
+    /// -u, which `arrange` has already applied
+    fn output_config(&self) -> Cow<'_, Config> {
+        if self.config.key_command.is_some() && self.config.unique {
+            Cow::Owned(Config {
+                unique: false,
+                ..self.config.clone()
+            })
+        } else {
+            Cow::Borrowed(&self.config)
+        }
+    }
+}
+
+impl Default for Sorter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `records` with their 1-based positions, for --number
+fn numbered<T: AsRef<[u8]>>(records: &[T]) -> Vec<Numbered<&[u8]>> {
+    records
+        .iter()
+        .zip(1..)
+        .map(|(record, number)| Numbered {
+            number,
+            record: record.as_ref(),
+        })
+        .collect()
+}
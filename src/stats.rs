@@ -0,0 +1,150 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Counters describing what a sort run did
+///
+/// Comparisons are only counted when asked for (`Sorter::collect_stats`);
+/// otherwise `comparisons` stays 0 and sorting runs uninstrumented.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SortStats {
+    /// Records read from all inputs
+    pub records_read: u64,
+    /// Records read from each input, in order; filled in by `sort_files`
+    pub input_records: Vec<(PathBuf, u64)>,
+    /// Input bytes, counting one delimiter per record
+    pub bytes_read: u64,
+    /// Records written to the output (fewer than read when -u removed duplicates)
+    pub records_written: u64,
+    /// Output bytes, delimiters included
+    pub bytes_written: u64,
+    /// Records dropped by -u
+    pub duplicates_removed: u64,
+    /// Record comparisons made while sorting
+    pub comparisons: u64,
+    /// Sorted runs spilled to temporary files (sorting is in memory, so 0)
+    pub runs_spilled: u64,
+    /// Spilled runs merged back together (0, as none are spilled)
+    pub runs_merged: u64,
+    /// Record bytes held in memory at the peak; filled in by `sort_files`
+    pub arena_bytes: u64,
+    /// Time spent reading input
+    pub read_time: Duration,
+    /// Time spent sorting
+    pub sort_time: Duration,
+    /// Time spent writing output
+    pub write_time: Duration,
+}
+
+/// How --stats prints `SortStats`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// A few lines for people
+    Text,
+    /// One JSON object
+    Json,
+}
+
+impl StatsFormat {
+    /// Parse a --stats argument: `text` or `json`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(StatsFormat::Text),
+            "json" => Some(StatsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl SortStats {
+    /// Write these counters as --stats does, ending with a newline
+    pub fn write_summary<W: Write>(&self, mut writer: W, format: StatsFormat) -> io::Result<()> {
+        match format {
+            StatsFormat::Text => {
+                writeln!(writer, "rsort: stats:")?;
+                for (path, records) in &self.input_records {
+                    writeln!(writer, "  input {}: {} records", path.display(), records)?;
+                }
+                writeln!(writer, "  read: {} records, {} bytes", self.records_read, self.bytes_read)?;
+                writeln!(writer, "  written: {} records, {} bytes", self.records_written, self.bytes_written)?;
+                writeln!(writer, "  duplicates removed: {}", self.duplicates_removed)?;
+                writeln!(writer, "  comparisons: {}", self.comparisons)?;
+                writeln!(writer, "  runs: {} spilled, {} merged", self.runs_spilled, self.runs_merged)?;
+                writeln!(writer, "  peak memory: {} bytes", self.arena_bytes)?;
+                writeln!(
+                    writer,
+                    "  time: read {:.3}s, sort {:.3}s, write {:.3}s",
+                    self.read_time.as_secs_f64(),
+                    self.sort_time.as_secs_f64(),
+                    self.write_time.as_secs_f64()
+                )
+            }
+            StatsFormat::Json => {
+                write!(writer, "{{\"inputs\":[")?;
+                for (i, (path, records)) in self.input_records.iter().enumerate() {
+                    let comma = if i > 0 { "," } else { "" };
+                    write!(writer, "{}{{\"path\":", comma)?;
+                    write_json_string(&mut writer, &path.to_string_lossy())?;
+                    write!(writer, ",\"records\":{}}}", records)?;
+                }
+                writeln!(
+                    writer,
+                    "],\"records_read\":{},\"bytes_read\":{},\"records_written\":{},\"bytes_written\":{},\
+                     \"duplicates_removed\":{},\"comparisons\":{},\"runs_spilled\":{},\"runs_merged\":{},\
+                     \"arena_bytes\":{},\"read_seconds\":{},\"sort_seconds\":{},\"write_seconds\":{}}}",
+                    self.records_read,
+                    self.bytes_read,
+                    self.records_written,
+                    self.bytes_written,
+                    self.duplicates_removed,
+                    self.comparisons,
+                    self.runs_spilled,
+                    self.runs_merged,
+                    self.arena_bytes,
+                    self.read_time.as_secs_f64(),
+                    self.sort_time.as_secs_f64(),
+                    self.write_time.as_secs_f64()
+                )
+            }
+        }
+    }
+}
+
+/// `text` as a quoted JSON string
+pub(crate) fn write_json_string<W: Write>(writer: &mut W, text: &str) -> io::Result<()> {
+    write!(writer, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+/// Writer that counts the bytes passed through it
+pub(crate) struct CountingWriter<W> {
+    inner: W,
+    pub(crate) bytes: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        CountingWriter { inner, bytes: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,26 @@
+//! Internal logging (feature `trace`): `tracing` spans around each phase
+//! and events for what it did, all compiled away without the feature
+//!
+//! Nothing is logged until a subscriber is installed, as the binary does
+//! for `--log-level`.
+
+/// Enter an info-level span, like `tracing::info_span!`, until the end of
+/// the enclosing block
+macro_rules! trace_span {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!($($arg)+).entered();
+    };
+}
+
+/// A debug-level event, like `tracing::debug!`
+///
+/// Without the feature the arguments are not evaluated at all.
+macro_rules! trace_event {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "trace")]
+        tracing::debug!($($arg)+);
+    };
+}
+
+pub(crate) use {trace_event, trace_span};
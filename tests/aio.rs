@@ -0,0 +1,129 @@
+//! Async reading and merging through tokio duplex pipes
+#![cfg(feature = "async")]
+
+use std::future::Future;
+use std::io::{BufRead, Cursor};
+
+use rsort::{aio, merge, Config, RsortError, Sorter};
+use tokio::io::{AsyncWriteExt, BufReader, DuplexStream};
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// A pipe whose far end is fed `data` in small chunks by a spawned task
+fn pipe(data: &'static [u8]) -> BufReader<DuplexStream> {
+    let (mut tx, rx) = tokio::io::duplex(8);
+    tokio::spawn(async move {
+        for chunk in data.chunks(3) {
+            tx.write_all(chunk).await.unwrap();
+        }
+    });
+    BufReader::new(rx)
+}
+
+#[test]
+fn test_read_all_records_over_pipe() {
+    let (records, had_trailing) = block_on(async {
+        aio::read_all_records(pipe(b"b\nlonger record\n\na"), b'\n').await.unwrap()
+    });
+    let expected: [&[u8]; 4] = [b"b", b"longer record", b"", b"a"];
+    assert_eq!(records, expected);
+    assert!(!had_trailing);
+
+    let (_, had_trailing) = block_on(async { aio::read_all_records(pipe(b"b\na\n"), b'\n').await.unwrap() });
+    assert!(had_trailing);
+}
+
+#[test]
+fn test_sort_matches_sync() {
+    const INPUT: &[u8] = b"b 10\nA 2\na 1\nc 10\nB 2\n";
+    for sorter in [
+        Sorter::new(),
+        Sorter::new().fold_case(true).unique(true),
+        Sorter::new().numeric(true).key("2,2").unwrap().reverse(true),
+    ] {
+        let mut output = Vec::new();
+        let stats = block_on(async {
+            aio::sort_reader_to_writer(&sorter, pipe(INPUT), &mut output).await.unwrap()
+        });
+        assert_eq!(output, sorter.sort_bytes(INPUT).unwrap());
+        assert_eq!(stats.records_read, 5);
+    }
+}
+
+#[test]
+fn test_merge_matches_sync() {
+    let inputs: [&'static [u8]; 3] = [b"a\nc\ne\n", b"B\nb\nd", b""];
+    for config in [
+        Config::default(),
+        Config::builder().fold_case(true).unique(true).build().unwrap(),
+    ] {
+        let mut expected = Vec::new();
+        let sources: Vec<Box<dyn BufRead>> = inputs
+            .iter()
+            .map(|input| Box::new(Cursor::new(*input)) as Box<dyn BufRead>)
+            .collect();
+        let sync_stats = merge::merge_streams(sources, &config, &mut expected).unwrap();
+
+        let mut output = Vec::new();
+        let stats = block_on(async {
+            let sources = inputs.iter().map(|input| pipe(input)).collect();
+            aio::merge_streams(sources, &config, &mut output).await.unwrap()
+        });
+        assert_eq!(output, expected);
+        assert_eq!(stats, sync_stats);
+    }
+}
+
+#[test]
+fn test_crlf_matches_sync() {
+    // Three-byte pipe chunks split some "\r\n" pairs
+    const INPUT: &[u8] = b"bb\r\na\rz\r\nc\nd\r\nab";
+    let config = Config::builder().record_delimiter_bytes(b"\r\n").build().unwrap();
+    let sorter = Sorter::from_config(config.clone());
+    let mut output = Vec::new();
+    block_on(async {
+        aio::sort_reader_to_writer(&sorter, pipe(INPUT), &mut output).await.unwrap()
+    });
+    assert_eq!(output, b"a\rz\r\nab\r\nbb\r\nc\nd\r\n");
+    assert_eq!(output, sorter.sort_bytes(INPUT).unwrap());
+
+    let mut merged = Vec::new();
+    block_on(async {
+        let sources = vec![pipe(b"a\r\nc\r\n"), pipe(b"b\rx\r\nd")];
+        aio::merge_streams(sources, &config, &mut merged).await.unwrap()
+    });
+    assert_eq!(merged, b"a\r\nb\rx\r\nc\r\nd\r\n");
+}
+
+#[test]
+fn test_max_record_size_matches_sync() {
+    const INPUT: &[u8] = b"ab\r\nabc\r\nabcd\r\nx";
+    let config = Config::builder()
+        .record_delimiter_bytes(b"\r\n")
+        .max_record_size(3)
+        .build()
+        .unwrap();
+    let sorter = Sorter::from_config(config.clone());
+    let err = block_on(async { aio::sort_reader_to_writer(&sorter, pipe(INPUT), Vec::new()).await.unwrap_err() });
+    assert!(matches!(err, RsortError::RecordTooLarge { offset: 9, limit: 3, .. }), "{err}");
+
+    let err = block_on(async {
+        let sources = vec![pipe(b"a\r\n"), pipe(b"b\r\nlonger")];
+        aio::merge_streams(sources, &config, Vec::new()).await.unwrap_err()
+    });
+    assert!(matches!(err, RsortError::RecordTooLarge { offset: 3, limit: 3, .. }), "{err}");
+
+    // Three-byte pipe chunks end at "abc\r", still within the limit until
+    // the "\n" shows where the record ends
+    let mut output = Vec::new();
+    block_on(async {
+        let sources = vec![pipe(b"abc\r\n")];
+        aio::merge_streams(sources, &config, &mut output).await.unwrap()
+    });
+    assert_eq!(output, b"abc\r\n");
+}
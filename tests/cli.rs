@@ -0,0 +1,172 @@
+//! The command line itself: operands, RSORT_OPTS, completions and the man page
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[cfg(windows)]
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_file_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join(OsStr::from_bytes(b"in-\xff.txt"));
+    let out = dir.path().join(OsStr::from_bytes(b"out-\xfe.txt"));
+    fs::write(&input, b"b\na\n").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .arg("-o")
+        .arg(&out)
+        .arg(&input)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read(&out).unwrap(), b"a\nb\n");
+
+    let missing = dir.path().join(OsStr::from_bytes(b"missing-\xff"));
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).arg(&missing).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("missing-\u{fffd}"), "{}", stderr);
+}
+
+/// cmd.exe and PowerShell leave `*.txt` to rsort
+#[cfg(windows)]
+#[test]
+fn test_wildcard_operands() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "one.txt", b"c\n");
+    write(dir.path(), "two.txt", b"a\n");
+    write(dir.path(), "three.txt", b"b\nd\n");
+    write(dir.path(), "skipped.log", b"z\n");
+    let rsort = |operand: PathBuf| {
+        Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .arg(operand)
+            .output()
+            .unwrap()
+    };
+
+    let output = rsort(dir.path().join("*.txt"));
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"a\nb\nc\nd\n");
+
+    let pattern = dir.path().join("*.csv");
+    let output = rsort(pattern.clone());
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("rsort: no files match '{}'\n", pattern.display())
+    );
+
+    // After `--` it is a file name, which cannot be opened
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .arg("--")
+        .arg(&pattern)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("rsort: cannot read: "));
+}
+
+/// Paths past MAX_PATH (260) work as input and as the -o file
+#[cfg(windows)]
+#[test]
+fn test_long_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut deep = dir.path().to_path_buf();
+    while deep.as_os_str().len() <= 300 {
+        deep.push("d".repeat(40));
+    }
+    fs::create_dir_all(&deep).unwrap();
+    let input = write(&deep, "input.txt", b"b\nc\na\n");
+    let out = deep.join("output.txt");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .arg("-o")
+        .arg(&out)
+        .arg(&input)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read(&out).unwrap(), b"a\nb\nc\n");
+    // And -o replacing the input in place
+    assert_eq!(cli_sort(&[out.clone()], &["-r", "-o", out.to_str().unwrap()]), b"");
+    assert_eq!(fs::read(&out).unwrap(), b"c\nb\na\n");
+}
+
+#[test]
+fn test_generate_completions() {
+    for shell in ["bash", "zsh", "fish", "powershell"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .arg(format!("--generate-completions={}", shell))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", shell);
+        let script = String::from_utf8(output.stdout).unwrap();
+        for option in ["numeric-sort", "field-separator", "output-encoding", "decompress-program"] {
+            assert!(script.contains(option), "{}: {}", shell, option);
+        }
+        // Enumerated values are completed too
+        assert!(script.contains("utf-16le"), "{}", shell);
+    }
+
+    // Not an option anyone sees in --help
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).arg("--help").output().unwrap();
+    assert!(!String::from_utf8(output.stdout).unwrap().contains("completions"));
+}
+
+#[test]
+fn test_generate_man() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).arg("--generate-man").output().unwrap();
+    assert!(output.status.success());
+    let page = String::from_utf8(output.stdout).unwrap();
+    assert!(page.starts_with(".ie"), "{}", page);
+    assert!(page.contains(".SH OPTIONS"));
+}
+
+#[test]
+fn test_rsort_opts() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(dir.path(), "in.txt", b"b:2 x\na:10 y\nc:1 z\n");
+    let rsort = |env: &str, args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .env("RSORT_OPTS", env)
+            .args(args)
+            .arg(&input)
+            .output()
+            .unwrap()
+    };
+
+    let output = rsort("-n -t ':' -k2", &[]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"c:1 z\nb:2 x\na:10 y\n");
+    // The command line wins
+    let output = rsort("-t ':' -k2", &["-t", " ", "-r"]);
+    assert_eq!(output.stdout, b"c:1 z\na:10 y\nb:2 x\n");
+    let output = rsort("'-t '", &["-k2"]);
+    assert_eq!(output.stdout, b"b:2 x\na:10 y\nc:1 z\n");
+
+    let output = rsort("-o elsewhere.txt", &[]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "rsort: RSORT_OPTS cannot give --output\n");
+    assert!(!dir.path().join("elsewhere.txt").exists());
+}
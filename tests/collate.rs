@@ -0,0 +1,56 @@
+//! --locale collation against ICU's reference orderings
+#![cfg(feature = "collate")]
+
+use rsort::{Config, Sorter};
+
+const WORDS: &str = "zebra\nöga\napa\nåska\närlig\nÄrlig\nApa\n";
+
+fn sort(input: &str, config: Config) -> String {
+    String::from_utf8(Sorter::from_config(config).sort_bytes(input.as_bytes()).unwrap()).unwrap()
+}
+
+#[test]
+fn test_german_and_swedish_orders() {
+    // German puts ä, å and ö with a and o; lower case first on ties
+    let german = Config::builder().locale("de").build().unwrap();
+    assert_eq!(sort(WORDS, german), "apa\nApa\närlig\nÄrlig\nåska\nöga\nzebra\n");
+    // Swedish puts å, ä, ö after z, in that order
+    let swedish = Config::builder().locale("sv").build().unwrap();
+    assert_eq!(sort(WORDS, swedish), "apa\nApa\nzebra\nåska\närlig\nÄrlig\nöga\n");
+    // Bytewise, every non-ASCII word comes last
+    assert_eq!(sort(WORDS, Config::default()), "Apa\napa\nzebra\nÄrlig\närlig\nåska\nöga\n");
+}
+
+#[test]
+fn test_keys_reverse_and_unique() {
+    let input = "3 Äpfel\n1 Apfel\n2 Bär\n4 Baum\n5 apfel\n";
+    let by_word = |builder: rsort::ConfigBuilder| builder.locale("de").add_key("2,2").build().unwrap();
+    assert_eq!(
+        sort(input, by_word(Config::builder())),
+        "5 apfel\n1 Apfel\n3 Äpfel\n2 Bär\n4 Baum\n"
+    );
+    assert_eq!(
+        sort(input, by_word(Config::builder().reverse(true))),
+        "4 Baum\n2 Bär\n3 Äpfel\n1 Apfel\n5 apfel\n"
+    );
+
+    // Numeric keys ignore the locale
+    let numeric = Config::builder().locale("de").add_key("1,1n").build().unwrap();
+    assert_eq!(sort(input, numeric), "1 Apfel\n2 Bär\n3 Äpfel\n4 Baum\n5 apfel\n");
+
+    // -u drops only records the collator finds equal, not just alike
+    let unique = Config::builder().locale("de").unique(true).build().unwrap();
+    assert_eq!(sort("Bär\nbär\nBär\n", unique), "bär\nBär\n");
+}
+
+#[test]
+fn test_last_resort_is_bytewise() {
+    // "a\u{308}" (a + combining diaeresis) and "ä" collate equal; the
+    // last-resort comparison still orders them by bytes
+    let config = Config::builder().locale("de").build().unwrap();
+    assert_eq!(sort("\u{e4}\na\u{308}\n", config.clone()), "a\u{308}\n\u{e4}\n");
+    assert_eq!(sort("a\u{308}\n\u{e4}\n", config), "a\u{308}\n\u{e4}\n");
+
+    let err = Config::builder().locale("??").build().unwrap_err();
+    assert!(err.to_string().starts_with("invalid --locale '??'"), "{}", err);
+}
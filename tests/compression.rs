@@ -0,0 +1,192 @@
+//! Compressed inputs and outputs: gzip and zstd by extension or --decompress,
+//! and external programs
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rsort::Config;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(members: &[&[u8]]) -> Vec<u8> {
+    use std::io::Write;
+
+    // Concatenated members, as `cat a.gz b.gz` makes
+    let mut compressed = Vec::new();
+    for member in members {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(member).unwrap();
+        compressed.extend(encoder.finish().unwrap());
+    }
+    compressed
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let multi = write(dir.path(), "multi.gz", &gzip(&[b"d 4\nb 2\n", b"", b"f 6\na 1"]));
+    let plain = write(dir.path(), "plain.txt", b"e 5\nc 3\n");
+
+    let inputs = vec![multi.clone(), plain.clone()];
+    assert_eq!(cli_sort(&inputs, &[]), b"a 1\nb 2\nc 3\nd 4\ne 5\nf 6\n");
+    let out = dir.path().join("out.txt");
+    rsort::sort_files(&inputs, &out, &Config::default()).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), b"a 1\nb 2\nc 3\nd 4\ne 5\nf 6\n");
+
+    // Merge reads each input through its own decoder
+    let sorted = write(dir.path(), "sorted.gz", &gzip(&[b"b\nd\n", b"f\n"]));
+    let other = write(dir.path(), "other.txt", b"a\nc\ne\n");
+    assert_eq!(cli_sort(&[sorted, other], &["-m"]), b"a\nb\nc\nd\ne\nf\n");
+
+    // --decompress covers files without the extension, and stdin
+    let bare = vec![write(dir.path(), "bare", &gzip(&[b"y\0x\0"]))];
+    assert_eq!(cli_sort(&bare, &["--decompress=gzip", "-z"]), b"x\0y\0");
+    assert_eq!(cli_sort(&bare, &["-c", "--decompress=gzip", "-z", "-r"]), b"");
+    let in_place = vec![write(dir.path(), "in-place.gz", &gzip(&[b"b\na\n"]))];
+    assert_eq!(cli_sort(&in_place, &["-o", in_place[0].to_str().unwrap()]), b"");
+    assert_eq!(gunzip(&fs::read(&in_place[0]).unwrap()), b"a\nb\n");
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(compressed: &[u8]) -> Vec<u8> {
+    use std::io::Read;
+
+    let mut text = Vec::new();
+    flate2::read::MultiGzDecoder::new(compressed).read_to_end(&mut text).unwrap();
+    text
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_gzip_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let text: Vec<u8> = (0..3000).flat_map(|i| format!("{}\n", i * 7919 % 3001).into_bytes()).collect();
+    let inputs = vec![write(dir.path(), "numbers.txt", &text)];
+    let expected = cli_sort(&inputs, &["-n"]);
+
+    let out = dir.path().join("sorted.gz");
+    let out_arg = out.to_str().unwrap();
+    assert_eq!(cli_sort(&inputs, &["-n", "-o", out_arg]), b"");
+    let compressed = fs::read(&out).unwrap();
+    assert_eq!(gunzip(&compressed), expected);
+
+    let fast = cli_sort(&inputs, &["-n", "--compress-level=1", "-o", out_arg]);
+    assert!(fast.is_empty());
+    assert_eq!(gunzip(&fs::read(&out).unwrap()), expected);
+
+    // Merging into a .gz and writing UTF-16 go through the same encoder
+    assert_eq!(cli_sort(std::slice::from_ref(&out), &["-m", "-o", out_arg]), b"");
+    assert_eq!(gunzip(&fs::read(&out).unwrap()), expected);
+    cli_sort(&inputs, &["-n", "--output-encoding=utf-16le", "-o", out_arg]);
+    let utf16: Vec<u8> = expected.iter().flat_map(|&b| [b, 0]).collect();
+    assert_eq!(gunzip(&fs::read(&out).unwrap()), utf16);
+
+    cli_sort(&inputs, &["-n", "--no-auto-compress", "-o", out_arg]);
+    assert_eq!(fs::read(&out).unwrap(), expected);
+
+    let config = Config::builder().numeric(true).compress_level(9).build().unwrap();
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    assert_eq!(gunzip(&fs::read(&out).unwrap()), expected);
+
+    // An appended run adds a second gzip member
+    cli_sort(&inputs, &["-n", "--append", "-o", out_arg]);
+    assert_eq!(gunzip(&fs::read(&out).unwrap()), [expected.clone(), expected].concat());
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_zstd_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let text: Vec<u8> = (0..2000).rev().flat_map(|i| format!("{} line {}\n", i % 97, i).into_bytes()).collect();
+    let plain = vec![write(dir.path(), "lines.txt", &text)];
+    // Two frames, split mid-record
+    let mut frames = zstd::encode_all(&text[..5000], 3).unwrap();
+    frames.extend(zstd::encode_all(&text[5000..], 19).unwrap());
+    let zst = vec![write(dir.path(), "lines.zst", &frames)];
+    for args in [&[][..], &["-n", "-k1,1", "-s"], &["-u", "-r"]] {
+        assert_eq!(cli_sort(&zst, args), cli_sort(&plain, args), "{:?}", args);
+    }
+
+    let bare = vec![write(dir.path(), "bare", &zstd::encode_all(&b"b\na\0c\0"[..], 0).unwrap())];
+    assert_eq!(cli_sort(&bare, &["--decompress=zstd", "-z"]), b"b\na\0c\0");
+    assert_eq!(cli_sort(&bare, &["--decompress=ZSTD", "-z", "-r"]), b"c\0b\na\0");
+
+    #[cfg(feature = "gzip")]
+    {
+        let inputs = vec![
+            write(dir.path(), "a.zst", &zstd::encode_all(&b"d\nb\n"[..], 3).unwrap()),
+            write(dir.path(), "b.txt", b"c\n"),
+            write(dir.path(), "c.gz", &gzip(&[b"a\ne\n"])),
+        ];
+        assert_eq!(cli_sort(&inputs, &[]), b"a\nb\nc\nd\ne\n");
+    }
+
+    let out = dir.path().join("sorted.zst");
+    cli_sort(&zst, &["-n", "-k1,1", "--compress-level=19", "-o", out.to_str().unwrap()]);
+    let decoded = zstd::decode_all(&fs::read(&out).unwrap()[..]).unwrap();
+    assert_eq!(decoded, cli_sort(&plain, &["-n", "-k1,1"]));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_decompress_program() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    // `cat -d` is an error, so wrap it; rsort always passes -d
+    let passthrough = write(dir.path(), "passthrough", b"#!/bin/sh\nexec cat\n");
+    fs::set_permissions(&passthrough, fs::Permissions::from_mode(0o755)).unwrap();
+    let passthrough = format!("--decompress-program={}", passthrough.display());
+
+    let inputs = vec![write(dir.path(), "b.txt", b"d\nb\n"), write(dir.path(), "a.txt", b"c\na\n")];
+    assert_eq!(cli_sort(&inputs, &[&passthrough]), b"a\nb\nc\nd\n");
+    assert_eq!(cli_sort(&inputs, &[&passthrough, "-m", "-r"]), b"d\nc\nb\na\n");
+
+    let compressed = Command::new("gzip").arg("-c").arg(&inputs[0]).output().unwrap().stdout;
+    let gz = vec![write(dir.path(), "b.data", &compressed), inputs[1].clone()];
+    let config = Config::builder().decompress_program("gzip").build().unwrap();
+    let out = dir.path().join("out.txt");
+    let err = rsort::sort_files(&gz, &out, &config).unwrap_err();
+    // The plain file is not gzip, so gzip -d fails on it
+    assert!(err.to_string().starts_with("gzip failed on "), "{}", err);
+    assert!(err.to_string().ends_with("a.txt: exit status: 1"), "{}", err);
+    assert_eq!(cli_sort(&gz[..1], &["--decompress-program=gzip"]), b"b\nd\n");
+}
+
+#[cfg(all(unix, feature = "gzip"))]
+#[test]
+fn test_compress_output_program() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "b.txt", b"d\nb\n"), write(dir.path(), "a.txt", b"c\na\n")];
+    assert_eq!(cli_sort(&inputs, &["--compress-output-program=cat"]), b"a\nb\nc\nd\n");
+    assert_eq!(gunzip(&cli_sort(&inputs, &["--compress-output-program=gzip", "-m"])), b"c\na\nd\nb\n");
+
+    // The program replaces compressing by extension
+    let out = dir.path().join("out.gz");
+    let out_arg = out.to_str().unwrap();
+    assert_eq!(cli_sort(&inputs, &["--compress-output-program=cat", "-o", out_arg]), b"");
+    assert_eq!(fs::read(&out).unwrap(), b"a\nb\nc\nd\n");
+
+    let config = Config::builder().compress_output_program("gzip").build().unwrap();
+    let out = dir.path().join("out.txt");
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    assert_eq!(gunzip(&fs::read(&out).unwrap()), b"a\nb\nc\nd\n");
+}
@@ -0,0 +1,219 @@
+//! What rsort reports about a sort: --stats, --debug, --explain and
+//! -c --format=json
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rsort::Config;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_stats() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![
+        write(dir.path(), "one.txt", b"b\na\nb\n"),
+        write(dir.path(), "two.txt", b"a\nc\n"),
+    ];
+    let stats = |format: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .args([format, "-u"])
+            .args(&inputs)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"a\nb\nc\n");
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let text = stats("--stats");
+    assert!(text.starts_with("rsort: stats:\n"), "{}", text);
+    assert!(text.contains(&format!("  input {}: 3 records\n", inputs[0].display())), "{}", text);
+    assert!(text.contains(&format!("  input {}: 2 records\n", inputs[1].display())), "{}", text);
+    assert!(text.contains("  read: 5 records, 10 bytes\n"), "{}", text);
+    assert!(text.contains("  written: 3 records, 6 bytes\n"), "{}", text);
+    assert!(text.contains("  duplicates removed: 2\n"), "{}", text);
+
+    let json: serde_json::Value = serde_json::from_str(&stats("--stats=json")).unwrap();
+    assert_eq!(json["inputs"][1]["path"], inputs[1].to_str().unwrap());
+    assert_eq!(json["inputs"][1]["records"], 2);
+    assert_eq!(json["records_read"], 5);
+    assert_eq!(json["records_written"], 3);
+    assert_eq!(json["duplicates_removed"], 2);
+    assert_eq!(json["arena_bytes"], 5);
+    assert!(json["comparisons"].as_u64().unwrap() > 0);
+    assert!(json["sort_seconds"].is_f64());
+
+    // The library returns the same counters
+    let config = Config::builder().unique(true).build().unwrap();
+    let stats = rsort::sort_files(&inputs, dir.path().join("out"), &config).unwrap();
+    assert_eq!(stats.input_records, [(inputs[0].clone(), 3), (inputs[1].clone(), 2)]);
+    assert_eq!((stats.records_written, stats.duplicates_removed, stats.arena_bytes), (3, 2, 5));
+}
+
+#[test]
+fn test_debug_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(dir.path(), "in.txt", b"x:10:b\ny:2\nx:10:a\n");
+
+    let stdout = cli_sort(std::slice::from_ref(&input), &["--debug=json", "-t:", "-k2,2n", "-k3"]);
+    let lines: Vec<serde_json::Value> = std::str::from_utf8(&stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["index"], 0);
+    assert_eq!(lines[0]["record"], "y:2");
+    assert_eq!(lines[0]["lossy"], false);
+    assert_eq!(lines[0]["keys"][0], serde_json::json!({"key": 1, "start": 2, "end": 3, "matched": true}));
+    assert_eq!(lines[0]["keys"][1], serde_json::json!({"key": 2, "start": 3, "end": 3, "matched": false}));
+    assert_eq!(lines[1]["record"], "x:10:a");
+    assert_eq!(lines[1]["keys"][0], serde_json::json!({"key": 1, "start": 2, "end": 4, "matched": true}));
+    assert_eq!(lines[1]["keys"][1], serde_json::json!({"key": 2, "start": 5, "end": 6, "matched": true}));
+    assert_eq!(lines[2]["index"], 2);
+
+    // -u annotates only the records it keeps
+    let stdout = cli_sort(&[input], &["--debug=json", "-t:", "-k2,2n", "-u"]);
+    let records: Vec<String> = std::str::from_utf8(&stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["record"].to_string())
+        .collect();
+    assert_eq!(records, [r#""y:2""#, r#""x:10:b""#]);
+}
+
+#[test]
+fn test_debug_decisions() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(dir.path(), "in.txt", b"x:10:b\ny:2\nx:10:a\nx:10:a\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(["--debug=decisions", "-t:", "-k2,2n", "-k3"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"y:2\nx:10:a\nx:10:a\nx:10:b\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let decisions: Vec<&str> = stderr.lines().filter(|line| line.starts_with("rsort: lines ")).collect();
+    assert_eq!(
+        decisions,
+        [
+            "rsort: lines 1-2: key 1 puts '2' before '10'",
+            "rsort: lines 2-3: identical lines",
+            "rsort: lines 3-4: key 2 puts 'a' before 'b'",
+        ]
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(["--debug=decisions", "-t:", "-k1,1"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("rsort: lines 1-2: identical lines"));
+    assert!(stderr.contains("rsort: lines 2-3: keys equal; last resort puts 'x:10:a' before 'x:10:b'"));
+    assert!(stderr.contains("rsort: lines 3-4: key 1 puts 'x' before 'y'"));
+}
+
+#[test]
+fn test_check_json_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let check = |name: &str, records: &[u8], args: &[&str]| {
+        let input = write(dir.path(), name, records);
+        let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .args(["-c", "--format=json"])
+            .args(args)
+            .arg(&input)
+            .output()
+            .unwrap();
+        let report = (!output.stdout.is_empty()).then(|| serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap());
+        (output.status.code(), report, input)
+    };
+
+    let (status, report, input) = check("keys.txt", b"a,1\nc,3\nb,2\n", &["-t,", "-k1,1", "-k2,2n"]);
+    let report = report.unwrap();
+    assert_eq!(status, Some(1));
+    assert_eq!(report["status"], "unsorted");
+    assert_eq!(report["file"], input.to_str().unwrap());
+    assert_eq!(report["record_index"], 2);
+    assert_eq!(report["kind"], "disorder");
+    assert_eq!(report["previous"], "c,3");
+    assert_eq!(report["current"], "b,2");
+    assert_eq!(report["deciding_key"], 1);
+
+    let (status, report, _) = check("keys.txt", b"x,3\nx,2\n", &["-t,", "-k1,1", "-k2,2n"]);
+    assert_eq!(status, Some(1));
+    assert_eq!(report.unwrap()["deciding_key"], 2);
+
+    let (_, report, _) = check("tie.txt", b"x,02\nx,2\nx,1\n", &["-t,", "-k2,2n"]);
+    let report = report.unwrap();
+    assert_eq!(report["record_index"], 2);
+    assert_eq!(report["deciding_key"], 1);
+
+    let (status, report, _) = check("dup.txt", b"a\nA\n", &["-u", "-f"]);
+    let report = report.unwrap();
+    assert_eq!(status, Some(1));
+    assert_eq!(report["kind"], "duplicate");
+    assert_eq!(report["deciding_key"], serde_json::Value::Null);
+
+    let (status, report, _) = check("binary.txt", b"\xffa\nb\n", &[]);
+    let report = report.unwrap();
+    assert_eq!(status, Some(1));
+    assert_eq!(report["previous"], serde_json::json!({"base64": "/2E="}));
+    assert_eq!(report["current"], "b");
+
+    let (status, report, _) = check("sorted.txt", b"a\nb\n", &[]);
+    assert_eq!((status, report), (Some(0), None));
+    let (status, report, _) = check("sorted.txt", b"a\nb\n", &["--verbose"]);
+    assert_eq!(status, Some(0));
+    assert_eq!(report.unwrap()["records"], 2);
+}
+
+#[test]
+fn test_explain() {
+    let dir = tempfile::tempdir().unwrap();
+    let explain = |records: &[u8], args: &[&str]| {
+        let input = write(dir.path(), "pair.txt", records);
+        String::from_utf8(cli_sort(&[input], &[&["--explain"], args].concat())).unwrap()
+    };
+
+    // Numeric tie broken by the last resort, then kept with -s
+    let narration = explain(b"x,09\nx,9\n", &["-t,", "-k2,2n"]);
+    assert!(narration.contains("key 1 (-k 2,2n): '09' vs '9', numeric: equal\n"));
+    assert!(narration.contains("last resort: whole line, bytewise: before\n"));
+    assert!(narration.ends_with("result: record 1 sorts before record 2\n"));
+    let narration = explain(b"x,09\nx,9\n", &["-t,", "-k2,2n", "-s"]);
+    assert!(narration.contains("last resort: off (-s)"));
+    assert!(narration.ends_with("result: equal; they keep their input order\n"));
+
+    // Fold-case tie, and -r reversing the last resort
+    let narration = explain(b"ABC\nabc\n", &["-f", "-r"]);
+    assert!(narration.contains("key (whole line): 'ABC' vs 'abc', bytewise, case folded: equal\n"));
+    assert!(narration.contains("last resort: whole line, bytewise: after (reversed by -r)\n"));
+    assert!(narration.ends_with("result: record 1 sorts after record 2\n"));
+
+    // A missing field sorts first; later keys are shown but not needed
+    let narration = explain(b"b\na,1\n", &["-t,", "-k2,2", "-k1,1"]);
+    assert!(narration.starts_with("record 1: 'b'\nrecord 2: 'a,1'\n"));
+    assert!(narration.contains("key 1 (-k 2,2): missing field vs '1', bytewise: before\n"));
+    assert!(narration.contains("key 2 (-k 1,1): 'b' vs 'a', bytewise: after; not needed\n"));
+    assert!(narration.contains("last resort: not needed, key 1 decided\n"));
+}
@@ -3,10 +3,10 @@
 //! All tests run with LC_ALL=C LANG=C to ensure bytewise collation.
 //! On Windows, uses WSL to access GNU sort.
 //! On Unix, uses native sort command.
+#![cfg(feature = "cli")]
 
 use std::io::Write;
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::{Command, Output, Stdio};
 
 /// Check if we're running on Windows
 fn is_windows() -> bool {
@@ -19,8 +19,8 @@ fn shell_escape(arg: &str) -> String {
     format!("'{}'", arg.replace('\'', "'\\''"))
 }
 
-/// Run GNU sort command and return its stdout
-fn run_gnu_sort(input: &[u8], args: &[&str]) -> Vec<u8> {
+/// Run GNU sort command and return its output
+fn run_gnu_sort(input: &[u8], args: &[&str]) -> Output {
     if is_windows() {
         // Use WSL to run GNU sort on Windows
         // Must use 'env' to set LC_ALL inside WSL (Windows env vars don't pass through)
@@ -37,10 +37,11 @@ fn run_gnu_sort(input: &[u8], args: &[&str]) -> Vec<u8> {
             .expect("failed to spawn wsl sort - is WSL installed?");
 
         if let Some(mut stdin) = cmd.stdin.take() {
-            stdin.write_all(input).expect("failed to write stdin");
+            // Usage errors exit before reading; a broken pipe is expected then
+            let _ = stdin.write_all(input);
         }
 
-        cmd.wait_with_output().expect("failed to wait").stdout
+        cmd.wait_with_output().expect("failed to wait")
     } else {
         // Use native sort on Unix
         let mut cmd = Command::new("sort")
@@ -54,24 +55,19 @@ fn run_gnu_sort(input: &[u8], args: &[&str]) -> Vec<u8> {
             .expect("failed to spawn sort");
 
         if let Some(mut stdin) = cmd.stdin.take() {
-            stdin.write_all(input).expect("failed to write stdin");
+            // Usage errors exit before reading; a broken pipe is expected then
+            let _ = stdin.write_all(input);
         }
 
-        cmd.wait_with_output().expect("failed to wait").stdout
+        cmd.wait_with_output().expect("failed to wait")
     }
 }
 
-/// Run rsort and return its stdout
-fn run_rsort(input: &[u8], args: &[&str]) -> Vec<u8> {
-    let rsort_path = if Path::new("./target/release/rsort.exe").exists() {
-        "./target/release/rsort.exe"
-    } else if Path::new("./target/release/rsort").exists() {
-        "./target/release/rsort"
-    } else if Path::new("./target/debug/rsort.exe").exists() {
-        "./target/debug/rsort.exe"
-    } else {
-        "./target/debug/rsort"
-    };
+/// Run rsort and return its output
+fn run_rsort(input: &[u8], args: &[&str]) -> Output {
+    // Cargo's path to the binary under test; a leftover release build
+    // (e.g. from `cargo bench`) must never shadow it
+    let rsort_path = env!("CARGO_BIN_EXE_rsort");
 
     let mut cmd = Command::new(rsort_path)
         .args(args)
@@ -84,17 +80,18 @@ fn run_rsort(input: &[u8], args: &[&str]) -> Vec<u8> {
         .unwrap_or_else(|e| panic!("failed to spawn rsort at {}: {}", rsort_path, e));
 
     if let Some(mut stdin) = cmd.stdin.take() {
-        stdin.write_all(input).expect("failed to write stdin");
+        // Usage errors exit before reading; a broken pipe is expected then
+        let _ = stdin.write_all(input);
     }
 
-    cmd.wait_with_output().expect("failed to wait").stdout
+    cmd.wait_with_output().expect("failed to wait")
 }
 
 /// Compare rsort output against GNU sort
 /// Returns true if outputs match byte-for-byte
 fn compare_with_gnu(input: &[u8], args: &[&str]) -> bool {
-    let gnu = run_gnu_sort(input, args);
-    let rsort = run_rsort(input, args);
+    let gnu = run_gnu_sort(input, args).stdout;
+    let rsort = run_rsort(input, args).stdout;
 
     if gnu != rsort {
         eprintln!("========== MISMATCH ==========");
@@ -109,6 +106,27 @@ fn compare_with_gnu(input: &[u8], args: &[&str]) -> bool {
     }
 }
 
+/// Compare exit status and diagnostics against GNU sort
+/// The leading program name ("sort:" vs "rsort:") is ignored
+fn compare_status_with_gnu(input: &[u8], args: &[&str]) -> bool {
+    let gnu = run_gnu_sort(input, args);
+    let rsort = run_rsort(input, args);
+    let gnu_stderr = gnu.stderr.strip_prefix(b"sort").unwrap_or(&gnu.stderr);
+    let rsort_stderr = rsort.stderr.strip_prefix(b"rsort").unwrap_or(&rsort.stderr);
+
+    if gnu.status.code() != rsort.status.code() || gnu_stderr != rsort_stderr {
+        eprintln!("========== MISMATCH ==========");
+        eprintln!("Args: {:?}", args);
+        eprintln!("Input ({} bytes): {:?}", input.len(), String::from_utf8_lossy(input));
+        eprintln!("GNU   {:?}: {:?}", gnu.status.code(), String::from_utf8_lossy(&gnu.stderr));
+        eprintln!("rsort {:?}: {:?}", rsort.status.code(), String::from_utf8_lossy(&rsort.stderr));
+        eprintln!("==============================");
+        false
+    } else {
+        true
+    }
+}
+
 // ============================================================
 // Basic Functionality Tests
 // ============================================================
@@ -210,6 +228,69 @@ fn test_numeric_reverse() {
     assert!(compare_with_gnu(b"1\n10\n2\n", &["-n", "-r"]));
 }
 
+#[test]
+fn test_numeric_beyond_f64() {
+    assert!(compare_with_gnu(
+        b"9007199254740993\n9007199254740992\n0.30000000000000001\n0.3\n",
+        &["-n"]
+    ));
+}
+
+// ============================================================
+// Human (-h), Month (-M) and Version (-V) Tests
+// ============================================================
+
+#[test]
+fn test_human_numeric() {
+    assert!(compare_with_gnu(b"2K\n1M\n1023\n1.5G\n-1K\n0K\nk\n1k\n", &["-h"]));
+    assert!(compare_with_gnu(b"10K\n9M\n-2G\n1\n", &["-h", "-r"]));
+}
+
+#[test]
+fn test_month() {
+    assert!(compare_with_gnu(b"Dec\nfeb\n  JAN\nfoo\nmarch\nJA\n\n", &["-M"]));
+    assert!(compare_with_gnu(b"x Dec\ny jan\nz ???\n", &["-M", "-k2,2"]));
+}
+
+#[test]
+fn test_version() {
+    assert!(compare_with_gnu(
+        b"a-1.10.tar.gz\na-1.9.tar.gz\n1.0~rc1\n1.0\n.hidden\n01\n1\nv2\nV1\n",
+        &["-V"]
+    ));
+    assert!(compare_with_gnu(b"1_b\n1_A\n1_a\n1_B\n", &["-V", "-f"]));
+}
+
+#[test]
+fn test_version_leading_dot() {
+    // The whole name can be a file suffix: ".a" has an empty version
+    let input = b".a\n.5\n.a1\n.51\n.5a\n.\n..\n.~\n.a.b\n.5.a\n.A\n.b~\n";
+    assert!(compare_with_gnu(input, &["-V"]));
+    assert!(compare_with_gnu(input, &["-V", "-f"]));
+    assert!(compare_with_gnu(input, &["-V", "-r"]));
+}
+
+#[test]
+fn test_incompatible_orderings() {
+    assert!(compare_status_with_gnu(b"1\n", &["-n", "-M"]));
+    assert!(compare_status_with_gnu(b"1\n", &["-f", "-h", "-V"]));
+}
+
+#[test]
+fn test_incompatible_ordering_pairs() {
+    let orderings = ["h", "M", "n", "V"];
+    for (i, first) in orderings.iter().enumerate() {
+        for second in &orderings[i + 1..] {
+            assert!(compare_status_with_gnu(b"1\n", &[&format!("-{}", first), &format!("-{}", second)]));
+            assert!(compare_status_with_gnu(b"1\n", &[&format!("-k1,1{}{}", first, second)]));
+        }
+        // -f goes with any of them, and a key's own ordering replaces the global one
+        assert!(compare_status_with_gnu(b"1\n", &["-f", &format!("-{}", first)]));
+        assert!(compare_status_with_gnu(b"1\n", &[&format!("-k1,1f{}", first)]));
+        assert!(compare_status_with_gnu(b"1\n", &["-n", &format!("-k1,1{}", first)]));
+    }
+}
+
 // ============================================================
 // Case Folding (-f) Tests
 // ============================================================
@@ -320,6 +401,30 @@ fn test_key_multiple_keys() {
     assert!(compare_with_gnu(b"a 2\na 1\nb 1\n", &["-k1,1", "-k2,2", "-n"]));
 }
 
+#[test]
+fn test_key_modifiers() {
+    let input = b"b 10\na 9\nB 10\nc 9\na 10\n";
+    assert!(compare_with_gnu(input, &["-k2,2n"]));
+    assert!(compare_with_gnu(input, &["-k2,2nr", "-k1,1"]));
+    assert!(compare_with_gnu(input, &["-k1,1f", "-k2,2n"]));
+    assert!(compare_with_gnu(input, &["-r", "-k2,2n"]));
+    assert!(compare_with_gnu(input, &["-r", "-k1,1", "-k2,2n"]));
+    assert!(compare_with_gnu(input, &["-n", "-k1,1r"]));
+    assert!(compare_with_gnu(input, &["-s", "-k2n,2"]));
+    assert!(compare_with_gnu(b"x, b\ny,a\nz,  c\n", &["-t,", "-k2,2b"]));
+    assert!(compare_with_gnu(b"x, b\ny,a\nz,  c\n", &["-t,", "-k2b,2"]));
+    // b skips the blanks before the start character is counted
+    assert!(compare_with_gnu(b"x,  ba\ny,ab\n", &["-t,", "-k2.2b,2"]));
+    assert!(compare_with_gnu(b"x,  ba\ny,ab\nw, , c\nv,  \n", &["-t,", "-k2.2b"]));
+    assert!(compare_with_gnu(b"1.10\n1.9\n1.2\n", &["-k1,1V"]));
+}
+
+#[test]
+fn test_key_modifier_errors() {
+    assert!(compare_status_with_gnu(b"a\n", &["-k1,1nM"]));
+    assert!(compare_status_with_gnu(b"a\n", &["-k1n,1V"]));
+}
+
 #[test]
 fn test_key_overlapping() {
     // Overlapping key ranges
@@ -330,6 +435,10 @@ fn test_key_overlapping() {
 fn test_key_char_positions() {
     // -k1.2,1.4 means characters 2 through 4 of field 1
     assert!(compare_with_gnu(b"xabc\nxdef\nxghi\n", &["-k1.2,1.4"]));
+    // Character positions past the end of the field run into the next one
+    let input = b"ab,zz\nab,aa\nw, , c\nw, ,ab\n";
+    assert!(compare_with_gnu(input, &["-t,", "-k1.4"]));
+    assert!(compare_with_gnu(input, &["-t,", "-k2.2,2.4"]));
 }
 
 // ============================================================
@@ -384,6 +493,14 @@ fn test_zero_terminated_numeric() {
     assert!(compare_with_gnu(b"10\x002\x001\x00", &["-z", "-n"]));
 }
 
+#[test]
+fn test_zero_terminated_newline_is_blank() {
+    // A newline inside a -z record is a blank, for b and for fields
+    assert!(compare_with_gnu(b"\nb\0a\0", &["-z", "-k1b"]));
+    assert!(compare_with_gnu(b"\nb\0a\0x\n3\0y 2\0 \n1\0", &["-z", "-k1bn"]));
+    assert!(compare_with_gnu(b"x\n3\0y 2\0z\t1\0", &["-z", "-k2,2n"]));
+}
+
 // ============================================================
 // Last-Resort Comparison Edge Cases
 // ============================================================
@@ -472,6 +589,69 @@ fn test_all_byte_values() {
     assert!(compare_with_gnu(&input, &[]));
 }
 
+// ============================================================
+// Debug (--debug) Tests
+// ============================================================
+
+#[test]
+fn test_debug_whole_line() {
+    assert!(compare_with_gnu(b"b\tx\na\n\nc", &["--debug"]));
+    assert!(compare_with_gnu(b"b\nB\na\n", &["--debug", "-f"]));
+    assert!(compare_with_gnu(b"b\na\n", &["--debug", "-r"]));
+}
+
+#[test]
+fn test_debug_keys() {
+    let input = b"x:10:feb\ny:-3.5k:Jan\nz::mar\nw\n";
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k2,2n", "-k3"]));
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k2,2h", "-s"]));
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k3M", "-k1.2"]));
+    assert!(compare_with_gnu(b" 10 z\n2 y\nabc\n", &["--debug", "-n"]));
+}
+
+#[test]
+fn test_debug_tab_separated() {
+    let input = b"a1\tbob smith\t 42\nb2\tann lee\t7\nc3\t\t-1.5\n";
+    assert!(compare_with_gnu(input, &["--debug", "-t\t", "-k2,2", "-k3,3n"]));
+    assert!(compare_with_gnu(input, &["--debug", "-t\t", "-k3n", "-s"]));
+}
+
+#[test]
+fn test_debug_empty_and_missing_fields() {
+    let input = b"a::c\na\nb:x\n:\n";
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k2,2"]));
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k3", "-s"]));
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k2,2n", "-k1,1"]));
+}
+
+#[test]
+fn test_debug_numeric_part_of_key() {
+    let input = b"  -12.5kg\nabc\n\n-\n.5\n5.\n1,234\n";
+    assert!(compare_with_gnu(input, &["--debug", "-n"]));
+    assert!(compare_with_gnu(input, &["--debug", "-h"]));
+    let input = b"x:  -12.5kg\nx::\nx\nx:7 apples\n";
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k2,2n"]));
+    assert!(compare_with_gnu(input, &["--debug", "-t:", "-k2,2h", "-s"]));
+}
+
+#[test]
+fn test_debug_unique_and_nul() {
+    assert!(compare_with_gnu(b"b 1\na 2\nb 3\n", &["--debug", "-u", "-k1,1"]));
+    assert!(compare_with_gnu(b"b\0a\tc\0a\0", &["--debug", "-z", "-u"]));
+}
+
+#[test]
+fn test_debug_last_resort_marks() {
+    // Equal numbers are ordered by the whole line, underlined after the
+    // key; -s turns that off and drops the second mark
+    let input = b"10\n9\n09\n";
+    assert!(compare_with_gnu(input, &["--debug", "-n"]));
+    assert!(compare_with_gnu(input, &["--debug", "-n", "-s"]));
+    let marks = |args: &[&str]| String::from_utf8(run_rsort(input, args).stdout).unwrap().lines().count();
+    assert_eq!(marks(&["--debug", "-n"]), 9);
+    assert_eq!(marks(&["--debug", "-n", "-s"]), 6);
+}
+
 // ============================================================
 // No Trailing Newline Edge Cases
 // ============================================================
@@ -602,3 +782,99 @@ fn test_alternating_duplicates() {
 fn test_alternating_duplicates_unique() {
     assert!(compare_with_gnu(b"a\nb\na\nb\na\nb\n", &["-u"]));
 }
+
+// ============================================================
+// Check Mode (-c / -C)
+// ============================================================
+
+#[test]
+fn test_check_sorted() {
+    assert!(compare_status_with_gnu(b"a\nb\nb\nc\n", &["-c"]));
+    assert!(compare_status_with_gnu(b"", &["-c"]));
+}
+
+#[test]
+fn test_check_disorder() {
+    assert!(compare_status_with_gnu(b"a\nc\nb\n", &["-c"]));
+    assert!(compare_status_with_gnu(b"b\na", &["-c"]));
+}
+
+#[test]
+fn test_check_quiet() {
+    assert!(compare_status_with_gnu(b"a\nc\nb\n", &["-C"]));
+    assert!(compare_status_with_gnu(b"a\nb\n", &["-C"]));
+}
+
+#[test]
+fn test_check_unique() {
+    assert!(compare_status_with_gnu(b"a\na\n", &["-c", "-u"]));
+    assert!(compare_status_with_gnu(b"A\na\n", &["-c", "-u", "-f"]));
+}
+
+#[test]
+fn test_check_options() {
+    assert!(compare_status_with_gnu(b"10\n9\n1\n", &["-c", "-n", "-r"]));
+    assert!(compare_status_with_gnu(b"x 2\ny 1\n", &["-c", "-k2,2"]));
+    assert!(compare_status_with_gnu(b"a\0c\0b\0", &["-c", "-z"]));
+}
+
+// ============================================================
+// Merge Mode (-m)
+// ============================================================
+
+/// Write each input to its own temp file and compare `-m` over them
+fn compare_merge_with_gnu(inputs: &[&[u8]], args: &[&str]) -> bool {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let mut paths = Vec::new();
+    for (i, input) in inputs.iter().enumerate() {
+        let path = dir.path().join(format!("in{}", i));
+        std::fs::write(&path, input).expect("failed to write input");
+        paths.push(path.to_str().unwrap().to_string());
+    }
+
+    let mut full_args = vec!["-m"];
+    full_args.extend_from_slice(args);
+    full_args.extend(paths.iter().map(String::as_str));
+    compare_with_gnu(b"", &full_args)
+}
+
+#[test]
+fn test_merge_basic() {
+    assert!(compare_merge_with_gnu(&[b"a\nc\ne\n", b"b\nd\nf\n"], &[]));
+}
+
+#[test]
+fn test_merge_single_and_empty_sources() {
+    assert!(compare_merge_with_gnu(&[b"a\nb\n"], &[]));
+    assert!(compare_merge_with_gnu(&[b"", b"a\n", b""], &[]));
+}
+
+#[test]
+fn test_merge_no_trailing_newline() {
+    assert!(compare_merge_with_gnu(&[b"a\nc", b"b\nd"], &[]));
+}
+
+#[test]
+fn test_merge_stable_ties() {
+    assert!(compare_merge_with_gnu(&[b"a\nB\n", b"A\nb\n"], &["-f", "-s"]));
+}
+
+#[test]
+fn test_merge_unique() {
+    assert!(compare_merge_with_gnu(&[b"A\nb\n", b"a\nB\n"], &["-f", "-u"]));
+    assert!(compare_merge_with_gnu(&[b"a\na\nb\n", b"a\nc\n"], &["-u"]));
+}
+
+#[test]
+fn test_merge_keys_and_nul() {
+    assert!(compare_merge_with_gnu(&[b"x 1\nx 10\n", b"y 2\ny 3\n"], &["-n", "-k2,2"]));
+    assert!(compare_merge_with_gnu(&[b"a\0c\0", b"b\0"], &["-z"]));
+}
+
+#[test]
+fn test_merge_stdin_operand() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let path = dir.path().join("in");
+    std::fs::write(&path, b"b\nd\n").unwrap();
+    assert!(compare_with_gnu(b"a\nc\n", &["-m", "-", path.to_str().unwrap()]));
+}
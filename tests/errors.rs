@@ -0,0 +1,566 @@
+//! I/O failures name the file and exit 2, in GNU sort's style
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn rsort(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run rsort")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_missing_input_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let present = dir.path().join("present.txt");
+    fs::write(&present, b"a\n").unwrap();
+    let missing = dir.path().join("nonexistent.txt");
+
+    let output = rsort(&[&present, &missing, &present]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        format!("rsort: cannot read: {}: No such file or directory\n", missing.display())
+    );
+}
+
+#[test]
+fn test_unwritable_output_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("no-such-dir").join("out.txt");
+
+    let output = rsort(&[Path::new("-o"), &out]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        format!("rsort: open failed: {}: No such file or directory\n", out.display())
+    );
+}
+
+#[test]
+fn test_output_is_opened_before_reading() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("no-such-dir").join("out.txt");
+
+    for mode in ["-s", "-m"] {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .arg(mode)
+            .arg("-o")
+            .arg(&out)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // stdin stays open: rsort must fail without waiting for it
+        let stdin = child.stdin.take();
+        let status = child.wait().unwrap();
+        drop(stdin);
+        assert_eq!(status.code(), Some(2), "{}", mode);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_only_output_directory_is_named() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let locked = dir.path().join("locked");
+    fs::create_dir(&locked).unwrap();
+    let out = locked.join("out.txt");
+    fs::write(&out, b"kept\n").unwrap();
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+    if fs::File::create(locked.join("probe")).is_ok() {
+        // Running as root: permissions are not enforced
+        return;
+    }
+
+    let output = rsort_stdin(&["-o".as_ref(), out.as_os_str()], b"b\na\n");
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        format!("rsort: open failed: {}: Permission denied\n", out.display())
+    );
+    assert_eq!(fs::read(&out).unwrap(), b"kept\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_full_output_device_is_named() {
+    let output = rsort_stdin(&["-o".as_ref(), "/dev/full".as_ref()], b"b\na\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: write failed: /dev/full: No space left on device\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_failing_tee_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out.txt");
+    let args = ["-o".as_ref(), out.as_os_str(), "--tee".as_ref(), "/dev/full".as_ref()];
+    let output = rsort_stdin(&args, b"b\na\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: write failed: /dev/full: No space left on device\n");
+    // The -o file is not replaced by a run that failed
+    assert!(!out.exists());
+
+    let missing = dir.path().join("no-such-dir").join("tee.txt");
+    let output = rsort_stdin(&["--tee".as_ref(), missing.as_os_str()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        format!("rsort: open failed: {}: No such file or directory\n", missing.display())
+    );
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_directory_input_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = rsort(&[dir.path()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        format!("rsort: read failed: {}: Is a directory\n", dir.path().display())
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_permission_denied_is_named() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let secret = dir.path().join("secret.txt");
+    fs::write(&secret, b"a\n").unwrap();
+    fs::set_permissions(&secret, fs::Permissions::from_mode(0o000)).unwrap();
+    if fs::File::open(&secret).is_ok() {
+        // Running as root: permissions are not enforced
+        return;
+    }
+
+    let output = rsort(&[&secret]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        format!("rsort: cannot read: {}: Permission denied\n", secret.display())
+    );
+}
+
+#[test]
+fn test_exit_statuses() {
+    let status = |args: &[&str], input: &[u8]| {
+        let args: Vec<&std::ffi::OsStr> = args.iter().map(|arg| arg.as_ref()).collect();
+        rsort_stdin(&args, input).status.code()
+    };
+
+    assert_eq!(status(&[], b"b\na\n"), Some(0));
+    assert_eq!(status(&["-c"], b"a\nb\n"), Some(0));
+    assert_eq!(status(&["-c"], b"b\na\n"), Some(1));
+    assert_eq!(status(&["-C"], b"b\na\n"), Some(1));
+    assert_eq!(status(&["-k", "0"], b"a\n"), Some(2));
+    assert_eq!(status(&["-t", "ab"], b"a\n"), Some(2));
+    assert_eq!(status(&["-n", "-M"], b"a\n"), Some(2));
+    assert_eq!(status(&["-z", "--record-delimiter=;"], b"a\n"), Some(2));
+    assert_eq!(status(&["-z", "--crlf"], b"a\n"), Some(2));
+    assert_eq!(status(&["-m", "--preserve-final-newline"], b"a\n"), Some(2));
+    assert_eq!(status(&["--no-such-option"], b"a\n"), Some(2));
+    assert_eq!(status(&["/nonexistent/input.txt"], b""), Some(2));
+    assert_eq!(status(&["-c", "/nonexistent/input.txt"], b""), Some(2));
+}
+
+/// Run rsort with `input` piped to stdin
+fn rsort_stdin(args: &[&std::ffi::OsStr], input: &[u8]) -> Output {
+    use std::io::Write;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Usage errors exit before reading
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_disorder_record_is_escaped() {
+    let output = rsort_stdin(&["-c".as_ref(), "-z".as_ref()], b"b\nx\0a\x1b\0");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "rsort: -:2: disorder: a\\x1b\0");
+}
+
+#[test]
+fn test_explain_needs_two_records() {
+    let output = rsort_stdin(&["--explain".as_ref()], b"a\nb\nc\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: --explain needs exactly two records; read 3\n");
+}
+
+#[test]
+fn test_encoding_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let truncated = dir.path().join("truncated.txt");
+    fs::write(&truncated, b"b\x00\n\x00a").unwrap();
+
+    let args = ["--encoding=utf-16le".as_ref(), truncated.as_os_str()];
+    let output = rsort_stdin(&args, b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "rsort: read failed: {}: truncated UTF-16 input: odd number of bytes\n",
+            truncated.display()
+        )
+    );
+
+    let output = rsort_stdin(&["--encoding=latin1".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "rsort: invalid --encoding argument 'latin1'; expected utf-8, utf-16le or utf-16be\n"
+    );
+
+    // Bytes that are not UTF-8 cannot be written as UTF-16
+    let output = rsort_stdin(&["--output-encoding=utf-16be".as_ref()], b"\xff\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("not valid UTF-8"), "{}", stderr(&output));
+}
+
+#[test]
+fn test_stdin_read_where_first_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    let c = dir.path().join("c.txt");
+    fs::write(&a, b"a2\na1\n").unwrap();
+    fs::write(&c, b"c1\n").unwrap();
+
+    let output = rsort_stdin(&[a.as_os_str(), "-".as_ref(), c.as_os_str()], b"b2\nb1\n");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, b"a1\na2\nb1\nb2\nc1\n");
+
+    let output = rsort_stdin(&["-m".as_ref(), c.as_os_str(), "-".as_ref()], b"b1\nb2\n");
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, b"b1\nb2\nc1\n");
+}
+
+#[test]
+fn test_stdin_named_twice_is_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.txt");
+    fs::write(&a, b"a\n").unwrap();
+
+    for mode in [None, Some("-m")] {
+        let mut args: Vec<&std::ffi::OsStr> = mode.iter().map(|m| m.as_ref()).collect();
+        args.extend([a.as_os_str(), "-".as_ref(), a.as_os_str(), "-".as_ref()]);
+        let output = rsort_stdin(&args, b"b\n");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(output.stdout.is_empty());
+        assert_eq!(stderr(&output), "rsort: cannot read standard input more than once\n");
+    }
+}
+
+#[test]
+fn test_field_separator_errors() {
+    let output = rsort_stdin(&["-t".as_ref(), "ab".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: multi-character tab 'ab'\n");
+
+    let output = rsort_stdin(&["-t".as_ref(), "\\q".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).starts_with("rsort: invalid escape '\\q'; expected \\t,"));
+
+    let output = rsort_stdin(&["-t".as_ref(), "\\xZZ".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    let expected = "rsort: invalid escape '\\xZZ'; \\x takes exactly two hex digits\n";
+    assert_eq!(stderr(&output), expected);
+
+    let args = ["-t".as_ref(), ":".as_ref(), "--field-separator-str=::".as_ref()];
+    assert_eq!(rsort_stdin(&args, b"a\n").status.code(), Some(2));
+
+    #[cfg(feature = "regex")]
+    {
+        let output = rsort_stdin(&["--field-separator-regex= *".as_ref()], b"a\n");
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(
+            stderr(&output),
+            "rsort: invalid --field-separator-regex ' *': it can match the empty string\n"
+        );
+    }
+
+    // One character, however many bytes
+    let args = ["-t".as_ref(), "§".as_ref(), "-k2".as_ref()];
+    let output = rsort_stdin(&args, "x§b\ny§a\n".as_bytes());
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, "y§a\nx§b\n".as_bytes());
+}
+
+#[test]
+fn test_no_terminal_hint_for_piped_stdin() {
+    let output = rsort_stdin(&[], b"b\na\n");
+    assert_eq!(output.stdout, b"a\nb\n");
+    assert!(output.stderr.is_empty(), "{}", stderr(&output));
+}
+
+#[test]
+fn test_max_record_size_names_input_and_offset() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = dir.path().join("text.txt");
+    let blob = dir.path().join("blob.bin");
+    fs::write(&text, b"b\na\n").unwrap();
+    let mut contents = b"head\n".to_vec();
+    contents.extend(std::iter::repeat_n(0xA5u8, 4096));
+    fs::write(&blob, &contents).unwrap();
+
+    let output = rsort(&["--max-record-size=1K".as_ref(), text.as_path(), blob.as_path()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        stderr(&output),
+        format!(
+            "rsort: {}: record starting at byte 5 is longer than --max-record-size (1024 bytes); \
+             binary input? use -z for NUL-delimited records\n",
+            blob.display()
+        )
+    );
+
+    let output = rsort(&["--max-record-size=1K".as_ref(), "-c".as_ref(), blob.as_path()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("record starting at byte 5"), "{}", stderr(&output));
+
+    let output = rsort(&["--max-record-size=1K".as_ref(), "-m".as_ref(), text.as_path(), blob.as_path()]);
+    assert_eq!(output.status.code(), Some(2));
+    let named = format!("rsort: {}: record starting at byte 5", blob.display());
+    assert!(stderr(&output).starts_with(&named), "{}", stderr(&output));
+}
+
+#[test]
+fn test_records_within_max_record_size_are_unaffected() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = dir.path().join("text.txt");
+    fs::write(&text, b"bb\naaa\nc").unwrap();
+
+    let output = rsort(&["--max-record-size=3".as_ref(), text.as_path()]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(output.stdout, b"aaa\nbb\nc\n");
+
+    let output = rsort(&["--max-record-size=2".as_ref(), text.as_path()]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = rsort(&["--max-record-size=12Q".as_ref(), text.as_path()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: invalid --max-record-size argument '12Q'\n");
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_truncated_gzip_is_named() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"b\na\n").unwrap();
+    let compressed = encoder.finish().unwrap();
+    let truncated = dir.path().join("truncated.gz");
+    fs::write(&truncated, &compressed[..compressed.len() / 2]).unwrap();
+    let plain = dir.path().join("plain.txt");
+    fs::write(&plain, b"c\n").unwrap();
+
+    let expected = format!("rsort: {}: invalid gzip data: ", truncated.display());
+    for merge in [false, true] {
+        let mut args = vec![plain.as_os_str(), truncated.as_os_str()];
+        if merge {
+            args.insert(0, "-m".as_ref());
+        }
+        let output = rsort_stdin(&args, b"");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(stderr(&output).starts_with(&expected), "{}", stderr(&output));
+    }
+
+    // A bad level is caught before the output is truncated
+    let out = dir.path().join("out.gz");
+    fs::write(&out, b"kept").unwrap();
+    let output = rsort_stdin(&["--compress-level=10".as_ref(), "-o".as_ref(), out.as_os_str()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "rsort: invalid --compress-level '10' for gzip output; expected 0 to 9\n"
+    );
+    assert_eq!(fs::read(&out).unwrap(), b"kept");
+
+    let output = rsort_stdin(&["--decompress=xz".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: invalid --decompress argument 'xz'; expected gzip or zstd\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failing_decompress_program_is_named() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.xz");
+    fs::write(&input, b"b\na\n").unwrap();
+
+    for mode in ["-c", "-m", "-s"] {
+        let args = [mode.as_ref(), "--decompress-program=false".as_ref(), input.as_os_str()];
+        let output = rsort_stdin(&args, b"");
+        assert_eq!(output.status.code(), Some(2), "{}", mode);
+        assert!(output.stdout.is_empty());
+        assert_eq!(
+            stderr(&output),
+            format!("rsort: false failed on {}: exit status: 1\n", input.display())
+        );
+    }
+
+    let output = rsort_stdin(&["--decompress-program=/no/such/program".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "rsort: cannot run --decompress-program '/no/such/program': No such file or directory\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failing_compress_output_program_is_named() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    // Reads a little of a large output, then fails
+    let program = dir.path().join("fails-partway");
+    fs::write(&program, b"#!/bin/sh\nhead -c 10 >/dev/null\nexit 3\n").unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+    let option = format!("--compress-output-program={}", program.display());
+    let input: Vec<u8> = (0..200_000).flat_map(|n| format!("{}\n", n).into_bytes()).collect();
+
+    let output = rsort_stdin(&[option.as_ref()], &input);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), format!("rsort: {} failed: exit status: 3\n", program.display()));
+
+    // A failed program leaves no output file behind
+    let out = dir.path().join("out.txt");
+    let args = ["--compress-output-program=false".as_ref(), "-o".as_ref(), out.as_os_str()];
+    let output = rsort_stdin(&args, b"b\na\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), format!("rsort: false failed on {}: exit status: 1\n", out.display()));
+    assert!(!out.exists());
+
+    let output = rsort_stdin(&["--compress-output-program=/no/such/program".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr(&output),
+        "rsort: cannot run --compress-output-program '/no/such/program': No such file or directory\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failing_key_command_is_named() {
+    let output = rsort_stdin(&["--key-command=cat; exit 4".as_ref()], b"b\na\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(stderr(&output), "rsort: cat; exit 4 failed: exit status: 4\n");
+
+    for (command, keys) in [("head -1", 1), ("cat; echo extra", 3)] {
+        let option = format!("--key-command={}", command);
+        let output = rsort_stdin(&[option.as_ref()], b"b\na\n");
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(
+            stderr(&output),
+            format!("rsort: --key-command '{}' printed {} keys for 2 records\n", command, keys)
+        );
+    }
+
+    let output = rsort_stdin(&["--key-command=cat".as_ref(), "-c".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: options '--key-command' and '-c' are incompatible\n");
+}
+
+#[test]
+fn test_record_containing_output_delimiter_is_error() {
+    for mode in ["-s", "-m"] {
+        let args = [mode.as_ref(), "-z".as_ref(), "--output-delimiter=\\n".as_ref()];
+        let output = rsort_stdin(&args, b"a\0two\nlines\0");
+        assert_eq!(output.status.code(), Some(2), "{}", mode);
+        assert_eq!(
+            stderr(&output),
+            "rsort: a record contains the --output-delimiter '\\n'; use --force to write it anyway\n"
+        );
+    }
+
+    let output = rsort_stdin(&["--output-delimiter=ab".as_ref()], b"a\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "rsort: invalid --output-delimiter argument 'ab'\n");
+}
+
+/// Block SIGPIPE in rsort, as a parent can: resetting it to SIG_DFL does
+/// not unblock it, so writes to a closed pipe fail with EPIPE instead
+#[cfg(unix)]
+#[allow(unsafe_code)] // pre_exec runs between fork and exec
+fn block_sigpipe(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    unsafe {
+        command.pre_exec(|| {
+            let mut set = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+            libc::sigemptyset(set.as_mut_ptr());
+            libc::sigaddset(set.as_mut_ptr(), libc::SIGPIPE);
+            libc::pthread_sigmask(libc::SIG_BLOCK, set.as_ptr(), std::ptr::null_mut());
+            Ok(())
+        });
+    }
+}
+
+/// Run rsort with `args` on 200,000 records, reading one line of its
+/// output and closing the pipe, like `rsort big.txt | head -1`
+fn read_first_line(args: &[&std::ffi::OsStr]) -> Output {
+    use std::io::{BufRead, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("big.txt");
+    let records: String = (0..200_000).map(|i| format!("{:06}\n", i)).collect();
+    fs::write(&input, records).unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rsort"));
+    command.args(args).arg(&input).stdout(Stdio::piped()).stderr(Stdio::piped());
+    #[cfg(unix)]
+    block_sigpipe(&mut command);
+    let mut child = command.spawn().unwrap();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first).unwrap();
+    assert_eq!(first, "000000\n");
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_closed_stdout_is_success() {
+    let output = read_first_line(&[]);
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = read_first_line(&["-m".as_ref()]);
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+
+    // The --tee file still gets all of the output
+    let dir = tempfile::tempdir().unwrap();
+    let tee = dir.path().join("tee.txt");
+    let output = read_first_line(&["--tee".as_ref(), tee.as_os_str()]);
+    assert_eq!(stderr(&output), "");
+    assert_eq!(output.status.code(), Some(0));
+    let teed = fs::read_to_string(&tee).unwrap();
+    assert_eq!(teed.lines().count(), 200_000);
+    assert!(teed.ends_with("199999\n"));
+}
@@ -0,0 +1,361 @@
+//! Reading records: --take, --header, comment lines, record delimiters, byte
+//! order marks, encodings, and fixed-size or framed records
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rsort::Config;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_take_stops_mid_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![
+        write(dir.path(), "one.txt", b"c\na\nb\n"),
+        write(dir.path(), "two.txt", b"z\ny\nx\n"),
+        write(dir.path(), "three.txt", b"0\n"),
+    ];
+    let out = dir.path().join("out.txt");
+
+    let config = Config::builder().take(4).build().unwrap();
+    let stats = rsort::sort_files(&inputs, &out, &config).unwrap();
+
+    assert_eq!(fs::read(&out).unwrap(), b"a\nb\nc\nz\n");
+    assert_eq!(fs::read(&out).unwrap(), cli_sort(&inputs, &["--take=4"]));
+    assert_eq!(stats.records_read, 4);
+}
+
+#[test]
+fn test_take_with_nul_delimiter_and_unique() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.bin", b"b\0a\0b\0c\0")];
+    assert_eq!(cli_sort(&inputs, &["-z", "-u", "--take", "3"]), b"a\0b\0");
+}
+
+#[test]
+fn test_take_zero_is_empty_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"b\na\n")];
+    assert_eq!(cli_sort(&inputs, &["--take=0"]), b"");
+}
+
+#[test]
+fn test_comment_char_with_nul_delimiter_and_output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.bin", b"b\0;x\0a\0;x\0a\0")];
+    let out = dir.path().join("out.bin");
+    let out_arg = format!("-o{}", out.display());
+
+    let stdout = cli_sort(&inputs, &["-z", "-u", "--comment-char=;", &out_arg]);
+    assert!(stdout.is_empty());
+    assert_eq!(fs::read(&out).unwrap(), b";x\0;x\0a\0b\0");
+
+    let config = Config::builder()
+        .record_delimiter(0)
+        .unique(true)
+        .comment_char(b';')
+        .build()
+        .unwrap();
+    let library_out = dir.path().join("library.bin");
+    rsort::sort_files(&inputs, &library_out, &config).unwrap();
+    assert_eq!(fs::read(&library_out).unwrap(), fs::read(&out).unwrap());
+}
+
+#[test]
+fn test_header_stays_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(
+        dir.path(),
+        "scores.csv",
+        b"name,score\ncarol,9\nalice,10\nbob,9\nname,score\n",
+    )];
+
+    assert_eq!(
+        cli_sort(&inputs, &["-t,", "-n", "-k2,2", "--header=1"]),
+        b"name,score\nname,score\nbob,9\ncarol,9\nalice,10\n"
+    );
+    // The header is never a duplicate of a data line
+    assert_eq!(
+        cli_sort(&inputs, &["-t,", "-k1,1", "-u", "--header=1"]),
+        b"name,score\nalice,10\nbob,9\ncarol,9\nname,score\n"
+    );
+
+    let out = dir.path().join("out.csv");
+    let config = Config::builder()
+        .field_separator(b',')
+        .numeric(true)
+        .add_key("2,2")
+        .header(1)
+        .build()
+        .unwrap();
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), cli_sort(&inputs, &["-t,", "-n", "-k2,2", "--header=1"]));
+}
+
+#[test]
+fn test_header_spans_concatenated_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![
+        write(dir.path(), "one.txt", b"h1\n"),
+        write(dir.path(), "two.txt", b"h2\nb\na\n"),
+    ];
+    assert_eq!(cli_sort(&inputs, &["--header=2"]), b"h1\nh2\na\nb\n");
+}
+
+#[test]
+fn test_header_longer_than_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"b\na")];
+    assert_eq!(cli_sort(&inputs, &["--header=5"]), b"b\na\n");
+}
+
+#[test]
+fn test_header_counts_towards_take() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.bin", b"id\0c\0b\0a\0")];
+    assert_eq!(cli_sort(&inputs, &["-z", "--header=1", "--take=3"]), b"id\0b\0c\0");
+}
+
+#[test]
+fn test_record_delimiter_byte() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "rs.txt", b"b 2\x1ea\n3\x1ec 1")];
+
+    // Newlines are data; every output record ends in 0x1e
+    assert_eq!(cli_sort(&inputs, &["--record-delimiter=\\x1e"]), b"a\n3\x1eb 2\x1ec 1\x1e");
+    // A newline inside a record is a blank, as under -z: "a\n3" has field 2
+    let sorted = cli_sort(&inputs, &["--record-delimiter=\\036", "-k2,2n"]);
+    assert_eq!(sorted, b"c 1\x1eb 2\x1ea\n3\x1e");
+
+    let inputs = vec![write(dir.path(), "semi.txt", b"b;a;c;")];
+    assert_eq!(cli_sort(&inputs, &["--record-delimiter=;", "-r"]), b"c;b;a;");
+}
+
+#[test]
+fn test_record_delimiter_crlf() {
+    let dir = tempfile::tempdir().unwrap();
+    let crlf = write(dir.path(), "crlf.txt", b"c\r\na\rz\r\nb\r\n");
+    let unterminated = write(dir.path(), "last.txt", b"d\r\nb2");
+
+    // Round-trips byte for byte; the lone \r stays inside "a\rz"
+    let sorted = cli_sort(&[crlf], &["--record-delimiter=\\r\\n"]);
+    assert_eq!(sorted, b"a\rz\r\nb\r\nc\r\n");
+    let sorted = cli_sort(&[unterminated], &["--record-delimiter=\\r\\n", "-r"]);
+    assert_eq!(sorted, b"d\r\nb2\r\n");
+
+    let first = write(dir.path(), "first.txt", b"a\rz\r\nb\r\nc\r\n");
+    let second = write(dir.path(), "second.txt", b"b2\r\nd");
+    let merged = cli_sort(&[first, second], &["--record-delimiter=\\r\\n", "-m"]);
+    assert_eq!(merged, b"a\rz\r\nb\r\nb2\r\nc\r\nd\r\n");
+}
+
+#[test]
+fn test_crlf_keeps_each_line_ending() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "mixed.txt", b"x 10\r\ny 9\nz 100\r\nw 9\r")];
+
+    let sorted = cli_sort(&inputs, &["--crlf", "-k2,2n"]);
+    assert_eq!(sorted, b"w 9\r\ny 9\nx 10\r\nz 100\r\n");
+    // The last field is "9" on both 9 lines, so the tie goes to the whole line
+    let sorted = cli_sort(&inputs, &["--crlf", "-k2,2", "-r"]);
+    assert_eq!(sorted, b"y 9\nw 9\r\nz 100\r\nx 10\r\n");
+    // Without --crlf, "9\r" sorts after "9"
+    assert_eq!(cli_sort(&inputs, &["-k2,2", "-r"]), b"w 9\r\ny 9\nz 100\r\nx 10\r\n");
+}
+
+#[test]
+fn test_crlf_unique() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "dups.txt", b"b\r\na\na\r\nb\n")];
+
+    // Lines differing only by the \r are duplicates; the first one wins
+    assert_eq!(cli_sort(&inputs, &["--crlf", "-u"]), b"a\nb\r\n");
+    assert_eq!(cli_sort(&inputs, &["-u"]), b"a\na\r\nb\nb\r\n");
+    assert_eq!(cli_sort(&inputs, &["--crlf", "-s"]), b"a\na\r\nb\r\nb\n");
+}
+
+#[test]
+fn test_ignore_trailing_blanks() {
+    let dir = tempfile::tempdir().unwrap();
+    let dups = vec![write(dir.path(), "dups.txt", b"b \t\na\na  \nb\n a\n")];
+
+    // Padding no longer splits duplicates; leading blanks still do
+    assert_eq!(cli_sort(&dups, &["--ignore-trailing-blanks", "-u"]), b" a\na\nb \t\n");
+    assert_eq!(cli_sort(&dups, &["-u"]), b" a\na\na  \nb\nb \t\n");
+    // The last resort still sees the padding
+    assert_eq!(cli_sort(&dups, &["--ignore-trailing-blanks"]), b" a\na\na  \nb\nb \t\n");
+
+    let padded = vec![write(dir.path(), "padded.txt", b"1,x,ab  \n2,y,ab \n3,z,a\n")];
+    let sorted = cli_sort(&padded, &["--ignore-trailing-blanks", "-s", "-t,", "-k3"]);
+    assert_eq!(sorted, b"3,z,a\n1,x,ab  \n2,y,ab \n");
+    assert_eq!(cli_sort(&padded, &["-s", "-t,", "-k3"]), b"3,z,a\n2,y,ab \n1,x,ab  \n");
+}
+
+#[test]
+fn test_strip_bom() {
+    let dir = tempfile::tempdir().unwrap();
+    let bom = write(dir.path(), "bom.txt", b"\xEF\xBB\xBFname 2\nage 1\n");
+    let second = write(dir.path(), "second.txt", b"\xEF\xBB\xBFzeta 3\n");
+    let inputs = vec![bom.clone()];
+
+    // GNU sort keeps the BOM, so "name" sorts after every ASCII line
+    assert_eq!(cli_sort(&inputs, &[]), b"age 1\n\xEF\xBB\xBFname 2\n");
+    assert_eq!(cli_sort(&inputs, &["--strip-bom"]), b"age 1\nname 2\n");
+    assert_eq!(cli_sort(&inputs, &["--strip-bom", "-k1,1", "-r"]), b"name 2\nage 1\n");
+    assert_eq!(cli_sort(&inputs, &["--keep-bom"]), b"\xEF\xBB\xBFage 1\nname 2\n");
+
+    // Only the first input loses its BOM
+    let sorted = cli_sort(&[bom.clone(), second.clone()], &["--strip-bom"]);
+    assert_eq!(sorted, b"age 1\nname 2\n\xEF\xBB\xBFzeta 3\n");
+    let sorted = cli_sort(&[second, bom], &["--strip-bom", "-m"]);
+    assert_eq!(sorted, b"zeta 3\n\xEF\xBB\xBFname 2\nage 1\n");
+
+    let only_bom = vec![write(dir.path(), "only.txt", b"\xEF\xBB\xBF")];
+    assert_eq!(cli_sort(&only_bom, &["--strip-bom"]), b"");
+    assert_eq!(cli_sort(&only_bom, &["--keep-bom"]), b"\xEF\xBB\xBF");
+
+    let nul = vec![write(dir.path(), "nul.txt", b"\xEF\xBB\xBFb\0a\0")];
+    assert_eq!(cli_sort(&nul, &["--strip-bom", "-z"]), b"a\0b\0");
+}
+
+fn utf16le(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+#[test]
+fn test_utf16_encoding() {
+    let dir = tempfile::tempdir().unwrap();
+    let text = "zoë,3\nÉcole,10\nabc,2\n𝄞 clef,1\n";
+    let utf8 = vec![write(dir.path(), "utf8.txt", text.as_bytes())];
+    let mut bom = vec![0xFF, 0xFE];
+    bom.extend(utf16le(text));
+    let utf16 = vec![write(dir.path(), "utf16.txt", &bom)];
+
+    for args in [&[][..], &["-t,", "-k2,2n"], &["-r"]] {
+        let expected = cli_sort(&utf8, args);
+        let mut utf16_args = vec!["--encoding=utf-16le"];
+        utf16_args.extend(args);
+        assert_eq!(cli_sort(&utf16, &utf16_args), expected, "{:?}", args);
+
+        utf16_args.push("--output-encoding=UTF-16LE");
+        let expected = utf16le(std::str::from_utf8(&expected).unwrap());
+        assert_eq!(cli_sort(&utf16, &utf16_args), expected, "{:?}", args);
+    }
+
+    let utf16be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let utf16be = vec![write(dir.path(), "utf16be.txt", &utf16be)];
+    assert_eq!(cli_sort(&utf16be, &["--encoding=utf-16be"]), cli_sort(&utf8, &[]));
+}
+
+#[test]
+fn test_record_size() {
+    let dir = tempfile::tempdir().unwrap();
+    // Every byte value, newlines and NULs included, in 8-byte records
+    let bytes: Vec<u8> = (0..=255u8).rev().collect();
+    let input = write(dir.path(), "records.bin", &bytes);
+    let sorted = cli_sort(std::slice::from_ref(&input), &["--record-size=8"]);
+    let mut expected: Vec<&[u8]> = bytes.chunks(8).collect();
+    expected.sort();
+    assert_eq!(sorted, expected.concat());
+    let restored = cli_sort(&[write(dir.path(), "sorted.bin", &sorted)], &["--record-size=8", "-r"]);
+    assert_eq!(restored, bytes);
+
+    // A big-endian u32 at bytes 5-8 of 12-byte records, between junk
+    let values = [70_000u32, 255, 3, 256, 0xffff_fffe, 65_536];
+    let mut records = Vec::new();
+    for (i, value) in values.iter().enumerate() {
+        records.extend_from_slice(&[b'\n', 9 - i as u8, 0, b'x']);
+        records.extend_from_slice(&value.to_be_bytes());
+        records.extend_from_slice(&[0xff, b'\n', i as u8, 0]);
+    }
+    let input = write(dir.path(), "ints.bin", &records);
+    let sorted = cli_sort(&[input], &["--record-size", "12", "--key-bytes=5,8"]);
+    assert_eq!(sorted.len(), records.len());
+    let keys: Vec<u32> = sorted
+        .chunks(12)
+        .map(|record| u32::from_be_bytes(record[4..8].try_into().unwrap()))
+        .collect();
+    assert_eq!(keys, [3, 255, 256, 65_536, 70_000, 0xffff_fffe]);
+
+    let input = write(dir.path(), "partial.bin", b"abcdefghij");
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).arg("--record-size=4").arg(&input).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "rsort: {}: trailing partial record at byte 8: 2 bytes, short of --record-size (4 bytes)\n",
+            input.display()
+        )
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).args(["--record-size=4", "-z"]).arg(&input).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "rsort: options '--record-size' and '-z' are incompatible\n"
+    );
+}
+
+#[test]
+fn test_len32le_framing() {
+    let frame = |records: &[&[u8]]| -> Vec<u8> {
+        records
+            .iter()
+            .flat_map(|record| [&(record.len() as u32).to_le_bytes()[..], record].concat())
+            .collect()
+    };
+    let dir = tempfile::tempdir().unwrap();
+
+    // Records holding every byte value, NUL and newline included
+    let bytes: Vec<u8> = (0..=255u8).rev().collect();
+    let mut records: Vec<&[u8]> = bytes.chunks(7).collect();
+    records.push(b"");
+    let input = write(dir.path(), "records.bin", &frame(&records));
+    let sorted = cli_sort(std::slice::from_ref(&input), &["--framing=len32le"]);
+    let mut expected = records.clone();
+    expected.sort();
+    assert_eq!(sorted, frame(&expected));
+    let restored = cli_sort(&[write(dir.path(), "sorted.bin", &sorted)], &["--framing", "len32le", "-r"]);
+    expected.reverse();
+    assert_eq!(restored, frame(&expected));
+
+    // -k, -n and -u on text payloads
+    let input = write(dir.path(), "text.bin", &frame(&[b"b:10\nx", b"a:9", b"c:10", b"d:2"]));
+    let sorted = cli_sort(std::slice::from_ref(&input), &["--framing=len32le", "-t:", "-k2n"]);
+    assert_eq!(sorted, frame(&[b"d:2", b"a:9", b"b:10\nx", b"c:10"]));
+    let sorted = cli_sort(&[input], &["--framing=len32le", "-t:", "-k2,2n", "-u"]);
+    assert_eq!(sorted, frame(&[b"d:2", b"a:9", b"b:10\nx"]));
+
+    // A length reaching past the end of the input
+    let mut corrupted = frame(&[b"ok", b"broken"]);
+    corrupted[6] = 0x60;
+    let input = write(dir.path(), "corrupted.bin", &corrupted);
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).arg("--framing=len32le").arg(&input).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "rsort: {}: record at byte 6: declares 96 bytes, but the input ends after 6; corrupted length prefix?\n",
+            input.display()
+        )
+    );
+}
@@ -0,0 +1,153 @@
+//! Keys named by header column (--key-name) or JSON pointer (--json-key), or
+//! printed by a command (--key-command)
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_key_name_sorts_by_header_column() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(
+        dir.path(),
+        "orders.csv",
+        b"region,\"customer_id\",amount\nwest,7,10\neast,3,9\nwest,3,100\n",
+    )];
+
+    assert_eq!(
+        cli_sort(&inputs, &["-t,", "--header=1", "--key-name=amount:nr"]),
+        b"region,\"customer_id\",amount\nwest,3,100\nwest,7,10\neast,3,9\n"
+    );
+    assert_eq!(
+        cli_sort(&inputs, &["-t,", "--header=1", "--key-name=amount:nr"]),
+        cli_sort(&inputs, &["-t,", "--header=1", "-k3,3nr"])
+    );
+    // -k keys come first, then the named ones
+    assert_eq!(
+        cli_sort(&inputs, &["-t,", "--header=1", "--key-name=customer_id:n", "-k1,1"]),
+        b"region,\"customer_id\",amount\neast,3,9\nwest,3,100\nwest,7,10\n"
+    );
+}
+
+#[test]
+fn test_csv_with_header_and_key_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(
+        dir.path(),
+        "orders.csv",
+        b"\"customer, name\";\"total\"\nb;\"10\"\na;9\n",
+    )];
+
+    assert_eq!(
+        cli_sort(&inputs, &["--csv", "-t;", "--header=1", "--key-name=total:n"]),
+        b"\"customer, name\";\"total\"\na;9\nb;\"10\"\n"
+    );
+    assert_eq!(
+        cli_sort(&inputs, &["--csv", "-t;", "--header=1", "--key-name=customer, name:r"]),
+        b"\"customer, name\";\"total\"\nb;\"10\"\na;9\n"
+    );
+}
+
+#[test]
+fn test_json_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(
+        dir.path(),
+        "in.jsonl",
+        br#"{"id": 3, "user": {"name": "c", "score": 2.5}}
+{"id": 1, "user": {"name": "\u0062", "score": 10}}
+not json
+{"id": 2, "user": {"name": "a\"q", "score": 0.25}}
+{"id": 4}
+"#,
+    );
+    let ids = |args: &[&str]| -> Vec<String> {
+        let stdout = String::from_utf8(cli_sort(std::slice::from_ref(&input), args)).unwrap();
+        stdout
+            .lines()
+            .map(|line| line.split([',', '}']).next().unwrap().trim_start_matches("{\"id\": ").to_string())
+            .collect()
+    };
+
+    // Nested pointer, numeric float field; records without it come first
+    assert_eq!(ids(&["--json-key=/user/score", "-n"]), ["not json", "4", "2", "3", "1"]);
+    assert_eq!(ids(&["--json-key=/user/score", "-n", "-r"]), ["1", "3", "2", "4", "not json"]);
+    // Escaped strings compare unescaped: a"q < b < c
+    assert_eq!(ids(&["--json-key", "/user/name"]), ["not json", "4", "2", "1", "3"]);
+    // The records themselves are written as they were
+    let stdout = cli_sort(std::slice::from_ref(&input), &["--json-key=/id", "-n"]);
+    assert!(stdout.ends_with(b"{\"id\": 2, \"user\": {\"name\": \"a\\\"q\", \"score\": 0.25}}\n{\"id\": 3, \"user\": {\"name\": \"c\", \"score\": 2.5}}\n{\"id\": 4}\n"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort")).args(["--json-key=id"]).arg(&input).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "rsort: invalid --json-key argument 'id'; a JSON pointer starts with '/'\n"
+    );
+}
+
+#[test]
+fn test_unknown_key_name_lists_columns() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(dir.path(), "orders.csv", b"region,amount\nwest,10\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(["-t,", "--header=1", "--key-name=total"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "rsort: --key-name 'total': no such column in header (columns: region, amount)\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_key_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let words = write(dir.path(), "words.txt", b"banana\napple\ncherry\nApple\n");
+    let sort = |input: &PathBuf, args: &[&str]| String::from_utf8(cli_sort(std::slice::from_ref(input), args)).unwrap();
+
+    // Keys case folded by tr; the records break the Apple/apple tie
+    assert_eq!(sort(&words, &["--key-command=tr a-z A-Z"]), "Apple\napple\nbanana\ncherry\n");
+    // -u dedupes on the keys, keeping the first of the input
+    assert_eq!(sort(&words, &["--key-command", "tr a-z A-Z", "-u"]), "apple\nbanana\ncherry\n");
+
+    // The ordering options apply to the keys, -r included
+    let counts = write(dir.path(), "counts.txt", b"banana 3\nApple 10\ncherry 2\napple 1\n");
+    let by_count = ["--key-command=awk '{print $2}'", "-n"];
+    assert_eq!(sort(&counts, &by_count), "apple 1\ncherry 2\nbanana 3\nApple 10\n");
+    assert_eq!(sort(&counts, &[&by_count[..], &["-r"]].concat()), "Apple 10\nbanana 3\ncherry 2\napple 1\n");
+    // -k picks from the key lines
+    assert_eq!(
+        sort(&counts, &["--key-command=awk '{print $2, $1}'", "-k2,2"]),
+        "Apple 10\napple 1\nbanana 3\ncherry 2\n"
+    );
+
+    // The records stream through while the keys come back
+    let lines: Vec<String> = (0..200_000).map(|n| format!("{:06}", 199_999 - n)).collect();
+    let input = write(dir.path(), "many.txt", (lines.join("\n") + "\n").as_bytes());
+    let sorted = cli_sort(&[input], &["--key-command=cat"]);
+    let mut expected = lines.clone();
+    expected.sort();
+    assert_eq!(sorted, (expected.join("\n") + "\n").into_bytes());
+}
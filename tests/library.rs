@@ -0,0 +1,284 @@
+//! Tests for the embedding API (`rsort::Sorter`)
+
+use std::io::Cursor;
+
+use rsort::input::Framing;
+use rsort::{Config, RsortError, Sorter};
+
+#[test]
+fn test_default_is_bytewise() {
+    let sorted = Sorter::new().sort_bytes(b"b\nB\na\n").unwrap();
+    assert_eq!(sorted, b"B\na\nb\n");
+}
+
+#[test]
+fn test_builder_chain() {
+    let sorter = Sorter::new()
+        .numeric(true)
+        .key("2,2")
+        .unwrap()
+        .field_separator(b',')
+        .reverse(true);
+    assert_eq!(sorter.sort_bytes(b"a,1\nb,10\nc,2\n").unwrap(), b"b,10\nc,2\na,1\n");
+}
+
+#[test]
+fn test_invalid_key_is_error() {
+    let err = Sorter::new().key("0").unwrap_err();
+    assert!(matches!(err, RsortError::InvalidKey(_)));
+}
+
+#[test]
+fn test_unique_fold_case() {
+    let sorter = Sorter::new().fold_case(true).unique(true);
+    assert_eq!(sorter.sort_bytes(b"b\nA\na\nB\n").unwrap(), b"A\nb\n");
+}
+
+#[test]
+fn test_stable_preserves_input_order() {
+    let sorter = Sorter::new().stable(true).key("1,1").unwrap();
+    assert_eq!(sorter.sort_bytes(b"b 2\na 2\nb 1\na 1\n").unwrap(), b"a 2\na 1\nb 2\nb 1\n");
+}
+
+#[test]
+fn test_nul_delimited() {
+    let sorter = Sorter::new().record_delimiter(0);
+    assert_eq!(sorter.sort_bytes(b"b\na\0a\0").unwrap(), b"a\0b\na\0");
+}
+
+#[test]
+fn test_missing_trailing_delimiter_is_added() {
+    assert_eq!(Sorter::new().sort_bytes(b"b\na").unwrap(), b"a\nb\n");
+}
+
+#[test]
+fn test_sort_bytes_reports_unwritable_record() {
+    let config = Config::builder().output_delimiter(b' ').build().unwrap();
+    let err = Sorter::from_config(config).sort_bytes(b"a b\n").unwrap_err();
+    assert!(matches!(err, RsortError::Usage(_)), "{err}");
+    assert!(err.to_string().contains("--output-delimiter ' '"), "{err}");
+}
+
+#[test]
+fn test_sort_bytes_reports_bad_input() {
+    let err = Sorter::new().record_size(4).sort_bytes(b"abcdef").unwrap_err();
+    assert!(matches!(err, RsortError::PartialRecord { offset: 4, len: 2, .. }), "{err}");
+
+    let config = Config::builder().framing(Framing::Len32Le).build().unwrap();
+    let err = Sorter::from_config(config).sort_bytes(b"\x05\0\0\0ab").unwrap_err();
+    assert!(matches!(err, RsortError::BadFrame { offset: 0, .. }), "{err}");
+
+    let config = Config::builder()
+        .header(1)
+        .field_separator(b',')
+        .add_key_name("price")
+        .build()
+        .unwrap();
+    let err = Sorter::from_config(config).sort_bytes(b"name,qty\nb,2\n").unwrap_err();
+    assert!(err.to_string().contains("--key-name 'price': no such column"), "{err}");
+}
+
+#[test]
+fn test_sort_reader_to_writer_stats() {
+    let mut out = Vec::new();
+    let stats = Sorter::new()
+        .unique(true)
+        .sort_reader_to_writer(Cursor::new(b"c\na\nc\nb\na\n"), &mut out)
+        .unwrap();
+    assert_eq!(out, b"a\nb\nc\n");
+    assert_eq!(stats.records_read, 5);
+    assert_eq!(stats.records_written, 3);
+    assert_eq!(stats.duplicates_removed, 2);
+    assert_eq!((stats.bytes_read, stats.bytes_written), (10, 6));
+    assert_eq!(stats.runs_spilled, 0);
+}
+
+#[test]
+fn test_stats_count_comparisons_only_when_enabled() {
+    let input = b"d\nb\na\nc\nb\n";
+    let stats = Sorter::new().sort_reader_to_writer(&input[..], Vec::new()).unwrap();
+    assert_eq!(stats.comparisons, 0);
+    assert_eq!(stats.duplicates_removed, 0);
+
+    let stats = Sorter::new()
+        .collect_stats(true)
+        .sort_reader_to_writer(&input[..], Vec::new())
+        .unwrap();
+    assert!(stats.comparisons >= 4, "{:?}", stats);
+    assert_eq!(stats.records_written, 5);
+}
+
+#[test]
+fn test_stats_unique_by_key() {
+    let mut records = vec![&b"a 1"[..], b"b 1", b"a 2", b"c 3", b"b 2"];
+    let mut out = Vec::new();
+    let stats = Sorter::new()
+        .unique(true)
+        .key("1,1")
+        .unwrap()
+        .sort_and_write(&mut records, &mut out, true)
+        .unwrap();
+    assert_eq!(out, b"a 1\nb 1\nc 3\n");
+    assert_eq!(stats.records_read, 5);
+    assert_eq!(stats.duplicates_removed, 2);
+}
+
+#[test]
+fn test_conflicting_modes_are_error() {
+    let sorter = Sorter::new().numeric(true).month(true);
+    let err = sorter.sort_bytes(b"b\na\n").unwrap_err();
+    assert_eq!(err.to_string(), "options '-Mn' are incompatible");
+    assert!(sorter.sort_records(&mut [b"a"]).is_err());
+    assert!(sorter.sort_reader_to_writer(&b"a\n"[..], Vec::new()).is_err());
+
+    let config = Config::builder().version(true).build().unwrap();
+    let sorter = Sorter::from_config(config).fold_case(true).human_numeric(true);
+    let err = sorter.sort_indices(&[b"a"]).unwrap_err();
+    assert_eq!(err.to_string(), "options '-fhV' are incompatible");
+
+    // Switching the first one off again leaves one
+    let sorter = Sorter::new().numeric(true).month(true).numeric(false);
+    assert_eq!(sorter.sort_bytes(b"FEB\nJAN\n").unwrap(), b"JAN\nFEB\n");
+}
+
+#[test]
+fn test_sort_records_in_place() {
+    let mut records = vec![b"10".to_vec(), b"9".to_vec(), b"-1".to_vec()];
+    Sorter::new().numeric(true).sort_records(&mut records).unwrap();
+    assert_eq!(records, vec![b"-1".to_vec(), b"9".to_vec(), b"10".to_vec()]);
+}
+
+#[test]
+fn test_sort_indices_leaves_storage_alone() {
+    let records: Vec<std::sync::Arc<[u8]>> =
+        vec![b"b".as_slice().into(), b"c".as_slice().into(), b"a".as_slice().into()];
+    let perm = Sorter::new().sort_indices(&records).unwrap();
+    assert_eq!(perm, vec![2, 0, 1]);
+    assert_eq!(&*records[0], b"b");
+}
+
+fn vowels(s: &[u8]) -> usize {
+    s.iter().filter(|b| b"aeiouAEIOU".contains(b)).count()
+}
+
+fn by_vowels() -> Sorter {
+    Sorter::new().compare_keys_with(|a, b| vowels(a).cmp(&vowels(b)))
+}
+
+#[test]
+fn test_custom_comparator_orders_keys() {
+    // Ties on vowel count fall back to the bytewise last resort
+    let sorted = by_vowels().sort_bytes(b"banana\nkiwi\nfig\nplum\napple\n").unwrap();
+    assert_eq!(sorted, b"fig\nplum\napple\nkiwi\nbanana\n");
+
+    let sorted = by_vowels().reverse(true).sort_bytes(b"fig\nbanana\nkiwi\n").unwrap();
+    assert_eq!(sorted, b"banana\nkiwi\nfig\n");
+}
+
+#[test]
+fn test_custom_comparator_applies_per_key() {
+    let sorter = by_vowels().field_separator(b':').key("2,2").unwrap().key("1,1").unwrap();
+    let sorted = sorter.sort_bytes(b"b:aa\na:e\nc:xyz\n").unwrap();
+    assert_eq!(sorted, b"c:xyz\na:e\nb:aa\n");
+}
+
+#[test]
+fn test_custom_comparator_stable_and_unique() {
+    let sorted = by_vowels().stable(true).sort_bytes(b"plum\nfig\nkiwi\nmint\n").unwrap();
+    assert_eq!(sorted, b"plum\nfig\nmint\nkiwi\n");
+
+    let sorted = by_vowels().unique(true).sort_bytes(b"plum\nfig\nkiwi\nmint\n").unwrap();
+    assert_eq!(sorted, b"plum\nkiwi\n");
+}
+
+#[test]
+fn test_custom_comparator_reaches_check_and_merge() {
+    let sorter = by_vowels().stable(true);
+
+    let result = rsort::check::check_sorted(&b"fig\nkiwi\nbanana\n"[..], sorter.config()).unwrap();
+    assert!(result.is_sorted());
+    let result = rsort::check::check_sorted(&b"kiwi\nfig\n"[..], sorter.config()).unwrap();
+    assert!(!result.is_sorted());
+
+    let sources: Vec<Box<dyn std::io::BufRead>> =
+        vec![Box::new(&b"fig\nbanana\n"[..]), Box::new(&b"kiwi\n"[..])];
+    let mut merged = Vec::new();
+    rsort::merge::merge_streams(sources, sorter.config(), &mut merged).unwrap();
+    assert_eq!(merged, b"fig\nkiwi\nbanana\n");
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn test_rsorted_unique_matches_cli() {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use rsort::{Config, SortedExt};
+
+    let input = b"b 2\nA 1\na 3\nB 1\nc 0\na 1\n";
+    let cases: [&[&str]; 3] = [&["-u"], &["-u", "-f"], &["-u", "-f", "-k1,1"]];
+    for args in cases {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let cli = child.wait_with_output().unwrap().stdout;
+
+        let mut builder = Config::builder().fold_case(args.contains(&"-f"));
+        if args.contains(&"-k1,1") {
+            builder = builder.add_key("1,1");
+        }
+        let config = builder.build().unwrap();
+        let records = input.split(|&b| b == b'\n').filter(|r| !r.is_empty());
+        let mut lib = Vec::new();
+        for record in records.rsorted_unique(&config) {
+            lib.extend_from_slice(record);
+            lib.push(b'\n');
+        }
+        assert_eq!(lib, cli, "{:?}", args);
+    }
+}
+
+#[test]
+fn test_take_leaves_rest_of_input_unread() {
+    use std::io::{BufReader, Read};
+
+    /// Errors on any read past the two records it holds
+    struct Tripwire(Cursor<&'static [u8]>);
+
+    impl Read for Tripwire {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.read(buf)? {
+                0 => Err(std::io::Error::other("read past the cutoff")),
+                n => Ok(n),
+            }
+        }
+    }
+
+    let config = Config::builder().take(2).build().unwrap();
+    let reader = BufReader::new(Tripwire(Cursor::new(b"b\na\n")));
+    let mut output = Vec::new();
+    let stats = Sorter::from_config(config).sort_reader_to_writer(reader, &mut output).unwrap();
+    assert_eq!(output, b"a\nb\n");
+    assert_eq!(stats.records_read, 2);
+}
+
+#[test]
+fn test_comments_float_to_top_in_input_order() {
+    let input = b"b\n# second\na\n#first\nc\n# second\n";
+    let sorter = Sorter::from_config(Config::builder().comment_char(b'#').build().unwrap());
+    assert_eq!(sorter.sort_bytes(input).unwrap(), b"# second\n#first\n# second\na\nb\nc\n");
+
+    let config = Config::builder().comment_char(b'#').unique(true).reverse(true).build().unwrap();
+    let sorter = Sorter::from_config(config);
+    assert_eq!(sorter.sort_bytes(input).unwrap(), b"# second\n#first\n# second\nc\nb\na\n");
+
+    // Without --comment-char, '#' is just a byte
+    assert_eq!(
+        Sorter::new().sort_bytes(input).unwrap(),
+        b"# second\n# second\n#first\na\nb\nc\n"
+    );
+}
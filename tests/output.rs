@@ -0,0 +1,406 @@
+//! Writing the result: -o targets, delimiters and final newlines, --append,
+//! --tee, --group, --print-key, --number, --split-output and --verify
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rsort::Config;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn test_merge_appending_to_an_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = write(dir.path(), "log.txt", b"1\n3\n");
+    let new = write(dir.path(), "new.txt", b"2\n4\n");
+    let hard = dir.path().join("hard.txt");
+    let soft = dir.path().join("soft.txt");
+    fs::hard_link(&log, &hard).unwrap();
+    std::os::unix::fs::symlink(&log, &soft).unwrap();
+
+    // Each name for the output is merged as it was before the run, never
+    // as the run leaves it
+    let log_arg = log.to_str().unwrap();
+    let inputs = vec![log.clone(), new.clone()];
+    assert_eq!(cli_sort(&inputs, &["-m", "--append", "-o", log_arg]), b"");
+    assert_eq!(fs::read(&log).unwrap(), b"1\n3\n1\n2\n3\n4\n");
+
+    fs::write(&log, b"1\n3\n").unwrap();
+    let inputs = vec![hard, new.clone(), soft];
+    assert_eq!(cli_sort(&inputs, &["-m", "--append", "-o", log_arg]), b"");
+    assert_eq!(fs::read(&log).unwrap(), b"1\n3\n1\n1\n2\n3\n3\n4\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_output_to_special_files_and_symlinks() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"b\na\n")];
+
+    assert_eq!(cli_sort(&inputs, &["-o", "/dev/null"]), b"");
+    assert_eq!(cli_sort(&inputs, &["--fsync", "-o", "/dev/null"]), b"");
+    assert_eq!(cli_sort(&inputs, &["-o", "/dev/stdout"]), b"a\nb\n");
+
+    let fifo = dir.path().join("fifo");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    let reader = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || fs::read(fifo).unwrap())
+    };
+    assert_eq!(cli_sort(&inputs, &["-o", fifo.to_str().unwrap()]), b"");
+    assert_eq!(reader.join().unwrap(), b"a\nb\n");
+
+    // A symlink is followed: the file it names is replaced, the link kept
+    let target = write(dir.path(), "target.txt", b"old\n");
+    let link = dir.path().join("link.txt");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+    assert_eq!(cli_sort(&inputs, &["-o", link.to_str().unwrap()]), b"");
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read(&target).unwrap(), b"a\nb\n");
+
+    // A dangling one creates the file it names
+    let missing = dir.path().join("missing.txt");
+    let dangling = dir.path().join("dangling.txt");
+    std::os::unix::fs::symlink(&missing, &dangling).unwrap();
+    assert_eq!(cli_sort(&inputs, &["-o", dangling.to_str().unwrap()]), b"");
+    assert!(fs::symlink_metadata(&dangling).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read(&missing).unwrap(), b"a\nb\n");
+}
+
+#[test]
+fn test_output_delimiter() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "lines.txt", b"b\nc\na\nb\n")];
+
+    let nul = cli_sort(&inputs, &["--output-delimiter=\\0", "-u"]);
+    let records: Vec<&[u8]> = nul.split(|&b| b == 0).collect();
+    assert_eq!(records, [&b"a"[..], b"b", b"c", b""]);
+    let sorted = write(dir.path(), "sorted.txt", b"a\nb\nb\n");
+    let merged = cli_sort(&[sorted.clone(), sorted], &["-m", "-u", "--output-delimiter=\\0"]);
+    assert_eq!(merged, b"a\0b\0");
+
+    // And back, with a newline inside a record
+    let inputs = vec![write(dir.path(), "records", b"y\0two\nlines\0x\0")];
+    let lines = cli_sort(&inputs, &["-z", "--output-delimiter=\\n", "--force"]);
+    assert_eq!(lines, b"two\nlines\nx\ny\n");
+    let config = Config::builder().record_delimiter(0).output_delimiter(b'\n').build().unwrap();
+    let out = dir.path().join("out.txt");
+    assert!(rsort::sort_files(&inputs, &out, &config).is_err());
+    assert!(!out.exists());
+}
+
+#[test]
+fn test_preserve_final_newline() {
+    let dir = tempfile::tempdir().unwrap();
+    let unterminated = vec![write(dir.path(), "unterminated.txt", b"b\na")];
+    let terminated = vec![write(dir.path(), "terminated.txt", b"b\na\n")];
+
+    assert_eq!(cli_sort(&unterminated, &[]), b"a\nb\n");
+    assert_eq!(cli_sort(&unterminated, &["--preserve-final-newline"]), b"a\nb");
+    assert_eq!(cli_sort(&terminated, &["--preserve-final-newline"]), b"a\nb\n");
+    let nul = vec![write(dir.path(), "nul", b"b\0a")];
+    assert_eq!(cli_sort(&nul, &["-z", "--preserve-final-newline"]), b"a\0b");
+
+    let sorter = rsort::Sorter::from_config(Config::builder().preserve_final_newline(true).build().unwrap());
+    assert_eq!(sorter.sort_bytes(b"b\na").unwrap(), b"a\nb");
+    assert_eq!(sorter.sort_bytes(b"b\na\n").unwrap(), b"a\nb\n");
+}
+
+#[test]
+fn test_final_newline_of_several_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let unterminated = write(dir.path(), "unterminated.txt", b"d\nc");
+    let terminated = write(dir.path(), "terminated.txt", b"b\na\n");
+    let empty = write(dir.path(), "empty.txt", b"");
+    let last_byte = |inputs: &[PathBuf], args: &[&str]| *cli_sort(inputs, args).last().unwrap();
+
+    // Each file's last record stands alone; by default every record ends in a newline
+    let first_unterminated = [unterminated.clone(), terminated.clone()];
+    let last_unterminated = [terminated.clone(), unterminated.clone()];
+    assert_eq!(cli_sort(&first_unterminated, &[]), b"a\nb\nc\nd\n");
+    assert_eq!(last_byte(&last_unterminated, &[]), b'\n');
+
+    // With --preserve-final-newline the last input with records decides
+    let preserve = ["--preserve-final-newline"];
+    assert_eq!(last_byte(&first_unterminated, &preserve), b'\n');
+    assert_eq!(cli_sort(&last_unterminated, &preserve), b"a\nb\nc\nd");
+    assert_eq!(last_byte(&[unterminated.clone(), empty], &preserve), b'd');
+    assert_eq!(last_byte(std::slice::from_ref(&terminated), &preserve), b'\n');
+
+    // Stdin counts as one more input
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(["--preserve-final-newline", "-"])
+        .arg(&terminated)
+        .stdin(fs::File::open(&unterminated).unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"a\nb\nc\nd\n");
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .arg("--preserve-final-newline")
+        .stdin(fs::File::open(&unterminated).unwrap())
+        .output()
+        .unwrap();
+    assert_eq!(output.stdout, b"c\nd");
+}
+
+#[test]
+fn test_line_buffered_merge_streams_each_record() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(["-m", "--line-buffered"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let (lines, received) = mpsc::channel();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let reader = std::thread::spawn(move || {
+        for line in stdout.lines() {
+            lines.send(line.unwrap()).unwrap();
+        }
+    });
+
+    // Each record comes out before the next one goes in
+    for record in ["a", "b"] {
+        writeln!(stdin, "{}", record).unwrap();
+        stdin.flush().unwrap();
+        assert_eq!(received.recv_timeout(Duration::from_secs(10)).unwrap(), record);
+    }
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+    reader.join().unwrap();
+}
+
+#[test]
+fn test_append() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = vec![write(dir.path(), "monday.txt", b"b\na\nb\n")];
+    let second = vec![write(dir.path(), "tuesday.txt", b"c\nb\n")];
+    let out = dir.path().join("all.txt");
+    let out_arg = out.to_str().unwrap();
+
+    // Created when missing; -u removes duplicates within each run only
+    assert_eq!(cli_sort(&first, &["--append", "-u", "-o", out_arg]), b"");
+    assert_eq!(cli_sort(&second, &["--append", "-u", "--fsync", "-o", out_arg]), b"");
+    assert_eq!(fs::read(&out).unwrap(), b"a\nb\nb\nc\n");
+
+    let config = Config::builder().append(true).build().unwrap();
+    rsort::sort_files(&first, &out, &config).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), b"a\nb\nb\nc\na\nb\nb\n");
+
+    // Ignored for stdout
+    assert_eq!(cli_sort(&second, &["--append"]), b"b\nc\n");
+}
+
+#[test]
+fn test_tee() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"b\0c\0a\0")];
+    let (one, two, out) = (dir.path().join("one"), dir.path().join("two"), dir.path().join("out"));
+    let (one_arg, two_arg, out_arg) = (one.to_str().unwrap(), two.to_str().unwrap(), out.to_str().unwrap());
+
+    // Every sink gets the same bytes
+    let stdout = cli_sort(&inputs, &["-z", "--tee", one_arg, "--tee", two_arg]);
+    assert_eq!(stdout, b"a\0b\0c\0");
+    assert_eq!(fs::read(&one).unwrap(), stdout);
+    assert_eq!(fs::read(&two).unwrap(), stdout);
+
+    // With -o, the -o file takes the place of stdout; --append applies to
+    // the tee too
+    assert_eq!(cli_sort(&inputs, &["-z", "-r", "--append", "-o", out_arg, "--tee", one_arg]), b"");
+    assert_eq!(fs::read(&out).unwrap(), b"c\0b\0a\0");
+    assert_eq!(fs::read(&one).unwrap(), b"a\0b\0c\0c\0b\0a\0");
+
+    let lines = vec![write(dir.path(), "lines.txt", b"y\nx\n")];
+    let config = Config::builder().tee(&two).build().unwrap();
+    rsort::sort_files(&lines, &out, &config).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), b"x\ny\n");
+    assert_eq!(fs::read(&two).unwrap(), b"x\ny\n");
+}
+
+#[test]
+fn test_group() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"B 7\na 007\nb 3\nA 8\n")];
+
+    // A separator between runs only, never first or last
+    assert_eq!(cli_sort(&inputs, &["--group", "-f", "-k1,1"]), b"A 8\na 007\n\nB 7\nb 3\n");
+    assert_eq!(cli_sort(&inputs, &["--group=--", "-k2,2n"]), b"b 3\n--\nB 7\na 007\n--\nA 8\n");
+    // Ties between unequal records in one group still take the last resort
+    assert_eq!(cli_sort(&inputs, &["--group", "-t", " ", "-k2n", "-r"]), b"b 3\n\na 007\nB 7\n\nA 8\n");
+
+    let nul = vec![write(dir.path(), "nul", b"b\0a\0B\0")];
+    assert_eq!(cli_sort(&nul, &["-z", "--group", "-f"]), b"a\0\0B\0b\0");
+    let sorted = vec![write(dir.path(), "sorted", b"a\0A\0b\0")];
+    assert_eq!(cli_sort(&sorted, &["-z", "--group", "-f", "-m"]), b"a\0A\0\0b\0");
+    let one = vec![write(dir.path(), "one.txt", b"x\n")];
+    assert_eq!(cli_sort(&one, &["--group"]), b"x\n");
+}
+
+#[test]
+fn test_print_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"x:b  2:q\ny:a 1\nz\n")];
+
+    // Multi-field spans, and a missing field as an empty record
+    assert_eq!(cli_sort(&inputs, &["--print-key", "-t:", "-k2,3"]), b"\na 1\nb  2:q\n");
+    // Several keys joined by a tab, after b skips the blanks
+    assert_eq!(cli_sort(&inputs, &["--print-key", "-t ", "-k2b", "-k1,1"]), b"\tz\n1\ty:a\n2:q\tx:b\n");
+    assert_eq!(
+        cli_sort(&inputs, &["--print-key", "--key-output-separator=|", "-t:", "-k3,3", "-k1,1"]),
+        b"|y\n|z\nq|x\n"
+    );
+    // One key per record -u keeps
+    assert_eq!(cli_sort(&inputs, &["--print-key", "-u", "-t:", "-k3,3"]), b"\nq\n");
+    // Without keys, the whole record
+    assert_eq!(cli_sort(&inputs, &["--print-key", "-r"]), b"z\ny:a 1\nx:b  2:q\n");
+
+    let config = Config::builder().print_key(true).add_key("2n").field_separator(b':').build().unwrap();
+    let out = dir.path().join("out");
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    // No key is a number, so the last resort decides
+    assert_eq!(fs::read(&out).unwrap(), b"b  2:q\na 1\n\n");
+}
+
+#[test]
+fn test_number() {
+    let dir = tempfile::tempdir().unwrap();
+    let text: Vec<u8> = (0..60).flat_map(|i| format!("{} {}\n", i % 4, 60 - i).into_bytes()).collect();
+    let inputs = vec![
+        write(dir.path(), "one.txt", &text[..text.len() / 2]),
+        write(dir.path(), "two.txt", &text[text.len() / 2..]),
+    ];
+
+    // A stable sort keeps each run of equal keys in rising position order,
+    // counted on across the second file
+    let output = cli_sort(&inputs, &["--number", "-s", "-k1,1n"]);
+    let lines: Vec<(u64, &[u8])> = output
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let tab = line.iter().position(|&b| b == b'\t').unwrap();
+            (std::str::from_utf8(&line[..tab]).unwrap().parse().unwrap(), &line[tab + 1..])
+        })
+        .collect();
+    assert_eq!(lines.len(), 60);
+    for pair in lines.windows(2) {
+        let (key, next_key) = (pair[0].1[0], pair[1].1[0]);
+        assert!(key < next_key || (key == next_key && pair[0].0 < pair[1].0), "{:?}", pair);
+    }
+    // Each number is the record's place in the two files together
+    for (number, line) in &lines {
+        let i = (number - 1) as usize;
+        assert_eq!(*line, format!("{} {}", i % 4, 60 - i).as_bytes());
+    }
+
+    // -u numbers the record kept, the first of its run
+    assert_eq!(cli_sort(&inputs, &["--number", "-u", "-k1,1"]), b"1\t0 60\n2\t1 59\n3\t2 58\n4\t3 57\n");
+}
+
+#[test]
+fn test_split_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let text: Vec<u8> = (0..100).flat_map(|i| format!("{} {}\n", i * 37 % 23, i).into_bytes()).collect();
+    let inputs = vec![write(dir.path(), "in.txt", &text)];
+    let prefix = dir.path().join("part");
+    let prefix_arg = prefix.to_str().unwrap();
+    let chunk = |i: usize| fs::read(dir.path().join(format!("part.{:03}", i))).unwrap();
+
+    // The files end to end are the single output, -u applied before splitting
+    let cases: [(&[&str], &[&str]); 3] =
+        [(&["-n"], &[]), (&["-n", "-u", "-k1,1"], &[]), (&["-r", "-k1,1n"], &["--split-by-key"])];
+    for (args, split) in cases {
+        let split_args = [args, split, &["--split-output", "7", "--output-prefix", prefix_arg]].concat();
+        assert_eq!(cli_sort(&inputs, &split_args), b"");
+        let joined: Vec<u8> = (0..7).flat_map(chunk).collect();
+        assert_eq!(joined, cli_sort(&inputs, args), "{:?}", args);
+    }
+    // 100 records: two files of 15, then five of 14
+    cli_sort(&inputs, &["-n", "--split-output", "7", "--output-prefix", prefix_arg]);
+    let counts: Vec<usize> = (0..7).map(|i| chunk(i).iter().filter(|&&b| b == b'\n').count()).collect();
+    assert_eq!(counts, [15, 15, 14, 14, 14, 14, 14]);
+
+    // More files than records: the last ones are empty
+    let few = vec![write(dir.path(), "few.txt", b"b\na")];
+    cli_sort(&few, &["--split-output", "4", "--output-prefix", prefix_arg]);
+    assert_eq!((chunk(0), chunk(1), chunk(2), chunk(3)), (b"a\n".to_vec(), b"b\n".to_vec(), vec![], vec![]));
+}
+
+#[test]
+fn test_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(dir.path(), "in.txt", b"10\n2\n1\n");
+    let out = dir.path().join("out.txt");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rsort"))
+            .args(args)
+            .arg(&input)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["--verify", "-n", "-o", out.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read(&out).unwrap(), b"1\n2\n10\n");
+
+    // Every entry point without a single output file refuses it alike
+    let prefix = dir.path().join("part");
+    for args in [
+        &["--verify", "-n"][..],
+        &["--verify", "-m"],
+        &["--verify", "--split-output", "2", "--output-prefix", prefix.to_str().unwrap()],
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert_eq!(output.stdout, b"");
+        assert_eq!(output.stderr, b"rsort: --verify requires -o FILE\n");
+    }
+}
+
+/// Redirected stdout on Windows is a byte sink: nothing is translated
+#[cfg(windows)]
+#[test]
+fn test_redirected_stdout_keeps_every_byte() {
+    let dir = tempfile::tempdir().unwrap();
+    // Every byte value: 0x00 ends each record, which starts with one of the
+    // others, so the input is already in byte order
+    let records: Vec<u8> = (1..=255u8).flat_map(|byte| [byte, b'x', 0]).collect();
+    let input = write(dir.path(), "bytes.bin", &records);
+    let out = dir.path().join("out.bin");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .arg("-z")
+        .arg(&input)
+        .stdout(fs::File::create(&out).unwrap())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let checksum = |bytes: &[u8]| {
+        bytes.iter().fold(0u64, |sum, &byte| sum.wrapping_mul(31).wrapping_add(byte.into()))
+    };
+    let written = fs::read(&out).unwrap();
+    assert_eq!(written.len(), records.len());
+    assert_eq!(checksum(&written), checksum(&records));
+}
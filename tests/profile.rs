@@ -0,0 +1,56 @@
+//! `.rsort.toml` discovery through the binary
+#![cfg(all(feature = "cli", feature = "profile"))]
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn run_in(dir: &Path, input: &[u8], args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn rsort");
+    // rsort may exit on a bad profile before reading; a broken pipe is fine
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_default_profile_is_loaded_from_cwd() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".rsort.toml"), "numeric = true\nkeys = [\"2,2\"]\n").unwrap();
+
+    let output = run_in(dir.path(), b"a 10\nb 9\n", &[]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"b 9\na 10\n");
+
+    // -k on the command line replaces the profile's keys; -n still applies
+    let output = run_in(dir.path(), b"2 b\n10 a\n", &["-k1,1"]);
+    assert_eq!(output.stdout, b"2 b\n10 a\n");
+}
+
+#[test]
+fn test_explicit_profile_wins_over_cwd() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".rsort.toml"), "reverse = true\n").unwrap();
+    std::fs::write(dir.path().join("plain.toml"), "").unwrap();
+
+    let output = run_in(dir.path(), b"a\nb\n", &["--profile", "plain.toml"]);
+    assert_eq!(output.stdout, b"a\nb\n");
+}
+
+#[test]
+fn test_malformed_profile_fails_with_path_and_line() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join(".rsort.toml"), "numeric = true\nbogus = 1\n").unwrap();
+
+    let output = run_in(dir.path(), b"a\n", &[]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(".rsort.toml:2"), "{}", stderr);
+    assert!(stderr.contains("bogus"), "{}", stderr);
+}
@@ -0,0 +1,197 @@
+//! Property-based tests for the comparison contract and sort invariants
+//!
+//! Unlike the differential suite these need no GNU sort: they check that
+//! the comparator is a total order and that sorting only permutes records.
+
+use std::cmp::Ordering;
+
+use proptest::prelude::*;
+use rsort::compare::Comparator;
+use rsort::config::Config;
+use rsort::key::KeySpec;
+use rsort::output::write_records;
+use rsort::sort::sort_records;
+
+/// Bytes chosen to collide often: case pairs, digits, signs, separators, high bytes
+fn record() -> impl Strategy<Value = Vec<u8>> {
+    let byte = prop::sample::select(&b"aAbB019 \t:-+.,\xff"[..]);
+    prop::collection::vec(byte, 0..12)
+}
+
+fn records() -> impl Strategy<Value = Vec<Vec<u8>>> {
+    prop::collection::vec(record(), 0..40)
+}
+
+/// Valid keydefs: FIELD[.CHAR][,FIELD[.CHAR]] with end >= start
+fn key_spec() -> impl Strategy<Value = KeySpec> {
+    (1usize..4, prop::option::of(1usize..4), prop::option::of((0usize..3, prop::option::of(1usize..4))))
+        .prop_map(|(start_field, start_char, end)| {
+            let mut keydef = start_field.to_string();
+            if let Some(c) = start_char {
+                keydef.push_str(&format!(".{}", c));
+            }
+            if let Some((extra, end_char)) = end {
+                let end_field = start_field + extra;
+                keydef.push_str(&format!(",{}", end_field));
+                if let Some(c) = end_char {
+                    // Same field: end char may not precede start char
+                    let c = if extra == 0 { c.max(start_char.unwrap_or(1)) } else { c };
+                    keydef.push_str(&format!(".{}", c));
+                }
+            }
+            KeySpec::parse(&keydef).unwrap()
+        })
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (
+        any::<[bool; 5]>(),
+        prop::sample::select(vec![None, Some(':'), Some(' '), Some(','), Some('§')]),
+        prop::collection::vec(key_spec(), 0..3),
+    )
+        .prop_map(|(flags, field_separator, keys)| {
+            let builder = keys
+                .into_iter()
+                .fold(Config::builder(), |builder, key| builder.key(key))
+                .reverse(flags[0])
+                .numeric(flags[1])
+                .fold_case(flags[2])
+                .unique(flags[3])
+                .stable(flags[4]);
+            let builder = match field_separator {
+                Some(c) => builder.field_separator(c),
+                None => builder,
+            };
+            builder.build().unwrap()
+        })
+}
+
+fn sorted_copy(records: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut sorted = records.to_vec();
+    sorted.sort();
+    sorted
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn compare_is_reflexive(config in config(), a in record()) {
+        prop_assert_eq!(Comparator::new(&config).cmp(&a, &a), Ordering::Equal);
+    }
+
+    #[test]
+    fn compare_is_antisymmetric(config in config(), a in record(), b in record()) {
+        let comparator = Comparator::new(&config);
+        prop_assert_eq!(comparator.cmp(&a, &b), comparator.cmp(&b, &a).reverse());
+    }
+
+    #[test]
+    fn compare_is_transitive(config in config(), a in record(), b in record(), c in record()) {
+        let comparator = Comparator::new(&config);
+        let ab = comparator.cmp(&a, &b);
+        let bc = comparator.cmp(&b, &c);
+        // a <= b <= c implies a <= c, with equality only if both steps are equal
+        if ab != Ordering::Greater && bc != Ordering::Greater {
+            let ac = comparator.cmp(&a, &c);
+            prop_assert_ne!(ac, Ordering::Greater);
+            if ab == Ordering::Less || bc == Ordering::Less {
+                prop_assert_eq!(ac, Ordering::Less);
+            }
+        }
+    }
+
+    #[test]
+    fn last_resort_only_ties_identical_records(config in config(), a in record(), b in record()) {
+        let mut config = config;
+        config.stable = false;
+        config.unique = false;
+        let ordering = Comparator::new(&config).cmp(&a, &b);
+        prop_assert_eq!(ordering == Ordering::Equal, a == b);
+    }
+
+    #[test]
+    fn sort_is_ordered_permutation(config in config(), input in records()) {
+        let mut output = input.clone();
+        sort_records(&mut output, &config);
+
+        prop_assert_eq!(sorted_copy(&output), sorted_copy(&input));
+        let comparator = Comparator::new(&config);
+        for pair in output.windows(2) {
+            prop_assert_ne!(comparator.cmp(&pair[0], &pair[1]), Ordering::Greater);
+        }
+    }
+
+    #[test]
+    fn sort_is_idempotent(config in config(), input in records()) {
+        let mut once = input;
+        sort_records(&mut once, &config);
+        let mut twice = once.clone();
+        sort_records(&mut twice, &config);
+        prop_assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn stable_sort_preserves_input_order_of_equals(config in config(), input in records()) {
+        let mut config = config;
+        config.stable = true;
+        let comparator = Comparator::new(&config);
+
+        let mut output = input.clone();
+        sort_records(&mut output, &config);
+
+        // Each run of equal records must appear in input order
+        let position = |record: &Vec<u8>, skip: &mut Vec<bool>| {
+            let idx = input
+                .iter()
+                .enumerate()
+                .position(|(i, r)| !skip[i] && r == record)
+                .unwrap();
+            skip[idx] = true;
+            idx
+        };
+        let mut used = vec![false; input.len()];
+        let positions: Vec<usize> = output.iter().map(|r| position(r, &mut used)).collect();
+        for i in 1..output.len() {
+            if comparator.cmp(&output[i - 1], &output[i]) == Ordering::Equal
+                && output[i - 1] != output[i]
+            {
+                prop_assert!(positions[i - 1] < positions[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn unique_output_has_no_adjacent_equal_keys(config in config(), input in records()) {
+        let mut config = config;
+        config.unique = true;
+        // Records must not contain the delimiter for the output to split back cleanly
+        let input: Vec<Vec<u8>> = input
+            .into_iter()
+            .map(|r| r.into_iter().filter(|&b| b != b'\n').collect())
+            .collect();
+
+        let mut sorted = input;
+        sort_records(&mut sorted, &config);
+        let mut output = Vec::new();
+        write_records(&mut output, &sorted, &config, true).unwrap();
+
+        let written: Vec<&[u8]> = output
+            .strip_suffix(b"\n")
+            .map(|body| body.split(|&b| b == b'\n').collect())
+            .unwrap_or_default();
+        let comparator = Comparator::new(&config);
+        for pair in written.windows(2) {
+            prop_assert_eq!(comparator.cmp(pair[0], pair[1]), Ordering::Less);
+        }
+        // Every input record is represented by a key-equal survivor
+        for record in &sorted {
+            prop_assert!(written.iter().any(|w| comparator.cmp(w, record) == Ordering::Equal));
+        }
+    }
+
+    #[test]
+    fn keyspec_display_round_trips(spec in key_spec()) {
+        prop_assert_eq!(KeySpec::parse(&spec.to_string()).unwrap(), spec);
+    }
+}
@@ -3,86 +3,64 @@
 //! These tests verify the core comparison behavior independent of GNU sort.
 //! They document and enforce the expected semantics of rsort's comparison contract.
 
-use rsort::compare::compare_records;
-use rsort::config::Config;
-use rsort::key::KeySpec;
+use rsort::compare::Comparator;
+use rsort::config::{Config, ConfigBuilder};
+use rsort::key::{KeyKind, KeyValue};
 use std::cmp::Ordering;
 
-/// Create a default test configuration
-fn default_config() -> Config {
-    Config {
-        reverse: false,
-        numeric: false,
-        fold_case: false,
-        unique: false,
-        stable: false,
-        debug: false,
-        record_delimiter: b'\n',
-        field_separator: None,
-        keys: vec![],
-        output_file: None,
-        input_files: vec![],
-    }
-}
-
 // ============================================================
 // Last-Resort Comparison Contract
 // ============================================================
 
 #[test]
 fn test_last_resort_is_bytewise() {
-    let config = default_config();
+    let config = Config::default();
     // 'A' (0x41) < 'a' (0x61) bytewise
-    assert_eq!(compare_records(b"A", b"a", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Less);
 }
 
 #[test]
 fn test_last_resort_uses_unsigned_bytes() {
-    let config = default_config();
+    let config = Config::default();
     // 0x7F < 0x80 (unsigned comparison)
-    assert_eq!(compare_records(b"\x7f", b"\x80", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"\x7f", b"\x80"), Ordering::Less);
     // 0xFF is the largest byte value
-    assert_eq!(compare_records(b"\xff", b"\x00", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"\xff", b"\x00"), Ordering::Greater);
 }
 
 #[test]
 fn test_last_resort_ignores_fold_case() {
-    let mut config = default_config();
-    config.fold_case = true;
+    let config = Config::builder().fold_case(true).build().unwrap();
 
     // With -f: A == a on keys, but last-resort sees A < a
-    assert_eq!(compare_records(b"A", b"a", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Less);
 }
 
 #[test]
 fn test_last_resort_ignores_numeric() {
-    let mut config = default_config();
-    config.numeric = true;
+    let config = Config::builder().numeric(true).build().unwrap();
 
     // "10" and "010" equal numerically, but "010" < "10" bytewise
     // '0' (0x30) < '1' (0x31)
-    assert_eq!(compare_records(b"010", b"10", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"010", b"10"), Ordering::Less);
 }
 
 #[test]
 fn test_last_resort_applies_reverse() {
-    let mut config = default_config();
-    config.reverse = true;
+    let config = Config::builder().reverse(true).build().unwrap();
 
     // With -r: last-resort is also reversed
     // Normally A < a, but with -r: A > a
-    assert_eq!(compare_records(b"A", b"a", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Greater);
 }
 
 #[test]
 fn test_last_resort_reverse_with_fold() {
-    let mut config = default_config();
-    config.fold_case = true;
-    config.reverse = true;
+    let config = Config::builder().fold_case(true).reverse(true).build().unwrap();
 
     // With -f -r: keys compare equal (A == a folded), then reversed
     // Last-resort would be A < a, but reversed becomes A > a
-    assert_eq!(compare_records(b"A", b"a", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Greater);
 }
 
 // ============================================================
@@ -91,32 +69,26 @@ fn test_last_resort_reverse_with_fold() {
 
 #[test]
 fn test_stable_disables_last_resort() {
-    let mut config = default_config();
-    config.fold_case = true;
-    config.stable = true;
+    let config = Config::builder().fold_case(true).stable(true).build().unwrap();
 
     // With -s -f: A == a (no last-resort to break tie)
-    assert_eq!(compare_records(b"A", b"a", &config), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Equal);
 }
 
 #[test]
 fn test_unique_disables_last_resort() {
-    let mut config = default_config();
-    config.fold_case = true;
-    config.unique = true;
+    let config = Config::builder().fold_case(true).unique(true).build().unwrap();
 
     // With -u -f: A == a (no last-resort)
-    assert_eq!(compare_records(b"A", b"a", &config), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"A", b"a"), Ordering::Equal);
 }
 
 #[test]
 fn test_stable_numeric_no_last_resort() {
-    let mut config = default_config();
-    config.numeric = true;
-    config.stable = true;
+    let config = Config::builder().numeric(true).stable(true).build().unwrap();
 
     // With -s -n: "10" == "010" (numerically equal, no last-resort)
-    assert_eq!(compare_records(b"010", b"10", &config), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"010", b"10"), Ordering::Equal);
 }
 
 // ============================================================
@@ -125,62 +97,125 @@ fn test_stable_numeric_no_last_resort() {
 
 #[test]
 fn test_numeric_basic_order() {
-    let mut config = default_config();
-    config.numeric = true;
+    let config = Config::builder().numeric(true).build().unwrap();
 
-    assert_eq!(compare_records(b"1", b"2", &config), Ordering::Less);
-    assert_eq!(compare_records(b"10", b"2", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"1", b"2"), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"10", b"2"), Ordering::Greater);
 }
 
 #[test]
 fn test_numeric_negative_numbers() {
-    let mut config = default_config();
-    config.numeric = true;
+    let config = Config::builder().numeric(true).build().unwrap();
 
-    assert_eq!(compare_records(b"-5", b"0", &config), Ordering::Less);
-    assert_eq!(compare_records(b"-5", b"-3", &config), Ordering::Less);
-    assert_eq!(compare_records(b"-5", b"-10", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"-5", b"0"), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"-5", b"-3"), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"-5", b"-10"), Ordering::Greater);
 }
 
 #[test]
 fn test_numeric_decimals() {
-    let mut config = default_config();
-    config.numeric = true;
+    let config = Config::builder().numeric(true).build().unwrap();
 
-    assert_eq!(compare_records(b"1.5", b"1.6", &config), Ordering::Less);
-    assert_eq!(compare_records(b"1.10", b"1.5", &config), Ordering::Less); // 1.10 < 1.5
+    assert_eq!(Comparator::new(&config).cmp(b"1.5", b"1.6"), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"1.10", b"1.5"), Ordering::Less); // 1.10 < 1.5
 }
 
 #[test]
 fn test_numeric_leading_whitespace() {
-    let mut config = default_config();
-    config.numeric = true;
-    config.stable = true; // Disable last-resort to test numeric comparison only
+    let config = Config::builder()
+        .numeric(true)
+        .stable(true) // Disable last-resort to test numeric comparison only
+        .build()
+        .unwrap();
 
     // Leading whitespace is ignored
-    assert_eq!(compare_records(b"  5", b"5", &config), Ordering::Equal);
-    assert_eq!(compare_records(b"\t10", b"10", &config), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"  5", b"5"), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"\t10", b"10"), Ordering::Equal);
 }
 
 #[test]
 fn test_numeric_non_numeric_is_zero() {
-    let mut config = default_config();
-    config.numeric = true;
-    config.stable = true; // Disable last-resort to test numeric comparison only
+    let config = Config::builder()
+        .numeric(true)
+        .stable(true) // Disable last-resort to test numeric comparison only
+        .build()
+        .unwrap();
 
     // Non-numeric text compares as 0
-    assert_eq!(compare_records(b"abc", b"0", &config), Ordering::Equal);
-    assert_eq!(compare_records(b"xyz", b"1", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"abc", b"0"), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"xyz", b"1"), Ordering::Less);
 }
 
 #[test]
 fn test_numeric_empty_is_zero() {
-    let mut config = default_config();
-    config.numeric = true;
-    config.stable = true; // Disable last-resort to test numeric comparison only
+    let config = Config::builder()
+        .numeric(true)
+        .stable(true) // Disable last-resort to test numeric comparison only
+        .build()
+        .unwrap();
+
+    assert_eq!(Comparator::new(&config).cmp(b"", b"0"), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"   ", b"0"), Ordering::Equal);
+}
+
+#[test]
+fn test_numeric_is_exact() {
+    // Beyond f64 precision; GNU compares digit strings
+    let a = KeyValue::parse(b"9007199254740993", KeyKind::Numeric);
+    let b = KeyValue::parse(b"9007199254740992", KeyKind::Numeric);
+    assert_eq!(a.cmp(&b), Ordering::Greater);
+}
+
+#[test]
+fn test_key_value_matches_comparator() {
+    // The public parsed value orders keys exactly as the comparator does
+    let cases: [(KeyKind, ConfigBuilder, &[u8], &[u8]); 5] = [
+        (KeyKind::Numeric, Config::builder().numeric(true), b"-1.50", b"-1.5"),
+        (KeyKind::Human, Config::builder().human_numeric(true), b"1023K", b"1M"),
+        (KeyKind::Month, Config::builder().month(true), b" mar", b"FEB"),
+        (KeyKind::Version, Config::builder().version(true), b"2.0~rc1", b"2.0"),
+        (KeyKind::FoldCase, Config::builder().fold_case(true), b"abc", b"ABD"),
+    ];
+
+    for (kind, builder, a, b) in cases {
+        let config = builder.stable(true).build().unwrap();
+        let expected = KeyValue::parse(a, kind).cmp(&KeyValue::parse(b, kind));
+        assert_eq!(Comparator::new(&config).cmp(a, b), expected, "{:?}", kind);
+        assert_eq!(config.key_kind(), kind);
+    }
+}
 
-    assert_eq!(compare_records(b"", b"0", &config), Ordering::Equal);
-    assert_eq!(compare_records(b"   ", b"0", &config), Ordering::Equal);
+// ============================================================
+// Human, Month and Version Semantics
+// ============================================================
+
+#[test]
+fn test_human_suffix_before_value() {
+    let h = |a: &[u8], b: &[u8]| {
+        KeyValue::parse(a, KeyKind::Human).cmp(&KeyValue::parse(b, KeyKind::Human))
+    };
+    assert_eq!(h(b"999K", b"1M"), Ordering::Less);
+    assert_eq!(h(b"-1G", b"-999K"), Ordering::Less);
+    assert_eq!(h(b"1k", b"1K"), Ordering::Equal);
+}
+
+#[test]
+fn test_month_unknown_first() {
+    fn m(key: &[u8]) -> KeyValue<'_> {
+        KeyValue::parse(key, KeyKind::Month)
+    }
+    assert!(m(b"foo") < m(b"jan"));
+    assert!(m(b"Jan") < m(b"dec"));
+    assert_eq!(m(b"  SEPT"), m(b"sep"));
+}
+
+#[test]
+fn test_version_numeric_runs() {
+    fn v(key: &[u8]) -> KeyValue<'_> {
+        KeyValue::parse(key, KeyKind::Version)
+    }
+    assert!(v(b"file9") < v(b"file10"));
+    assert!(v(b"1.0~beta") < v(b"1.0"));
 }
 
 // ============================================================
@@ -189,31 +224,31 @@ fn test_numeric_empty_is_zero() {
 
 #[test]
 fn test_fold_case_ascii_only() {
-    let mut config = default_config();
-    config.fold_case = true;
+    let config = Config::builder().fold_case(true).build().unwrap();
 
     // ASCII letters are folded
-    assert_eq!(compare_records(b"ABC", b"abc", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"ABC", b"abc"), Ordering::Less);
     // Last-resort breaks the tie: A < a
 }
 
 #[test]
 fn test_fold_case_mixed() {
-    let mut config = default_config();
-    config.fold_case = true;
+    let config = Config::builder().fold_case(true).build().unwrap();
 
     // "Apple" and "apple" compare equal on keys, A < a in last-resort
-    assert_eq!(compare_records(b"Apple", b"apple", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"Apple", b"apple"), Ordering::Less);
 }
 
 #[test]
 fn test_fold_case_non_ascii_unchanged() {
-    let mut config = default_config();
-    config.fold_case = true;
-    config.stable = true; // Disable last-resort for clear testing
+    let config = Config::builder()
+        .fold_case(true)
+        .stable(true) // Disable last-resort for clear testing
+        .build()
+        .unwrap();
 
     // High bytes (>127) are not affected by case folding
-    assert_eq!(compare_records(b"\xe0", b"\xc0", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"\xe0", b"\xc0"), Ordering::Greater);
 }
 
 // ============================================================
@@ -222,53 +257,53 @@ fn test_fold_case_non_ascii_unchanged() {
 
 #[test]
 fn test_key_extracts_correct_field() {
-    let mut config = default_config();
-    config.numeric = true;
-    config.keys = vec![KeySpec::parse("2,2").unwrap()];
+    let config = Config::builder().numeric(true).add_key("2,2").build().unwrap();
 
     // Sort by second field numerically
     // "a 10" vs "b 2": key is "10" vs "2", so "a 10" > "b 2"
     assert_eq!(
-        compare_records(b"a 10", b"b 2", &config),
+        Comparator::new(&config).cmp(b"a 10", b"b 2"),
         Ordering::Greater
     );
 }
 
 #[test]
 fn test_key_missing_field_is_empty() {
-    let mut config = default_config();
-    config.stable = true; // Disable last-resort
-    config.keys = vec![KeySpec::parse("5,5").unwrap()];
+    let config = Config::builder()
+        .stable(true) // Disable last-resort
+        .add_key("5,5")
+        .build()
+        .unwrap();
 
     // Field 5 doesn't exist, so both keys are empty
-    assert_eq!(compare_records(b"a b c", b"x y z", &config), Ordering::Equal);
+    assert_eq!(Comparator::new(&config).cmp(b"a b c", b"x y z"), Ordering::Equal);
 }
 
 #[test]
 fn test_multiple_keys_first_wins() {
-    let mut config = default_config();
-    config.stable = true;
-    config.keys = vec![
-        KeySpec::parse("1,1").unwrap(),
-        KeySpec::parse("2,2").unwrap(),
-    ];
+    let config = Config::builder()
+        .stable(true)
+        .add_key("1,1")
+        .add_key("2,2")
+        .build()
+        .unwrap();
 
     // First keys differ, so second key doesn't matter
-    assert_eq!(compare_records(b"a 1", b"b 2", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"a 1", b"b 2"), Ordering::Less);
 }
 
 #[test]
 fn test_multiple_keys_fallback() {
-    let mut config = default_config();
-    config.stable = true;
-    config.numeric = true;
-    config.keys = vec![
-        KeySpec::parse("1,1").unwrap(),
-        KeySpec::parse("2,2").unwrap(),
-    ];
+    let config = Config::builder()
+        .stable(true)
+        .numeric(true)
+        .add_key("1,1")
+        .add_key("2,2")
+        .build()
+        .unwrap();
 
     // First keys equal, fall back to second key
-    assert_eq!(compare_records(b"a 10", b"a 2", &config), Ordering::Greater);
+    assert_eq!(Comparator::new(&config).cmp(b"a 10", b"a 2"), Ordering::Greater);
 }
 
 // ============================================================
@@ -277,28 +312,32 @@ fn test_multiple_keys_fallback() {
 
 #[test]
 fn test_custom_separator() {
-    let mut config = default_config();
-    config.numeric = true;
-    config.field_separator = Some(b':');
-    config.keys = vec![KeySpec::parse("2,2").unwrap()];
+    let config = Config::builder()
+        .numeric(true)
+        .field_separator(b':')
+        .add_key("2,2")
+        .build()
+        .unwrap();
 
     // With -t:, fields are split on ':'
     assert_eq!(
-        compare_records(b"a:10", b"b:2", &config),
+        Comparator::new(&config).cmp(b"a:10", b"b:2"),
         Ordering::Greater
     );
 }
 
 #[test]
 fn test_consecutive_separators() {
-    let mut config = default_config();
-    config.stable = true;
-    config.field_separator = Some(b':');
-    config.keys = vec![KeySpec::parse("2,2").unwrap()];
+    let config = Config::builder()
+        .stable(true)
+        .field_separator(b':')
+        .add_key("2,2")
+        .build()
+        .unwrap();
 
     // "a::c" has empty field 2, "a:b:c" has "b" as field 2
     // Empty string < "b"
-    assert_eq!(compare_records(b"a::c", b"a:b:c", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"a::c", b"a:b:c"), Ordering::Less);
 }
 
 // ============================================================
@@ -307,34 +346,32 @@ fn test_consecutive_separators() {
 
 #[test]
 fn test_full_flow_with_last_resort() {
-    let config = default_config();
+    let config = Config::default();
 
     // Direct bytewise comparison, last-resort enabled
-    assert_eq!(compare_records(b"apple", b"banana", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"apple", b"banana"), Ordering::Less);
     assert_eq!(
-        compare_records(b"APPLE", b"apple", &config),
+        Comparator::new(&config).cmp(b"APPLE", b"apple"),
         Ordering::Less
     );
 }
 
 #[test]
 fn test_full_flow_numeric_last_resort() {
-    let mut config = default_config();
-    config.numeric = true;
+    let config = Config::builder().numeric(true).build().unwrap();
 
     // "1" and "01" are numerically equal (both = 1)
     // Last-resort: "01" < "1" bytewise
-    assert_eq!(compare_records(b"01", b"1", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"01", b"1"), Ordering::Less);
 }
 
 #[test]
 fn test_full_flow_key_then_last_resort() {
-    let mut config = default_config();
-    config.keys = vec![KeySpec::parse("1,1").unwrap()];
+    let config = Config::builder().add_key("1,1").build().unwrap();
 
     // Keys "a" and "a" are equal
     // Last-resort compares full lines: "a x" < "a y"
-    assert_eq!(compare_records(b"a x", b"a y", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"a x", b"a y"), Ordering::Less);
 }
 
 // ============================================================
@@ -343,26 +380,26 @@ fn test_full_flow_key_then_last_resort() {
 
 #[test]
 fn test_empty_vs_empty() {
-    let config = default_config();
-    assert_eq!(compare_records(b"", b"", &config), Ordering::Equal);
+    let config = Config::default();
+    assert_eq!(Comparator::new(&config).cmp(b"", b""), Ordering::Equal);
 }
 
 #[test]
 fn test_empty_vs_nonempty() {
-    let config = default_config();
+    let config = Config::default();
     // Empty string comes before any non-empty string
-    assert_eq!(compare_records(b"", b"a", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"", b"a"), Ordering::Less);
 }
 
 #[test]
 fn test_prefix_comparison() {
-    let config = default_config();
+    let config = Config::default();
     // "ab" < "abc" (prefix is less)
-    assert_eq!(compare_records(b"ab", b"abc", &config), Ordering::Less);
+    assert_eq!(Comparator::new(&config).cmp(b"ab", b"abc"), Ordering::Less);
 }
 
 #[test]
 fn test_same_length_different_content() {
-    let config = default_config();
-    assert_eq!(compare_records(b"abc", b"abd", &config), Ordering::Less);
+    let config = Config::default();
+    assert_eq!(Comparator::new(&config).cmp(b"abc", b"abd"), Ordering::Less);
 }
@@ -0,0 +1,141 @@
+//! Field separators beyond one byte: CSV, multibyte characters, strings,
+//! regexes and escapes
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rsort::Config;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_csv_keys_respect_quotes() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(
+        dir.path(),
+        "people.csv",
+        b"\"Smith, John\",42\n\"Doe, Jane\",7\nAdams,100\n",
+    )];
+
+    // The quoted comma doesn't split, so field 2 is the number
+    assert_eq!(
+        cli_sort(&inputs, &["--csv", "-k2,2n"]),
+        b"\"Doe, Jane\",7\n\"Smith, John\",42\nAdams,100\n"
+    );
+    // Keys compare the content; output keeps the quotes
+    assert_eq!(
+        cli_sort(&inputs, &["--csv", "-k1,1"]),
+        b"Adams,100\n\"Doe, Jane\",7\n\"Smith, John\",42\n"
+    );
+    assert_eq!(
+        cli_sort(&inputs, &["-t,", "-k1,1"]),
+        b"\"Doe, Jane\",7\n\"Smith, John\",42\nAdams,100\n"
+    );
+}
+
+#[test]
+fn test_csv_empty_and_stray_quotes() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.csv", b"b,2\n\"\",3\nab\"c,1\"\n")];
+
+    // "" is an empty field; a quote mid-field is an ordinary byte
+    assert_eq!(cli_sort(&inputs, &["--csv", "-k1,1"]), b"\"\",3\nab\"c,1\"\nb,2\n");
+    assert_eq!(cli_sort(&inputs, &["--csv", "-k2,2n"]), b"ab\"c,1\"\nb,2\n\"\",3\n");
+}
+
+#[test]
+fn test_multibyte_field_separator() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(
+        dir.path(),
+        "export.txt",
+        "b¦10¦x\na¦9¦y\nc¦9¦w\n".as_bytes(),
+    )];
+
+    assert_eq!(cli_sort(&inputs, &["-t¦", "-k2,2n"]), "a¦9¦y\nc¦9¦w\nb¦10¦x\n".as_bytes());
+    // The key spans fields 2-3 with the separator bytes between them
+    assert_eq!(cli_sort(&inputs, &["-t¦", "-k2,3"]), "b¦10¦x\nc¦9¦w\na¦9¦y\n".as_bytes());
+
+    let config = Config::builder().field_separator('¦').add_key("2,2n").build().unwrap();
+    let out = dir.path().join("out.txt");
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), cli_sort(&inputs, &["-t¦", "-k2,2n"]));
+}
+
+#[test]
+fn test_string_field_separator() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(
+        dir.path(),
+        "app.log",
+        b"web | 3 | GET /\ndb |  | vacuum\nweb | 12 | POST /a|b\n",
+    )];
+
+    assert_eq!(
+        cli_sort(&inputs, &["--field-separator-str= | ", "-k2,2n"]),
+        b"db |  | vacuum\nweb | 3 | GET /\nweb | 12 | POST /a|b\n"
+    );
+    // Fields 2-3 keep the " | " between them, so the empty field sorts first
+    assert_eq!(
+        cli_sort(&inputs, &["--field-separator-str= | ", "-k2,3"]),
+        b"db |  | vacuum\nweb | 12 | POST /a|b\nweb | 3 | GET /\n"
+    );
+
+    let config = Config::builder().field_separator_str(" | ").add_key("2,2n").build().unwrap();
+    let out = dir.path().join("out.txt");
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    let expected = cli_sort(&inputs, &["--field-separator-str= | ", "-k2,2n"]);
+    assert_eq!(fs::read(&out).unwrap(), expected);
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_regex_field_separator() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "mixed.txt", b"x,  10\ny 9\n,z,\t2\n")];
+
+    // Commas and blank runs both separate; a leading comma makes an empty field 1
+    assert_eq!(
+        cli_sort(&inputs, &[r"--field-separator-regex=[,\s]+", "-k2,2n"]),
+        b",z,\t2\ny 9\nx,  10\n"
+    );
+    assert_eq!(
+        cli_sort(&inputs, &[r"--field-separator-regex=[,\s]+", "-k3,3n"]),
+        b"x,  10\ny 9\n,z,\t2\n"
+    );
+
+    let config = Config::builder()
+        .field_separator_regex(r"[,\s]+")
+        .add_key("2,2n")
+        .build()
+        .unwrap();
+    let out = dir.path().join("out.txt");
+    rsort::sort_files(&inputs, &out, &config).unwrap();
+    assert_eq!(fs::read(&out).unwrap(), b",z,\t2\ny 9\nx,  10\n");
+}
+
+#[test]
+fn test_hex_escape_field_separator() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "us.txt", b"a\x1f10\x1fx\nb\x1f9\x1fy\nc\x1f100\x1fz\n")];
+
+    let sorted = b"b\x1f9\x1fy\na\x1f10\x1fx\nc\x1f100\x1fz\n";
+    assert_eq!(cli_sort(&inputs, &["-t\\x1f", "-k2,2n"]), sorted);
+    assert_eq!(cli_sort(&inputs, &["-t\\037", "-k2,2n"]), sorted);
+}
@@ -0,0 +1,149 @@
+//! `rsort::sort_file`, `sort_files` and `sort_files_split` against the binary on
+//! the same files
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rsort::Config;
+
+fn cli_sort(inputs: &[PathBuf], args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(args)
+        .args(inputs)
+        .output()
+        .expect("failed to run rsort");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_sort_file_matches_cli() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = write(dir.path(), "in.txt", b"b 10\na 9\nc 9\nB 1\n");
+    let out = dir.path().join("out.txt");
+
+    let config = Config::builder().numeric(true).add_key("2,2").build().unwrap();
+    let stats = rsort::sort_file(&input, &out, &config).unwrap();
+
+    assert_eq!(fs::read(&out).unwrap(), cli_sort(&[input], &["-n", "-k2,2"]));
+    assert_eq!(stats.records_read, 4);
+    assert_eq!(stats.records_written, 4);
+}
+
+#[test]
+fn test_sort_files_concatenates_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![
+        write(dir.path(), "one.txt", b"b\nA\n"),
+        write(dir.path(), "two.txt", b"a\nB\nb"),
+    ];
+    let out = dir.path().join("out.txt");
+
+    let config = Config::builder().fold_case(true).unique(true).build().unwrap();
+    let stats = rsort::sort_files(&inputs, &out, &config).unwrap();
+
+    assert_eq!(fs::read(&out).unwrap(), cli_sort(&inputs, &["-f", "-u"]));
+    assert_eq!(stats.duplicates_removed, 3);
+}
+
+#[test]
+fn test_sort_file_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), "data.txt", b"3\n1\n2\n");
+    rsort::sort_file(&path, &path, &Config::default()).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"1\n2\n3\n");
+
+    let other = write(dir.path(), "other.txt", b"0\n4\n");
+    let inputs = vec![path.clone(), other];
+    assert_eq!(cli_sort(&inputs, &["-m", "-o", path.to_str().unwrap()]), b"");
+    assert_eq!(fs::read(&path).unwrap(), b"0\n1\n2\n3\n4\n");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2, "temporary file left");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_replaced_output_keeps_its_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), "shared.txt", b"b\na\n");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o664)).unwrap();
+    rsort::sort_file(&path, &path, &Config::default()).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"a\nb\n");
+    assert_eq!(mode(&path), 0o664);
+
+    // Only root may give the file to another owner
+    if std::os::unix::fs::chown(&path, Some(1), Some(1)).is_ok() {
+        use std::os::unix::fs::MetadataExt;
+
+        rsort::sort_file(&path, &path, &Config::builder().reverse(true).build().unwrap()).unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        assert_eq!((meta.uid(), meta.gid()), (1, 1));
+        assert_eq!(mode(&path), 0o664);
+    }
+
+    // A new file gets the usual mode, 0666 less the umask
+    let created = dir.path().join("created.txt");
+    fs::File::create(&created).unwrap();
+    let out = dir.path().join("new.txt");
+    rsort::sort_file(&path, &out, &Config::default()).unwrap();
+    assert_eq!(mode(&out), mode(&created));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_failed_in_place_sort_keeps_the_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write(dir.path(), "data.txt", b"3\n1\n2\n");
+    // The output fails after it has started
+    let config = Config::builder().compress_output_program("false").build().unwrap();
+    assert!(rsort::sort_file(&path, &path, &config).is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"3\n1\n2\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rsort"))
+        .args(["-m", "--compress-output-program=false", "-o"])
+        .args([&path, &path])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(fs::read(&path).unwrap(), b"3\n1\n2\n");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1, "temporary file left");
+}
+
+#[test]
+fn test_sort_file_missing_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.txt");
+    let err = rsort::sort_file(&missing, dir.path().join("out"), &Config::default()).unwrap_err();
+    assert!(matches!(err, rsort::RsortError::OpenInput { .. }));
+    assert!(!dir.path().join("out").exists());
+}
+
+#[test]
+fn test_split_by_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs = vec![write(dir.path(), "in.txt", b"b 1\na 1\na 2\nc 1\na 3\nb 2\n")];
+    let prefix = dir.path().join("part");
+    let config = Config::builder().add_key("1,1").split_by_key(true).build().unwrap();
+    let stats = rsort::sort_files_split(&inputs, &prefix, 3, &config).unwrap();
+    assert_eq!(stats.records_written, 6);
+    let chunk = |i: usize| fs::read(dir.path().join(format!("part.{:03}", i))).unwrap();
+    // Two records each would split the a's; each key moves to one file
+    assert_eq!(chunk(0), b"a 1\na 2\na 3\n");
+    assert_eq!(chunk(1), b"b 1\nb 2\n");
+    assert_eq!(chunk(2), b"c 1\n");
+
+    let config = Config::builder().add_key("1,1").build().unwrap();
+    rsort::sort_files_split(&inputs, &prefix, 3, &config).unwrap();
+    assert_eq!(chunk(0), b"a 1\na 2\n");
+    assert_eq!(chunk(1), b"a 3\nb 1\n");
+}
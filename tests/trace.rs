@@ -0,0 +1,72 @@
+//! The spans and events of --log-level, caught by a test subscriber
+#![cfg(feature = "trace")]
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use rsort::Config;
+
+/// Everything the subscriber wrote
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What running `f` logs at debug level
+fn logged(f: impl FnOnce()) -> String {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, f);
+    let bytes = captured.0.lock().unwrap().clone();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn test_sort_phases_are_logged() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("in.txt");
+    std::fs::write(&input, b"c\na\nb\n").unwrap();
+    let output = dir.path().join("out.txt");
+
+    let log = logged(|| {
+        rsort::sort_file(&input, &output, &Config::default()).unwrap();
+    });
+    let line = |message: &str| {
+        log.lines()
+            .find(|line| line.contains(message))
+            .unwrap_or_else(|| panic!("no {:?} in\n{}", message, log))
+            .to_string()
+    };
+    assert!(line("opened input").contains(&format!("path={}", input.display())));
+    let read = line("read input");
+    assert!(read.contains(":read:"), "{}", read);
+    assert!(read.contains("records=3 bytes=3"), "{}", read);
+    assert!(line("sorted").contains("sort{records=3}"));
+    assert!(line("written").contains("records=3 bytes=6"));
+}
+
+#[test]
+fn test_merge_fan_in_is_logged() {
+    let config = Config::builder().merge(true).build().unwrap();
+    let sources: Vec<Box<dyn io::BufRead>> =
+        vec![Box::new(&b"a\nc\n"[..]), Box::new(&b"b\n"[..]), Box::new(&b""[..])];
+    let log = logged(|| {
+        rsort::merge::merge_streams(sources, &config, io::sink()).unwrap();
+    });
+    assert!(log.contains("merge{fan_in=3}"), "{}", log);
+    assert!(log.contains("merged records_read=3 records_written=3"), "{}", log);
+}